@@ -1,3 +1,26 @@
+/// One OpenID Connect provider, configured via `OIDC_PROVIDERS` and `OIDC_<NAME>_*`.
+#[derive(Clone)]
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+}
+
+/// An S3-compatible bucket, configured via `STORAGE_S3_*`.
+#[derive(Clone)]
+pub struct S3StorageConfig {
+    /// Base URL without the bucket, e.g. `https://s3.eu-west-1.amazonaws.com` or
+    /// `http://minio:9000`; requests are path-style.
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
 #[derive(Clone)]
 pub struct Config {
     pub port: u16,
@@ -7,18 +30,78 @@ pub struct Config {
     pub github_authorize_url: String,
     pub github_token_url: String,
     pub github_api_base: String,
+    pub github_state_ttl_seconds: u64,
+    /// Lowercase org logins; when this or `github_allowed_teams` is set, only members may sign in.
+    pub github_allowed_orgs: Vec<String>,
+    /// Lowercase `org/team-slug` entries.
+    pub github_allowed_teams: Vec<String>,
+    /// Absolute origins (`https://app.example.com`) a login may redirect back to via `return_to`.
+    /// Relative paths are always allowed.
+    pub return_to_allowed_origins: Vec<String>,
     pub redis_url: Option<String>,
+    /// Absolute lifetime of a session; it also ends after `session_idle_ttl_seconds` without
+    /// requests. The `remember_me` pair applies to logins that ask to be remembered.
     pub session_ttl_seconds: u64,
+    pub session_idle_ttl_seconds: u64,
+    pub session_remember_me_ttl_seconds: u64,
+    pub session_remember_me_idle_ttl_seconds: u64,
     pub verify_email_token_ttl_seconds: u64,
+    pub reset_password_token_ttl_seconds: u64,
+    pub team_invite_token_ttl_seconds: u64,
     pub cookie_secure: bool,
     pub cookie_domain: Option<String>,
     pub session_key_prefix: String,
+    pub mfa_encryption_key: Option<String>,
+    pub mfa_totp_issuer: String,
+    pub mfa_challenge_ttl_seconds: u64,
+    pub webauthn_rp_id: Option<String>,
+    pub webauthn_rp_origin: Option<String>,
+    pub webauthn_rp_name: String,
+    pub webauthn_challenge_ttl_seconds: u64,
+    pub jwt_enabled: bool,
+    pub jwt_algorithm: String,
+    pub jwt_issuer: String,
+    pub jwt_audience: Option<String>,
+    pub jwt_access_token_ttl_seconds: u64,
+    pub jwt_key_rotation_seconds: u64,
+    pub jwt_private_key_path: Option<String>,
+    pub jwt_previous_key_paths: Vec<String>,
+    /// Refresh tokens are issued alongside JWT access tokens; a login's token family ends after
+    /// the absolute TTL, and each token ends early if unused for the idle TTL.
+    pub refresh_token_absolute_ttl_seconds: u64,
+    pub refresh_token_idle_ttl_seconds: u64,
+    pub oauth_code_ttl_seconds: u64,
+    /// Sign-in page for `/oauth/authorize` when there is no session; receives `return_to`.
+    pub oauth_login_url: Option<String>,
+    /// Consent page for third-party clients; receives `consent_id`.
+    pub oauth_consent_url: Option<String>,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    pub oidc_state_ttl_seconds: u64,
+    /// Sign-in page `/api/v1/auth/verify` redirects browsers to; receives `return_to`.
+    pub forward_auth_login_url: Option<String>,
+    /// How long `/api/v1/auth/verify` trusts a resolved session or token; 0 disables the cache.
+    pub forward_auth_cache_ttl_seconds: u64,
+    /// Account uids or emails granted the admin role at startup. Removing an entry does not
+    /// revoke the role.
+    pub bootstrap_admins: Vec<String>,
+    /// `local` or `s3`.
+    pub storage_backend: String,
+    pub storage_local_dir: String,
+    pub storage_s3: Option<S3StorageConfig>,
+    pub avatar_max_bytes: u64,
+    /// Edge lengths of the square thumbnails made from each upload, ascending.
+    pub avatar_sizes: Vec<u32>,
+    /// Public URL under which the `avatars/` storage prefix is served.
+    pub avatar_url_base: String,
 
     // Optional email delivery (cold-start friendly). When set, registration will send a
     // verification email via Resend.
     pub resend_api_key: Option<String>,
     pub email_from: Option<String>,
     pub verify_email_url_base: Option<String>,
+    pub reset_password_url_base: Option<String>,
+    /// Page that accepts or declines a team invitation; receives `token`.
+    pub team_invite_url_base: Option<String>,
     pub email_provider: Option<String>,
 
     pub smtp_host: Option<String>,
@@ -8,6 +8,9 @@ pub struct Model {
     pub account_id: i64,
     pub token_hash: String,
     pub token_type: String,
+    pub family_id: Option<Uuid>,
+    pub metadata: Option<Json>,
+    pub last_used_at: Option<DateTimeWithTimeZone>,
     pub expires_at: Option<DateTimeWithTimeZone>,
     pub revoked_at: Option<DateTimeWithTimeZone>,
     pub created_at: DateTimeWithTimeZone,
@@ -9,6 +9,8 @@ pub struct Model {
     pub account_type: String,
     pub username: Option<String>,
     pub email: Option<String>,
+    /// When `email` was last proven to belong to the account; cleared whenever it changes.
+    pub email_verified_at: Option<DateTimeWithTimeZone>,
     pub phone: Option<String>,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
@@ -0,0 +1,30 @@
+use sea_orm::entity::prelude::*;
+
+/// A client registered to use the OAuth authorization server. Public clients (SPAs, native
+/// apps) have no secret and must use PKCE; first-party clients skip the consent step.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "oauth_clients")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub client_id: String,
+    pub client_secret_hash: Option<String>,
+    pub name: String,
+    pub redirect_uris: Json,
+    pub allowed_scopes: Json,
+    pub is_public: bool,
+    pub first_party: bool,
+    pub owner_account_id: Option<i64>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+    pub created_by: Option<Uuid>,
+    pub updated_by: Option<Uuid>,
+    pub deleted_by: Option<Uuid>,
+    pub purge_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,28 @@
+use sea_orm::entity::prelude::*;
+
+/// A JWT signing key. Keys sign new tokens until `not_after` and stay in the JWKS until
+/// `expires_at`, so tokens issued just before a rotation keep verifying.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "signing_keys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub kid: String,
+    pub algorithm: String,
+    pub private_key_pem: String,
+    pub public_jwk: Json,
+    pub not_after: DateTimeWithTimeZone,
+    pub expires_at: DateTimeWithTimeZone,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+    pub created_by: Option<Uuid>,
+    pub updated_by: Option<Uuid>,
+    pub deleted_by: Option<Uuid>,
+    pub purge_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
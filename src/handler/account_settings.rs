@@ -0,0 +1,242 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{
+    entities::accounts,
+    handler::{
+        accounts::visible_account,
+        auth::password::{error_response, ErrorResponse},
+        principal::Principal,
+    },
+    service::account_settings::{
+        AccountSettings, AccountSettingsError, UpdateAccountSettingsInput,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateAccountSettingsRequest {
+    /// Omit to keep the current value, or send an empty string to clear it.
+    pub nickname: Option<String>,
+    /// Omit to keep the current value, or send an empty string to clear it.
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AccountSettingsResponse {
+    pub nickname: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+impl From<AccountSettings> for AccountSettingsResponse {
+    fn from(settings: AccountSettings) -> Self {
+        Self {
+            nickname: settings.nickname,
+            avatar_url: settings.avatar_url,
+        }
+    }
+}
+
+fn settings_error_response(err: AccountSettingsError) -> Response {
+    let status = match err.code {
+        "db_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    error_response(status, err.code, err.message)
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(
+            "/api/v1/me/settings",
+            get(get_my_settings).patch(update_my_settings),
+        )
+        .route(
+            "/api/v1/accounts/:uid/settings",
+            get(get_account_settings).patch(update_account_settings),
+        )
+        .with_state(state)
+}
+
+async fn read_settings(state: &AppState, account: &accounts::Model) -> Response {
+    match state.account_settings().get(account).await {
+        Ok(settings) => Json(AccountSettingsResponse::from(settings)).into_response(),
+        Err(err) => settings_error_response(err),
+    }
+}
+
+async fn write_settings(
+    state: &AppState,
+    principal: &Principal,
+    account: &accounts::Model,
+    payload: UpdateAccountSettingsRequest,
+) -> Response {
+    let input = UpdateAccountSettingsInput {
+        nickname: payload.nickname,
+        avatar_url: payload.avatar_url,
+        updated_by: principal.account.uid,
+    };
+    match state.account_settings().update(account, input).await {
+        Ok(settings) => Json(AccountSettingsResponse::from(settings)).into_response(),
+        Err(err) => settings_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/settings",
+    responses(
+        (status = 200, description = "Profile settings of the caller", body = AccountSettingsResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn get_my_settings(State(state): State<Arc<AppState>>, principal: Principal) -> Response {
+    read_settings(&state, &principal.account).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/me/settings",
+    request_body = UpdateAccountSettingsRequest,
+    responses(
+        (status = 200, description = "Updated settings", body = AccountSettingsResponse),
+        (status = 400, description = "Invalid nickname or avatar URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn update_my_settings(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Json(payload): Json<UpdateAccountSettingsRequest>,
+) -> Response {
+    write_settings(&state, &principal, &principal.account, payload).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/accounts/{uid}/settings",
+    params(("uid" = String, Path, description = "Account uid")),
+    responses(
+        (status = 200, description = "Profile settings of the account", body = AccountSettingsResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 404, description = "Not found, or not visible to the caller", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn get_account_settings(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(uid): Path<String>,
+) -> Response {
+    match visible_account(&state, &principal, &uid).await {
+        Ok(account) => read_settings(&state, &account).await,
+        Err(response) => response,
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/accounts/{uid}/settings",
+    request_body = UpdateAccountSettingsRequest,
+    params(("uid" = String, Path, description = "Account uid")),
+    responses(
+        (status = 200, description = "Updated settings", body = AccountSettingsResponse),
+        (status = 400, description = "Invalid nickname or avatar URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Visible to the caller but not writable", body = ErrorResponse),
+        (status = 404, description = "Not found, or not visible to the caller", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn update_account_settings(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(uid): Path<String>,
+    Json(payload): Json<UpdateAccountSettingsRequest>,
+) -> Response {
+    let account = match visible_account(&state, &principal, &uid).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    if !principal.can_write(&account) {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "updating this account needs accounts:write",
+        );
+    }
+    write_settings(&state, &principal, &account, payload).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn settings_are_created_on_first_update() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        for email in ["ada@example.com", "grace@example.com"] {
+            register(&app, email).await;
+            verify_email(&state, &app, email).await;
+        }
+        let ada_cookie = session_cookie(&login(&app, "ada@example.com").await);
+        let grace_cookie = session_cookie(&login(&app, "grace@example.com").await);
+
+        let response = send(&app, "GET", "/api/v1/me/settings", Some(&ada_cookie), None).await;
+        assert_eq!(json_body(response).await["nickname"], Value::Null);
+        let response = send(
+            &app,
+            "PATCH",
+            "/api/v1/me/settings",
+            Some(&ada_cookie),
+            Some(json!({ "avatar_url": "javascript:alert(1)" })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = send(
+            &app,
+            "PATCH",
+            "/api/v1/me/settings",
+            Some(&ada_cookie),
+            Some(json!({ "nickname": " Ada ", "avatar_url": "https://example.com/ada.png" })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, "GET", "/api/v1/me", Some(&ada_cookie), None).await;
+        let me = json_body(response).await;
+        assert_eq!(me["nickname"], "Ada");
+        assert_eq!(me["avatar_url"], "https://example.com/ada.png");
+
+        let settings_uri = format!(
+            "/api/v1/accounts/{}/settings",
+            me["account_uid"].as_str().unwrap()
+        );
+        let response = send(&app, "GET", &settings_uri, Some(&grace_cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = send(
+            &app,
+            "PATCH",
+            &settings_uri,
+            Some(&ada_cookie),
+            Some(json!({ "nickname": "" })),
+        )
+        .await;
+        let settings = json_body(response).await;
+        assert_eq!(settings["nickname"], Value::Null);
+        assert_eq!(settings["avatar_url"], "https://example.com/ada.png");
+    }
+}
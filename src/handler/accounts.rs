@@ -1,28 +1,46 @@
 use axum::{
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
-    routing::{delete, get, patch, post},
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch},
     Json, Router,
 };
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
     entities::accounts,
-    service::accounts::{CreateAccountInput, UpdateAccountInput},
+    handler::{
+        auth::password::{error_response, ErrorResponse},
+        principal::{AccountsRead, Principal, RequirePermission},
+    },
+    repo::accounts::AccountListQuery,
+    service::{
+        accounts::{CreateAccountInput, UpdateAccountInput},
+        rbac::PERMISSION_ACCOUNTS_WRITE,
+        verification::TOKEN_TYPE_VERIFY_EMAIL,
+    },
     state::AppState,
 };
 
+const ACCOUNT_TYPES: [&str; 3] = ["user", "team", "robot"];
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 200;
+/// Robot accounts are created by any account, so letting them claim addresses would allow
+/// squatting on emails that invites and admin bootstrap match against.
+const ROBOT_EMAIL_FORBIDDEN: &str = "giving a robot account an email needs accounts:write";
+
 #[derive(Deserialize, ToSchema)]
 pub struct CreateAccount {
+    /// `user`, `team` or `robot`; anything but robot accounts needs `accounts:write`.
     pub account_type: String,
     pub username: Option<String>,
+    /// Needs `accounts:write`, whatever the account type.
     pub email: Option<String>,
     pub phone: Option<String>,
-    pub created_by: Option<Uuid>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -30,7 +48,33 @@ pub struct UpdateAccount {
     pub username: Option<String>,
     pub email: Option<String>,
     pub phone: Option<String>,
-    pub updated_by: Option<Uuid>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ListAccountsQuery {
+    /// `user`, `team` or `robot`.
+    pub account_type: Option<String>,
+    /// Case-insensitive prefix of the email or the username.
+    pub q: Option<String>,
+    /// RFC 3339, inclusive.
+    pub created_from: Option<DateTime<Utc>>,
+    /// RFC 3339, exclusive.
+    pub created_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub include_deleted: bool,
+    /// `desc` (newest first, the default) or `asc`, by creation.
+    pub order: Option<String>,
+    /// `next_cursor` of the previous page, with the same filters and order.
+    pub cursor: Option<String>,
+    /// 1 to 200, 50 by default.
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AccountListResponse {
+    pub accounts: Vec<AccountResponse>,
+    /// Absent on the last page.
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -60,34 +104,150 @@ impl From<accounts::Model> for AccountResponse {
     }
 }
 
+fn not_found() -> Response {
+    error_response(StatusCode::NOT_FOUND, "not_found", "account not found")
+}
+
+fn forbidden(message: &str) -> Response {
+    error_response(StatusCode::FORBIDDEN, "forbidden", message.to_string())
+}
+
+/// The account at `uid` if the caller may read it. Accounts the caller may not see are
+/// reported as missing, so that uids cannot be probed.
+pub(crate) async fn visible_account(
+    state: &AppState,
+    principal: &Principal,
+    uid: &str,
+) -> Result<accounts::Model, Response> {
+    let Ok(uid) = Uuid::parse_str(uid) else {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_uid",
+            "uid must be a uuid",
+        ));
+    };
+    match state.accounts().get(uid).await {
+        Ok(Some(account)) if principal.can_read(&account) => Ok(account),
+        Ok(_) => Err(not_found()),
+        Err(err) => Err(error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "db_error",
+            format!("account lookup failed: {}", err),
+        )),
+    }
+}
+
 #[utoipa::path(
     post,
     path = "/api/v1/accounts",
     request_body = CreateAccount,
     responses(
         (status = 201, description = "Created", body = AccountResponse),
-        (status = 400, description = "Invalid payload")
+        (status = 400, description = "Invalid payload", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Creating user and team accounts, or giving a robot an email, needs accounts:write", body = ErrorResponse)
     )
 )]
 pub async fn create_account(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Json(payload): Json<CreateAccount>,
-) -> Result<(StatusCode, Json<AccountResponse>), StatusCode> {
+) -> Response {
+    if !ACCOUNT_TYPES.contains(&payload.account_type.as_str()) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_account_type",
+            "account_type must be user, team or robot",
+        );
+    }
+    if payload.account_type != "robot" && !principal.has_permission(PERMISSION_ACCOUNTS_WRITE) {
+        return forbidden("creating user and team accounts needs accounts:write");
+    }
+    if payload.email.is_some() && !principal.has_permission(PERMISSION_ACCOUNTS_WRITE) {
+        return forbidden(ROBOT_EMAIL_FORBIDDEN);
+    }
     let input = CreateAccountInput {
         account_type: payload.account_type,
         username: payload.username,
         email: payload.email,
         phone: payload.phone,
-        created_by: payload.created_by,
+        created_by: principal.account.uid,
     };
 
-    let inserted = state
-        .accounts()
-        .create(input)
-        .await
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    match state.accounts().create(input).await {
+        Ok(inserted) => {
+            (StatusCode::CREATED, Json(AccountResponse::from(inserted))).into_response()
+        }
+        Err(err) => error_response(StatusCode::BAD_REQUEST, "invalid_account", err.to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/accounts",
+    params(ListAccountsQuery),
+    responses(
+        (status = 200, description = "One page of accounts", body = AccountListResponse),
+        (status = 400, description = "Invalid filter, order, cursor or limit", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Needs accounts:read", body = ErrorResponse)
+    )
+)]
+pub async fn list_accounts(
+    State(state): State<Arc<AppState>>,
+    _principal: RequirePermission<AccountsRead>,
+    Query(query): Query<ListAccountsQuery>,
+) -> Response {
+    let invalid = |code: &str, message: &str| {
+        error_response(StatusCode::BAD_REQUEST, code, message.to_string())
+    };
+    if query
+        .account_type
+        .as_deref()
+        .is_some_and(|account_type| !ACCOUNT_TYPES.contains(&account_type))
+    {
+        return invalid(
+            "invalid_account_type",
+            "account_type must be user, team or robot",
+        );
+    }
+    let descending = match query.order.as_deref() {
+        None | Some("desc") => true,
+        Some("asc") => false,
+        Some(_) => return invalid("invalid_order", "order must be asc or desc"),
+    };
+    let after_id = match query.cursor.as_deref().map(str::parse::<i64>) {
+        None => None,
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return invalid("invalid_cursor", "cursor is not a next_cursor value"),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return invalid("invalid_limit", "limit must be between 1 and 200");
+    }
 
-    Ok((StatusCode::CREATED, Json(inserted.into())))
+    let list_query = AccountListQuery {
+        account_type: query.account_type,
+        search: query.q.filter(|q| !q.trim().is_empty()),
+        created_from: query.created_from,
+        created_until: query.created_until,
+        include_deleted: query.include_deleted,
+        after_id,
+        descending,
+        limit,
+    };
+    match state.accounts().list(list_query).await {
+        Ok(page) => Json(AccountListResponse {
+            accounts: page.accounts.into_iter().map(Into::into).collect(),
+            next_cursor: page.next_cursor.map(|id| id.to_string()),
+        })
+        .into_response(),
+        Err(err) => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "db_error",
+            format!("account listing failed: {}", err),
+        ),
+    }
 }
 
 #[utoipa::path(
@@ -98,23 +258,18 @@ pub async fn create_account(
     ),
     responses(
         (status = 200, description = "Account", body = AccountResponse),
-        (status = 404, description = "Not found")
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 404, description = "Not found, or not visible to the caller", body = ErrorResponse)
     )
 )]
 pub async fn get_account(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(uid): Path<String>,
-) -> Result<Json<AccountResponse>, StatusCode> {
-    let uid = Uuid::parse_str(&uid).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let account = state
-        .accounts()
-        .get(uid)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    match account {
-        Some(model) => Ok(Json(model.into())),
-        None => Err(StatusCode::NOT_FOUND),
+) -> Response {
+    match visible_account(&state, &principal, &uid).await {
+        Ok(account) => Json(AccountResponse::from(account)).into_response(),
+        Err(response) => response,
     }
 }
 
@@ -126,32 +281,79 @@ pub async fn get_account(
         ("uid" = String, Path, description = "Account uid")
     ),
     responses(
-        (status = 200, description = "Updated", body = AccountResponse),
-        (status = 404, description = "Not found")
+        (status = 200, description = "Updated; a changed email is unverified until the link sent to it is followed", body = AccountResponse),
+        (status = 400, description = "Invalid payload", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Visible to the caller but not writable, or setting a robot's email without accounts:write", body = ErrorResponse),
+        (status = 404, description = "Not found, or not visible to the caller", body = ErrorResponse)
     )
 )]
 pub async fn update_account(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(uid): Path<String>,
     Json(payload): Json<UpdateAccount>,
-) -> Result<Json<AccountResponse>, StatusCode> {
-    let uid = Uuid::parse_str(&uid).map_err(|_| StatusCode::BAD_REQUEST)?;
+) -> Response {
+    let account = match visible_account(&state, &principal, &uid).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    if !principal.can_write(&account) {
+        return forbidden("updating this account needs accounts:write");
+    }
+    if account.account_type == "robot"
+        && payload.email.is_some()
+        && !principal.has_permission(PERMISSION_ACCOUNTS_WRITE)
+    {
+        return forbidden(ROBOT_EMAIL_FORBIDDEN);
+    }
     let input = UpdateAccountInput {
         username: payload.username,
         email: payload.email,
         phone: payload.phone,
-        updated_by: payload.updated_by,
+        updated_by: principal.account.uid,
     };
 
-    let updated = state
-        .accounts()
-        .update(uid, input)
-        .await
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    match state.accounts().update(account.uid, input).await {
+        Ok(Some(model)) => {
+            if model.email_verified_at.is_none() && model.email != account.email {
+                send_verification(&state, &model).await;
+            }
+            Json(AccountResponse::from(model)).into_response()
+        }
+        Ok(None) => not_found(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, "invalid_account", err.to_string()),
+    }
+}
 
-    match updated {
-        Some(model) => Ok(Json(model.into())),
-        None => Err(StatusCode::NOT_FOUND),
+/// Sends a verification link to a changed email. Until it is followed the address cannot be
+/// used to sign in, reset the password or accept team invites.
+async fn send_verification(state: &AppState, account: &accounts::Model) {
+    let Some(email) = &account.email else {
+        return;
+    };
+    let token = match state
+        .verification()
+        .create_token(account.id, TOKEN_TYPE_VERIFY_EMAIL)
+        .await
+    {
+        Ok(token) => token,
+        Err(err) => {
+            eprintln!(
+                "warning: failed to issue verification token: {}",
+                err.message
+            );
+            return;
+        }
+    };
+    if let Err(err) = crate::service::email::try_send_verification_email(
+        state.config().values(),
+        email,
+        &token.token,
+    )
+    .await
+    {
+        eprintln!("warning: failed to send verification email: {}", err);
     }
 }
 
@@ -159,49 +361,190 @@ pub async fn update_account(
     delete,
     path = "/api/v1/accounts/{uid}",
     params(
-        ("uid" = String, Path, description = "Account uid"),
-        ("x-actor-id" = Option<String>, Header, description = "Optional actor uid for audit")
+        ("uid" = String, Path, description = "Account uid")
     ),
     responses(
         (status = 204, description = "Deleted"),
-        (status = 404, description = "Not found")
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Deleting accounts other than robots the caller created needs accounts:write", body = ErrorResponse),
+        (status = 404, description = "Not found, or not visible to the caller", body = ErrorResponse)
     )
 )]
 pub async fn delete_account(
     State(state): State<Arc<AppState>>,
-    headers: HeaderMap,
+    principal: Principal,
     Path(uid): Path<String>,
-) -> Result<StatusCode, StatusCode> {
-    let uid = Uuid::parse_str(&uid).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let deleted_by = parse_actor_id(&headers).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let deleted = state
-        .accounts()
-        .delete(uid, deleted_by)
-        .await
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-
-    match deleted {
-        Some(_) => Ok(StatusCode::NO_CONTENT),
-        None => Err(StatusCode::NOT_FOUND),
+) -> Response {
+    let account = match visible_account(&state, &principal, &uid).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    // Owning an account is enough to update it, but only robots may be deleted that way.
+    let may_delete = principal.has_permission(PERMISSION_ACCOUNTS_WRITE)
+        || (account.account_type == "robot" && principal.can_write(&account));
+    if !may_delete {
+        return forbidden("deleting accounts other than robots you created needs accounts:write");
     }
-}
 
-fn parse_actor_id(headers: &HeaderMap) -> Result<Option<Uuid>, ()> {
-    let Some(raw) = headers.get("x-actor-id") else {
-        return Ok(None);
-    };
-    let value = raw.to_str().map_err(|_| ())?;
-    if value.trim().is_empty() {
-        return Ok(None);
+    match state
+        .accounts()
+        .delete(account.uid, principal.account.uid)
+        .await
+    {
+        Ok(Some(_)) => StatusCode::NO_CONTENT.into_response(),
+        Ok(None) => not_found(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, "invalid_account", err.to_string()),
     }
-    Uuid::parse_str(value.trim()).map(Some).map_err(|_| ())
 }
 
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
-        .route("/api/v1/accounts", post(create_account))
+        .route("/api/v1/accounts", get(list_accounts).post(create_account))
         .route("/api/v1/accounts/:uid", get(get_account))
         .route("/api/v1/accounts/:uid", patch(update_account))
         .route("/api/v1/accounts/:uid", delete(delete_account))
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::service::auth::PasswordResetOutput;
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn accounts_are_scoped_to_the_caller() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        register(&app, "grace@example.com").await;
+        let grace = account(&state, "grace@example.com").await;
+        let grace_uri = format!("/api/v1/accounts/{}", grace.uid);
+
+        let response = send(&app, "GET", &grace_uri, None, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&app, "GET", &grace_uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = json!({ "account_type": "user", "username": "mallory" });
+        let response = send(&app, "POST", "/api/v1/accounts", Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let body = json!({ "account_type": "robot", "email": "root@example.com" });
+        let response = send(&app, "POST", "/api/v1/accounts", Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = json!({ "account_type": "robot", "username": "ci-bot" });
+        let response = send(&app, "POST", "/api/v1/accounts", Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let robot_uri = format!(
+            "/api/v1/accounts/{}",
+            json_body(response).await["uid"].as_str().unwrap()
+        );
+        let robot = state
+            .accounts_repo()
+            .find_by_username("ci-bot")
+            .await
+            .unwrap()
+            .unwrap();
+        let ada = account(&state, "ada@example.com").await;
+        assert_eq!(robot.created_by, Some(ada.uid));
+
+        let body = json!({ "phone": "+15550100" });
+        let response = send(&app, "PATCH", &robot_uri, Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json!({ "email": "root@example.com" });
+        let response = send(&app, "PATCH", &robot_uri, Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(&app, "DELETE", &robot_uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn changing_the_email_clears_its_verification() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let ada = account(&state, "ada@example.com").await;
+        assert!(ada.email_verified_at.is_some());
+
+        let uri = format!("/api/v1/accounts/{}", ada.uid);
+        let body = json!({ "email": "grace@example.com" });
+        let response = send(&app, "PATCH", &uri, Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let ada = account(&state, "grace@example.com").await;
+        assert!(ada.email_verified_at.is_none());
+
+        // The new address is unusable until it is verified, and asking for a reset link only
+        // gets another verification link.
+        let response = login(&app, "grace@example.com").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json_body(response).await["code"], "email_not_verified");
+        let output = state
+            .auth()
+            .request_password_reset("grace@example.com")
+            .await
+            .unwrap();
+        assert!(matches!(
+            output,
+            Some(PasswordResetOutput::VerifyEmail { .. })
+        ));
+
+        verify_email(&state, &app, "grace@example.com").await;
+        let response = login(&app, "grace@example.com").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let output = state
+            .auth()
+            .request_password_reset("grace@example.com")
+            .await
+            .unwrap();
+        assert!(matches!(output, Some(PasswordResetOutput::Reset { .. })));
+    }
+
+    #[tokio::test]
+    async fn admins_page_through_accounts() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        for email in ["ada@example.com", "grace@example.com", "alan@example.com"] {
+            register(&app, email).await;
+            verify_email(&state, &app, email).await;
+        }
+        let cookie = session_cookie(&login(&app, "ada@example.com").await);
+        let response = send(&app, "GET", "/api/v1/accounts", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let ada = state
+            .accounts_repo()
+            .find_by_email("ada@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        state
+            .rbac()
+            .grant_role(&ada, crate::service::rbac::ROLE_ADMIN, ada.uid)
+            .await
+            .unwrap();
+        let emails = |page: &Value| -> Vec<String> {
+            page["accounts"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|account| account["email"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let uri = "/api/v1/accounts?order=asc&limit=2";
+        let page = json_body(send(&app, "GET", uri, Some(&cookie), None).await).await;
+        assert_eq!(emails(&page), ["ada@example.com", "grace@example.com"]);
+        let uri = format!("{}&cursor={}", uri, page["next_cursor"].as_str().unwrap());
+        let page = json_body(send(&app, "GET", &uri, Some(&cookie), None).await).await;
+        assert_eq!(emails(&page), ["alan@example.com"]);
+        assert_eq!(page["next_cursor"], Value::Null);
+
+        let uri = "/api/v1/accounts?q=A&account_type=user";
+        let page = json_body(send(&app, "GET", uri, Some(&cookie), None).await).await;
+        assert_eq!(emails(&page), ["alan@example.com", "ada@example.com"]);
+        let response = send(&app, "GET", "/api/v1/accounts?limit=0", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
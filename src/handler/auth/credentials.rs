@@ -0,0 +1,218 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    handler::{
+        auth::password::{error_response, ErrorResponse},
+        session::current_session,
+    },
+    service::credentials::CredentialError,
+    state::AppState,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct CredentialResponse {
+    pub id: i64,
+    pub provider: String,
+    pub provider_subject: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CredentialListResponse {
+    pub credentials: Vec<CredentialResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LinkProviderResponse {
+    pub provider: String,
+    pub provider_subject: String,
+}
+
+/// The credentials API only accepts the session cookie: bearer tokens never manage the login
+/// methods of the account they act for.
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/me/credentials", get(list_credentials))
+        .route("/api/v1/me/credentials/:id", delete(unlink_credential))
+        .with_state(state)
+}
+
+fn credential_error_response(err: CredentialError) -> Response {
+    let status = match err.code {
+        "credential_not_found" => StatusCode::NOT_FOUND,
+        "provider_already_linked" | "provider_exists" | "last_credential" => StatusCode::CONFLICT,
+        "db_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    error_response(status, err.code, err.message)
+}
+
+/// Finishes an OAuth/OIDC callback that was started as a link for `link_account`.
+///
+/// The callback must arrive with a session for that same account; otherwise someone could start
+/// a link for their own account and trick a victim into completing it with the victim's identity.
+pub(crate) async fn complete_link(
+    state: &AppState,
+    jar: &CookieJar,
+    link_account: Uuid,
+    provider: &str,
+    provider_subject: &str,
+    metadata: Option<serde_json::Value>,
+    return_to: Option<String>,
+) -> Response {
+    let current = match current_session(state, jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+    if current.account.uid != link_account {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "link_session_mismatch",
+            "the link was started from a different account",
+        );
+    }
+
+    if let Err(err) = state
+        .credentials()
+        .link(&current.account, provider, provider_subject, metadata)
+        .await
+    {
+        return credential_error_response(err);
+    }
+
+    if let Some(return_to) = return_to {
+        return Redirect::to(&return_to).into_response();
+    }
+    (
+        StatusCode::OK,
+        Json(LinkProviderResponse {
+            provider: provider.to_string(),
+            provider_subject: provider_subject.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/credentials",
+    responses(
+        (status = 200, description = "Login methods linked to the current account", body = CredentialListResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn list_credentials(State(state): State<Arc<AppState>>, jar: CookieJar) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    match state.credentials().list(current.account.id).await {
+        Ok(credentials) => {
+            let credentials = credentials
+                .into_iter()
+                .map(|credential| CredentialResponse {
+                    id: credential.id,
+                    provider: credential.provider,
+                    provider_subject: credential.provider_subject,
+                    created_at: credential.created_at.to_rfc3339(),
+                })
+                .collect();
+            (StatusCode::OK, Json(CredentialListResponse { credentials })).into_response()
+        }
+        Err(err) => credential_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/credentials/{id}",
+    params(("id" = i64, Path, description = "Credential id")),
+    responses(
+        (status = 204, description = "Credential removed"),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 404, description = "Credential not found", body = ErrorResponse),
+        (status = 409, description = "Last remaining login method", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn unlink_credential(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Path(id): Path<i64>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    match state.credentials().unlink(&current.account, id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => credential_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn unlinked_identities_can_be_linked_again() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let ada = account(&state, "ada@example.com").await;
+        let github = state
+            .credentials()
+            .link(&ada, "github", "42", None)
+            .await
+            .unwrap();
+
+        let response = send(&app, "GET", "/api/v1/me/credentials", None, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&app, "GET", "/api/v1/me/credentials", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await["credentials"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let uri = format!("/api/v1/me/credentials/{}", github.id);
+        let response = send(&app, "DELETE", &uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send(&app, "DELETE", &uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = send(&app, "GET", "/api/v1/me/credentials", Some(&cookie), None).await;
+        assert_eq!(
+            json_body(response).await["credentials"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let relinked = state
+            .credentials()
+            .link(&ada, "github", "42", None)
+            .await
+            .unwrap();
+        assert_ne!(relinked.id, github.id);
+    }
+}
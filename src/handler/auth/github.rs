@@ -1,18 +1,35 @@
 use axum::{
     extract::{Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Redirect},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
     Json,
 };
-use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
-use cookie::time::Duration;
+use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::{service::accounts::GetOrCreateByProviderSubjectInput, state::AppState};
+use crate::{
+    handler::{
+        auth::password::{error_response, AccessTokenResponse},
+        session::current_session,
+    },
+    service::{accounts::GetOrCreateByProviderSubjectInput, pkce},
+    state::AppState,
+};
+
+const STATE_NAMESPACE: &str = "github";
+
+#[derive(Deserialize)]
+pub struct GithubStartQuery {
+    return_to: Option<String>,
+    #[serde(default)]
+    remember_me: bool,
+}
 
 #[derive(Deserialize)]
 pub struct GithubCallbackQuery {
     code: Option<String>,
+    state: Option<String>,
     error: Option<String>,
     error_description: Option<String>,
 }
@@ -23,11 +40,20 @@ pub struct GithubAuthResponse {
     pub username: Option<String>,
     pub email: Option<String>,
     pub provider_subject: String,
+    #[serde(flatten)]
+    pub token: Option<AccessTokenResponse>,
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    message: String,
+/// Stored in Redis under the `state` value until the callback consumes it.
+#[derive(Serialize, Deserialize)]
+struct PendingGithubAuth {
+    code_verifier: String,
+    return_to: Option<String>,
+    /// Set when the flow links GitHub to this already signed-in account instead of logging in.
+    #[serde(default)]
+    link_account: Option<Uuid>,
+    #[serde(default)]
+    remember_me: bool,
 }
 
 #[derive(Deserialize)]
@@ -36,11 +62,36 @@ struct GithubTokenResponse {
 }
 
 #[derive(Deserialize)]
-#[allow(dead_code)]
 struct GithubUserResponse {
     id: u64,
     login: String,
-    email: Option<String>,
+    name: Option<String>,
+    avatar_url: Option<String>,
+    html_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubEmailResponse {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+#[derive(Deserialize)]
+struct GithubOrganization {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GithubOrgMembership {
+    state: String,
+    organization: GithubOrganization,
+}
+
+#[derive(Deserialize)]
+struct GithubTeam {
+    slug: String,
+    organization: GithubOrganization,
 }
 
 struct GithubOAuthConfig {
@@ -50,11 +101,23 @@ struct GithubOAuthConfig {
     authorize_url: String,
     token_url: String,
     api_base: String,
+    allowed_orgs: Vec<String>,
+    allowed_teams: Vec<String>,
+}
+
+impl GithubOAuthConfig {
+    fn restricts_membership(&self) -> bool {
+        !self.allowed_orgs.is_empty() || !self.allowed_teams.is_empty()
+    }
 }
 
 pub fn routes(state: std::sync::Arc<AppState>) -> axum::Router {
     axum::Router::new()
         .route("/api/v1/auth/github", axum::routing::get(start_github_auth))
+        .route(
+            "/api/v1/auth/github/link",
+            axum::routing::get(start_github_link),
+        )
         .route(
             "/api/v1/auth/github/callback",
             axum::routing::get(github_callback),
@@ -62,33 +125,25 @@ pub fn routes(state: std::sync::Arc<AppState>) -> axum::Router {
         .with_state(state)
 }
 
-fn github_config(
-    state: &std::sync::Arc<AppState>,
-) -> Result<GithubOAuthConfig, (StatusCode, Json<ErrorResponse>)> {
+fn github_not_configured(name: &str) -> Response {
+    error_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "github_not_configured",
+        format!("{} is not set", name),
+    )
+}
+
+/// Returns the name of the first missing variable when GitHub login is not configured.
+fn github_config(state: &std::sync::Arc<AppState>) -> Result<GithubOAuthConfig, &'static str> {
     let config = state.config().values();
     let Some(client_id) = &config.github_client_id else {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                message: "AUTH_GITHUB_CLIENT_ID is not set".to_string(),
-            }),
-        ));
+        return Err("AUTH_GITHUB_CLIENT_ID");
     };
     let Some(client_secret) = &config.github_client_secret else {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                message: "AUTH_GITHUB_CLIENT_SECRET is not set".to_string(),
-            }),
-        ));
+        return Err("AUTH_GITHUB_CLIENT_SECRET");
     };
     let Some(redirect_url) = &config.github_redirect_url else {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                message: "AUTH_GITHUB_REDIRECT_URL is not set".to_string(),
-            }),
-        ));
+        return Err("AUTH_GITHUB_REDIRECT_URL");
     };
 
     Ok(GithubOAuthConfig {
@@ -98,56 +153,171 @@ fn github_config(
         authorize_url: config.github_authorize_url.clone(),
         token_url: config.github_token_url.clone(),
         api_base: config.github_api_base.clone(),
+        allowed_orgs: config.github_allowed_orgs.clone(),
+        allowed_teams: config.github_allowed_teams.clone(),
     })
 }
 
-async fn start_github_auth(State(state): State<std::sync::Arc<AppState>>) -> impl IntoResponse {
-    let config = match github_config(&state) {
+async fn start_github_auth(
+    State(state): State<std::sync::Arc<AppState>>,
+    Query(query): Query<GithubStartQuery>,
+) -> Response {
+    begin_github_auth(&state, query.return_to, None, query.remember_me).await
+}
+
+async fn start_github_link(
+    State(state): State<std::sync::Arc<AppState>>,
+    jar: CookieJar,
+    Query(query): Query<GithubStartQuery>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+    begin_github_auth(&state, query.return_to, Some(current.account.uid), false).await
+}
+
+async fn begin_github_auth(
+    state: &std::sync::Arc<AppState>,
+    return_to: Option<String>,
+    link_account: Option<Uuid>,
+    remember_me: bool,
+) -> Response {
+    let config = match github_config(state) {
         Ok(config) => config,
-        Err(response) => return response.into_response(),
+        Err(name) => return github_not_configured(name),
+    };
+
+    let return_to = match return_to {
+        Some(value) => match super::validate_return_to(state.config().values(), &value) {
+            Some(value) => Some(value),
+            None => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_return_to",
+                    "return_to must be a relative path or an allowed origin".to_string(),
+                );
+            }
+        },
+        None => None,
+    };
+
+    let oauth_state = pkce::random_token();
+    let pending = PendingGithubAuth {
+        code_verifier: pkce::random_token(),
+        return_to,
+        link_account,
+        remember_me,
+    };
+    let value = match serde_json::to_string(&pending) {
+        Ok(value) => value,
+        Err(err) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "session_error",
+                format!("state encode failed: {}", err),
+            );
+        }
     };
+    if let Err(err) = state
+        .challenges()
+        .put_challenge(
+            STATE_NAMESPACE,
+            &oauth_state,
+            &value,
+            state.config().values().github_state_ttl_seconds,
+        )
+        .await
+    {
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "session_error",
+            format!("state store failed: {}", err),
+        );
+    }
+
     let authorize_url = &config.authorize_url;
     let delimiter = if authorize_url.contains('?') {
         "&"
     } else {
         "?"
     };
+    let scope = if config.restricts_membership() {
+        "read:user%20user:email%20read:org"
+    } else {
+        "read:user%20user:email"
+    };
     let url = format!(
-        "{}{}client_id={}&redirect_uri={}&scope=read:user%20user:email",
+        "{}{}client_id={}&redirect_uri={}&scope={}&state={}&code_challenge={}&code_challenge_method=S256",
         authorize_url,
         delimiter,
         urlencoding::encode(&config.client_id),
-        urlencoding::encode(&config.redirect_url)
+        urlencoding::encode(&config.redirect_url),
+        scope,
+        oauth_state,
+        pkce::s256_challenge(&pending.code_verifier),
     );
     Redirect::temporary(&url).into_response()
 }
 
 async fn github_callback(
     State(state): State<std::sync::Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
     Query(query): Query<GithubCallbackQuery>,
-) -> impl IntoResponse {
+) -> Response {
+    // Consume the state before anything else so a callback can never be replayed, including
+    // ones that carry an error from GitHub.
+    let Some(oauth_state) = query.state.filter(|value| !value.is_empty()) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_state",
+            "missing state".to_string(),
+        );
+    };
+    let pending = match state
+        .challenges()
+        .take_challenge(STATE_NAMESPACE, &oauth_state)
+        .await
+    {
+        Ok(Some(value)) => serde_json::from_str::<PendingGithubAuth>(&value).ok(),
+        Ok(None) => None,
+        Err(err) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "session_error",
+                format!("state lookup failed: {}", err),
+            );
+        }
+    };
+    let Some(pending) = pending else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_state",
+            "state is invalid, expired or already used".to_string(),
+        );
+    };
+
     if let Some(error) = query.error {
         let message = if let Some(desc) = query.error_description {
             format!("github oauth error: {} ({})", error, desc)
         } else {
             format!("github oauth error: {}", error)
         };
-        return (StatusCode::BAD_REQUEST, Json(ErrorResponse { message })).into_response();
+        return error_response(StatusCode::BAD_REQUEST, "github_oauth_error", message);
     }
 
     let Some(code) = query.code else {
-        return (
+        return error_response(
             StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                message: "missing code".to_string(),
-            }),
-        )
-            .into_response();
+            "invalid_request",
+            "missing code".to_string(),
+        );
     };
 
     let config = match github_config(&state) {
         Ok(config) => config,
-        Err(response) => return response.into_response(),
+        Err(name) => return github_not_configured(name),
     };
 
     let client = reqwest::Client::new();
@@ -159,32 +329,29 @@ async fn github_callback(
             ("client_secret", config.client_secret.as_str()),
             ("code", code.as_str()),
             ("redirect_uri", config.redirect_url.as_str()),
+            ("code_verifier", pending.code_verifier.as_str()),
         ])
         .send()
         .await
     {
         Ok(response) => response,
         Err(err) => {
-            return (
+            return error_response(
                 StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse {
-                    message: format!("token request failed: {}", err),
-                }),
-            )
-                .into_response();
+                "github_error",
+                format!("token request failed: {}", err),
+            );
         }
     };
 
     let token_response = match token.json::<GithubTokenResponse>().await {
         Ok(payload) => payload,
         Err(err) => {
-            return (
+            return error_response(
                 StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse {
-                    message: format!("token response parse failed: {}", err),
-                }),
-            )
-                .into_response();
+                "github_error",
+                format!("token response parse failed: {}", err),
+            );
         }
     };
 
@@ -200,35 +367,83 @@ async fn github_callback(
     {
         Ok(response) => response,
         Err(err) => {
-            return (
+            return error_response(
                 StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse {
-                    message: format!("user request failed: {}", err),
-                }),
-            )
-                .into_response();
+                "github_error",
+                format!("user request failed: {}", err),
+            );
         }
     };
 
     let user = match user_response.json::<GithubUserResponse>().await {
         Ok(payload) => payload,
         Err(err) => {
-            return (
+            return error_response(
                 StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse {
-                    message: format!("user response parse failed: {}", err),
-                }),
-            )
-                .into_response();
+                "github_error",
+                format!("user response parse failed: {}", err),
+            );
+        }
+    };
+
+    let email = match fetch_primary_email(&client, &config, &token_response.access_token).await {
+        Ok(value) => value,
+        Err(message) => return error_response(StatusCode::BAD_GATEWAY, "github_error", message),
+    };
+
+    let teams = if config.restricts_membership() {
+        let memberships =
+            match fetch_memberships(&client, &config, &token_response.access_token).await {
+                Ok(value) => value,
+                Err(message) => {
+                    return error_response(StatusCode::BAD_GATEWAY, "github_error", message)
+                }
+            };
+        if !memberships.is_allowed(&config) {
+            return error_response(
+                StatusCode::FORBIDDEN,
+                "github_org_forbidden",
+                "your GitHub account is not a member of an allowed organization or team",
+            );
         }
+        Some(memberships.teams)
+    } else {
+        None
     };
 
+    // Raw profile kept on the credential; refreshed on every login. `teams` (`org/slug`) is the
+    // hook for mapping GitHub teams onto local roles and teams.
+    let metadata = serde_json::json!({
+        "id": user.id,
+        "login": user.login,
+        "name": user.name,
+        "avatar_url": user.avatar_url,
+        "html_url": user.html_url,
+        "email": email,
+        "teams": teams,
+        "refreshed_at": chrono::Utc::now(),
+    });
+
+    if let Some(link_account) = pending.link_account {
+        return super::credentials::complete_link(
+            &state,
+            &jar,
+            link_account,
+            "github",
+            &user.id.to_string(),
+            Some(metadata),
+            pending.return_to,
+        )
+        .await;
+    }
+
     let input = GetOrCreateByProviderSubjectInput {
         provider: "github".to_string(),
         provider_subject: user.id.to_string(),
         account_type: "user".to_string(),
-        username: None,
-        email: None,
+        username: Some(user.login.clone()),
+        email,
+        metadata: Some(metadata),
         created_by: None,
     };
 
@@ -239,49 +454,414 @@ async fn github_callback(
     {
         Ok(model) => model,
         Err(err) => {
-            return (
+            return error_response(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    message: format!("account upsert failed: {}", err),
-                }),
-            )
-                .into_response();
+                "db_error",
+                format!("account upsert failed: {}", err),
+            );
         }
     };
 
-    let session_id = match state.sessions().create(account.uid).await {
+    let context = super::session_context(&headers, "github", pending.remember_me);
+    let session_id = match state.sessions().create(account.uid, context).await {
         Ok(value) => value,
         Err(err) => {
-            return (
+            return error_response(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    message: format!("session create failed: {}", err),
-                }),
-            )
-                .into_response();
+                "session_error",
+                format!("session create failed: {}", err),
+            );
         }
     };
 
-    let mut cookie = Cookie::new("sid", session_id);
-    cookie.set_http_only(true);
-    cookie.set_path("/");
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_max_age(Duration::seconds(
-        state.config().values().session_ttl_seconds as i64,
-    ));
-    if state.config().values().cookie_secure {
-        cookie.set_secure(true);
-    }
-    if let Some(domain) = &state.config().values().cookie_domain {
-        cookie.set_domain(domain.to_string());
+    let cookie = super::session_cookie(state.config().values(), session_id, pending.remember_me);
+    let jar = CookieJar::new().add(cookie);
+
+    // Browser redirects carry only the cookie; a token would otherwise end up in the URL.
+    if let Some(return_to) = pending.return_to {
+        return (jar, Redirect::to(&return_to)).into_response();
     }
 
+    let token = match super::access_token(&state, &account, None).await {
+        Ok(token) => token,
+        Err(response) => return response,
+    };
     let response = GithubAuthResponse {
         account_uid: account.uid.to_string(),
         username: account.username,
         email: account.email,
         provider_subject: user.id.to_string(),
+        token,
     };
-    let jar = CookieJar::new().add(cookie);
     (StatusCode::OK, jar, Json(response)).into_response()
 }
+
+/// The primary address from `/user/emails`, if GitHub has verified it.
+async fn fetch_primary_email(
+    client: &reqwest::Client,
+    config: &GithubOAuthConfig,
+    access_token: &str,
+) -> Result<Option<String>, String> {
+    let response = client
+        .get(format!(
+            "{}/user/emails",
+            config.api_base.trim_end_matches('/')
+        ))
+        .header("Authorization", format!("Bearer {}", access_token))
+        .header("User-Agent", "auth-api")
+        .send()
+        .await
+        .map_err(|err| format!("emails request failed: {}", err))?;
+    // Tokens granted before `user:email` was requested can't read emails; log in without one.
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let emails = response
+        .json::<Vec<GithubEmailResponse>>()
+        .await
+        .map_err(|err| format!("emails response parse failed: {}", err))?;
+
+    Ok(emails
+        .into_iter()
+        .find(|entry| entry.primary && entry.verified)
+        .map(|entry| entry.email.to_lowercase()))
+}
+
+/// Lowercase org logins and `org/team-slug` entries the user belongs to.
+struct GithubMemberships {
+    orgs: Vec<String>,
+    teams: Vec<String>,
+}
+
+impl GithubMemberships {
+    fn is_allowed(&self, config: &GithubOAuthConfig) -> bool {
+        self.orgs
+            .iter()
+            .any(|org| config.allowed_orgs.contains(org))
+            || self
+                .teams
+                .iter()
+                .any(|team| config.allowed_teams.contains(team))
+    }
+}
+
+/// Follows `page=` until GitHub returns a short page (capped at 10 pages).
+async fn fetch_github_list<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    config: &GithubOAuthConfig,
+    access_token: &str,
+    path: &str,
+) -> Result<Vec<T>, String> {
+    const PER_PAGE: usize = 100;
+    let mut items = Vec::new();
+    for page in 1..=10 {
+        let response = client
+            .get(format!(
+                "{}{}?per_page={}&page={}",
+                config.api_base.trim_end_matches('/'),
+                path,
+                PER_PAGE,
+                page
+            ))
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("User-Agent", "auth-api")
+            .send()
+            .await
+            .map_err(|err| format!("{} request failed: {}", path, err))?;
+        if !response.status().is_success() {
+            return Err(format!("{} request returned {}", path, response.status()));
+        }
+        let batch = response
+            .json::<Vec<T>>()
+            .await
+            .map_err(|err| format!("{} response parse failed: {}", path, err))?;
+        let done = batch.len() < PER_PAGE;
+        items.extend(batch);
+        if done {
+            break;
+        }
+    }
+    Ok(items)
+}
+
+async fn fetch_memberships(
+    client: &reqwest::Client,
+    config: &GithubOAuthConfig,
+    access_token: &str,
+) -> Result<GithubMemberships, String> {
+    let orgs = if config.allowed_orgs.is_empty() {
+        Vec::new()
+    } else {
+        fetch_github_list::<GithubOrgMembership>(
+            client,
+            config,
+            access_token,
+            "/user/memberships/orgs",
+        )
+        .await?
+        .into_iter()
+        .filter(|membership| membership.state == "active")
+        .map(|membership| membership.organization.login.to_lowercase())
+        .collect()
+    };
+    let teams = fetch_github_list::<GithubTeam>(client, config, access_token, "/user/teams")
+        .await?
+        .into_iter()
+        .map(|team| format!("{}/{}", team.organization.login, team.slug).to_lowercase())
+        .collect();
+    Ok(GithubMemberships { orgs, teams })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        extract::State as AxumState,
+        http::{header, StatusCode},
+        response::Response,
+        routing::{get, post},
+        Form, Json, Router,
+    };
+    use serde_json::{json, Value};
+    use std::{collections::HashMap, sync::Arc};
+    use tokio::sync::Mutex;
+
+    use crate::{
+        handler::{router, test_support::*},
+        service::pkce,
+        state::AppState,
+    };
+
+    /// GitHub's token endpoint and REST API, with the user's data set by each test.
+    struct MockGithub {
+        base: String,
+        /// `code_challenge` the browser approved, checked against the verifier like GitHub does.
+        approved: Mutex<Option<String>>,
+        user: Mutex<Value>,
+        emails: Mutex<Value>,
+        orgs: Mutex<Value>,
+        teams: Mutex<Value>,
+    }
+
+    async fn token(
+        AxumState(github): AxumState<Arc<MockGithub>>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<Value>, StatusCode> {
+        let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+        let approved = github.approved.lock().await.take();
+        if form.get("code").map(String::as_str) != Some("good-code")
+            || approved != Some(pkce::s256_challenge(&verifier))
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(Json(
+            json!({ "access_token": "gho_test", "token_type": "bearer" }),
+        ))
+    }
+
+    async fn start_mock_github() -> Arc<MockGithub> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let github = Arc::new(MockGithub {
+            base: format!("http://{}", listener.local_addr().unwrap()),
+            approved: Mutex::new(None),
+            user: Mutex::new(json!({ "id": 42, "login": "Octo-Cat" })),
+            emails: Mutex::new(json!([])),
+            orgs: Mutex::new(json!([])),
+            teams: Mutex::new(json!([])),
+        });
+        let app = Router::new()
+            .route("/login/oauth/access_token", post(token))
+            .route(
+                "/user",
+                get(|AxumState(github): AxumState<Arc<MockGithub>>| async move {
+                    Json(github.user.lock().await.clone())
+                }),
+            )
+            .route(
+                "/user/emails",
+                get(|AxumState(github): AxumState<Arc<MockGithub>>| async move {
+                    Json(github.emails.lock().await.clone())
+                }),
+            )
+            .route(
+                "/user/memberships/orgs",
+                get(|AxumState(github): AxumState<Arc<MockGithub>>| async move {
+                    Json(github.orgs.lock().await.clone())
+                }),
+            )
+            .route(
+                "/user/teams",
+                get(|AxumState(github): AxumState<Arc<MockGithub>>| async move {
+                    Json(github.teams.lock().await.clone())
+                }),
+            )
+            .with_state(github.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        github
+    }
+
+    async fn github_state(
+        github: &MockGithub,
+        configure: impl FnOnce(&mut crate::config::Config),
+    ) -> Arc<AppState> {
+        AppState::in_memory_with(|config| {
+            config.github_client_id = Some("client-1".to_string());
+            config.github_client_secret = Some("secret-1".to_string());
+            config.github_redirect_url = Some("http://localhost/callback".to_string());
+            config.github_authorize_url = "https://github.example/login/oauth/authorize".into();
+            config.github_token_url = format!("{}/login/oauth/access_token", github.base);
+            config.github_api_base = github.base.clone();
+            configure(config);
+        })
+        .await
+    }
+
+    fn query_param(url: &str, name: &str) -> String {
+        url.split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix(&format!("{}=", name)))
+            .unwrap()
+            .to_string()
+    }
+
+    /// Starts a login and returns the authorization URL the browser is sent to.
+    async fn authorize_url(app: &Router, query: &str) -> String {
+        let uri = format!("/api/v1/auth/github{}", query);
+        let response = send(app, "GET", &uri, None, None).await;
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        response.headers()[header::LOCATION]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Approves the authorization like the user would on GitHub and follows the callback.
+    async fn sign_in(app: &Router, github: &MockGithub, query: &str) -> Response {
+        let url = authorize_url(app, query).await;
+        *github.approved.lock().await = Some(query_param(&url, "code_challenge"));
+        callback(app, &query_param(&url, "state")).await
+    }
+
+    async fn callback(app: &Router, state: &str) -> Response {
+        let uri = format!(
+            "/api/v1/auth/github/callback?code=good-code&state={}",
+            state
+        );
+        send(app, "GET", &uri, None, None).await
+    }
+
+    #[tokio::test]
+    async fn callbacks_check_state_pkce_and_return_to() {
+        let github = start_mock_github().await;
+        let state = github_state(&github, |_| {}).await;
+        let app = router(state.clone());
+
+        let response = send(
+            &app,
+            "GET",
+            "/api/v1/auth/github?return_to=https://evil.example/",
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["code"], "invalid_return_to");
+
+        let url = authorize_url(&app, "").await;
+        assert_eq!(query_param(&url, "code_challenge_method"), "S256");
+        let response = send(
+            &app,
+            "GET",
+            "/api/v1/auth/github/callback?code=good-code",
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(json_body(response).await["code"], "invalid_state");
+        let response = callback(&app, "forged").await;
+        assert_eq!(json_body(response).await["code"], "invalid_state");
+
+        // GitHub refuses a verifier that does not match the approved challenge.
+        *github.approved.lock().await = Some(pkce::s256_challenge("another-verifier"));
+        let oauth_state = query_param(&url, "state");
+        let response = callback(&app, &oauth_state).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let response = callback(&app, &oauth_state).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["code"], "invalid_state");
+
+        let response = sign_in(&app, &github, "?return_to=/welcome").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/welcome");
+        let cookie = session_cookie(&response);
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn only_verified_primary_emails_are_taken() {
+        let github = start_mock_github().await;
+        let state = github_state(&github, |_| {}).await;
+        let app = router(state.clone());
+
+        *github.emails.lock().await = json!([
+            { "email": "octo@example.com", "primary": true, "verified": false },
+            { "email": "other@example.com", "primary": false, "verified": true },
+        ]);
+        let response = sign_in(&app, &github, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["username"], "octo-cat");
+        assert_eq!(body["email"], Value::Null);
+        assert_eq!(body["provider_subject"], "42");
+
+        // A second GitHub user with the same login gets a suffixed username.
+        *github.user.lock().await = json!({ "id": 43, "login": "octo-cat" });
+        *github.emails.lock().await = json!([
+            { "email": "Hubot@Example.com", "primary": true, "verified": true },
+        ]);
+        let response = sign_in(&app, &github, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["username"], "octo-cat-2");
+        assert_eq!(body["email"], "hubot@example.com");
+        let account = account(&state, "hubot@example.com").await;
+        assert!(account.email_verified_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn sign_in_is_limited_to_allowed_orgs_and_teams() {
+        let github = start_mock_github().await;
+        let state = github_state(&github, |config| {
+            config.github_allowed_orgs = vec!["acme".to_string()];
+            config.github_allowed_teams = vec!["other/core".to_string()];
+        })
+        .await;
+        let app = router(state.clone());
+        let url = authorize_url(&app, "").await;
+        assert!(query_param(&url, "scope").contains("read:org"));
+
+        *github.orgs.lock().await = json!([
+            { "state": "pending", "organization": { "login": "Acme" } },
+        ]);
+        *github.teams.lock().await = json!([
+            { "slug": "core", "organization": { "login": "Acme" } },
+        ]);
+        let response = sign_in(&app, &github, "").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json_body(response).await["code"], "github_org_forbidden");
+
+        *github.teams.lock().await = json!([
+            { "slug": "core", "organization": { "login": "Other" } },
+        ]);
+        let response = sign_in(&app, &github, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        *github.teams.lock().await = json!([]);
+        *github.orgs.lock().await = json!([
+            { "state": "active", "organization": { "login": "Acme" } },
+        ]);
+        let response = sign_in(&app, &github, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
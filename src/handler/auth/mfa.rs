@@ -0,0 +1,274 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::{
+        auth::password::{error_response, ErrorResponse, LoginResponse},
+        session::current_session,
+    },
+    service::mfa::MfaError,
+    state::AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    /// A current TOTP code or an unused recovery code.
+    pub code: String,
+    /// Repeats the choice made at login, which is not carried by the MFA token.
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TotpEnrollResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TotpConfirmResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/auth/mfa/verify", post(verify_mfa))
+        .route("/api/v1/me/mfa/totp/enroll", post(enroll_totp))
+        .route("/api/v1/me/mfa/totp/confirm", post(confirm_totp))
+        .route("/api/v1/me/mfa/totp/disable", post(disable_totp))
+        .with_state(state)
+}
+
+fn mfa_error_status(code: &str) -> StatusCode {
+    match code {
+        "invalid_mfa_code" | "invalid_mfa_token" => StatusCode::UNAUTHORIZED,
+        "mfa_already_enabled" | "mfa_enrollment_changed" => StatusCode::CONFLICT,
+        "mfa_not_configured"
+        | "mfa_crypto_error"
+        | "mfa_metadata_invalid"
+        | "db_error"
+        | "session_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+fn mfa_error_response(err: MfaError) -> Response {
+    error_response(mfa_error_status(err.code), err.code, err.message)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/mfa/verify",
+    request_body = MfaVerifyRequest,
+    responses(
+        (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 401, description = "Invalid code or challenge", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn verify_mfa(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<MfaVerifyRequest>,
+) -> Response {
+    let context = super::session_context(&headers, "password+totp", payload.remember_me);
+    let output = match state
+        .auth()
+        .complete_mfa_login(&payload.mfa_token, &payload.code, context)
+        .await
+    {
+        Ok(output) => output,
+        Err(err) => return error_response(mfa_error_status(err.code), err.code, err.message),
+    };
+
+    let token = match super::access_token(&state, &output.account, None).await {
+        Ok(token) => token,
+        Err(response) => return response,
+    };
+    let cookie = super::session_cookie(
+        state.config().values(),
+        output.session_id,
+        payload.remember_me,
+    );
+    let response = LoginResponse {
+        account_uid: output.account.uid.to_string(),
+        username: output.account.username,
+        email: output.account.email,
+        token,
+    };
+
+    let jar = CookieJar::new().add(cookie);
+    (StatusCode::OK, jar, Json(response)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/mfa/totp/enroll",
+    responses(
+        (status = 200, description = "Enrollment started", body = TotpEnrollResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 409, description = "TOTP already enabled", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn enroll_totp(State(state): State<Arc<AppState>>, jar: CookieJar) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    match state.mfa().begin_totp_enrollment(&current.account).await {
+        Ok(enrollment) => (
+            StatusCode::OK,
+            Json(TotpEnrollResponse {
+                secret: enrollment.secret,
+                otpauth_uri: enrollment.otpauth_uri,
+            }),
+        )
+            .into_response(),
+        Err(err) => mfa_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/mfa/totp/confirm",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "TOTP enabled; recovery codes are only shown once", body = TotpConfirmResponse),
+        (status = 401, description = "Invalid code or session", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn confirm_totp(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Json(payload): Json<TotpCodeRequest>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    match state
+        .mfa()
+        .confirm_totp_enrollment(&current.account, &payload.code)
+        .await
+    {
+        Ok(recovery_codes) => {
+            (StatusCode::OK, Json(TotpConfirmResponse { recovery_codes })).into_response()
+        }
+        Err(err) => mfa_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/mfa/totp/disable",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 204, description = "TOTP disabled"),
+        (status = 401, description = "Invalid code or session", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn disable_totp(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Json(payload): Json<TotpCodeRequest>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    match state
+        .mfa()
+        .disable_totp(&current.account, &payload.code)
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => mfa_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use base64::Engine;
+    use serde_json::json;
+
+    use crate::{
+        handler::{router, test_support::*},
+        service::mfa::current_totp,
+        state::AppState,
+    };
+
+    #[tokio::test]
+    async fn concurrent_mfa_logins_each_keep_their_challenge() {
+        let state = AppState::in_memory_with(|config| {
+            config.mfa_encryption_key =
+                Some(base64::engine::general_purpose::STANDARD.encode([7u8; 32]));
+        })
+        .await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "totp@example.com").await;
+
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/me/mfa/totp/enroll",
+            Some(&cookie),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let secret = json_body(response).await["secret"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let body = json!({ "code": current_totp(&secret) });
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/me/mfa/totp/confirm",
+            Some(&cookie),
+            Some(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let recovery_codes = json_body(response).await["recovery_codes"].clone();
+
+        // Two devices start signing in before either finishes.
+        let mut challenges = Vec::new();
+        for _ in 0..2 {
+            let response = login(&app, "totp@example.com").await;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            challenges.push(json_body(response).await["mfa_token"].clone());
+        }
+
+        for (mfa_token, code) in challenges.iter().zip(recovery_codes.as_array().unwrap()) {
+            let body = json!({ "mfa_token": mfa_token, "code": code });
+            let response = send(&app, "POST", "/api/v1/auth/mfa/verify", None, Some(body)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let body = json!({ "mfa_token": challenges[0], "code": recovery_codes[2] });
+        let response = send(&app, "POST", "/api/v1/auth/mfa/verify", None, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
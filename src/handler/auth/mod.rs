@@ -1,2 +1,153 @@
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use chrono::Utc;
+use cookie::time::Duration;
+
+use crate::{
+    config::Config,
+    entities::accounts,
+    handler::auth::password::AccessTokenResponse,
+    service::{
+        refresh_tokens::{RefreshGrant, RefreshToken},
+        session::SessionContext,
+    },
+    state::AppState,
+};
+
+pub mod credentials;
 pub mod github;
+pub mod mfa;
+pub mod oidc;
 pub mod password;
+pub mod token;
+pub mod verify;
+pub mod webauthn;
+
+/// Builds the `sid` cookie shared by every login flow.
+/// `Max-Age` matches the absolute lifetime of the session, so the cookie never outlives it.
+pub(crate) fn session_cookie(
+    config: &Config,
+    session_id: String,
+    remember_me: bool,
+) -> Cookie<'static> {
+    let max_age = if remember_me {
+        config.session_remember_me_ttl_seconds
+    } else {
+        config.session_ttl_seconds
+    };
+    let mut cookie = Cookie::new("sid", session_id);
+    cookie.set_http_only(true);
+    cookie.set_path("/");
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(Duration::seconds(max_age as i64));
+    if config.cookie_secure {
+        cookie.set_secure(true);
+    }
+    if let Some(domain) = &config.cookie_domain {
+        cookie.set_domain(domain.to_string());
+    }
+    cookie
+}
+
+/// Expires the `sid` cookie; it must carry the same attributes as the one that was set.
+pub(crate) fn cleared_session_cookie(config: &Config) -> Cookie<'static> {
+    let mut cookie = session_cookie(config, String::new(), false);
+    cookie.set_max_age(Duration::seconds(0));
+    cookie
+}
+
+/// Client details recorded on a new session. The address is taken from proxy headers, so it is
+/// only as trustworthy as the proxy in front of the service.
+pub(crate) fn session_context(
+    headers: &HeaderMap,
+    login_method: impl Into<String>,
+    remember_me: bool,
+) -> SessionContext {
+    let header_value = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let ip = header_value("x-forwarded-for")
+        .and_then(|value| value.split(',').next())
+        .map(str::trim)
+        .or_else(|| header_value("x-real-ip"))
+        .map(str::to_string);
+    SessionContext {
+        ip,
+        user_agent: header_value(header::USER_AGENT.as_str()).map(str::to_string),
+        login_method: login_method.into(),
+        remember_me,
+    }
+}
+
+/// Signs a JWT access token for the account, paired with a new refresh token, when
+/// `JWT_ENABLED` is set. Pass `refresh_token` when a rotation already produced one.
+pub(crate) async fn access_token(
+    state: &AppState,
+    account: &accounts::Model,
+    refresh_token: Option<RefreshToken>,
+) -> Result<Option<AccessTokenResponse>, Response> {
+    let token_error = |code: &str, message: &str| {
+        password::error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "token_error",
+            format!("{}: {}", code, message),
+        )
+    };
+
+    let access_token = match state.jwt().issue_access_token(account).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return Ok(None),
+        Err(err) => return Err(token_error(err.code, &err.message)),
+    };
+    let refresh_token = match refresh_token {
+        Some(token) => token,
+        None => match state
+            .refresh_tokens()
+            .issue(account, RefreshGrant::default(), None)
+            .await
+        {
+            Ok(token) => token,
+            Err(err) => return Err(token_error(err.code, &err.message)),
+        },
+    };
+    Ok(Some(AccessTokenResponse {
+        access_token: access_token.token,
+        token_type: "Bearer".to_string(),
+        expires_in: access_token.expires_in,
+        refresh_token: refresh_token.token,
+        refresh_expires_in: (refresh_token.expires_at - Utc::now()).num_seconds().max(0) as u64,
+    }))
+}
+
+/// Accepts `return_to` targets that cannot be used as an open redirect: same-site relative paths,
+/// or absolute URLs whose origin is listed in `AUTH_RETURN_TO_ALLOWED_ORIGINS`.
+pub(crate) fn validate_return_to(config: &Config, value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() || value.contains('\\') || value.chars().any(char::is_control) {
+        return None;
+    }
+    if value.starts_with('/') {
+        return (!value.starts_with("//")).then(|| value.to_string());
+    }
+
+    let (scheme, rest) = value.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if authority.is_empty() || authority.contains('@') {
+        return None;
+    }
+    let origin = format!("{}://{}", scheme, authority).to_ascii_lowercase();
+    config
+        .return_to_allowed_origins
+        .contains(&origin)
+        .then(|| value.to_string())
+}
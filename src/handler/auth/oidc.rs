@@ -0,0 +1,243 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Json, Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    handler::{
+        auth::password::{error_response, AccessTokenResponse, ErrorResponse},
+        session::current_session,
+    },
+    service::{accounts::GetOrCreateByProviderSubjectInput, oidc::OidcError},
+    state::AppState,
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct OidcStartQuery {
+    /// Keep the session for the longer "remember me" lifetime.
+    #[serde(default)]
+    remember_me: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OidcAuthResponse {
+    pub account_uid: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub provider: String,
+    pub provider_subject: String,
+    #[serde(flatten)]
+    pub token: Option<AccessTokenResponse>,
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/auth/oidc/:provider", get(start_oidc_auth))
+        .route("/api/v1/auth/oidc/:provider/link", get(start_oidc_link))
+        .route("/api/v1/auth/oidc/:provider/callback", get(oidc_callback))
+        .with_state(state)
+}
+
+fn oidc_error_response(err: OidcError) -> Response {
+    let status = match err.code {
+        "unknown_provider" => StatusCode::NOT_FOUND,
+        "invalid_state" => StatusCode::BAD_REQUEST,
+        "invalid_id_token" => StatusCode::UNAUTHORIZED,
+        "oidc_provider_error" => StatusCode::BAD_GATEWAY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error_response(status, err.code, err.message)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/oidc/{provider}",
+    params(
+        ("provider" = String, Path, description = "Provider name from OIDC_PROVIDERS"),
+        OidcStartQuery
+    ),
+    responses(
+        (status = 307, description = "Redirect to the provider's authorization endpoint"),
+        (status = 404, description = "Unknown provider", body = ErrorResponse),
+        (status = 502, description = "Provider discovery failed", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn start_oidc_auth(
+    State(state): State<Arc<AppState>>,
+    Path(provider): Path<String>,
+    Query(query): Query<OidcStartQuery>,
+) -> Response {
+    match state
+        .oidc()
+        .authorization_url(&provider, None, query.remember_me)
+        .await
+    {
+        Ok(url) => Redirect::temporary(&url).into_response(),
+        Err(err) => oidc_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/oidc/{provider}/link",
+    params(("provider" = String, Path, description = "Provider name from OIDC_PROVIDERS")),
+    responses(
+        (status = 307, description = "Redirect to the provider; the callback links the identity to the current account"),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 404, description = "Unknown provider", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn start_oidc_link(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Path(provider): Path<String>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    match state
+        .oidc()
+        .authorization_url(&provider, Some(current.account.uid), false)
+        .await
+    {
+        Ok(url) => Redirect::temporary(&url).into_response(),
+        Err(err) => oidc_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/oidc/{provider}/callback",
+    params(
+        ("provider" = String, Path, description = "Provider name from OIDC_PROVIDERS"),
+        OidcCallbackQuery
+    ),
+    responses(
+        (status = 200, description = "Logged in, or identity linked for link flows", body = OidcAuthResponse),
+        (status = 400, description = "Provider error or invalid state", body = ErrorResponse),
+        (status = 401, description = "ID token rejected", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn oidc_callback(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Path(provider): Path<String>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Response {
+    if let Some(error) = query.error {
+        // Consume the state so a callback carrying a provider error can never be replayed.
+        if let Some(oauth_state) = query.state.as_deref().filter(|value| !value.is_empty()) {
+            if let Err(err) = state.oidc().discard(oauth_state).await {
+                return oidc_error_response(err);
+            }
+        }
+        let message = if let Some(desc) = query.error_description {
+            format!("oidc error: {} ({})", error, desc)
+        } else {
+            format!("oidc error: {}", error)
+        };
+        return error_response(StatusCode::BAD_REQUEST, "oidc_error", message);
+    }
+
+    let (Some(code), Some(oauth_state)) = (query.code, query.state) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "missing code or state".to_string(),
+        );
+    };
+
+    let identity = match state.oidc().complete(&provider, &oauth_state, &code).await {
+        Ok(identity) => identity,
+        Err(err) => return oidc_error_response(err),
+    };
+
+    if let Some(link_account) = identity.link_account {
+        return super::credentials::complete_link(
+            &state,
+            &jar,
+            link_account,
+            &identity.provider,
+            &identity.subject,
+            None,
+            None,
+        )
+        .await;
+    }
+
+    let input = GetOrCreateByProviderSubjectInput {
+        provider: identity.provider.clone(),
+        provider_subject: identity.subject.clone(),
+        account_type: "user".to_string(),
+        username: None,
+        email: None,
+        metadata: None,
+        created_by: None,
+    };
+
+    let account = match state
+        .accounts()
+        .get_or_create_by_provider_subject(input)
+        .await
+    {
+        Ok(model) => model,
+        Err(err) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "db_error",
+                format!("account upsert failed: {}", err),
+            );
+        }
+    };
+
+    let context =
+        super::session_context(&headers, format!("oidc:{}", provider), identity.remember_me);
+    let session_id = match state.sessions().create(account.uid, context).await {
+        Ok(value) => value,
+        Err(err) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "session_error",
+                format!("session create failed: {}", err),
+            );
+        }
+    };
+
+    let token = match super::access_token(&state, &account, None).await {
+        Ok(token) => token,
+        Err(response) => return response,
+    };
+    let cookie = super::session_cookie(state.config().values(), session_id, identity.remember_me);
+    let response = OidcAuthResponse {
+        account_uid: account.uid.to_string(),
+        username: account.username,
+        email: account.email,
+        provider: identity.provider,
+        provider_subject: identity.subject,
+        token,
+    };
+
+    let jar = CookieJar::new().add(cookie);
+    (StatusCode::OK, jar, Json(response)).into_response()
+}
@@ -1,17 +1,23 @@
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     routing::post,
     Json, Router,
 };
-use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
-use cookie::time::Duration;
+use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use utoipa::ToSchema;
 
-use crate::state::AppState;
+use crate::{
+    handler::session::current_account,
+    service::{
+        auth::{LoginResult, PasswordResetOutput},
+        session::SessionError,
+    },
+    state::AppState,
+};
 
 #[derive(Serialize, ToSchema)]
 pub struct ErrorResponse {
@@ -19,7 +25,11 @@ pub struct ErrorResponse {
     pub message: String,
 }
 
-fn error_response(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+pub(crate) fn error_response(
+    status: StatusCode,
+    code: &str,
+    message: impl Into<String>,
+) -> Response {
     (
         status,
         Json(ErrorResponse {
@@ -50,6 +60,20 @@ pub struct RegisterResponse {
 pub struct LoginRequest {
     pub identifier: String,
     pub password: String,
+    /// Keep the session for the longer "remember me" lifetime.
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+/// Present on login responses when `JWT_ENABLED` is set.
+#[derive(Serialize, ToSchema)]
+pub struct AccessTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+    /// Single-use; exchange it at `/api/v1/auth/token/refresh` for a new pair.
+    pub refresh_token: String,
+    pub refresh_expires_in: u64,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -57,6 +81,15 @@ pub struct LoginResponse {
     pub account_uid: String,
     pub username: Option<String>,
     pub email: Option<String>,
+    #[serde(flatten)]
+    pub token: Option<AccessTokenResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_at: String,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -69,12 +102,36 @@ pub struct VerifyEmailResponse {
     pub status: String,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ForgotPasswordResponse {
+    pub status: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ResetPasswordResponse {
+    pub status: String,
+}
+
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/api/v1/auth/register", post(register))
         .route("/api/v1/auth/login", post(login))
         .route("/api/v1/auth/logout", post(logout))
+        .route("/api/v1/auth/logout-all", post(logout_all))
         .route("/api/v1/auth/verify-email", post(verify_email))
+        .route("/api/v1/auth/password/forgot", post(forgot_password))
+        .route("/api/v1/auth/password/reset", post(reset_password))
         .with_state(state)
 }
 
@@ -135,6 +192,7 @@ pub async fn register(
     request_body = LoginRequest,
     responses(
         (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 202, description = "Second factor required", body = MfaChallengeResponse),
         (status = 400, description = "Bad request", body = ErrorResponse),
         (status = 401, description = "Invalid credentials", body = ErrorResponse),
         (status = 403, description = "Email not verified", body = ErrorResponse)
@@ -143,11 +201,13 @@ pub async fn register(
 )]
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
 ) -> Response {
+    let context = super::session_context(&headers, "password", payload.remember_me);
     let output = match state
         .auth()
-        .login(&payload.identifier, &payload.password)
+        .login(&payload.identifier, &payload.password, context)
         .await
     {
         Ok(output) => output,
@@ -161,24 +221,33 @@ pub async fn login(
         }
     };
 
-    let mut cookie = Cookie::new("sid", output.session_id);
-    cookie.set_http_only(true);
-    cookie.set_path("/");
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_max_age(Duration::seconds(
-        state.config().values().session_ttl_seconds as i64,
-    ));
-    if state.config().values().cookie_secure {
-        cookie.set_secure(true);
-    }
-    if let Some(domain) = &state.config().values().cookie_domain {
-        cookie.set_domain(domain.to_string());
-    }
+    let output = match output {
+        LoginResult::Authenticated(output) => output,
+        LoginResult::MfaRequired(challenge) => {
+            let response = MfaChallengeResponse {
+                mfa_required: true,
+                mfa_token: challenge.mfa_token,
+                expires_at: challenge.expires_at.to_rfc3339(),
+            };
+            return (StatusCode::ACCEPTED, Json(response)).into_response();
+        }
+    };
+
+    let token = match super::access_token(&state, &output.account, None).await {
+        Ok(token) => token,
+        Err(response) => return response,
+    };
+    let cookie = super::session_cookie(
+        state.config().values(),
+        output.session_id,
+        payload.remember_me,
+    );
 
     let response = LoginResponse {
         account_uid: output.account.uid.to_string(),
         username: output.account.username,
         email: output.account.email,
+        token,
     };
 
     let jar = CookieJar::new().add(cookie);
@@ -206,20 +275,63 @@ pub async fn logout(State(state): State<Arc<AppState>>, jar: CookieJar) -> Respo
             err.to_string(),
         );
     }
+    state.forward_auth_cache().remove(cookie.value());
+
+    let cleared = super::cleared_session_cookie(state.config().values());
+    let jar = jar.add(cleared);
+    (StatusCode::NO_CONTENT, jar).into_response()
+}
 
-    let mut cleared = Cookie::new("sid", "");
-    cleared.set_http_only(true);
-    cleared.set_path("/");
-    cleared.set_same_site(SameSite::Lax);
-    cleared.set_max_age(Duration::seconds(0));
-    if state.config().values().cookie_secure {
-        cleared.set_secure(true);
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/logout-all",
+    description = "Signs out every session of the account and revokes all of its refresh tokens, \
+                   including those held by OAuth clients. Access tokens already issued stay valid \
+                   until they expire, and personal access tokens are left alone; revoke those \
+                   under `/api/v1/me/tokens`.",
+    responses(
+        (status = 204, description = "Every session signed out and every refresh token revoked"),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 500, description = "Session delete or token revocation failed", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn logout_all(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Response {
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    let session_error = |err: SessionError| {
+        error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "session_error",
+            err.to_string(),
+        )
+    };
+
+    let sessions = match state.sessions().list_for_account(account.uid).await {
+        Ok(sessions) => sessions,
+        Err(err) => return session_error(err),
+    };
+    if let Err(err) = state
+        .sessions()
+        .delete_all_for_account(account.uid, None)
+        .await
+    {
+        return session_error(err);
     }
-    if let Some(domain) = &state.config().values().cookie_domain {
-        cleared.set_domain(domain.to_string());
+    for (session_id, _) in &sessions {
+        state.forward_auth_cache().remove(session_id);
+    }
+    if let Err(err) = state.refresh_tokens().revoke_all(account.id).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.code, err.message);
     }
 
-    let jar = jar.add(cleared);
+    let jar = jar.add(super::cleared_session_cookie(state.config().values()));
     (StatusCode::NO_CONTENT, jar).into_response()
 }
 
@@ -237,11 +349,7 @@ pub async fn verify_email(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<VerifyEmailRequest>,
 ) -> Response {
-    match state
-        .verification()
-        .verify_email_token(&payload.token)
-        .await
-    {
+    match state.auth().verify_email(&payload.token).await {
         Ok(_) => (
             StatusCode::OK,
             Json(VerifyEmailResponse {
@@ -252,3 +360,218 @@ pub async fn verify_email(
         Err(err) => error_response(StatusCode::BAD_REQUEST, err.code, err.message),
     }
 }
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/password/forgot",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 202, description = "Reset email sent if the account exists; an address that was never verified gets a verification email instead", body = ForgotPasswordResponse),
+        (status = 400, description = "Invalid payload", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn forgot_password(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Response {
+    let output = match state.auth().request_password_reset(&payload.email).await {
+        Ok(output) => output,
+        Err(err) if err.code == "invalid_email" => {
+            return error_response(StatusCode::BAD_REQUEST, err.code, err.message);
+        }
+        Err(err) => {
+            // Do not surface lookup failures: the response must not reveal whether the email exists.
+            eprintln!("warning: password reset request failed: {}", err.message);
+            None
+        }
+    };
+
+    // Delivery runs detached so response timing does not depend on whether an email was sent.
+    if let Some(output) = output {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let config = state.config().values();
+            let result = match &output {
+                PasswordResetOutput::Reset { email, reset_token } => {
+                    crate::service::email::try_send_password_reset_email(config, email, reset_token)
+                        .await
+                }
+                PasswordResetOutput::VerifyEmail {
+                    email,
+                    verify_token,
+                } => {
+                    crate::service::email::try_send_verification_email(config, email, verify_token)
+                        .await
+                }
+            };
+            if let Err(err) = result {
+                eprintln!("warning: failed to send password reset email: {}", err);
+            }
+        });
+    }
+
+    (
+        StatusCode::ACCEPTED,
+        Json(ForgotPasswordResponse {
+            status: "ok".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/password/reset",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 200, description = "Password updated; every session, refresh token and personal access token of the account is revoked", body = ResetPasswordResponse),
+        (status = 400, description = "Invalid or expired token, or weak password", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Response {
+    match state
+        .auth()
+        .reset_password(&payload.token, &payload.password)
+        .await
+    {
+        Ok(account) => {
+            state.forward_auth_cache().remove_account(account.uid);
+            (
+                StatusCode::OK,
+                Json(ResetPasswordResponse {
+                    status: "ok".to_string(),
+                }),
+            )
+                .into_response()
+        }
+        Err(err) => {
+            let status = match err.code {
+                "db_error" | "session_error" | "password_hash_failed" => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+                _ => StatusCode::BAD_REQUEST,
+            };
+            error_response(status, err.code, err.message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::service::{
+        personal_access_tokens::CreatePersonalAccessTokenInput, refresh_tokens::RefreshGrant,
+        verification::TOKEN_TYPE_RESET_PASSWORD,
+    };
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn password_reset_revokes_every_credential() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let ada = account(&state, "ada@example.com").await;
+        let refresh = state
+            .refresh_tokens()
+            .issue(&ada, RefreshGrant::default(), None)
+            .await
+            .unwrap();
+        let input = CreatePersonalAccessTokenInput {
+            name: "ci".to_string(),
+            scopes: Vec::new(),
+            expires_in_days: None,
+            account_uid: None,
+        };
+        let (pat, _) = state
+            .personal_access_tokens()
+            .create(&ada, input)
+            .await
+            .unwrap();
+
+        let reset = state
+            .verification()
+            .create_token(ada.id, TOKEN_TYPE_RESET_PASSWORD)
+            .await
+            .unwrap();
+        let body = json!({ "token": reset.token, "password": "Battery-Staple-8" });
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/auth/password/reset",
+            None,
+            Some(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let err = state
+            .refresh_tokens()
+            .rotate(&refresh.token, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "refresh_token_revoked");
+        assert!(state
+            .personal_access_tokens()
+            .authenticate(&pat)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn logout_all_revokes_refresh_tokens() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let ada = account(&state, "ada@example.com").await;
+        let refresh = state
+            .refresh_tokens()
+            .issue(&ada, RefreshGrant::default(), None)
+            .await
+            .unwrap();
+
+        let response = send(&app, "POST", "/api/v1/auth/logout-all", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(state
+            .refresh_tokens()
+            .rotate(&refresh.token, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn register_verify_login_logout() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+
+        register(&app, "ada@example.com").await;
+        let response = login(&app, "ada@example.com").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json_body(response).await["code"], "email_not_verified");
+
+        verify_email(&state, &app, "ada@example.com").await;
+        let response = login(&app, "ada@example.com").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = session_cookie(&response);
+
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["email"], "ada@example.com");
+
+        let response = send(&app, "POST", "/api/v1/auth/logout", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,107 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::auth::password::{error_response, AccessTokenResponse, ErrorResponse},
+    state::AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/auth/token/refresh", post(refresh_token))
+        .with_state(state)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/token/refresh",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "New access token and rotated refresh token", body = AccessTokenResponse),
+        (status = 401, description = "Refresh token invalid, expired or reused", body = ErrorResponse),
+        (status = 404, description = "Access tokens are disabled", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn refresh_token(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RefreshTokenRequest>,
+) -> Response {
+    // Checked before rotating, which would otherwise spend the caller's token for nothing.
+    if !state.jwt().enabled() {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            "jwt_disabled",
+            "access tokens are not enabled",
+        );
+    }
+    let (account, refresh_token, _) = match state
+        .refresh_tokens()
+        .rotate(payload.refresh_token.trim(), None)
+        .await
+    {
+        Ok(value) => value,
+        Err(err) => {
+            let status = match err.code {
+                "db_error" => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            return error_response(status, err.code, err.message);
+        }
+    };
+
+    match super::access_token(&state, &account, Some(refresh_token)).await {
+        Ok(Some(token)) => (StatusCode::OK, Json(token)).into_response(),
+        Ok(None) => error_response(
+            StatusCode::NOT_FOUND,
+            "jwt_disabled",
+            "access tokens are not enabled",
+        ),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::service::refresh_tokens::RefreshGrant;
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn disabled_access_tokens_leave_refresh_tokens_unspent() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        signed_in(&state, &app, "ada@example.com").await;
+        let ada = account(&state, "ada@example.com").await;
+        let refresh = state
+            .refresh_tokens()
+            .issue(&ada, RefreshGrant::default(), None)
+            .await
+            .unwrap();
+
+        let body = json!({ "refresh_token": refresh.token });
+        let response = send(&app, "POST", "/api/v1/auth/token/refresh", None, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json_body(response).await["code"], "jwt_disabled");
+        assert!(state
+            .refresh_tokens()
+            .rotate(&refresh.token, None)
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,170 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use std::sync::Arc;
+
+use crate::{
+    handler::{
+        auth::password::{error_response, ErrorResponse},
+        session::{bearer_token, current_account},
+    },
+    service::forward_auth::ForwardAuthIdentity,
+    state::AppState,
+};
+
+const ACCOUNT_UID_HEADER: HeaderName = HeaderName::from_static("x-auth-account-uid");
+const USERNAME_HEADER: HeaderName = HeaderName::from_static("x-auth-username");
+const EMAIL_HEADER: HeaderName = HeaderName::from_static("x-auth-email");
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/auth/verify", get(verify))
+        .with_state(state)
+}
+
+fn identity_response(identity: &ForwardAuthIdentity) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    let values = [
+        (ACCOUNT_UID_HEADER, Some(identity.account_uid.to_string())),
+        (USERNAME_HEADER, identity.username.clone()),
+        (EMAIL_HEADER, identity.email.clone()),
+    ];
+    for (name, value) in values {
+        if let Some(value) = value.and_then(|value| HeaderValue::from_str(&value).ok()) {
+            headers.insert(name, value);
+        }
+    }
+    (StatusCode::OK, headers).into_response()
+}
+
+/// The URL the proxy was asked for, rebuilt from the `X-Forwarded-*` headers it sends.
+fn forwarded_url(headers: &HeaderMap) -> Option<String> {
+    let value = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let host = value("x-forwarded-host")?;
+    let proto = value("x-forwarded-proto").unwrap_or("https");
+    let uri = value("x-forwarded-uri").unwrap_or("/");
+    Some(format!("{}://{}{}", proto, host, uri))
+}
+
+/// Browsers are sent to the login page when one is configured; API clients (anything that sent
+/// an `Authorization` header) always get a 401.
+fn unauthenticated(state: &AppState, headers: &HeaderMap, response: Response) -> Response {
+    let config = state.config().values();
+    let Some(login_url) = &config.forward_auth_login_url else {
+        return response;
+    };
+    if headers.contains_key(header::AUTHORIZATION) {
+        return response;
+    }
+    let return_to = forwarded_url(headers).and_then(|url| super::validate_return_to(config, &url));
+    let location = match return_to {
+        Some(return_to) => {
+            let separator = if login_url.contains('?') { '&' } else { '?' };
+            format!(
+                "{}{}return_to={}",
+                login_url,
+                separator,
+                urlencoding::encode(&return_to)
+            )
+        }
+        None => login_url.clone(),
+    };
+    (StatusCode::FOUND, [(header::LOCATION, location)]).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/verify",
+    responses(
+        (status = 200, description = "Authenticated; identity in X-Auth-Account-Uid, X-Auth-Username and X-Auth-Email"),
+        (status = 302, description = "Not signed in; redirect to FORWARD_AUTH_LOGIN_URL with return_to"),
+        (status = 401, description = "Missing or invalid session or bearer token", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn verify(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Response {
+    let credential = jar
+        .get("sid")
+        .map(|cookie| cookie.value().to_string())
+        .or_else(|| bearer_token(&headers).map(str::to_string));
+    let Some(credential) = credential else {
+        let response = error_response(
+            StatusCode::UNAUTHORIZED,
+            "missing_session",
+            "missing session",
+        );
+        return unauthenticated(&state, &headers, response);
+    };
+
+    if let Some(identity) = state.forward_auth_cache().get(&credential) {
+        return identity_response(&identity);
+    }
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) if response.status().is_server_error() => return response,
+        Err(response) => return unauthenticated(&state, &headers, response),
+    };
+    let identity = ForwardAuthIdentity {
+        account_uid: account.uid,
+        username: account.username,
+        email: account.email,
+    };
+    state
+        .forward_auth_cache()
+        .insert(&credential, identity.clone());
+    identity_response(&identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn revoked_tokens_leave_the_cache() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let body = json!({ "name": "proxy" });
+        let response = send(&app, "POST", "/api/v1/me/tokens", Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let created = json_body(response).await;
+        let token = created["token"].as_str().unwrap();
+
+        let response = send_bearer(&app, "GET", "/api/v1/auth/verify", token, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let uri = format!("/api/v1/me/tokens/{}", created["id"]);
+        let response = send(&app, "DELETE", &uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send_bearer(&app, "GET", "/api/v1/auth/verify", token, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn forward_auth_verify() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        register(&app, "grace@example.com").await;
+        verify_email(&state, &app, "grace@example.com").await;
+
+        let response = send(&app, "GET", "/api/v1/auth/verify", None, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let cookie = session_cookie(&login(&app, "grace@example.com").await);
+        let response = send(&app, "GET", "/api/v1/auth/verify", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-auth-email"], "grace@example.com");
+    }
+}
@@ -0,0 +1,496 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::{
+        auth::password::{error_response, ErrorResponse, LoginResponse},
+        session::current_session,
+    },
+    service::webauthn::{
+        AssertionCredential, CeremonyStart, RegistrationCredential, WebauthnError,
+    },
+    state::AppState,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct WebauthnStartResponse {
+    pub challenge_id: String,
+    /// `PublicKeyCredentialCreationOptions` / `PublicKeyCredentialRequestOptions` with
+    /// binary fields encoded as base64url.
+    #[schema(value_type = Object)]
+    pub public_key: serde_json::Value,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AttestationResponseJson {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RegistrationCredentialJson {
+    pub id: String,
+    pub response: AttestationResponseJson,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebauthnRegisterFinishRequest {
+    pub challenge_id: String,
+    /// Optional label shown in the credential list, e.g. "MacBook Touch ID".
+    pub name: Option<String>,
+    pub credential: RegistrationCredentialJson,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebauthnRegisterFinishResponse {
+    pub credential_id: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebauthnLoginStartRequest {
+    /// Email or username that pins the login to one account; omit to accept any passkey. The
+    /// options never list credentials, so only discoverable passkeys can sign in.
+    pub identifier: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AssertionResponseJson {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AssertionCredentialJson {
+    pub id: String,
+    pub response: AssertionResponseJson,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebauthnLoginFinishRequest {
+    pub challenge_id: String,
+    pub credential: AssertionCredentialJson,
+    /// Keep the session for the longer "remember me" lifetime.
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(
+            "/api/v1/auth/webauthn/register/start",
+            post(start_registration),
+        )
+        .route(
+            "/api/v1/auth/webauthn/register/finish",
+            post(finish_registration),
+        )
+        .route("/api/v1/auth/webauthn/login/start", post(start_login))
+        .route("/api/v1/auth/webauthn/login/finish", post(finish_login))
+        .with_state(state)
+}
+
+fn webauthn_error_response(err: WebauthnError) -> Response {
+    let status = match err.code {
+        "invalid_credential" | "invalid_challenge" | "credential_cloned" => {
+            StatusCode::UNAUTHORIZED
+        }
+        "credential_exists" => StatusCode::CONFLICT,
+        "webauthn_not_configured"
+        | "webauthn_error"
+        | "webauthn_metadata_invalid"
+        | "db_error"
+        | "session_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    error_response(status, err.code, err.message)
+}
+
+fn start_response(start: CeremonyStart) -> Response {
+    (
+        StatusCode::OK,
+        Json(WebauthnStartResponse {
+            challenge_id: start.challenge_id,
+            public_key: start.options,
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/webauthn/register/start",
+    responses(
+        (status = 200, description = "Registration options", body = WebauthnStartResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn start_registration(State(state): State<Arc<AppState>>, jar: CookieJar) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    match state.webauthn().start_registration(&current.account).await {
+        Ok(start) => start_response(start),
+        Err(err) => webauthn_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/webauthn/register/finish",
+    request_body = WebauthnRegisterFinishRequest,
+    responses(
+        (status = 201, description = "Passkey registered", body = WebauthnRegisterFinishResponse),
+        (status = 401, description = "Invalid session, challenge or credential", body = ErrorResponse),
+        (status = 409, description = "Credential already registered", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn finish_registration(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Json(payload): Json<WebauthnRegisterFinishRequest>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    let credential = RegistrationCredential {
+        id: payload.credential.id,
+        client_data_json: payload.credential.response.client_data_json,
+        attestation_object: payload.credential.response.attestation_object,
+        transports: payload.credential.response.transports,
+    };
+
+    match state
+        .webauthn()
+        .finish_registration(
+            &current.account,
+            &payload.challenge_id,
+            credential,
+            payload.name,
+        )
+        .await
+    {
+        Ok(model) => (
+            StatusCode::CREATED,
+            Json(WebauthnRegisterFinishResponse {
+                credential_id: model.provider_subject.unwrap_or_default(),
+            }),
+        )
+            .into_response(),
+        Err(err) => webauthn_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/webauthn/login/start",
+    request_body = WebauthnLoginStartRequest,
+    responses(
+        (status = 200, description = "Authentication options", body = WebauthnStartResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn start_login(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<WebauthnLoginStartRequest>,
+) -> Response {
+    match state
+        .webauthn()
+        .start_login(payload.identifier.as_deref())
+        .await
+    {
+        Ok(start) => start_response(start),
+        Err(err) => webauthn_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/webauthn/login/finish",
+    request_body = WebauthnLoginFinishRequest,
+    responses(
+        (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 401, description = "Invalid challenge or assertion", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn finish_login(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<WebauthnLoginFinishRequest>,
+) -> Response {
+    let credential = AssertionCredential {
+        id: payload.credential.id,
+        client_data_json: payload.credential.response.client_data_json,
+        authenticator_data: payload.credential.response.authenticator_data,
+        signature: payload.credential.response.signature,
+        user_handle: payload.credential.response.user_handle,
+    };
+
+    let account = match state
+        .webauthn()
+        .finish_login(&payload.challenge_id, credential)
+        .await
+    {
+        Ok(account) => account,
+        Err(err) => return webauthn_error_response(err),
+    };
+
+    let context = super::session_context(&headers, "webauthn", payload.remember_me);
+    let session_id = match state.sessions().create(account.uid, context).await {
+        Ok(value) => value,
+        Err(err) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "session_error",
+                format!("session create failed: {}", err),
+            );
+        }
+    };
+
+    let token = match super::access_token(&state, &account, None).await {
+        Ok(token) => token,
+        Err(response) => return response,
+    };
+    let cookie = super::session_cookie(state.config().values(), session_id, payload.remember_me);
+    let response = LoginResponse {
+        account_uid: account.uid.to_string(),
+        username: account.username,
+        email: account.email,
+        token,
+    };
+
+    let jar = CookieJar::new().add(cookie);
+    (StatusCode::OK, jar, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::StatusCode, Router};
+    use base64::Engine;
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+
+    use crate::{
+        handler::{router, test_support::*},
+        state::AppState,
+    };
+
+    const ORIGIN: &str = "https://auth.example";
+    const FLAGS_UP_UV: u8 = 0x05;
+    const FLAG_ATTESTED: u8 = 0x40;
+
+    fn b64(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    fn cbor(value: &ciborium::value::Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// A software ES256 authenticator with a fixed key and counter.
+    struct Authenticator {
+        id: Vec<u8>,
+        key: SigningKey,
+        sign_count: u32,
+    }
+
+    impl Authenticator {
+        fn new() -> Self {
+            Self {
+                id: vec![9; 16],
+                key: SigningKey::from_slice(&[7; 32]).unwrap(),
+                sign_count: 0,
+            }
+        }
+
+        fn auth_data(&self, flags: u8) -> Vec<u8> {
+            let mut data = Sha256::digest(b"auth.example").to_vec();
+            data.push(flags);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            data
+        }
+
+        fn client_data(kind: &str, options: &Value) -> Vec<u8> {
+            let challenge = options["challenge"].as_str().unwrap();
+            json!({ "type": kind, "challenge": challenge, "origin": ORIGIN })
+                .to_string()
+                .into_bytes()
+        }
+
+        fn attestation(&self, options: &Value, flags: u8) -> Value {
+            use ciborium::value::Value as Cbor;
+
+            let point = self.key.verifying_key().to_encoded_point(false);
+            let cose_key = Cbor::Map(vec![
+                (Cbor::from(1), Cbor::from(2)),
+                (Cbor::from(3), Cbor::from(-7)),
+                (Cbor::from(-1), Cbor::from(1)),
+                (Cbor::from(-2), Cbor::Bytes(point.x().unwrap().to_vec())),
+                (Cbor::from(-3), Cbor::Bytes(point.y().unwrap().to_vec())),
+            ]);
+            let mut auth_data = self.auth_data(flags | FLAG_ATTESTED);
+            auth_data.extend_from_slice(&[0; 16]);
+            auth_data.extend_from_slice(&(self.id.len() as u16).to_be_bytes());
+            auth_data.extend_from_slice(&self.id);
+            auth_data.extend_from_slice(&cbor(&cose_key));
+            let attestation = Cbor::Map(vec![
+                (Cbor::from("fmt"), Cbor::from("none")),
+                (Cbor::from("attStmt"), Cbor::Map(Vec::new())),
+                (Cbor::from("authData"), Cbor::Bytes(auth_data)),
+            ]);
+            json!({
+                "id": b64(&self.id),
+                "response": {
+                    "clientDataJSON": b64(&Self::client_data("webauthn.create", options)),
+                    "attestationObject": b64(&cbor(&attestation)),
+                },
+            })
+        }
+
+        fn assertion(&mut self, options: &Value, flags: u8) -> Value {
+            self.sign_count += 1;
+            let auth_data = self.auth_data(flags);
+            let client_data = Self::client_data("webauthn.get", options);
+            let mut message = auth_data.clone();
+            message.extend_from_slice(&Sha256::digest(&client_data));
+            let signature: Signature = self.key.sign(&message);
+            json!({
+                "id": b64(&self.id),
+                "response": {
+                    "clientDataJSON": b64(&client_data),
+                    "authenticatorData": b64(&auth_data),
+                    "signature": b64(signature.to_der().as_bytes()),
+                },
+            })
+        }
+    }
+
+    async fn webauthn_state() -> std::sync::Arc<AppState> {
+        AppState::in_memory_with(|config| {
+            config.webauthn_rp_id = Some("auth.example".to_string());
+            config.webauthn_rp_origin = Some(ORIGIN.to_string());
+        })
+        .await
+    }
+
+    /// Returns the challenge id and the `publicKey` options.
+    async fn start(app: &Router, uri: &str, cookie: Option<&str>, body: Value) -> (Value, Value) {
+        let response = send(app, "POST", uri, cookie, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        (body["challenge_id"].clone(), body["public_key"].clone())
+    }
+
+    #[tokio::test]
+    async fn registration_requires_user_verification() {
+        let state = webauthn_state().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "passkey@example.com").await;
+        let authenticator = Authenticator::new();
+
+        let uri = "/api/v1/auth/webauthn/register/start";
+        let (challenge_id, options) = start(&app, uri, Some(&cookie), json!({})).await;
+        assert_eq!(
+            options["authenticatorSelection"]["userVerification"],
+            "required"
+        );
+        let body = json!({
+            "challenge_id": challenge_id,
+            "credential": authenticator.attestation(&options, 0x01),
+        });
+        let uri = "/api/v1/auth/webauthn/register/finish";
+        let response = send(&app, "POST", uri, Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            json_body(response).await["message"],
+            "user verification flag not set"
+        );
+
+        let uri = "/api/v1/auth/webauthn/register/start";
+        let (challenge_id, options) = start(&app, uri, Some(&cookie), json!({})).await;
+        let body = json!({
+            "challenge_id": challenge_id,
+            "credential": authenticator.attestation(&options, FLAGS_UP_UV),
+        });
+        let uri = "/api/v1/auth/webauthn/register/finish";
+        let response = send(&app, "POST", uri, Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            json_body(response).await["credential_id"],
+            b64(&authenticator.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn login_requires_user_verification() {
+        let state = webauthn_state().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "passkey@example.com").await;
+        let mut authenticator = Authenticator::new();
+
+        let uri = "/api/v1/auth/webauthn/register/start";
+        let (challenge_id, options) = start(&app, uri, Some(&cookie), json!({})).await;
+        let body = json!({
+            "challenge_id": challenge_id,
+            "credential": authenticator.attestation(&options, FLAGS_UP_UV),
+        });
+        let uri = "/api/v1/auth/webauthn/register/finish";
+        let response = send(&app, "POST", uri, Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let uri = "/api/v1/auth/webauthn/login/start";
+        let (challenge_id, options) = start(&app, uri, None, json!({})).await;
+        assert_eq!(options["userVerification"], "required");
+        let body = json!({
+            "challenge_id": challenge_id,
+            "credential": authenticator.assertion(&options, 0x01),
+        });
+        let uri = "/api/v1/auth/webauthn/login/finish";
+        let response = send(&app, "POST", uri, None, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            json_body(response).await["message"],
+            "user verification flag not set"
+        );
+
+        let uri = "/api/v1/auth/webauthn/login/start";
+        let body = json!({ "identifier": "passkey@example.com" });
+        let (challenge_id, options) = start(&app, uri, None, body).await;
+        let body = json!({
+            "challenge_id": challenge_id,
+            "credential": authenticator.assertion(&options, FLAGS_UP_UV),
+        });
+        let uri = "/api/v1/auth/webauthn/login/finish";
+        let response = send(&app, "POST", uri, None, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(session_cookie(&response).starts_with("sid="));
+        assert_eq!(json_body(response).await["email"], "passkey@example.com");
+    }
+}
@@ -0,0 +1,264 @@
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Json, Router,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::{
+        auth::password::{error_response, ErrorResponse},
+        principal::Principal,
+    },
+    service::avatars::{Avatar, AvatarError, AvatarUpload},
+    state::AppState,
+};
+
+/// Room for the multipart boundaries and headers around the file itself.
+const MULTIPART_OVERHEAD_BYTES: usize = 16 * 1024;
+
+/// The `multipart/form-data` body of an avatar upload, for the API docs only; the handler reads
+/// the fields itself.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct AvatarUploadForm {
+    /// A PNG, JPEG, GIF or WebP image, sent with its content type.
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AvatarThumbnailResponse {
+    pub size: u32,
+    pub url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AvatarResponse {
+    /// The largest thumbnail, now the account's `avatar_url`.
+    pub avatar_url: String,
+    pub thumbnails: Vec<AvatarThumbnailResponse>,
+}
+
+impl From<Avatar> for AvatarResponse {
+    fn from(avatar: Avatar) -> Self {
+        Self {
+            avatar_url: avatar.avatar_url,
+            thumbnails: avatar
+                .thumbnails
+                .into_iter()
+                .map(|(size, url)| AvatarThumbnailResponse { size, url })
+                .collect(),
+        }
+    }
+}
+
+fn avatar_error_response(err: AvatarError) -> Response {
+    let status = match err.code {
+        "payload_too_large" => StatusCode::PAYLOAD_TOO_LARGE,
+        "unsupported_media_type" => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "db_error" | "storage_error" | "server_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    error_response(status, err.code, err.message)
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    let body_limit = state.avatars().max_bytes() + MULTIPART_OVERHEAD_BYTES;
+    Router::new()
+        .route(
+            "/api/v1/me/avatar",
+            put(upload_avatar).layer(DefaultBodyLimit::max(body_limit)),
+        )
+        .route("/api/v1/avatars/*path", get(get_avatar))
+        .with_state(state)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/me/avatar",
+    request_body(content = AvatarUploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Avatar stored and set as avatar_url", body = AvatarResponse),
+        (status = 400, description = "Missing file or undecodable image", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 413, description = "Larger than AVATAR_MAX_BYTES", body = ErrorResponse),
+        (status = 415, description = "Not a PNG, JPEG, GIF or WebP image", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn upload_avatar(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    mut multipart: Multipart,
+) -> Response {
+    let max_bytes = state.avatars().max_bytes();
+    let too_large = || {
+        error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            format!("avatars are limited to {} bytes", max_bytes),
+        )
+    };
+    let multipart_error = |err: axum::extract::multipart::MultipartError| {
+        error_response(err.status(), "invalid_multipart", err.body_text())
+    };
+
+    let mut upload = None;
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => return multipart_error(err),
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+        let content_type = field.content_type().unwrap_or_default().to_string();
+        let mut bytes = Vec::new();
+        loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) if bytes.len() + chunk.len() > max_bytes => return too_large(),
+                Ok(Some(chunk)) => bytes.extend_from_slice(&chunk),
+                Ok(None) => break,
+                Err(err) => return multipart_error(err),
+            }
+        }
+        upload = Some(AvatarUpload {
+            content_type,
+            bytes,
+        });
+        break;
+    }
+    let Some(upload) = upload else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "missing_file",
+            "send the image as the multipart field \"file\"",
+        );
+    };
+
+    match state
+        .avatars()
+        .upload(&principal.account, upload, principal.account.uid)
+        .await
+    {
+        Ok(avatar) => Json(AvatarResponse::from(avatar)).into_response(),
+        Err(err) => avatar_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/avatars/{path}",
+    params(("path" = String, Path, description = "<account uid>/<version>/<size>.png")),
+    responses(
+        (status = 200, description = "PNG thumbnail", content_type = "image/png"),
+        (status = 404, description = "No such avatar", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn get_avatar(State(state): State<Arc<AppState>>, Path(path): Path<String>) -> Response {
+    match state.avatars().thumbnail(&path).await {
+        // Every upload is stored under a new version, so a thumbnail never changes.
+        Ok(Some(png)) => (
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+            ],
+            png,
+        )
+            .into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "not_found", "avatar not found"),
+        Err(err) => avatar_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::state::AppState;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn avatar_upload_replaces_thumbnails() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        register(&app, "ada@example.com").await;
+        verify_email(&state, &app, "ada@example.com").await;
+        let cookie = session_cookie(&login(&app, "ada@example.com").await);
+        let body = serde_json::json!({ "nickname": "Ada" });
+        let response = send(
+            &app,
+            "PATCH",
+            "/api/v1/me/settings",
+            Some(&cookie),
+            Some(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let upload = |content_type: &'static str, bytes: Vec<u8>| {
+            let mut body = b"--boundary\r\nContent-Disposition: form-data; name=\"file\"; \
+                filename=\"avatar\"\r\nContent-Type: "
+                .to_vec();
+            body.extend_from_slice(content_type.as_bytes());
+            body.extend_from_slice(b"\r\n\r\n");
+            body.extend_from_slice(&bytes);
+            body.extend_from_slice(b"\r\n--boundary--\r\n");
+            let request = Request::builder()
+                .method("PUT")
+                .uri("/api/v1/me/avatar")
+                .header(header::COOKIE, &cookie)
+                .header(
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=boundary",
+                )
+                .body(Body::from(body))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(30, 20)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        let response = upload("text/plain", png.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = upload("image/jpeg", png.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = upload("image/png", png.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let first = json_body(response).await;
+        let first_url = first["avatar_url"].as_str().unwrap().to_string();
+        assert!(first_url.ends_with("/256.png"));
+        assert_eq!(first["thumbnails"].as_array().unwrap().len(), 3);
+        let response = send(&app, "GET", &first_url, None, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let thumbnail = image::load_from_memory(&bytes).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 256));
+
+        let response = upload("image/png", png).await.unwrap();
+        let second_url = json_body(response).await["avatar_url"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(json_body(response).await["avatar_url"], second_url.as_str());
+        let response = send(&app, "GET", "/api/v1/me/settings", Some(&cookie), None).await;
+        assert_eq!(json_body(response).await["nickname"], "Ada");
+        let response = send(&app, "GET", &first_url, None, None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,4 +1,42 @@
+use axum::Router;
+use std::sync::Arc;
+
+use crate::state::AppState;
+
+pub mod account_settings;
 pub mod accounts;
 pub mod auth;
+pub mod avatars;
 pub mod health;
+pub mod oauth;
+pub mod personal_access_tokens;
+pub mod principal;
+pub mod roles;
 pub mod session;
+pub mod teams;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod well_known;
+
+/// Every API route; the docs UI is added by `main`.
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .merge(health::routes())
+        .merge(accounts::routes(state.clone()))
+        .merge(account_settings::routes(state.clone()))
+        .merge(avatars::routes(state.clone()))
+        .merge(roles::routes(state.clone()))
+        .merge(teams::routes(state.clone()))
+        .merge(auth::github::routes(state.clone()))
+        .merge(auth::oidc::routes(state.clone()))
+        .merge(auth::credentials::routes(state.clone()))
+        .merge(auth::password::routes(state.clone()))
+        .merge(auth::token::routes(state.clone()))
+        .merge(auth::verify::routes(state.clone()))
+        .merge(auth::mfa::routes(state.clone()))
+        .merge(auth::webauthn::routes(state.clone()))
+        .merge(session::routes(state.clone()))
+        .merge(personal_access_tokens::routes(state.clone()))
+        .merge(oauth::routes(state.clone()))
+        .merge(well_known::routes(state))
+}
@@ -0,0 +1,1258 @@
+use axum::{
+    extract::{OriginalUri, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post},
+    Form, Json, Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    entities::{accounts, oauth_clients},
+    handler::{
+        auth::password::{error_response, ErrorResponse},
+        session::{bearer_token, current_account, current_session},
+    },
+    service::{
+        oauth::{
+            client_redirect_uris, client_scopes, resolve_scopes, AuthorizeRequest, OauthError,
+            RegisterClientInput,
+        },
+        refresh_tokens::RefreshGrant,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuthorizeQuery {
+    response_type: Option<String>,
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    nonce: Option<String>,
+    /// `none` fails instead of showing a login or consent page.
+    prompt: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Present when the `openid` scope was granted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    pub scope: String,
+}
+
+/// The `MeResponse` fields plus the standard OpenID Connect claims (`sub`, `preferred_username`,
+/// `email`, `email_verified`). `username` and `email` only appear when the token's scope covers
+/// them.
+#[derive(Serialize, ToSchema)]
+pub struct UserinfoResponse {
+    pub account_uid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+/// RFC 6749 error body, used by the token endpoint instead of `ErrorResponse`.
+#[derive(Serialize, ToSchema)]
+pub struct OauthErrorResponse {
+    pub error: String,
+    pub error_description: String,
+}
+
+/// Body of the introspection and revocation endpoints (RFC 7662, RFC 7009).
+#[derive(Deserialize, ToSchema)]
+pub struct TokenLookupRequest {
+    pub token: String,
+    /// `access_token`, `refresh_token` or `session`.
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// RFC 7662 response. Inactive tokens only carry `active: false`.
+#[derive(Serialize, ToSchema)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConsentResponse {
+    pub consent_required: bool,
+    pub consent_id: String,
+    pub client_id: String,
+    pub client_name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ConsentDecisionRequest {
+    pub consent_id: String,
+    pub approve: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConsentDecisionResponse {
+    /// Where the browser should go next: the client's redirect URI with a code or an error.
+    pub redirect_to: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateOauthClientRequest {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Public clients (SPAs, native apps) get no secret.
+    #[serde(default)]
+    pub public: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OauthClientResponse {
+    pub client_id: String,
+    /// Only returned when the client is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub public: bool,
+    pub created_at: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OauthClientListResponse {
+    pub clients: Vec<OauthClientResponse>,
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/oauth/authorize", get(authorize))
+        .route("/oauth/token", post(token))
+        .route("/oauth/userinfo", get(userinfo).post(userinfo))
+        .route("/oauth/introspect", post(introspect))
+        .route("/oauth/revoke", post(revoke))
+        .route("/api/v1/oauth/consent", post(decide_consent))
+        .route("/api/v1/oauth/consent/:consent_id", get(get_consent))
+        .route(
+            "/api/v1/oauth/clients",
+            get(list_clients).post(create_client),
+        )
+        .route("/api/v1/oauth/clients/:client_id", delete(delete_client))
+        .with_state(state)
+}
+
+fn client_response(
+    client: oauth_clients::Model,
+    client_secret: Option<String>,
+) -> OauthClientResponse {
+    OauthClientResponse {
+        redirect_uris: client_redirect_uris(&client),
+        scopes: client_scopes(&client),
+        client_id: client.client_id,
+        client_secret,
+        name: client.name,
+        public: client.is_public,
+        created_at: client.created_at.to_rfc3339(),
+    }
+}
+
+/// Appends query parameters to a registered redirect URI, which may already carry a query.
+fn redirect_uri_with(redirect_uri: &str, params: &[(&str, &str)]) -> String {
+    let mut url = redirect_uri.to_string();
+    for (index, (name, value)) in params.iter().enumerate() {
+        let delimiter = if index == 0 && !redirect_uri.contains('?') {
+            '?'
+        } else {
+            '&'
+        };
+        url.push(delimiter);
+        url.push_str(name);
+        url.push('=');
+        url.push_str(&urlencoding::encode(value));
+    }
+    url
+}
+
+/// Sends an authorization error back to the client; `state` is echoed so it can match requests.
+fn error_redirect(redirect_uri: &str, state: Option<&str>, code: &str, message: &str) -> String {
+    let mut params = vec![("error", code), ("error_description", message)];
+    if let Some(state) = state {
+        params.push(("state", state));
+    }
+    redirect_uri_with(redirect_uri, &params)
+}
+
+fn code_redirect(request: &AuthorizeRequest, code: &str) -> String {
+    let mut params = vec![("code", code)];
+    if let Some(state) = &request.state {
+        params.push(("state", state.as_str()));
+    }
+    redirect_uri_with(&request.redirect_uri, &params)
+}
+
+fn oauth_error_status(code: &str) -> StatusCode {
+    match code {
+        "invalid_client" => StatusCode::UNAUTHORIZED,
+        "server_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        "consent_not_found" | "client_not_found" => StatusCode::NOT_FOUND,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+fn oauth_error_response(err: OauthError) -> Response {
+    error_response(oauth_error_status(err.code), err.code, err.message)
+}
+
+fn token_error(code: &str, message: impl Into<String>) -> Response {
+    (
+        oauth_error_status(code),
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(OauthErrorResponse {
+            error: code.to_string(),
+            error_description: message.into(),
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/oauth/authorize",
+    params(AuthorizeQuery),
+    responses(
+        (status = 200, description = "Consent required and no OAUTH_CONSENT_URL is configured", body = ConsentResponse),
+        (status = 303, description = "Redirect to the client, the login page or the consent page"),
+        (status = 400, description = "Unknown client or unregistered redirect_uri", body = ErrorResponse),
+        (status = 401, description = "Not signed in and no OAUTH_LOGIN_URL is configured", body = ErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn authorize(
+    State(state): State<Arc<AppState>>,
+    OriginalUri(uri): OriginalUri,
+    jar: CookieJar,
+    Query(query): Query<AuthorizeQuery>,
+) -> Response {
+    let (Some(client_id), Some(redirect_uri)) = (&query.client_id, &query.redirect_uri) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "client_id and redirect_uri are required",
+        );
+    };
+    let client = match state
+        .oauth()
+        .authorize_client(client_id, redirect_uri)
+        .await
+    {
+        Ok(client) => client,
+        Err(err) => return oauth_error_response(err),
+    };
+
+    // From here on the redirect URI is trusted, so errors go back to the client.
+    let oauth_state = query.state.as_deref();
+    let fail = |code: &str, message: &str| {
+        Redirect::to(&error_redirect(redirect_uri, oauth_state, code, message)).into_response()
+    };
+    if query.response_type.as_deref() != Some("code") {
+        return fail(
+            "unsupported_response_type",
+            "only response_type=code is supported",
+        );
+    }
+    let Some(code_challenge) = query.code_challenge.clone().filter(|v| !v.is_empty()) else {
+        return fail("invalid_request", "code_challenge is required");
+    };
+    if query.code_challenge_method.as_deref() != Some("S256") {
+        return fail("invalid_request", "code_challenge_method must be S256");
+    }
+    let scopes = match resolve_scopes(&client, query.scope.as_deref()) {
+        Ok(scopes) => scopes,
+        Err(err) => return fail(err.code, &err.message),
+    };
+    let request = AuthorizeRequest {
+        client_id: client.client_id.clone(),
+        redirect_uri: redirect_uri.clone(),
+        scopes,
+        state: query.state.clone(),
+        code_challenge,
+        nonce: query.nonce.clone(),
+    };
+    let prompt_none = query.prompt.as_deref() == Some("none");
+
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => {
+            if prompt_none {
+                return fail("login_required", "the user is not signed in");
+            }
+            let Some(login_url) = &state.config().values().oauth_login_url else {
+                return response;
+            };
+            let return_to = uri
+                .path_and_query()
+                .map(|value| value.as_str())
+                .unwrap_or("/oauth/authorize");
+            return Redirect::to(&redirect_uri_with(login_url, &[("return_to", return_to)]))
+                .into_response();
+        }
+    };
+
+    let has_consent = match state
+        .oauth()
+        .has_consent(&current.account, &client, &request.scopes)
+        .await
+    {
+        Ok(value) => value,
+        Err(err) => return fail(err.code, &err.message),
+    };
+    if has_consent {
+        return match state.oauth().create_code(&current.account, &request).await {
+            Ok(code) => Redirect::to(&code_redirect(&request, &code)).into_response(),
+            Err(err) => fail(err.code, &err.message),
+        };
+    }
+    if prompt_none {
+        return fail("consent_required", "the user has not approved this client");
+    }
+
+    let consent_id = match state
+        .oauth()
+        .begin_consent(&current.account, &request)
+        .await
+    {
+        Ok(consent_id) => consent_id,
+        Err(err) => return fail(err.code, &err.message),
+    };
+    if let Some(consent_url) = &state.config().values().oauth_consent_url {
+        return Redirect::to(&redirect_uri_with(
+            consent_url,
+            &[("consent_id", &consent_id)],
+        ))
+        .into_response();
+    }
+    (
+        StatusCode::OK,
+        Json(ConsentResponse {
+            consent_required: true,
+            consent_id,
+            client_id: client.client_id,
+            client_name: client.name,
+            scopes: request.scopes,
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/oauth/consent/{consent_id}",
+    params(("consent_id" = String, Path, description = "Consent id from /oauth/authorize")),
+    responses(
+        (status = 200, description = "Client and scopes awaiting approval", body = ConsentResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 404, description = "Consent request not found or expired", body = ErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn get_consent(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Path(consent_id): Path<String>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+    match state
+        .oauth()
+        .find_consent(&current.account, &consent_id)
+        .await
+    {
+        Ok((client, request)) => (
+            StatusCode::OK,
+            Json(ConsentResponse {
+                consent_required: true,
+                consent_id,
+                client_id: client.client_id,
+                client_name: client.name,
+                scopes: request.scopes,
+            }),
+        )
+            .into_response(),
+        Err(err) => oauth_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/oauth/consent",
+    request_body = ConsentDecisionRequest,
+    responses(
+        (status = 200, description = "Consent recorded; continue at redirect_to", body = ConsentDecisionResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 404, description = "Consent request not found or expired", body = ErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn decide_consent(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Json(payload): Json<ConsentDecisionRequest>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+    let request = match state
+        .oauth()
+        .finish_consent(&current.account, &payload.consent_id, payload.approve)
+        .await
+    {
+        Ok(request) => request,
+        Err(err) => return oauth_error_response(err),
+    };
+
+    let redirect_to = if payload.approve {
+        match state.oauth().create_code(&current.account, &request).await {
+            Ok(code) => code_redirect(&request, &code),
+            Err(err) => return oauth_error_response(err),
+        }
+    } else {
+        error_redirect(
+            &request.redirect_uri,
+            request.state.as_deref(),
+            "access_denied",
+            "the user denied the request",
+        )
+    };
+    (
+        StatusCode::OK,
+        Json(ConsentDecisionResponse { redirect_to }),
+    )
+        .into_response()
+}
+
+/// Client credentials from HTTP Basic (RFC 6749 section 2.3.1) or, failing that, the form body.
+fn client_credentials(
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Option<(String, Option<String>)> {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| {
+            base64::engine::general_purpose::STANDARD
+                .decode(value.trim())
+                .ok()
+        })
+        .and_then(|value| String::from_utf8(value).ok());
+    if let Some(basic) = basic {
+        let (id, secret) = basic.split_once(':')?;
+        let id = urlencoding::decode(id).ok()?.into_owned();
+        let secret = urlencoding::decode(secret).ok()?.into_owned();
+        return Some((id, Some(secret)));
+    }
+    let id = client_id.filter(|value| !value.is_empty())?;
+    let secret = client_secret.filter(|value| !value.is_empty());
+    Some((id.to_string(), secret.map(str::to_string)))
+}
+
+/// Authenticates the calling client for the token, introspection and revocation endpoints.
+async fn authenticated_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<oauth_clients::Model, Response> {
+    let Some((client_id, client_secret)) = client_credentials(headers, client_id, client_secret)
+    else {
+        return Err(token_error(
+            "invalid_client",
+            "client authentication is required",
+        ));
+    };
+    state
+        .oauth()
+        .authenticate_client(&client_id, client_secret.as_deref())
+        .await
+        .map_err(|err| token_error(err.code, err.message))
+}
+
+#[utoipa::path(
+    post,
+    path = "/oauth/token",
+    request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Tokens issued", body = TokenResponse),
+        (status = 400, description = "Invalid grant or request", body = OauthErrorResponse),
+        (status = 401, description = "Client authentication failed", body = OauthErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(form): Form<TokenRequest>,
+) -> Response {
+    let client = match authenticated_client(
+        &state,
+        &headers,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await
+    {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+
+    let (grant, family_id) = match form.grant_type.as_str() {
+        "authorization_code" => {
+            let (Some(code), Some(redirect_uri), Some(code_verifier)) =
+                (&form.code, &form.redirect_uri, &form.code_verifier)
+            else {
+                return token_error(
+                    "invalid_request",
+                    "code, redirect_uri and code_verifier are required",
+                );
+            };
+            match state
+                .oauth()
+                .exchange_code(&client, code, redirect_uri, code_verifier)
+                .await
+            {
+                Ok(grant) => (
+                    TokenGrant {
+                        subject: grant.account.uid.to_string(),
+                        account: Some(grant.account),
+                        scope: grant.scope,
+                        nonce: grant.nonce,
+                    },
+                    Some(grant.family_id),
+                ),
+                Err(err) => return token_error(err.code, err.message),
+            }
+        }
+        "refresh_token" => {
+            let Some(refresh_token) = &form.refresh_token else {
+                return token_error("invalid_request", "refresh_token is required");
+            };
+            match state
+                .refresh_tokens()
+                .rotate(refresh_token, Some(&client.client_id))
+                .await
+            {
+                Ok((account, next, refresh_grant)) => {
+                    let grant = TokenGrant {
+                        subject: account.uid.to_string(),
+                        account: Some(account),
+                        scope: refresh_grant.scope.unwrap_or_default(),
+                        nonce: None,
+                    };
+                    return issue_tokens(&state, &client, grant, Some(next.token)).await;
+                }
+                Err(err) if err.code == "db_error" => {
+                    return token_error("server_error", err.message)
+                }
+                Err(err) => return token_error("invalid_grant", err.message),
+            }
+        }
+        "client_credentials" => {
+            if client.is_public {
+                return token_error(
+                    "unauthorized_client",
+                    "public clients cannot use client_credentials",
+                );
+            }
+            match resolve_scopes(&client, form.scope.as_deref()) {
+                Ok(scopes) => (
+                    TokenGrant {
+                        subject: client.client_id.clone(),
+                        account: None,
+                        scope: scopes.join(" "),
+                        nonce: None,
+                    },
+                    None,
+                ),
+                Err(err) => return token_error(err.code, err.message),
+            }
+        }
+        _ => {
+            return token_error(
+                "unsupported_grant_type",
+                format!("grant_type {} is not supported", form.grant_type),
+            )
+        }
+    };
+
+    // Only user grants get refresh tokens; a client can always re-run client_credentials.
+    let refresh_token = match &grant.account {
+        Some(account) => {
+            let refresh_grant = RefreshGrant {
+                client_id: Some(client.client_id.clone()),
+                scope: Some(grant.scope.clone()),
+            };
+            match state
+                .refresh_tokens()
+                .issue(account, refresh_grant, family_id)
+                .await
+            {
+                Ok(token) => Some(token.token),
+                Err(err) => return token_error("server_error", err.message),
+            }
+        }
+        None => None,
+    };
+    issue_tokens(&state, &client, grant, refresh_token).await
+}
+
+/// Who a token endpoint response is for: an account, or the client itself for
+/// `client_credentials`.
+struct TokenGrant {
+    subject: String,
+    account: Option<accounts::Model>,
+    scope: String,
+    nonce: Option<String>,
+}
+
+async fn issue_tokens(
+    state: &AppState,
+    client: &oauth_clients::Model,
+    grant: TokenGrant,
+    refresh_token: Option<String>,
+) -> Response {
+    let access_token = match state
+        .jwt()
+        .issue_client_access_token(&grant.subject, &client.client_id, &grant.scope)
+        .await
+    {
+        Ok(Some(token)) => token,
+        Ok(None) => return token_error("server_error", "access tokens are not enabled"),
+        Err(err) => return token_error("server_error", err.message),
+    };
+
+    let openid = grant
+        .scope
+        .split_whitespace()
+        .any(|scope| scope == "openid");
+    let id_token = match &grant.account {
+        Some(account) if openid => {
+            let mut claims = match state.oauth().identity_claims(account, &grant.scope).await {
+                Ok(claims) => claims,
+                Err(err) => return token_error("server_error", err.message),
+            };
+            if let Some(nonce) = grant.nonce {
+                claims.insert("nonce".to_string(), nonce.into());
+            }
+            match state.jwt().issue_id_token(&client.client_id, claims).await {
+                Ok(token) => token,
+                Err(err) => return token_error("server_error", err.message),
+            }
+        }
+        _ => None,
+    };
+
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(TokenResponse {
+            access_token: access_token.token,
+            token_type: "Bearer".to_string(),
+            expires_in: access_token.expires_in,
+            refresh_token,
+            id_token,
+            scope: grant.scope,
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/oauth/userinfo",
+    responses(
+        (status = 200, description = "Claims for the account the access token was issued to", body = UserinfoResponse),
+        (status = 401, description = "Missing, invalid or expired access token", body = OauthErrorResponse),
+        (status = 403, description = "Token lacks the openid scope", body = OauthErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn userinfo(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let bearer_error = |status: StatusCode, code: &str, message: &str| {
+        (
+            status,
+            [(
+                header::WWW_AUTHENTICATE,
+                format!("Bearer error=\"{}\"", code),
+            )],
+            Json(OauthErrorResponse {
+                error: code.to_string(),
+                error_description: message.to_string(),
+            }),
+        )
+            .into_response()
+    };
+    let Some(token) = bearer_token(&headers) else {
+        return bearer_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+            "a bearer access token is required",
+        );
+    };
+    let token = match state.jwt().verify_access_token(token).await {
+        Ok(token) => token,
+        Err(err) => return bearer_error(StatusCode::UNAUTHORIZED, "invalid_token", &err.message),
+    };
+    if token.client_id.is_some() && !token.has_scope("openid") {
+        return bearer_error(
+            StatusCode::FORBIDDEN,
+            "insufficient_scope",
+            "the openid scope is required",
+        );
+    }
+
+    let account = match uuid::Uuid::parse_str(&token.sub) {
+        Ok(uid) => state.accounts().get(uid).await.ok().flatten(),
+        Err(_) => None,
+    };
+    let Some(account) = account else {
+        return bearer_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+            "the token subject is not an account",
+        );
+    };
+
+    // First-party tokens carry no scope and see every claim.
+    let scope = token
+        .scope
+        .clone()
+        .unwrap_or_else(|| "openid profile email".to_string());
+    let claims = match state.oauth().identity_claims(&account, &scope).await {
+        Ok(claims) => claims,
+        Err(err) => return token_error("server_error", err.message),
+    };
+    (
+        StatusCode::OK,
+        Json(UserinfoResponse {
+            account_uid: account.uid.to_string(),
+            username: claims
+                .contains_key("preferred_username")
+                .then(|| account.username.clone())
+                .flatten(),
+            claims,
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/oauth/introspect",
+    request_body(content = TokenLookupRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token state; `active` is false for unknown or expired tokens", body = IntrospectionResponse),
+        (status = 401, description = "Client authentication failed", body = OauthErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn introspect(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(form): Form<TokenLookupRequest>,
+) -> Response {
+    let client = match authenticated_client(
+        &state,
+        &headers,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await
+    {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    // Public clients cannot keep a secret, so letting them probe tokens would let anyone do it.
+    if client.is_public {
+        return token_error(
+            "unauthorized_client",
+            "public clients cannot introspect tokens",
+        );
+    }
+
+    let info = match state
+        .introspection()
+        .introspect(&client, &form.token, form.token_type_hint.as_deref())
+        .await
+    {
+        Ok(info) => info,
+        Err(err) => return token_error(err.code, err.message),
+    };
+    let response = match info {
+        Some(info) => IntrospectionResponse {
+            active: true,
+            sub: Some(info.sub),
+            iat: info.iat,
+            exp: info.exp,
+            scope: info.scope,
+            client_id: info.client_id,
+            token_type: Some(info.kind.to_string()),
+        },
+        None => IntrospectionResponse {
+            active: false,
+            sub: None,
+            iat: None,
+            exp: None,
+            scope: None,
+            client_id: None,
+            token_type: None,
+        },
+    };
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(response),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/oauth/revoke",
+    request_body(content = TokenLookupRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token revoked, or it was unknown to begin with"),
+        (status = 400, description = "Access tokens cannot be revoked", body = OauthErrorResponse),
+        (status = 401, description = "Client authentication failed", body = OauthErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn revoke(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(form): Form<TokenLookupRequest>,
+) -> Response {
+    let client = match authenticated_client(
+        &state,
+        &headers,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await
+    {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match state
+        .introspection()
+        .revoke(&client, &form.token, form.token_type_hint.as_deref())
+        .await
+    {
+        Ok(()) => {
+            state.forward_auth_cache().remove(&form.token);
+            StatusCode::OK.into_response()
+        }
+        Err(err) => token_error(err.code, err.message),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/oauth/clients",
+    request_body = CreateOauthClientRequest,
+    responses(
+        (status = 201, description = "Client registered; the secret is shown once", body = OauthClientResponse),
+        (status = 400, description = "Invalid client metadata", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn create_client(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(payload): Json<CreateOauthClientRequest>,
+) -> Response {
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    let input = RegisterClientInput {
+        name: payload.name,
+        redirect_uris: payload.redirect_uris,
+        scopes: payload.scopes,
+        is_public: payload.public,
+    };
+    match state.oauth().register_client(&account, input).await {
+        Ok((client, secret)) => {
+            (StatusCode::CREATED, Json(client_response(client, secret))).into_response()
+        }
+        Err(err) => oauth_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/oauth/clients",
+    responses(
+        (status = 200, description = "Clients registered by the current account", body = OauthClientListResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn list_clients(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Response {
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    match state.oauth().list_clients(&account).await {
+        Ok(clients) => {
+            let clients = clients
+                .into_iter()
+                .map(|client| client_response(client, None))
+                .collect();
+            (StatusCode::OK, Json(OauthClientListResponse { clients })).into_response()
+        }
+        Err(err) => oauth_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/oauth/clients/{client_id}",
+    params(("client_id" = String, Path, description = "OAuth client id")),
+    responses(
+        (status = 204, description = "Client deleted"),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 404, description = "Client not found", body = ErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn delete_client(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Path(client_id): Path<String>,
+) -> Response {
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    match state.oauth().delete_client(&account, &client_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => oauth_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::service::pkce::s256_challenge;
+    use crate::state::AppState;
+    use axum::{
+        body::Body,
+        http::{header, Request, Response, StatusCode},
+        Router,
+    };
+    use base64::Engine;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    const REDIRECT_URI: &str = "https://client.example/callback";
+    const VERIFIER: &str = "a-code-verifier-long-enough-to-satisfy-rfc-7636-rules";
+
+    async fn oauth_state() -> Arc<AppState> {
+        AppState::in_memory_with(|config| config.jwt_enabled = true).await
+    }
+
+    /// Registers a client allowed `openid email`; returns its id and, for confidential clients,
+    /// its secret.
+    async fn client(app: &Router, cookie: &str, public: bool) -> (String, Option<String>) {
+        let body = json!({
+            "name": "Example",
+            "redirect_uris": [REDIRECT_URI],
+            "scopes": ["openid", "email"],
+            "public": public,
+        });
+        let response = send(
+            app,
+            "POST",
+            "/api/v1/oauth/clients",
+            Some(cookie),
+            Some(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let client = json_body(response).await;
+        let secret = client["client_secret"].as_str().map(str::to_string);
+        (client["client_id"].as_str().unwrap().to_string(), secret)
+    }
+
+    fn authorize_uri(client_id: &str) -> String {
+        format!(
+            "/oauth/authorize?response_type=code&client_id={}&redirect_uri={}&scope=openid%20email\
+             &state=xyz&nonce=n-1&code_challenge={}&code_challenge_method=S256",
+            client_id,
+            urlencoding::encode(REDIRECT_URI),
+            s256_challenge(VERIFIER),
+        )
+    }
+
+    /// The `code` parameter of a redirect back to the client, after checking `state` survived.
+    fn code_from(redirect: &str) -> String {
+        assert!(redirect.starts_with(REDIRECT_URI), "{}", redirect);
+        let url = reqwest::Url::parse(redirect).unwrap();
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        assert_eq!(param("state").as_deref(), Some("xyz"));
+        param("code").unwrap_or_else(|| panic!("no code in {}", redirect))
+    }
+
+    /// Runs authorize, approving the consent it asks for unless the client was already
+    /// approved; returns the authorization code.
+    async fn authorization_code(app: &Router, cookie: &str, client_id: &str) -> String {
+        let response = send(app, "GET", &authorize_uri(client_id), Some(cookie), None).await;
+        if response.status() == StatusCode::SEE_OTHER {
+            return code_from(response.headers()[header::LOCATION].to_str().unwrap());
+        }
+        assert_eq!(response.status(), StatusCode::OK);
+        let consent = json_body(response).await;
+        assert_eq!(consent["consent_required"], true);
+        assert_eq!(consent["scopes"], json!(["openid", "email"]));
+
+        let body = json!({ "consent_id": consent["consent_id"], "approve": true });
+        let response = send(
+            app,
+            "POST",
+            "/api/v1/oauth/consent",
+            Some(cookie),
+            Some(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        code_from(json_body(response).await["redirect_to"].as_str().unwrap())
+    }
+
+    /// Posts a form to the token endpoint, authenticating with HTTP Basic when `basic` is set.
+    async fn token(
+        app: &Router,
+        form: &[(&str, &str)],
+        basic: Option<(&str, &str)>,
+    ) -> Response<Body> {
+        let body = form
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, urlencoding::encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut request = Request::builder()
+            .method("POST")
+            .uri("/oauth/token")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some((id, secret)) = basic {
+            let credentials =
+                base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", id, secret));
+            request = request.header(header::AUTHORIZATION, format!("Basic {}", credentials));
+        }
+        app.clone()
+            .oneshot(request.body(Body::from(body)).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn exchange(
+        app: &Router,
+        client_id: &str,
+        secret: Option<&str>,
+        code: &str,
+        verifier: &str,
+    ) -> Response<Body> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", verifier),
+            ("client_id", client_id),
+        ];
+        if let Some(secret) = secret {
+            form.push(("client_secret", secret));
+        }
+        token(app, &form, None).await
+    }
+
+    async fn refresh(
+        app: &Router,
+        client_id: &str,
+        secret: &str,
+        refresh_token: &str,
+    ) -> Response<Body> {
+        let form = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ];
+        token(app, &form, Some((client_id, secret))).await
+    }
+
+    #[tokio::test]
+    async fn authorization_code_flow_issues_tokens() {
+        let state = oauth_state().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let (client_id, secret) = client(&app, &cookie, false).await;
+        let secret = secret.unwrap();
+
+        let code = authorization_code(&app, &cookie, &client_id).await;
+        let response = exchange(&app, &client_id, Some(&secret), &code, VERIFIER).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens = json_body(response).await;
+        assert_eq!(tokens["token_type"], "Bearer");
+        assert_eq!(tokens["scope"], "openid email");
+        assert!(tokens["id_token"].is_string());
+        let access_token = tokens["access_token"].as_str().unwrap();
+
+        let response = send_bearer(&app, "GET", "/oauth/userinfo", access_token, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let claims = json_body(response).await;
+        assert_eq!(claims["email"], "ada@example.com");
+        assert_eq!(claims["email_verified"], true);
+
+        let refresh_token = tokens["refresh_token"].as_str().unwrap();
+        let response = refresh(&app, &client_id, &secret, refresh_token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let rotated = json_body(response).await;
+        assert_ne!(rotated["refresh_token"], tokens["refresh_token"]);
+
+        // The approval is remembered, so the next authorization goes straight to the client.
+        let response = send(&app, "GET", &authorize_uri(&client_id), Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        code_from(response.headers()[header::LOCATION].to_str().unwrap());
+    }
+
+    #[tokio::test]
+    async fn replayed_codes_revoke_their_tokens() {
+        let state = oauth_state().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let (client_id, secret) = client(&app, &cookie, false).await;
+        let secret = secret.unwrap();
+
+        let code = authorization_code(&app, &cookie, &client_id).await;
+        let response = exchange(&app, &client_id, Some(&secret), &code, VERIFIER).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens = json_body(response).await;
+
+        let response = exchange(&app, &client_id, Some(&secret), &code, VERIFIER).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["error"], "invalid_grant");
+        let refresh_token = tokens["refresh_token"].as_str().unwrap();
+        let response = refresh(&app, &client_id, &secret, refresh_token).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["error"], "invalid_grant");
+    }
+
+    #[tokio::test]
+    async fn code_verifier_must_match_the_challenge() {
+        let state = oauth_state().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let (client_id, secret) = client(&app, &cookie, false).await;
+        let secret = secret.unwrap();
+
+        let code = authorization_code(&app, &cookie, &client_id).await;
+        let response = exchange(&app, &client_id, Some(&secret), &code, "another-verifier").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error = json_body(response).await;
+        assert_eq!(error["error"], "invalid_grant");
+        assert_eq!(error["error_description"], "code_verifier does not match");
+    }
+
+    #[tokio::test]
+    async fn clients_authenticate_by_type() {
+        let state = oauth_state().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let (confidential, secret) = client(&app, &cookie, false).await;
+        let secret = secret.unwrap();
+        let (public, no_secret) = client(&app, &cookie, true).await;
+        assert!(no_secret.is_none());
+
+        let code = authorization_code(&app, &cookie, &confidential).await;
+        let response = exchange(&app, &confidential, None, &code, VERIFIER).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json_body(response).await["error"], "invalid_client");
+        let code = authorization_code(&app, &cookie, &confidential).await;
+        let response = exchange(&app, &confidential, Some("wrong"), &code, VERIFIER).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let code = authorization_code(&app, &cookie, &public).await;
+        let response = exchange(&app, &public, Some("anything"), &code, VERIFIER).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let code = authorization_code(&app, &cookie, &public).await;
+        let response = exchange(&app, &public, None, &code, VERIFIER).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let form = [("grant_type", "client_credentials"), ("scope", "email")];
+        let response = token(&app, &form, Some((&confidential, &secret))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens = json_body(response).await;
+        assert_eq!(tokens["scope"], "email");
+        assert!(tokens.get("refresh_token").is_none());
+        let form = [
+            ("grant_type", "client_credentials"),
+            ("client_id", public.as_str()),
+        ];
+        let response = token(&app, &form, None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["error"], "unauthorized_client");
+    }
+}
@@ -0,0 +1,267 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    entities::accounts,
+    handler::{
+        auth::password::{error_response, ErrorResponse},
+        session::{bearer_token, current_account},
+    },
+    service::personal_access_tokens::{
+        CreatePersonalAccessTokenInput, PersonalAccessTokenError, PersonalAccessTokenInfo,
+        TOKEN_PREFIX,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreatePersonalAccessTokenRequest {
+    pub name: String,
+    /// Permissions the token is limited to, e.g. `accounts:read`; it keeps only those the
+    /// account holds. A scoped token is refused by endpoints its scopes do not cover. Leave
+    /// empty for a token that acts with all of the account's access.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Omit for a token that does not expire.
+    pub expires_in_days: Option<u32>,
+    /// Issue the token for a robot account you created instead of your own account.
+    pub account_uid: Option<Uuid>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PersonalAccessTokenResponse {
+    pub id: i64,
+    /// Only returned when the token is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub name: String,
+    /// The first characters of the token, to tell tokens apart.
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub account_uid: Uuid,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PersonalAccessTokenListResponse {
+    pub tokens: Vec<PersonalAccessTokenResponse>,
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/me/tokens", get(list_tokens).post(create_token))
+        .route("/api/v1/me/tokens/:token_id", delete(revoke_token))
+        .with_state(state)
+}
+
+fn token_response(
+    info: PersonalAccessTokenInfo,
+    token: Option<String>,
+) -> PersonalAccessTokenResponse {
+    PersonalAccessTokenResponse {
+        id: info.id,
+        token,
+        name: info.name,
+        prefix: info.prefix,
+        scopes: info.scopes,
+        account_uid: info.account_uid,
+        expires_at: info.expires_at.map(|value| value.to_rfc3339()),
+        last_used_at: info.last_used_at.map(|value| value.to_rfc3339()),
+        created_at: info.created_at.to_rfc3339(),
+    }
+}
+
+fn token_error_response(err: PersonalAccessTokenError) -> Response {
+    let status = match err.code {
+        "forbidden" => StatusCode::FORBIDDEN,
+        "not_found" => StatusCode::NOT_FOUND,
+        "db_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    error_response(status, err.code, err.message)
+}
+
+/// Managing tokens needs a session or login access token, so a leaked personal access token
+/// cannot be used to mint more of them.
+async fn token_owner(
+    state: &AppState,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<accounts::Model, Response> {
+    if jar.get("sid").is_none()
+        && bearer_token(headers).is_some_and(|token| token.starts_with(TOKEN_PREFIX))
+    {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "personal access tokens cannot manage tokens",
+        ));
+    }
+    current_account(state, headers, jar).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/tokens",
+    responses(
+        (status = 200, description = "Active tokens of the account and of robot accounts it created", body = PersonalAccessTokenListResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn list_tokens(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Response {
+    let owner = match token_owner(&state, &headers, &jar).await {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+    match state.personal_access_tokens().list(&owner).await {
+        Ok(tokens) => (
+            StatusCode::OK,
+            Json(PersonalAccessTokenListResponse {
+                tokens: tokens
+                    .into_iter()
+                    .map(|info| token_response(info, None))
+                    .collect(),
+            }),
+        )
+            .into_response(),
+        Err(err) => token_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/tokens",
+    request_body = CreatePersonalAccessTokenRequest,
+    responses(
+        (status = 201, description = "Token created; it is shown once", body = PersonalAccessTokenResponse),
+        (status = 400, description = "Invalid name, scopes or expiry", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 403, description = "Not your robot account, or called with a personal access token", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn create_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(payload): Json<CreatePersonalAccessTokenRequest>,
+) -> Response {
+    let owner = match token_owner(&state, &headers, &jar).await {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+    let input = CreatePersonalAccessTokenInput {
+        name: payload.name,
+        scopes: payload.scopes,
+        expires_in_days: payload.expires_in_days,
+        account_uid: payload.account_uid,
+    };
+    match state.personal_access_tokens().create(&owner, input).await {
+        Ok((token, info)) => {
+            (StatusCode::CREATED, Json(token_response(info, Some(token)))).into_response()
+        }
+        Err(err) => token_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/tokens/{token_id}",
+    params(("token_id" = i64, Path, description = "Token id")),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 404, description = "Token not found", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn revoke_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Path(token_id): Path<i64>,
+) -> Response {
+    let owner = match token_owner(&state, &headers, &jar).await {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+    match state
+        .personal_access_tokens()
+        .revoke(&owner, token_id)
+        .await
+    {
+        Ok(info) => {
+            state.forward_auth_cache().remove_account(info.account_uid);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(err) => token_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::service::rbac::ROLE_ADMIN;
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn scoped_tokens_stay_within_their_scopes() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        register(&app, "grace@example.com").await;
+        let ada = account(&state, "ada@example.com").await;
+        let grace = account(&state, "grace@example.com").await;
+        state
+            .rbac()
+            .grant_role(&ada, ROLE_ADMIN, ada.uid)
+            .await
+            .unwrap();
+
+        let body = json!({ "name": "read-only", "scopes": ["accounts:read"] });
+        let response = send(&app, "POST", "/api/v1/me/tokens", Some(&cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let token = json_body(response).await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let grace_uri = format!("/api/v1/accounts/{}", grace.uid);
+        let response = send_bearer(&app, "GET", &grace_uri, &token, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send_bearer(&app, "GET", "/api/v1/accounts", &token, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = json!({ "phone": "+15550100" });
+        let response = send_bearer(&app, "PATCH", &grace_uri, &token, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send_bearer(&app, "DELETE", &grace_uri, &token, None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let grant_uri = format!("{}/roles/admin", grace_uri);
+        let response = send_bearer(&app, "PUT", &grant_uri, &token, None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send_bearer(&app, "POST", "/api/v1/auth/logout-all", &token, None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
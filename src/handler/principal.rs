@@ -0,0 +1,188 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, Method, StatusCode},
+    response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::{
+    entities::accounts,
+    handler::{
+        auth::password::error_response,
+        session::{current_caller, current_session},
+    },
+    service::rbac::{PERMISSION_ACCOUNTS_READ, PERMISSION_ACCOUNTS_WRITE, PERMISSION_ROLES_WRITE},
+    state::AppState,
+};
+
+/// The authenticated caller, from a session cookie or a bearer token as accepted by
+/// `current_account`, with the permissions granted through their roles. Rejects with 401 when
+/// neither is present and valid.
+///
+/// A personal access token restricted to scopes only keeps the permissions its scopes name, and
+/// reaches an endpoint only when its scopes cover it: reads need `accounts:read` and writes
+/// `accounts:write`, unless the endpoint asks for a permission through `RequirePermission`.
+pub struct Principal {
+    pub account: accounts::Model,
+    pub permissions: Vec<String>,
+    /// The token's scopes; `None` when the caller is not limited by scopes.
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Principal {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|granted| granted == permission)
+    }
+
+    fn allows(&self, scope: &str) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.iter().any(|granted| granted == scope))
+    }
+
+    /// The caller's own account, or a robot account they created. Needs no permission.
+    fn owns(&self, account: &accounts::Model) -> bool {
+        account.uid == self.account.uid
+            || (account.account_type == "robot" && account.created_by == Some(self.account.uid))
+    }
+
+    pub fn can_read(&self, account: &accounts::Model) -> bool {
+        (self.owns(account) && self.allows(PERMISSION_ACCOUNTS_READ))
+            || self.has_permission(PERMISSION_ACCOUNTS_READ)
+    }
+
+    pub fn can_write(&self, account: &accounts::Model) -> bool {
+        (self.owns(account) && self.allows(PERMISSION_ACCOUNTS_WRITE))
+            || self.has_permission(PERMISSION_ACCOUNTS_WRITE)
+    }
+
+    /// The caller without checking that their scopes cover the endpoint.
+    async fn resolve(parts: &Parts, state: &Arc<AppState>) -> Result<Self, Response> {
+        let jar = CookieJar::from_headers(&parts.headers);
+        if jar.get("sid").is_none() {
+            let (account, scopes) = current_caller(state, &parts.headers, &jar).await?;
+            let mut permissions = state
+                .rbac()
+                .permissions_for(&account)
+                .await
+                .map_err(permissions_error)?;
+            if let Some(scopes) = &scopes {
+                permissions.retain(|permission| scopes.contains(permission));
+            }
+            return Ok(Self {
+                account,
+                permissions,
+                scopes,
+            });
+        }
+
+        // Sessions carry the permissions so that most requests skip the role lookup.
+        let current = current_session(state, &jar).await?;
+        let permissions = match current.session.permissions {
+            Some(permissions) => permissions,
+            None => {
+                let permissions = state
+                    .rbac()
+                    .permissions_for(&current.account)
+                    .await
+                    .map_err(permissions_error)?;
+                state
+                    .sessions()
+                    .set_permissions(&current.session_id, Some(permissions.clone()))
+                    .await
+                    .map_err(|err| {
+                        error_response(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "session_error",
+                            format!("session update failed: {}", err),
+                        )
+                    })?;
+                permissions
+            }
+        };
+        Ok(Self {
+            account: current.account,
+            permissions,
+            scopes: None,
+        })
+    }
+}
+
+fn permissions_error(err: crate::service::rbac::RbacError) -> Response {
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, err.code, err.message)
+}
+
+fn insufficient_scope(scope: &str) -> Response {
+    error_response(
+        StatusCode::FORBIDDEN,
+        "insufficient_scope",
+        format!("token scopes do not include {}", scope),
+    )
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Principal {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let principal = Self::resolve(parts, state).await?;
+        let scope = if matches!(parts.method, Method::GET | Method::HEAD) {
+            PERMISSION_ACCOUNTS_READ
+        } else {
+            PERMISSION_ACCOUNTS_WRITE
+        };
+        if !principal.allows(scope) {
+            return Err(insufficient_scope(scope));
+        }
+        Ok(principal)
+    }
+}
+
+/// A permission checked by `RequirePermission`.
+pub trait Permission: Send + Sync {
+    const NAME: &'static str;
+}
+
+pub struct AccountsRead;
+
+impl Permission for AccountsRead {
+    const NAME: &'static str = PERMISSION_ACCOUNTS_READ;
+}
+
+pub struct RolesWrite;
+
+impl Permission for RolesWrite {
+    const NAME: &'static str = PERMISSION_ROLES_WRITE;
+}
+
+/// A `Principal` holding the permission `P`; rejects with 403 otherwise, e.g.
+/// `RequirePermission::<RolesWrite>`.
+pub struct RequirePermission<P: Permission>(pub Principal, pub PhantomData<P>);
+
+#[async_trait]
+impl<P: Permission> FromRequestParts<Arc<AppState>> for RequirePermission<P> {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let principal = Principal::resolve(parts, state).await?;
+        if !principal.allows(P::NAME) {
+            return Err(insufficient_scope(P::NAME));
+        }
+        if !principal.has_permission(P::NAME) {
+            return Err(error_response(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                format!("missing permission {}", P::NAME),
+            ));
+        }
+        Ok(Self(principal, PhantomData))
+    }
+}
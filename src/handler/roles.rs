@@ -0,0 +1,247 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Json, Router,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::{
+        accounts::visible_account,
+        auth::password::{error_response, ErrorResponse},
+        principal::{Principal, RequirePermission, RolesWrite},
+    },
+    service::rbac::{RbacError, RoleInfo},
+    state::AppState,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct RoleResponse {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RoleListResponse {
+    pub roles: Vec<RoleResponse>,
+}
+
+impl From<RoleInfo> for RoleResponse {
+    fn from(info: RoleInfo) -> Self {
+        Self {
+            name: info.name,
+            description: info.description,
+            permissions: info.permissions,
+        }
+    }
+}
+
+fn role_list(roles: Vec<RoleInfo>) -> Response {
+    Json(RoleListResponse {
+        roles: roles.into_iter().map(RoleResponse::from).collect(),
+    })
+    .into_response()
+}
+
+fn rbac_error_response(err: RbacError) -> Response {
+    let status = match err.code {
+        "not_found" => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error_response(status, err.code, err.message)
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/roles", get(list_roles))
+        .route("/api/v1/accounts/:uid/roles", get(list_account_roles))
+        .route(
+            "/api/v1/accounts/:uid/roles/:role",
+            put(grant_role).delete(revoke_role),
+        )
+        .with_state(state)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/roles",
+    responses(
+        (status = 200, description = "Every role with its permissions", body = RoleListResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn list_roles(State(state): State<Arc<AppState>>, _principal: Principal) -> Response {
+    match state.rbac().list_roles().await {
+        Ok(roles) => role_list(roles),
+        Err(err) => rbac_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/accounts/{uid}/roles",
+    params(("uid" = String, Path, description = "Account uid")),
+    responses(
+        (status = 200, description = "Roles granted to the account", body = RoleListResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 404, description = "Not found, or not visible to the caller", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn list_account_roles(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(uid): Path<String>,
+) -> Response {
+    let account = match visible_account(&state, &principal, &uid).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    match state.rbac().roles_for(&account).await {
+        Ok(roles) => role_list(roles),
+        Err(err) => rbac_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/accounts/{uid}/roles/{role}",
+    params(
+        ("uid" = String, Path, description = "Account uid"),
+        ("role" = String, Path, description = "Role name")
+    ),
+    responses(
+        (status = 204, description = "Role granted, or already held"),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Needs roles:write", body = ErrorResponse),
+        (status = 404, description = "Account or role not found", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn grant_role(
+    State(state): State<Arc<AppState>>,
+    RequirePermission(principal, _): RequirePermission<RolesWrite>,
+    Path((uid, role)): Path<(String, String)>,
+) -> Response {
+    let account = match visible_account(&state, &principal, &uid).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    match state
+        .rbac()
+        .grant_role(&account, &role, principal.account.uid)
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => rbac_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/accounts/{uid}/roles/{role}",
+    params(
+        ("uid" = String, Path, description = "Account uid"),
+        ("role" = String, Path, description = "Role name")
+    ),
+    responses(
+        (status = 204, description = "Role revoked, or not held"),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Needs roles:write", body = ErrorResponse),
+        (status = 404, description = "Account or role not found", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn revoke_role(
+    State(state): State<Arc<AppState>>,
+    RequirePermission(principal, _): RequirePermission<RolesWrite>,
+    Path((uid, role)): Path<(String, String)>,
+) -> Response {
+    let account = match visible_account(&state, &principal, &uid).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    match state
+        .rbac()
+        .revoke_role(&account, &role, principal.account.uid)
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => rbac_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn roles_grant_permissions_to_live_sessions() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        for email in ["ada@example.com", "grace@example.com"] {
+            register(&app, email).await;
+            verify_email(&state, &app, email).await;
+        }
+        let find = |email: &'static str| {
+            let state = state.clone();
+            async move {
+                state
+                    .accounts_repo()
+                    .find_by_email(email)
+                    .await
+                    .unwrap()
+                    .unwrap()
+            }
+        };
+        let (ada, grace) = (
+            find("ada@example.com").await,
+            find("grace@example.com").await,
+        );
+        let grace_uri = format!("/api/v1/accounts/{}", grace.uid);
+        let grant_uri = format!("{}/roles/admin", grace_uri);
+
+        let cookie = session_cookie(&login(&app, "ada@example.com").await);
+        let response = send(&app, "GET", &grace_uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = send(&app, "PUT", &grant_uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // The session cached no permissions above; granting a role must not leave it stale.
+        state
+            .rbac()
+            .grant_role(&ada, crate::service::rbac::ROLE_ADMIN, ada.uid)
+            .await
+            .unwrap();
+        let response = send(&app, "GET", &grace_uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, "PUT", &grant_uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send(
+            &app,
+            "GET",
+            &format!("{}/roles", grace_uri),
+            Some(&cookie),
+            None,
+        )
+        .await;
+        assert_eq!(json_body(response).await["roles"][0]["name"], "admin");
+
+        let response = send(&app, "DELETE", &grant_uri, Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(state
+            .rbac()
+            .permissions_for(&grace)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
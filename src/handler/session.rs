@@ -1,10 +1,29 @@
-use crate::state::AppState;
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use crate::{
+    entities::accounts,
+    handler::{
+        auth::password::{error_response, ErrorResponse},
+        teams::TeamSummaryResponse,
+    },
+    service::{
+        auth::ChangePasswordInput,
+        personal_access_tokens,
+        session::{public_session_id, SessionData},
+    },
+    state::AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use axum_extra::extract::cookie::CookieJar;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
+/// Error body of `/api/v1/me`, which predates the `code` field and keeps its original shape.
 #[derive(Serialize)]
-struct ErrorResponse {
+struct MeErrorResponse {
     message: String,
 }
 
@@ -13,75 +32,575 @@ pub struct MeResponse {
     pub account_uid: String,
     pub username: Option<String>,
     pub email: Option<String>,
+    pub nickname: Option<String>,
+    pub avatar_url: Option<String>,
+    /// Teams the account belongs to, with its role in each.
+    pub teams: Vec<TeamSummaryResponse>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangePasswordRequest {
+    /// Required when the account already has a password.
+    pub current_password: Option<String>,
+    pub new_password: String,
+    /// Also revokes every refresh token and personal access token of the account, including
+    /// this device's refresh token.
+    #[serde(default)]
+    pub sign_out_other_sessions: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChangePasswordResponse {
+    pub status: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionResponse {
+    /// Stable identifier for this list; not the session cookie value.
+    pub id: String,
+    /// Whether this is the session making the request.
+    pub current: bool,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub login_method: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionListResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+pub struct CurrentSession {
+    pub session_id: String,
+    pub session: SessionData,
+    pub account: accounts::Model,
 }
 
 pub fn routes(state: std::sync::Arc<AppState>) -> axum::Router {
     axum::Router::new()
         .route("/api/v1/me", axum::routing::get(me))
+        .route("/api/v1/me/password", axum::routing::post(change_password))
+        .route("/api/v1/me/sessions", axum::routing::get(list_sessions))
+        .route(
+            "/api/v1/me/sessions/:session_id",
+            axum::routing::delete(revoke_session),
+        )
         .with_state(state)
 }
 
-async fn me(State(state): State<std::sync::Arc<AppState>>, jar: CookieJar) -> impl IntoResponse {
+/// Resolves the `sid` cookie into the live session and its account.
+pub async fn current_session(
+    state: &AppState,
+    jar: &CookieJar,
+) -> Result<CurrentSession, Response> {
     let Some(cookie) = jar.get("sid") else {
-        return (
+        return Err(error_response(
             StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                message: "missing session".to_string(),
-            }),
-        )
-            .into_response();
+            "missing_session",
+            "missing session",
+        ));
     };
 
     let session = match state.sessions().get(cookie.value()).await {
         Ok(value) => value,
         Err(err) => {
-            return (
+            return Err(error_response(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    message: format!("session lookup failed: {}", err),
-                }),
-            )
-                .into_response();
+                "session_error",
+                format!("session lookup failed: {}", err),
+            ));
         }
     };
 
     let Some(session) = session else {
-        return (
+        return Err(error_response(
             StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                message: "invalid session".to_string(),
-            }),
-        )
-            .into_response();
+            "invalid_session",
+            "invalid session",
+        ));
     };
 
     let account = match state.accounts().get(session.account_uid).await {
         Ok(value) => value,
         Err(err) => {
-            return (
+            return Err(error_response(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    message: format!("account lookup failed: {}", err),
-                }),
-            )
-                .into_response();
+                "db_error",
+                format!("account lookup failed: {}", err),
+            ));
         }
     };
 
     let Some(account) = account else {
-        return (
+        return Err(error_response(
             StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                message: "account not found".to_string(),
-            }),
-        )
-            .into_response();
+            "account_not_found",
+            "account not found",
+        ));
+    };
+
+    Ok(CurrentSession {
+        session_id: cookie.value().to_string(),
+        session,
+        account,
+    })
+}
+
+/// Token from an `Authorization: Bearer` header.
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Resolves the caller from the `sid` cookie or, without one, from a bearer token: a personal
+/// access token, or an access token issued by a first-party login. Tokens issued to OAuth
+/// clients are for those clients' APIs and are not accepted here.
+///
+/// Personal access tokens restricted to scopes are refused with 403: endpoints that honour
+/// scopes take a `Principal` instead.
+pub async fn current_account(
+    state: &AppState,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<accounts::Model, Response> {
+    match current_caller(state, headers, jar).await? {
+        (account, None) => Ok(account),
+        (_, Some(_)) => Err(error_response(
+            StatusCode::FORBIDDEN,
+            "insufficient_scope",
+            "this endpoint is outside the token's scopes",
+        )),
+    }
+}
+
+/// Like `current_account`, but also accepts scoped personal access tokens and returns their
+/// scopes. `None` means the caller is not limited by scopes.
+pub(crate) async fn current_caller(
+    state: &AppState,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<(accounts::Model, Option<Vec<String>>), Response> {
+    let Some(token) = bearer_token(headers).filter(|_| jar.get("sid").is_none()) else {
+        return current_session(state, jar)
+            .await
+            .map(|current| (current.account, None));
+    };
+
+    let invalid = || error_response(StatusCode::UNAUTHORIZED, "invalid_token", "invalid token");
+    if token.starts_with(personal_access_tokens::TOKEN_PREFIX) {
+        return match state.personal_access_tokens().authenticate(token).await {
+            Ok(Some((account, info))) => {
+                Ok((account, (!info.scopes.is_empty()).then_some(info.scopes)))
+            }
+            Ok(None) => Err(invalid()),
+            Err(err) => Err(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                err.code,
+                err.message,
+            )),
+        };
+    }
+    let token = match state.jwt().verify_access_token(token).await {
+        Ok(token) if token.client_id.is_none() => token,
+        _ => return Err(invalid()),
+    };
+    let Ok(account_uid) = token.sub.parse() else {
+        return Err(invalid());
+    };
+    match state.accounts().get(account_uid).await {
+        Ok(Some(account)) => Ok((account, None)),
+        Ok(None) => Err(error_response(
+            StatusCode::UNAUTHORIZED,
+            "account_not_found",
+            "account not found",
+        )),
+        Err(err) => Err(error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "db_error",
+            format!("account lookup failed: {}", err),
+        )),
+    }
+}
+
+/// Reduces a `{code, message}` error from the shared helpers to `{message}`.
+async fn me_error(response: Response) -> Response {
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    let message = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    me_error_message(parts.status, message)
+}
+
+fn me_error_message(status: StatusCode, message: String) -> Response {
+    (status, Json(MeErrorResponse { message })).into_response()
+}
+
+async fn me(
+    State(state): State<std::sync::Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> impl IntoResponse {
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) => return me_error(response).await,
+    };
+
+    let settings = match state.account_settings().get(&account).await {
+        Ok(settings) => settings,
+        Err(err) => return me_error_message(StatusCode::INTERNAL_SERVER_ERROR, err.message),
+    };
+    let teams = match state.teams().teams_for(&account).await {
+        Ok(teams) => teams.into_iter().map(Into::into).collect(),
+        Err(err) => return me_error_message(StatusCode::INTERNAL_SERVER_ERROR, err.message),
     };
 
     let response = MeResponse {
         account_uid: account.uid.to_string(),
         username: account.username,
         email: account.email,
+        nickname: settings.nickname,
+        avatar_url: settings.avatar_url,
+        teams,
     };
     (StatusCode::OK, Json(response)).into_response()
 }
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/password",
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed", body = ChangePasswordResponse),
+        (status = 400, description = "Invalid payload or weak password", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 403, description = "Current password is incorrect", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn change_password(
+    State(state): State<std::sync::Arc<AppState>>,
+    jar: CookieJar,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Response {
+    let current = match current_session(&state, &jar).await {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+
+    let input = ChangePasswordInput {
+        account_uid: current.account.uid,
+        current_session_id: &current.session_id,
+        current_password: payload.current_password.as_deref(),
+        new_password: &payload.new_password,
+        sign_out_other_sessions: payload.sign_out_other_sessions,
+    };
+
+    match state.auth().change_password(input).await {
+        Ok(()) => {
+            if payload.sign_out_other_sessions {
+                state
+                    .forward_auth_cache()
+                    .remove_account(current.account.uid);
+            }
+            (
+                StatusCode::OK,
+                Json(ChangePasswordResponse {
+                    status: "ok".to_string(),
+                }),
+            )
+                .into_response()
+        }
+        Err(err) => {
+            let status = match err.code {
+                "invalid_current_password" => StatusCode::FORBIDDEN,
+                "account_not_found" => StatusCode::UNAUTHORIZED,
+                "db_error" | "session_error" | "password_hash_failed" => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+                _ => StatusCode::BAD_REQUEST,
+            };
+            error_response(status, err.code, err.message)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/sessions",
+    responses(
+        (status = 200, description = "Where the account is signed in, most recently active first", body = SessionListResponse),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn list_sessions(
+    State(state): State<std::sync::Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Response {
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    let mut sessions = match state.sessions().list_for_account(account.uid).await {
+        Ok(sessions) => sessions,
+        Err(err) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "session_error",
+                format!("session lookup failed: {}", err),
+            )
+        }
+    };
+    sessions.sort_by_key(|(_, session)| {
+        std::cmp::Reverse(session.last_seen_at.unwrap_or(session.created_at))
+    });
+
+    let current_session_id = jar.get("sid").map(|cookie| cookie.value());
+    let sessions = sessions
+        .into_iter()
+        .map(|(session_id, session)| SessionResponse {
+            id: public_session_id(&session_id),
+            current: current_session_id == Some(session_id.as_str()),
+            ip: session.ip,
+            user_agent: session.user_agent,
+            login_method: session.login_method,
+            created_at: session.created_at.to_rfc3339(),
+            last_seen_at: session.last_seen_at.map(|value| value.to_rfc3339()),
+        })
+        .collect();
+    (StatusCode::OK, Json(SessionListResponse { sessions })).into_response()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/sessions/{session_id}",
+    params(("session_id" = String, Path, description = "Session id from the session list")),
+    responses(
+        (status = 204, description = "Session signed out"),
+        (status = 401, description = "Missing or invalid session", body = ErrorResponse),
+        (status = 404, description = "Session not found", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn revoke_session(
+    State(state): State<std::sync::Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> Response {
+    let account = match current_account(&state, &headers, &jar).await {
+        Ok(account) => account,
+        Err(response) => return response,
+    };
+    let sessions = match state.sessions().list_for_account(account.uid).await {
+        Ok(sessions) => sessions,
+        Err(err) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "session_error",
+                format!("session lookup failed: {}", err),
+            )
+        }
+    };
+    let Some((session_id, _)) = sessions
+        .into_iter()
+        .find(|(session_id, _)| public_session_id(session_id) == id)
+    else {
+        return error_response(StatusCode::NOT_FOUND, "not_found", "session not found");
+    };
+
+    if let Err(err) = state.sessions().delete(&session_id).await {
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "session_error",
+            err.to_string(),
+        );
+    }
+    state.forward_auth_cache().remove(&session_id);
+
+    // Signing out the current session from the list behaves like a logout.
+    if jar
+        .get("sid")
+        .is_some_and(|cookie| cookie.value() == session_id)
+    {
+        let jar = jar.add(super::auth::cleared_session_cookie(state.config().values()));
+        return (StatusCode::NO_CONTENT, jar).into_response();
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn signing_out_on_password_change_revokes_tokens() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let body = json!({ "name": "ci" });
+        let response = send(&app, "POST", "/api/v1/me/tokens", Some(&cookie), Some(body)).await;
+        let token = json_body(response).await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let response = send_bearer(&app, "GET", "/api/v1/auth/verify", &token, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = json!({
+            "current_password": PASSWORD,
+            "new_password": "Battery-Staple-8",
+            "sign_out_other_sessions": true,
+        });
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/me/password",
+            Some(&cookie),
+            Some(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send_bearer(&app, "GET", "/api/v1/auth/verify", &token, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send_bearer(&app, "GET", "/api/v1/me", &token, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn me_errors_keep_their_original_shape() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+
+        let response = send(&app, "GET", "/api/v1/me", None, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            json_body(response).await,
+            json!({ "message": "missing session" })
+        );
+        let response = send(&app, "GET", "/api/v1/me", Some("sid=stale"), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            json_body(response).await,
+            json!({ "message": "invalid session" })
+        );
+    }
+
+    #[tokio::test]
+    async fn changing_the_password_needs_the_current_one() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+        let change = |body| {
+            send(
+                &app,
+                "POST",
+                "/api/v1/me/password",
+                Some(&cookie),
+                Some(body),
+            )
+        };
+
+        let response = change(json!({ "new_password": "Battery-Staple-9" })).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            json_body(response).await["code"],
+            "current_password_required"
+        );
+        let body =
+            json!({ "current_password": "Wrong-Horse-7", "new_password": "Battery-Staple-9" });
+        let response = change(body).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            json_body(response).await["code"],
+            "invalid_current_password"
+        );
+        assert_eq!(
+            login(&app, "ada@example.com").await.status(),
+            StatusCode::OK
+        );
+
+        let body = json!({ "current_password": PASSWORD, "new_password": "Battery-Staple-9" });
+        assert_eq!(change(body).await.status(), StatusCode::OK);
+        let response = login(&app, "ada@example.com").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = json!({ "identifier": "ada@example.com", "password": "Battery-Staple-9" });
+        let response = send(&app, "POST", "/api/v1/auth/login", None, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    fn set_cookie(response: &axum::response::Response) -> String {
+        response.headers()[axum::http::header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_expire_unless_remembered() {
+        let state = AppState::in_memory_with(|config| {
+            config.session_idle_ttl_seconds = 2;
+            config.session_ttl_seconds = 3600;
+            config.session_remember_me_idle_ttl_seconds = 3600;
+            config.session_remember_me_ttl_seconds = 7200;
+        })
+        .await;
+        let app = router(state.clone());
+        register(&app, "ada@example.com").await;
+        verify_email(&state, &app, "ada@example.com").await;
+
+        let body =
+            json!({ "identifier": "ada@example.com", "password": PASSWORD, "remember_me": true });
+        let response = send(&app, "POST", "/api/v1/auth/login", None, Some(body)).await;
+        assert!(set_cookie(&response).contains("Max-Age=7200"));
+        let remembered = session_cookie(&response);
+        let response = login(&app, "ada@example.com").await;
+        assert!(set_cookie(&response).contains("Max-Age=3600"));
+        let cookie = session_cookie(&response);
+
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&app, "GET", "/api/v1/me", Some(&remembered), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn active_sessions_still_end_at_their_absolute_lifetime() {
+        let state = AppState::in_memory_with(|config| {
+            config.session_idle_ttl_seconds = 3600;
+            config.session_ttl_seconds = 2;
+        })
+        .await;
+        let app = router(state.clone());
+        let cookie = signed_in(&state, &app, "ada@example.com").await;
+
+        for _ in 0..2 {
+            let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        let response = send(&app, "GET", "/api/v1/me", Some(&cookie), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
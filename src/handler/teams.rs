@@ -0,0 +1,541 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, patch, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    handler::{
+        accounts::AccountResponse,
+        auth::password::{error_response, ErrorResponse},
+        principal::Principal,
+    },
+    service::{
+        rbac::PERMISSION_ACCOUNTS_READ,
+        teams::{
+            CreateTeamInput, TeamInvite, TeamMember, TeamSummary, TeamsError, TEAM_ROLE_MEMBER,
+        },
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTeamRequest {
+    pub username: String,
+    pub email: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct InviteMemberRequest {
+    pub email: String,
+    /// `owner`, `admin` or `member` (the default). Only owners may invite owners.
+    pub role: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct InviteTokenRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangeMemberRoleRequest {
+    pub role: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamSummaryResponse {
+    pub uid: Uuid,
+    pub username: Option<String>,
+    /// The caller's role in the team.
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamMemberResponse {
+    pub account_uid: Uuid,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamMemberListResponse {
+    pub members: Vec<TeamMemberResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamInviteResponse {
+    pub id: i64,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamInviteListResponse {
+    pub invites: Vec<TeamInviteResponse>,
+}
+
+impl From<TeamSummary> for TeamSummaryResponse {
+    fn from(team: TeamSummary) -> Self {
+        Self {
+            uid: team.uid,
+            username: team.username,
+            role: team.role,
+            joined_at: team.joined_at,
+        }
+    }
+}
+
+impl From<TeamMember> for TeamMemberResponse {
+    fn from(member: TeamMember) -> Self {
+        Self {
+            account_uid: member.account_uid,
+            username: member.username,
+            email: member.email,
+            role: member.role,
+            joined_at: member.joined_at,
+        }
+    }
+}
+
+impl From<TeamInvite> for TeamInviteResponse {
+    fn from(invite: TeamInvite) -> Self {
+        Self {
+            id: invite.id,
+            email: invite.email,
+            role: invite.role,
+            invited_by: invite.invited_by,
+            expires_at: invite.expires_at,
+        }
+    }
+}
+
+fn teams_error_response(err: TeamsError) -> Response {
+    let status = match err.code {
+        "not_found" => StatusCode::NOT_FOUND,
+        "forbidden" | "email_mismatch" | "email_unverified" => StatusCode::FORBIDDEN,
+        "last_owner" | "already_member" | "username_taken" => StatusCode::CONFLICT,
+        "db_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    error_response(status, err.code, err.message)
+}
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/v1/teams", post(create_team))
+        .route("/api/v1/teams/:uid/members", get(list_members))
+        .route(
+            "/api/v1/teams/:uid/members/:member_uid",
+            patch(change_member_role).delete(remove_member),
+        )
+        .route(
+            "/api/v1/teams/:uid/invites",
+            get(list_invites).post(invite_member),
+        )
+        .route("/api/v1/team-invites/accept", post(accept_invite))
+        .route("/api/v1/team-invites/decline", post(decline_invite))
+        .with_state(state)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/teams",
+    request_body = CreateTeamRequest,
+    responses(
+        (status = 201, description = "Team created with the caller as owner", body = AccountResponse),
+        (status = 400, description = "Invalid payload", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 409, description = "Username taken", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn create_team(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Json(payload): Json<CreateTeamRequest>,
+) -> Response {
+    let input = CreateTeamInput {
+        username: payload.username,
+        email: payload.email,
+    };
+    match state.teams().create_team(&principal.account, input).await {
+        Ok(team) => (StatusCode::CREATED, Json(AccountResponse::from(team))).into_response(),
+        Err(err) => teams_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/teams/{uid}/members",
+    params(("uid" = Uuid, Path, description = "Team account uid")),
+    responses(
+        (status = 200, description = "Members of the team", body = TeamMemberListResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 404, description = "Not found, or the caller is not a member", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn list_members(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(uid): Path<Uuid>,
+) -> Response {
+    let read_any = principal.has_permission(PERMISSION_ACCOUNTS_READ);
+    match state
+        .teams()
+        .list_members(uid, &principal.account, read_any)
+        .await
+    {
+        Ok(members) => Json(TeamMemberListResponse {
+            members: members.into_iter().map(Into::into).collect(),
+        })
+        .into_response(),
+        Err(err) => teams_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/teams/{uid}/members/{member_uid}",
+    request_body = ChangeMemberRoleRequest,
+    params(
+        ("uid" = Uuid, Path, description = "Team account uid"),
+        ("member_uid" = Uuid, Path, description = "Member account uid")
+    ),
+    responses(
+        (status = 200, description = "Role changed", body = TeamMemberResponse),
+        (status = 400, description = "Invalid role", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Not allowed to assign this role", body = ErrorResponse),
+        (status = 404, description = "Team or member not found", body = ErrorResponse),
+        (status = 409, description = "The team would be left without an owner", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn change_member_role(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path((uid, member_uid)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<ChangeMemberRoleRequest>,
+) -> Response {
+    match state
+        .teams()
+        .change_role(uid, &principal.account, member_uid, &payload.role)
+        .await
+    {
+        Ok(member) => Json(TeamMemberResponse::from(member)).into_response(),
+        Err(err) => teams_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/teams/{uid}/members/{member_uid}",
+    params(
+        ("uid" = Uuid, Path, description = "Team account uid"),
+        ("member_uid" = Uuid, Path, description = "Member account uid; your own to leave the team")
+    ),
+    responses(
+        (status = 204, description = "Member removed"),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Not allowed to remove this member", body = ErrorResponse),
+        (status = 404, description = "Team or member not found", body = ErrorResponse),
+        (status = 409, description = "The team would be left without an owner", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path((uid, member_uid)): Path<(Uuid, Uuid)>,
+) -> Response {
+    match state
+        .teams()
+        .remove_member(uid, &principal.account, member_uid)
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => teams_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/teams/{uid}/invites",
+    params(("uid" = Uuid, Path, description = "Team account uid")),
+    responses(
+        (status = 200, description = "Pending invitations", body = TeamInviteListResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Only owners and admins see invitations", body = ErrorResponse),
+        (status = 404, description = "Not found, or the caller is not a member", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn list_invites(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(uid): Path<Uuid>,
+) -> Response {
+    match state.teams().list_invites(uid, &principal.account).await {
+        Ok(invites) => Json(TeamInviteListResponse {
+            invites: invites.into_iter().map(Into::into).collect(),
+        })
+        .into_response(),
+        Err(err) => teams_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/teams/{uid}/invites",
+    request_body = InviteMemberRequest,
+    params(("uid" = Uuid, Path, description = "Team account uid")),
+    responses(
+        (status = 201, description = "Invitation emailed", body = TeamInviteResponse),
+        (status = 400, description = "Invalid email or role", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "Not allowed to invite with this role", body = ErrorResponse),
+        (status = 404, description = "Not found, or the caller is not a member", body = ErrorResponse),
+        (status = 409, description = "Already a member", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn invite_member(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(uid): Path<Uuid>,
+    Json(payload): Json<InviteMemberRequest>,
+) -> Response {
+    let role = payload.role.as_deref().unwrap_or(TEAM_ROLE_MEMBER);
+    let (team, token, invite) = match state
+        .teams()
+        .invite(uid, &principal.account, &payload.email, role)
+        .await
+    {
+        Ok(output) => output,
+        Err(err) => return teams_error_response(err),
+    };
+
+    // Best-effort, as for verification emails; the invitation can be sent again.
+    let team_name = team.username.as_deref().unwrap_or("a team");
+    if let Err(err) = crate::service::email::try_send_team_invite_email(
+        state.config().values(),
+        &invite.email,
+        team_name,
+        &token,
+    )
+    .await
+    {
+        eprintln!("warning: failed to send team invite email: {}", err);
+    }
+
+    (StatusCode::CREATED, Json(TeamInviteResponse::from(invite))).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/team-invites/accept",
+    request_body = InviteTokenRequest,
+    responses(
+        (status = 200, description = "Joined the team", body = TeamSummaryResponse),
+        (status = 400, description = "Invalid or expired invitation", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid session or token", body = ErrorResponse),
+        (status = 403, description = "The invitation is for another email, or the email is not verified", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn accept_invite(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Json(payload): Json<InviteTokenRequest>,
+) -> Response {
+    match state
+        .teams()
+        .accept_invite(&principal.account, &payload.token)
+        .await
+    {
+        Ok(team) => Json(TeamSummaryResponse::from(team)).into_response(),
+        Err(err) => teams_error_response(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/team-invites/decline",
+    request_body = InviteTokenRequest,
+    responses(
+        (status = 204, description = "Invitation declined"),
+        (status = 400, description = "Invalid or expired invitation", body = ErrorResponse)
+    ),
+    tag = "accounts"
+)]
+pub async fn decline_invite(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<InviteTokenRequest>,
+) -> Response {
+    match state.teams().decline_invite(&payload.token).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => teams_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{router, test_support::*};
+    use crate::state::AppState;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn teams_keep_an_owner() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        for email in ["ada@example.com", "grace@example.com"] {
+            register(&app, email).await;
+            verify_email(&state, &app, email).await;
+        }
+        let ada_cookie = session_cookie(&login(&app, "ada@example.com").await);
+        let grace_cookie = session_cookie(&login(&app, "grace@example.com").await);
+
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/teams",
+            Some(&ada_cookie),
+            Some(json!({ "username": "analytical-engines" })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let team_uid = json_body(response).await["uid"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let members_uri = format!("/api/v1/teams/{}/members", team_uid);
+        let response = send(&app, "GET", &members_uri, Some(&grace_cookie), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // The token only travels by email, so take it from the service.
+        let ada = state
+            .accounts_repo()
+            .find_by_email("ada@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, token, _) = state
+            .teams()
+            .invite(
+                team_uid.parse().unwrap(),
+                &ada,
+                "grace@example.com",
+                "admin",
+            )
+            .await
+            .unwrap();
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/team-invites/accept",
+            Some(&grace_cookie),
+            Some(json!({ "token": token })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, "GET", "/api/v1/me", Some(&grace_cookie), None).await;
+        let me = json_body(response).await;
+        assert_eq!(me["teams"][0]["uid"], team_uid.as_str());
+        assert_eq!(me["teams"][0]["role"], "admin");
+
+        // Admins cannot touch owners, and the last owner can neither step down nor leave.
+        let ada_uri = format!("{}/{}", members_uri, ada.uid);
+        let response = send(
+            &app,
+            "PATCH",
+            &ada_uri,
+            Some(&grace_cookie),
+            Some(json!({ "role": "member" })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(
+            &app,
+            "PATCH",
+            &ada_uri,
+            Some(&ada_cookie),
+            Some(json!({ "role": "member" })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = send(&app, "DELETE", &ada_uri, Some(&ada_cookie), None).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = send(&app, "GET", &members_uri, Some(&grace_cookie), None).await;
+        assert_eq!(
+            json_body(response).await["members"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn invites_need_a_verified_email() {
+        let state = AppState::in_memory().await;
+        let app = router(state.clone());
+        let ada_cookie = signed_in(&state, &app, "ada@example.com").await;
+        let mallory_cookie = signed_in(&state, &app, "mallory@example.com").await;
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/teams",
+            Some(&ada_cookie),
+            Some(json!({ "username": "analytical-engines" })),
+        )
+        .await;
+        let team_uid = json_body(response).await["uid"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let ada = account(&state, "ada@example.com").await;
+        let (_, token, _) = state
+            .teams()
+            .invite(
+                team_uid.parse().unwrap(),
+                &ada,
+                "grace@example.com",
+                "member",
+            )
+            .await
+            .unwrap();
+
+        // Claiming the invited address is not enough without following its verification link.
+        let mallory = account(&state, "mallory@example.com").await;
+        let uri = format!("/api/v1/accounts/{}", mallory.uid);
+        let body = json!({ "email": "grace@example.com" });
+        let response = send(&app, "PATCH", &uri, Some(&mallory_cookie), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json!({ "token": token });
+        let response = send(
+            &app,
+            "POST",
+            "/api/v1/team-invites/accept",
+            Some(&mallory_cookie),
+            Some(body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json_body(response).await["code"], "email_unverified");
+    }
+}
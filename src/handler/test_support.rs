@@ -0,0 +1,123 @@
+//! Request helpers shared by the in-process handler tests.
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, request, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use crate::{entities::accounts, service::verification::TOKEN_TYPE_VERIFY_EMAIL, state::AppState};
+
+pub const PASSWORD: &str = "Correct-Horse-7";
+
+pub async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    cookie: Option<&str>,
+    body: Option<Value>,
+) -> Response {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    dispatch(app, request, body).await
+}
+
+/// Like `send`, authenticating with `Authorization: Bearer <token>` instead of a cookie.
+pub async fn send_bearer(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    token: &str,
+    body: Option<Value>,
+) -> Response {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token));
+    dispatch(app, request, body).await
+}
+
+async fn dispatch(app: &Router, mut request: request::Builder, body: Option<Value>) -> Response {
+    let body = match body {
+        Some(body) => {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    app.clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap()
+}
+
+pub async fn json_body(response: Response) -> Value {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+/// The `name=value` pair of the response's `Set-Cookie` header for `name`.
+pub fn cookie(response: &Response, name: &str) -> String {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| value.split(';').next())
+        .find(|pair| pair.starts_with(&format!("{}=", name)))
+        .unwrap_or_else(|| panic!("no {} cookie set", name))
+        .to_string()
+}
+
+/// The `sid=...` pair from the response's `Set-Cookie` header.
+pub fn session_cookie(response: &Response) -> String {
+    cookie(response, "sid")
+}
+
+pub async fn register(app: &Router, email: &str) {
+    let body = json!({ "email": email, "password": PASSWORD });
+    let response = send(app, "POST", "/api/v1/auth/register", None, Some(body)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+pub async fn account(state: &AppState, email: &str) -> accounts::Model {
+    state
+        .accounts_repo()
+        .find_by_email(email)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+/// Verifies the email with a freshly issued token, since the one issued at registration
+/// only leaves the service by email.
+pub async fn verify_email(state: &AppState, app: &Router, email: &str) {
+    let account = account(state, email).await;
+    let token = state
+        .verification()
+        .create_token(account.id, TOKEN_TYPE_VERIFY_EMAIL)
+        .await
+        .unwrap();
+    let body = json!({ "token": token.token });
+    let response = send(app, "POST", "/api/v1/auth/verify-email", None, Some(body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+pub async fn login(app: &Router, email: &str) -> Response {
+    let body = json!({ "identifier": email, "password": PASSWORD });
+    send(app, "POST", "/api/v1/auth/login", None, Some(body)).await
+}
+
+/// Registers a verified account and returns the cookie of a fresh session.
+pub async fn signed_in(state: &AppState, app: &Router, email: &str) -> String {
+    register(app, email).await;
+    verify_email(state, app, email).await;
+    let response = login(app, email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    session_cookie(&response)
+}
@@ -0,0 +1,105 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+
+use crate::{
+    handler::auth::password::{error_response, ErrorResponse},
+    state::AppState,
+};
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/.well-known/jwks.json", get(jwks))
+        .route(
+            "/.well-known/openid-configuration",
+            get(openid_configuration),
+        )
+        .with_state(state)
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "Public keys for verifying access tokens (RFC 7517 JWK Set)", body = Object),
+        (status = 500, description = "Signing keys could not be loaded", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+pub async fn jwks(State(state): State<Arc<AppState>>) -> Response {
+    match state.jwt().jwks().await {
+        // Short cache so verifiers pick up a rotated key well before tokens signed with it arrive.
+        Ok(keys) => (
+            StatusCode::OK,
+            [(header::CACHE_CONTROL, "public, max-age=300")],
+            Json(keys),
+        )
+            .into_response(),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err.code, err.message),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/openid-configuration",
+    responses(
+        (status = 200, description = "OpenID Connect discovery document", body = Object),
+        (status = 500, description = "Signing keys could not be loaded", body = ErrorResponse)
+    ),
+    tag = "oauth"
+)]
+pub async fn openid_configuration(State(state): State<Arc<AppState>>) -> Response {
+    // Endpoints hang off the issuer, so JWT_ISSUER must be this service's public base URL.
+    let issuer = state
+        .config()
+        .values()
+        .jwt_issuer
+        .trim_end_matches('/')
+        .to_string();
+    let keys = match state.jwt().jwks().await {
+        Ok(keys) => keys,
+        Err(err) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.code, err.message)
+        }
+    };
+    let mut algorithms: Vec<&str> = Vec::new();
+    for alg in keys["keys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|key| key["alg"].as_str())
+    {
+        if !algorithms.contains(&alg) {
+            algorithms.push(alg);
+        }
+    }
+
+    let document = serde_json::json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/oauth/authorize", issuer),
+        "token_endpoint": format!("{}/oauth/token", issuer),
+        "userinfo_endpoint": format!("{}/oauth/userinfo", issuer),
+        "jwks_uri": format!("{}/.well-known/jwks.json", issuer),
+        "introspection_endpoint": format!("{}/oauth/introspect", issuer),
+        "revocation_endpoint": format!("{}/oauth/revoke", issuer),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token", "client_credentials"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": algorithms,
+        "scopes_supported": ["openid", "profile", "email"],
+        "claims_supported": ["sub", "iss", "aud", "exp", "iat", "nonce", "preferred_username", "email", "email_verified"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+    });
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(document),
+    )
+        .into_response()
+}
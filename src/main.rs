@@ -1,4 +1,3 @@
-use axum::Router;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 use utoipa::OpenApi;
@@ -24,12 +23,7 @@ async fn main() {
     let _ = state.accounts_repo();
     let _ = state.config().values();
 
-    let app = Router::new()
-        .merge(handler::health::routes())
-        .merge(handler::accounts::routes(state.clone()))
-        .merge(handler::auth::github::routes(state.clone()))
-        .merge(handler::auth::password::routes(state.clone()))
-        .merge(handler::session::routes(state.clone()))
+    let app = handler::router(state.clone())
         .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", ApiDoc::openapi()));
     let addr = SocketAddr::from(([0, 0, 0, 0], state.config().port()));
 
@@ -3,12 +3,43 @@ use utoipa::OpenApi;
 use crate::{
     handler,
     handler::{
-        accounts::{AccountResponse, CreateAccount, UpdateAccount},
+        account_settings::{AccountSettingsResponse, UpdateAccountSettingsRequest},
+        accounts::{AccountListResponse, AccountResponse, CreateAccount, UpdateAccount},
+        auth::credentials::{CredentialListResponse, CredentialResponse, LinkProviderResponse},
+        auth::mfa::{MfaVerifyRequest, TotpCodeRequest, TotpConfirmResponse, TotpEnrollResponse},
+        auth::oidc::OidcAuthResponse,
         auth::password::{
-            ErrorResponse, LoginRequest, LoginResponse, RegisterRequest, RegisterResponse,
-            VerifyEmailRequest, VerifyEmailResponse,
+            AccessTokenResponse, ErrorResponse, ForgotPasswordRequest, ForgotPasswordResponse,
+            LoginRequest, LoginResponse, MfaChallengeResponse, RegisterRequest, RegisterResponse,
+            ResetPasswordRequest, ResetPasswordResponse, VerifyEmailRequest, VerifyEmailResponse,
         },
+        auth::token::RefreshTokenRequest,
+        auth::webauthn::{
+            AssertionCredentialJson, AssertionResponseJson, AttestationResponseJson,
+            RegistrationCredentialJson, WebauthnLoginFinishRequest, WebauthnLoginStartRequest,
+            WebauthnRegisterFinishRequest, WebauthnRegisterFinishResponse, WebauthnStartResponse,
+        },
+        avatars::{AvatarResponse, AvatarThumbnailResponse, AvatarUploadForm},
         health::Health,
+        oauth::{
+            ConsentDecisionRequest, ConsentDecisionResponse, ConsentResponse,
+            CreateOauthClientRequest, IntrospectionResponse, OauthClientListResponse,
+            OauthClientResponse, OauthErrorResponse, TokenLookupRequest, TokenRequest,
+            TokenResponse, UserinfoResponse,
+        },
+        personal_access_tokens::{
+            CreatePersonalAccessTokenRequest, PersonalAccessTokenListResponse,
+            PersonalAccessTokenResponse,
+        },
+        roles::{RoleListResponse, RoleResponse},
+        session::{
+            ChangePasswordRequest, ChangePasswordResponse, SessionListResponse, SessionResponse,
+        },
+        teams::{
+            ChangeMemberRoleRequest, CreateTeamRequest, InviteMemberRequest, InviteTokenRequest,
+            TeamInviteListResponse, TeamInviteResponse, TeamMemberListResponse, TeamMemberResponse,
+            TeamSummaryResponse,
+        },
     },
 };
 
@@ -16,32 +47,148 @@ use crate::{
 #[openapi(
     paths(
         handler::health::health,
+        handler::accounts::list_accounts,
         handler::accounts::create_account,
         handler::accounts::get_account,
         handler::accounts::update_account,
         handler::accounts::delete_account,
+        handler::account_settings::get_my_settings,
+        handler::account_settings::update_my_settings,
+        handler::account_settings::get_account_settings,
+        handler::account_settings::update_account_settings,
+        handler::avatars::upload_avatar,
+        handler::avatars::get_avatar,
+        handler::roles::list_roles,
+        handler::roles::list_account_roles,
+        handler::roles::grant_role,
+        handler::roles::revoke_role,
+        handler::teams::create_team,
+        handler::teams::list_members,
+        handler::teams::change_member_role,
+        handler::teams::remove_member,
+        handler::teams::list_invites,
+        handler::teams::invite_member,
+        handler::teams::accept_invite,
+        handler::teams::decline_invite,
         handler::auth::password::register,
         handler::auth::password::login,
         handler::auth::password::logout,
-        handler::auth::password::verify_email
+        handler::auth::password::logout_all,
+        handler::auth::password::verify_email,
+        handler::auth::password::forgot_password,
+        handler::auth::password::reset_password,
+        handler::session::change_password,
+        handler::session::list_sessions,
+        handler::session::revoke_session,
+        handler::personal_access_tokens::list_tokens,
+        handler::personal_access_tokens::create_token,
+        handler::personal_access_tokens::revoke_token,
+        handler::auth::mfa::verify_mfa,
+        handler::auth::mfa::enroll_totp,
+        handler::auth::mfa::confirm_totp,
+        handler::auth::mfa::disable_totp,
+        handler::auth::webauthn::start_registration,
+        handler::auth::webauthn::finish_registration,
+        handler::auth::webauthn::start_login,
+        handler::auth::webauthn::finish_login,
+        handler::auth::oidc::start_oidc_auth,
+        handler::auth::oidc::start_oidc_link,
+        handler::auth::oidc::oidc_callback,
+        handler::auth::credentials::list_credentials,
+        handler::auth::credentials::unlink_credential,
+        handler::auth::token::refresh_token,
+        handler::auth::verify::verify,
+        handler::well_known::jwks,
+        handler::well_known::openid_configuration,
+        handler::oauth::authorize,
+        handler::oauth::token,
+        handler::oauth::userinfo,
+        handler::oauth::introspect,
+        handler::oauth::revoke,
+        handler::oauth::get_consent,
+        handler::oauth::decide_consent,
+        handler::oauth::create_client,
+        handler::oauth::list_clients,
+        handler::oauth::delete_client
     ),
     components(schemas(
         Health,
         CreateAccount,
         UpdateAccount,
         AccountResponse,
+        AccountListResponse,
+        RoleResponse,
+        RoleListResponse,
+        AccountSettingsResponse,
+        UpdateAccountSettingsRequest,
+        AvatarUploadForm,
+        AvatarThumbnailResponse,
+        AvatarResponse,
+        CreateTeamRequest,
+        InviteMemberRequest,
+        InviteTokenRequest,
+        ChangeMemberRoleRequest,
+        TeamSummaryResponse,
+        TeamMemberResponse,
+        TeamMemberListResponse,
+        TeamInviteResponse,
+        TeamInviteListResponse,
         RegisterRequest,
         RegisterResponse,
         LoginRequest,
         LoginResponse,
+        AccessTokenResponse,
+        RefreshTokenRequest,
+        MfaChallengeResponse,
         VerifyEmailRequest,
         VerifyEmailResponse,
+        ForgotPasswordRequest,
+        ForgotPasswordResponse,
+        ResetPasswordRequest,
+        ResetPasswordResponse,
+        ChangePasswordRequest,
+        ChangePasswordResponse,
+        SessionResponse,
+        SessionListResponse,
+        CreatePersonalAccessTokenRequest,
+        PersonalAccessTokenResponse,
+        PersonalAccessTokenListResponse,
+        MfaVerifyRequest,
+        TotpEnrollResponse,
+        TotpCodeRequest,
+        TotpConfirmResponse,
+        WebauthnStartResponse,
+        AttestationResponseJson,
+        RegistrationCredentialJson,
+        WebauthnRegisterFinishRequest,
+        WebauthnRegisterFinishResponse,
+        WebauthnLoginStartRequest,
+        AssertionResponseJson,
+        AssertionCredentialJson,
+        WebauthnLoginFinishRequest,
+        OidcAuthResponse,
+        CredentialResponse,
+        CredentialListResponse,
+        LinkProviderResponse,
+        TokenRequest,
+        TokenResponse,
+        UserinfoResponse,
+        TokenLookupRequest,
+        IntrospectionResponse,
+        OauthErrorResponse,
+        ConsentResponse,
+        ConsentDecisionRequest,
+        ConsentDecisionResponse,
+        CreateOauthClientRequest,
+        OauthClientResponse,
+        OauthClientListResponse,
         ErrorResponse
     )),
     tags(
         (name = "health", description = "Health check"),
         (name = "accounts", description = "Accounts"),
-        (name = "auth", description = "Authentication")
+        (name = "auth", description = "Authentication"),
+        (name = "oauth", description = "OAuth 2.0 authorization server")
     )
 )]
 pub struct ApiDoc;
@@ -1,8 +1,10 @@
 use async_trait::async_trait;
 use chrono::Utc;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, EntityTrait, QueryFilter, Set,
+    prelude::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, EntityTrait,
+    QueryFilter, QueryOrder, Set, UpdateMany,
 };
+use uuid::Uuid;
 
 use crate::{entities::account_authorizations, state::DatabaseClient};
 
@@ -12,7 +14,6 @@ pub trait AccountAuthorizationsRepo: Send + Sync {
         &self,
         model: account_authorizations::ActiveModel,
     ) -> Result<account_authorizations::Model, sea_orm::DbErr>;
-    #[allow(dead_code)]
     async fn insert_with_txn(
         &self,
         txn: &DatabaseTransaction,
@@ -27,7 +28,52 @@ pub trait AccountAuthorizationsRepo: Send + Sync {
         account_id: i64,
         token_type: &str,
     ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr>;
+    /// Every active token of `token_type` on the account, newest first.
+    async fn list_active_by_account_and_type(
+        &self,
+        account_id: i64,
+        token_type: &str,
+    ) -> Result<Vec<account_authorizations::Model>, sea_orm::DbErr>;
     async fn revoke_by_id(&self, id: i64) -> Result<account_authorizations::Model, sea_orm::DbErr>;
+    /// Looks up a token regardless of whether it was revoked or has expired.
+    async fn find_by_token_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr>;
+    /// Revokes the row only if it is still unrevoked; returns whether this call revoked it.
+    async fn revoke_if_active(&self, id: i64) -> Result<bool, sea_orm::DbErr>;
+    async fn revoke_if_active_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        id: i64,
+    ) -> Result<bool, sea_orm::DbErr>;
+    async fn revoke_family(&self, family_id: Uuid) -> Result<u64, sea_orm::DbErr>;
+    /// Whether the family holds a token of `token_type` issued after row `id`, i.e. whether
+    /// that row was rotated rather than revoked where it stood.
+    async fn has_successor(
+        &self,
+        family_id: Uuid,
+        token_type: &str,
+        id: i64,
+    ) -> Result<bool, sea_orm::DbErr>;
+    /// Revokes every unrevoked token of `token_type` on the account; returns how many there were.
+    async fn revoke_all_for_account(
+        &self,
+        account_id: i64,
+        token_type: &str,
+    ) -> Result<u64, sea_orm::DbErr>;
+    async fn find_by_id(
+        &self,
+        id: i64,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr>;
+    /// Active tokens of `token_type` that belong to `account_id` or were created by `created_by`.
+    async fn list_active_by_type_for_owner(
+        &self,
+        account_id: i64,
+        created_by: Uuid,
+        token_type: &str,
+    ) -> Result<Vec<account_authorizations::Model>, sea_orm::DbErr>;
+    async fn touch_last_used(&self, id: i64) -> Result<(), sea_orm::DbErr>;
 }
 
 pub struct SeaOrmAccountAuthorizationsRepo {
@@ -50,6 +96,21 @@ impl SeaOrmAccountAuthorizationsRepo {
                     .add(account_authorizations::Column::ExpiresAt.gt(now)),
             )
     }
+
+    fn revoke_unrevoked(id: i64) -> UpdateMany<account_authorizations::Entity> {
+        let now = Utc::now();
+        account_authorizations::Entity::update_many()
+            .col_expr(
+                account_authorizations::Column::RevokedAt,
+                Expr::value(sea_orm::Value::from(now)),
+            )
+            .col_expr(
+                account_authorizations::Column::UpdatedAt,
+                Expr::value(sea_orm::Value::from(now)),
+            )
+            .filter(account_authorizations::Column::Id.eq(id))
+            .filter(account_authorizations::Column::RevokedAt.is_null())
+    }
 }
 
 #[async_trait]
@@ -93,6 +154,20 @@ impl AccountAuthorizationsRepo for SeaOrmAccountAuthorizationsRepo {
             .await
     }
 
+    async fn list_active_by_account_and_type(
+        &self,
+        account_id: i64,
+        token_type: &str,
+    ) -> Result<Vec<account_authorizations::Model>, sea_orm::DbErr> {
+        account_authorizations::Entity::find()
+            .filter(account_authorizations::Column::AccountId.eq(account_id))
+            .filter(account_authorizations::Column::TokenType.eq(token_type))
+            .filter(Self::active_condition())
+            .order_by_desc(account_authorizations::Column::CreatedAt)
+            .all(self.db.conn())
+            .await
+    }
+
     async fn revoke_by_id(&self, id: i64) -> Result<account_authorizations::Model, sea_orm::DbErr> {
         let Some(model) = account_authorizations::Entity::find_by_id(id)
             .one(self.db.conn())
@@ -107,4 +182,332 @@ impl AccountAuthorizationsRepo for SeaOrmAccountAuthorizationsRepo {
         active.revoked_at = Set(Some(chrono::Utc::now().into()));
         active.update(self.db.conn()).await
     }
+
+    async fn find_by_token_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr> {
+        account_authorizations::Entity::find()
+            .filter(account_authorizations::Column::TokenHash.eq(token_hash))
+            .filter(account_authorizations::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn revoke_if_active(&self, id: i64) -> Result<bool, sea_orm::DbErr> {
+        let result = Self::revoke_unrevoked(id).exec(self.db.conn()).await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn revoke_if_active_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        id: i64,
+    ) -> Result<bool, sea_orm::DbErr> {
+        let result = Self::revoke_unrevoked(id).exec(txn).await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<u64, sea_orm::DbErr> {
+        let now = Utc::now();
+        let result = account_authorizations::Entity::update_many()
+            .col_expr(
+                account_authorizations::Column::RevokedAt,
+                Expr::value(sea_orm::Value::from(now)),
+            )
+            .col_expr(
+                account_authorizations::Column::UpdatedAt,
+                Expr::value(sea_orm::Value::from(now)),
+            )
+            .filter(account_authorizations::Column::FamilyId.eq(family_id))
+            .filter(account_authorizations::Column::RevokedAt.is_null())
+            .exec(self.db.conn())
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    async fn has_successor(
+        &self,
+        family_id: Uuid,
+        token_type: &str,
+        id: i64,
+    ) -> Result<bool, sea_orm::DbErr> {
+        let successor = account_authorizations::Entity::find()
+            .filter(account_authorizations::Column::FamilyId.eq(family_id))
+            .filter(account_authorizations::Column::TokenType.eq(token_type))
+            .filter(account_authorizations::Column::Id.gt(id))
+            .one(self.db.conn())
+            .await?;
+        Ok(successor.is_some())
+    }
+
+    async fn revoke_all_for_account(
+        &self,
+        account_id: i64,
+        token_type: &str,
+    ) -> Result<u64, sea_orm::DbErr> {
+        let now = Utc::now();
+        let result = account_authorizations::Entity::update_many()
+            .col_expr(
+                account_authorizations::Column::RevokedAt,
+                Expr::value(sea_orm::Value::from(now)),
+            )
+            .col_expr(
+                account_authorizations::Column::UpdatedAt,
+                Expr::value(sea_orm::Value::from(now)),
+            )
+            .filter(account_authorizations::Column::AccountId.eq(account_id))
+            .filter(account_authorizations::Column::TokenType.eq(token_type))
+            .filter(account_authorizations::Column::RevokedAt.is_null())
+            .exec(self.db.conn())
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    async fn find_by_id(
+        &self,
+        id: i64,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr> {
+        account_authorizations::Entity::find_by_id(id)
+            .filter(account_authorizations::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn list_active_by_type_for_owner(
+        &self,
+        account_id: i64,
+        created_by: Uuid,
+        token_type: &str,
+    ) -> Result<Vec<account_authorizations::Model>, sea_orm::DbErr> {
+        account_authorizations::Entity::find()
+            .filter(account_authorizations::Column::TokenType.eq(token_type))
+            .filter(
+                Condition::any()
+                    .add(account_authorizations::Column::AccountId.eq(account_id))
+                    .add(account_authorizations::Column::CreatedBy.eq(created_by)),
+            )
+            .filter(Self::active_condition())
+            .order_by_desc(account_authorizations::Column::CreatedAt)
+            .all(self.db.conn())
+            .await
+    }
+
+    async fn touch_last_used(&self, id: i64) -> Result<(), sea_orm::DbErr> {
+        account_authorizations::Entity::update_many()
+            .col_expr(
+                account_authorizations::Column::LastUsedAt,
+                Expr::value(sea_orm::Value::from(Utc::now())),
+            )
+            .filter(account_authorizations::Column::Id.eq(id))
+            .exec(self.db.conn())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Process-local stand-in for `SeaOrmAccountAuthorizationsRepo` in tests; `_with_txn` variants
+/// write straight through.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryAccountAuthorizationsRepo {
+    rows: std::sync::Mutex<Vec<account_authorizations::Model>>,
+}
+
+#[cfg(test)]
+impl InMemoryAccountAuthorizationsRepo {
+    fn is_active(row: &account_authorizations::Model) -> bool {
+        row.deleted_at.is_none()
+            && row.revoked_at.is_none()
+            && row
+                .expires_at
+                .is_none_or(|expires_at| expires_at > Utc::now())
+    }
+
+    fn find(
+        &self,
+        predicate: impl Fn(&account_authorizations::Model) -> bool,
+    ) -> Option<account_authorizations::Model> {
+        let rows = self.rows.lock().unwrap();
+        rows.iter()
+            .filter(|row| row.deleted_at.is_none())
+            .find(|row| predicate(row))
+            .cloned()
+    }
+
+    /// Revokes every unrevoked row matching `predicate` and returns how many there were.
+    fn revoke_where(&self, predicate: impl Fn(&account_authorizations::Model) -> bool) -> u64 {
+        let now = Utc::now().into();
+        let mut rows = self.rows.lock().unwrap();
+        let mut revoked = 0;
+        for row in rows
+            .iter_mut()
+            .filter(|row| row.revoked_at.is_none() && predicate(row))
+        {
+            row.revoked_at = Some(now);
+            row.updated_at = now;
+            revoked += 1;
+        }
+        revoked
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl AccountAuthorizationsRepo for InMemoryAccountAuthorizationsRepo {
+    async fn insert(
+        &self,
+        mut model: account_authorizations::ActiveModel,
+    ) -> Result<account_authorizations::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = Utc::now().into();
+        let row = account_authorizations::Model {
+            id: rows.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            account_id: model.account_id.take().unwrap_or_default(),
+            token_hash: model.token_hash.take().unwrap_or_default(),
+            token_type: model.token_type.take().unwrap_or_default(),
+            family_id: model.family_id.take().flatten(),
+            metadata: model.metadata.take().flatten(),
+            last_used_at: model.last_used_at.take().flatten(),
+            expires_at: model.expires_at.take().flatten(),
+            revoked_at: model.revoked_at.take().flatten(),
+            created_at: model.created_at.take().unwrap_or(now),
+            updated_at: model.updated_at.take().unwrap_or(now),
+            deleted_at: model.deleted_at.take().flatten(),
+            created_by: model.created_by.take().flatten(),
+            updated_by: model.updated_by.take().flatten(),
+            deleted_by: model.deleted_by.take().flatten(),
+            purge_at: model.purge_at.take().flatten(),
+        };
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn insert_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        model: account_authorizations::ActiveModel,
+    ) -> Result<account_authorizations::Model, sea_orm::DbErr> {
+        self.insert(model).await
+    }
+
+    async fn find_active_by_token_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr> {
+        Ok(self.find(|row| row.token_hash == token_hash && Self::is_active(row)))
+    }
+
+    async fn find_active_by_account_and_type(
+        &self,
+        account_id: i64,
+        token_type: &str,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr> {
+        Ok(self.find(|row| {
+            row.account_id == account_id && row.token_type == token_type && Self::is_active(row)
+        }))
+    }
+
+    async fn list_active_by_account_and_type(
+        &self,
+        account_id: i64,
+        token_type: &str,
+    ) -> Result<Vec<account_authorizations::Model>, sea_orm::DbErr> {
+        let rows = self.rows.lock().unwrap();
+        let mut found: Vec<_> = rows
+            .iter()
+            .filter(|row| row.account_id == account_id && row.token_type == token_type)
+            .filter(|row| Self::is_active(row))
+            .cloned()
+            .collect();
+        found.sort_by_key(|row| std::cmp::Reverse(row.created_at));
+        Ok(found)
+    }
+
+    async fn revoke_by_id(&self, id: i64) -> Result<account_authorizations::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let Some(row) = rows.iter_mut().find(|row| row.id == id) else {
+            return Err(sea_orm::DbErr::RecordNotFound(
+                "account_authorization not found".to_string(),
+            ));
+        };
+        row.revoked_at = Some(Utc::now().into());
+        Ok(row.clone())
+    }
+
+    async fn find_by_token_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr> {
+        Ok(self.find(|row| row.token_hash == token_hash))
+    }
+
+    async fn revoke_if_active(&self, id: i64) -> Result<bool, sea_orm::DbErr> {
+        Ok(self.revoke_where(|row| row.id == id) == 1)
+    }
+
+    async fn revoke_if_active_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        id: i64,
+    ) -> Result<bool, sea_orm::DbErr> {
+        self.revoke_if_active(id).await
+    }
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<u64, sea_orm::DbErr> {
+        Ok(self.revoke_where(|row| row.family_id == Some(family_id)))
+    }
+
+    async fn has_successor(
+        &self,
+        family_id: Uuid,
+        token_type: &str,
+        id: i64,
+    ) -> Result<bool, sea_orm::DbErr> {
+        Ok(self
+            .find(|row| {
+                row.family_id == Some(family_id) && row.token_type == token_type && row.id > id
+            })
+            .is_some())
+    }
+
+    async fn revoke_all_for_account(
+        &self,
+        account_id: i64,
+        token_type: &str,
+    ) -> Result<u64, sea_orm::DbErr> {
+        Ok(self.revoke_where(|row| row.account_id == account_id && row.token_type == token_type))
+    }
+
+    async fn find_by_id(
+        &self,
+        id: i64,
+    ) -> Result<Option<account_authorizations::Model>, sea_orm::DbErr> {
+        Ok(self.find(|row| row.id == id))
+    }
+
+    async fn list_active_by_type_for_owner(
+        &self,
+        account_id: i64,
+        created_by: Uuid,
+        token_type: &str,
+    ) -> Result<Vec<account_authorizations::Model>, sea_orm::DbErr> {
+        let rows = self.rows.lock().unwrap();
+        let mut found: Vec<_> = rows
+            .iter()
+            .filter(|row| row.token_type == token_type && Self::is_active(row))
+            .filter(|row| row.account_id == account_id || row.created_by == Some(created_by))
+            .cloned()
+            .collect();
+        found.sort_by_key(|row| std::cmp::Reverse(row.created_at));
+        Ok(found)
+    }
+
+    async fn touch_last_used(&self, id: i64) -> Result<(), sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        if let Some(row) = rows.iter_mut().find(|row| row.id == id) {
+            row.last_used_at = Some(Utc::now().into());
+        }
+        Ok(())
+    }
 }
@@ -1,10 +1,19 @@
 use async_trait::async_trait;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter};
+use chrono::Utc;
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait,
+    QueryFilter, QueryOrder, Set,
+};
+use uuid::Uuid;
 
 use crate::{entities::account_credentials, state::DatabaseClient};
 
 #[async_trait]
 pub trait AccountCredentialsRepo: Send + Sync {
+    async fn insert(
+        &self,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr>;
     async fn insert_with_txn(
         &self,
         txn: &DatabaseTransaction,
@@ -15,7 +24,10 @@ pub trait AccountCredentialsRepo: Send + Sync {
         account_id: i64,
         provider: &str,
     ) -> Result<Option<account_credentials::Model>, sea_orm::DbErr>;
-    #[allow(dead_code)]
+    async fn list_by_account(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<account_credentials::Model>, sea_orm::DbErr>;
     async fn find_by_provider_subject(
         &self,
         provider: &str,
@@ -27,6 +39,23 @@ pub trait AccountCredentialsRepo: Send + Sync {
         provider: &str,
         provider_subject: &str,
     ) -> Result<Option<account_credentials::Model>, sea_orm::DbErr>;
+    async fn update(
+        &self,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr>;
+    async fn update_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr>;
+    /// Applies `model` only if the row still has `updated_at` as read; returns whether it did.
+    async fn update_if_unchanged(
+        &self,
+        model: account_credentials::ActiveModel,
+        updated_at: DateTimeWithTimeZone,
+    ) -> Result<bool, sea_orm::DbErr>;
+    /// Marks the credential deleted; the row stays for the audit trail.
+    async fn soft_delete(&self, id: i64, deleted_by: Uuid) -> Result<(), sea_orm::DbErr>;
 }
 
 pub struct SeaOrmAccountCredentialsRepo {
@@ -41,6 +70,13 @@ impl SeaOrmAccountCredentialsRepo {
 
 #[async_trait]
 impl AccountCredentialsRepo for SeaOrmAccountCredentialsRepo {
+    async fn insert(
+        &self,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr> {
+        model.insert(self.db.conn()).await
+    }
+
     async fn insert_with_txn(
         &self,
         txn: &DatabaseTransaction,
@@ -62,6 +98,18 @@ impl AccountCredentialsRepo for SeaOrmAccountCredentialsRepo {
             .await
     }
 
+    async fn list_by_account(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<account_credentials::Model>, sea_orm::DbErr> {
+        account_credentials::Entity::find()
+            .filter(account_credentials::Column::AccountId.eq(account_id))
+            .filter(account_credentials::Column::DeletedAt.is_null())
+            .order_by_asc(account_credentials::Column::Id)
+            .all(self.db.conn())
+            .await
+    }
+
     async fn find_by_provider_subject(
         &self,
         provider: &str,
@@ -88,4 +136,226 @@ impl AccountCredentialsRepo for SeaOrmAccountCredentialsRepo {
             .one(txn)
             .await
     }
+
+    async fn update(
+        &self,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr> {
+        model.update(self.db.conn()).await
+    }
+
+    async fn update_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr> {
+        model.update(txn).await
+    }
+
+    async fn update_if_unchanged(
+        &self,
+        mut model: account_credentials::ActiveModel,
+        updated_at: DateTimeWithTimeZone,
+    ) -> Result<bool, sea_orm::DbErr> {
+        let Some(id) = model.id.take() else {
+            return Err(sea_orm::DbErr::RecordNotUpdated);
+        };
+        model.updated_at = Set(Utc::now().into());
+        let result = account_credentials::Entity::update_many()
+            .set(model)
+            .filter(account_credentials::Column::Id.eq(id))
+            .filter(account_credentials::Column::UpdatedAt.eq(updated_at))
+            .filter(account_credentials::Column::DeletedAt.is_null())
+            .exec(self.db.conn())
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn soft_delete(&self, id: i64, deleted_by: Uuid) -> Result<(), sea_orm::DbErr> {
+        let model = account_credentials::ActiveModel {
+            id: Set(id),
+            deleted_at: Set(Some(Utc::now().into())),
+            deleted_by: Set(Some(deleted_by)),
+            updated_by: Set(Some(deleted_by)),
+            ..Default::default()
+        };
+        model.update(self.db.conn()).await?;
+        Ok(())
+    }
+}
+
+/// Process-local stand-in for `SeaOrmAccountCredentialsRepo` in tests; `_with_txn` variants
+/// write straight through.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryAccountCredentialsRepo {
+    rows: std::sync::Mutex<Vec<account_credentials::Model>>,
+}
+
+#[cfg(test)]
+impl InMemoryAccountCredentialsRepo {
+    fn live(
+        &self,
+        predicate: impl Fn(&account_credentials::Model) -> bool,
+    ) -> Vec<account_credentials::Model> {
+        let rows = self.rows.lock().unwrap();
+        rows.iter()
+            .filter(|row| row.deleted_at.is_none())
+            .filter(|row| predicate(row))
+            .cloned()
+            .collect()
+    }
+
+    /// Copies the set fields of `model` over `row`.
+    fn apply(
+        mut model: account_credentials::ActiveModel,
+        row: account_credentials::Model,
+    ) -> account_credentials::Model {
+        account_credentials::Model {
+            id: model.id.take().unwrap_or(row.id),
+            account_id: model.account_id.take().unwrap_or(row.account_id),
+            provider: model.provider.take().unwrap_or(row.provider),
+            provider_subject: model
+                .provider_subject
+                .take()
+                .unwrap_or(row.provider_subject),
+            password_hash: model.password_hash.take().unwrap_or(row.password_hash),
+            metadata: model.metadata.take().unwrap_or(row.metadata),
+            created_at: model.created_at.take().unwrap_or(row.created_at),
+            updated_at: model.updated_at.take().unwrap_or(row.updated_at),
+            deleted_at: model.deleted_at.take().unwrap_or(row.deleted_at),
+            created_by: model.created_by.take().unwrap_or(row.created_by),
+            updated_by: model.updated_by.take().unwrap_or(row.updated_by),
+            deleted_by: model.deleted_by.take().unwrap_or(row.deleted_by),
+            purge_at: model.purge_at.take().unwrap_or(row.purge_at),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl AccountCredentialsRepo for InMemoryAccountCredentialsRepo {
+    async fn insert(
+        &self,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = Utc::now().into();
+        let defaults = account_credentials::Model {
+            id: rows.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            account_id: 0,
+            provider: String::new(),
+            provider_subject: None,
+            password_hash: None,
+            metadata: None,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: None,
+            updated_by: None,
+            deleted_by: None,
+            purge_at: None,
+        };
+        let row = Self::apply(model, defaults);
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn insert_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr> {
+        self.insert(model).await
+    }
+
+    async fn find_by_account_and_provider(
+        &self,
+        account_id: i64,
+        provider: &str,
+    ) -> Result<Option<account_credentials::Model>, sea_orm::DbErr> {
+        Ok(self
+            .live(|row| row.account_id == account_id && row.provider == provider)
+            .into_iter()
+            .next())
+    }
+
+    async fn list_by_account(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<account_credentials::Model>, sea_orm::DbErr> {
+        Ok(self.live(|row| row.account_id == account_id))
+    }
+
+    async fn find_by_provider_subject(
+        &self,
+        provider: &str,
+        provider_subject: &str,
+    ) -> Result<Option<account_credentials::Model>, sea_orm::DbErr> {
+        Ok(self
+            .live(|row| {
+                row.provider == provider
+                    && row.provider_subject.as_deref() == Some(provider_subject)
+            })
+            .into_iter()
+            .next())
+    }
+
+    async fn find_by_provider_subject_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        provider: &str,
+        provider_subject: &str,
+    ) -> Result<Option<account_credentials::Model>, sea_orm::DbErr> {
+        self.find_by_provider_subject(provider, provider_subject)
+            .await
+    }
+
+    async fn update(
+        &self,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let id = model.id.clone().take();
+        let Some(row) = rows.iter_mut().find(|row| Some(row.id) == id) else {
+            return Err(sea_orm::DbErr::RecordNotUpdated);
+        };
+        *row = Self::apply(model, row.clone());
+        Ok(row.clone())
+    }
+
+    async fn update_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        model: account_credentials::ActiveModel,
+    ) -> Result<account_credentials::Model, sea_orm::DbErr> {
+        self.update(model).await
+    }
+
+    async fn update_if_unchanged(
+        &self,
+        mut model: account_credentials::ActiveModel,
+        updated_at: DateTimeWithTimeZone,
+    ) -> Result<bool, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let id = model.id.clone().take();
+        let Some(row) = rows.iter_mut().find(|row| {
+            Some(row.id) == id && row.updated_at == updated_at && row.deleted_at.is_none()
+        }) else {
+            return Ok(false);
+        };
+        model.updated_at = Set(Utc::now().into());
+        *row = Self::apply(model, row.clone());
+        Ok(true)
+    }
+
+    async fn soft_delete(&self, id: i64, deleted_by: Uuid) -> Result<(), sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        if let Some(row) = rows.iter_mut().find(|row| row.id == id) {
+            row.deleted_at = Some(Utc::now().into());
+            row.deleted_by = Some(deleted_by);
+            row.updated_by = Some(deleted_by);
+        }
+        Ok(())
+    }
 }
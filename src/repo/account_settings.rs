@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, Condition, EntityTrait, QueryFilter, Set,
+};
+use uuid::Uuid;
+
+use crate::{entities::account_settings, state::DatabaseClient};
+
+#[async_trait]
+pub trait AccountSettingsRepo: Send + Sync {
+    async fn find(
+        &self,
+        account_id: i64,
+    ) -> Result<Option<account_settings::Model>, sea_orm::DbErr>;
+    /// Writes both fields, creating the row if the account has none yet.
+    async fn upsert(
+        &self,
+        account_id: i64,
+        nickname: Option<String>,
+        avatar_url: Option<String>,
+        updated_by: Uuid,
+    ) -> Result<account_settings::Model, sea_orm::DbErr>;
+    /// Sets only `avatar_url`, and only while it still equals `expected` (`None` also matches an
+    /// account without settings). Returns whether it was written.
+    async fn replace_avatar_url(
+        &self,
+        account_id: i64,
+        expected: Option<&str>,
+        avatar_url: String,
+        updated_by: Uuid,
+    ) -> Result<bool, sea_orm::DbErr>;
+}
+
+pub struct SeaOrmAccountSettingsRepo {
+    db: std::sync::Arc<dyn DatabaseClient>,
+}
+
+impl SeaOrmAccountSettingsRepo {
+    pub fn new(db: std::sync::Arc<dyn DatabaseClient>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl AccountSettingsRepo for SeaOrmAccountSettingsRepo {
+    async fn find(
+        &self,
+        account_id: i64,
+    ) -> Result<Option<account_settings::Model>, sea_orm::DbErr> {
+        account_settings::Entity::find_by_id(account_id)
+            .filter(account_settings::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn upsert(
+        &self,
+        account_id: i64,
+        nickname: Option<String>,
+        avatar_url: Option<String>,
+        updated_by: Uuid,
+    ) -> Result<account_settings::Model, sea_orm::DbErr> {
+        let model = account_settings::ActiveModel {
+            account_id: Set(account_id),
+            nickname: Set(nickname),
+            avatar_url: Set(avatar_url),
+            deleted_at: Set(None),
+            deleted_by: Set(None),
+            created_by: Set(Some(updated_by)),
+            updated_by: Set(Some(updated_by)),
+            ..Default::default()
+        };
+        account_settings::Entity::insert(model)
+            .on_conflict(
+                OnConflict::column(account_settings::Column::AccountId)
+                    .update_columns([
+                        account_settings::Column::Nickname,
+                        account_settings::Column::AvatarUrl,
+                        account_settings::Column::DeletedAt,
+                        account_settings::Column::DeletedBy,
+                        account_settings::Column::UpdatedBy,
+                    ])
+                    .to_owned(),
+            )
+            .exec_with_returning(self.db.conn())
+            .await
+    }
+
+    async fn replace_avatar_url(
+        &self,
+        account_id: i64,
+        expected: Option<&str>,
+        avatar_url: String,
+        updated_by: Uuid,
+    ) -> Result<bool, sea_orm::DbErr> {
+        // A deleted row reads as no settings, so it only matches an expected `None`.
+        let current = match expected {
+            Some(expected) => Condition::all()
+                .add(account_settings::Column::AvatarUrl.eq(expected))
+                .add(account_settings::Column::DeletedAt.is_null()),
+            None => Condition::any()
+                .add(account_settings::Column::AvatarUrl.is_null())
+                .add(account_settings::Column::DeletedAt.is_not_null()),
+        };
+        let updated = account_settings::Entity::update_many()
+            .col_expr(
+                account_settings::Column::AvatarUrl,
+                Expr::value(avatar_url.clone()),
+            )
+            .col_expr(
+                account_settings::Column::DeletedAt,
+                Expr::value(Option::<chrono::DateTime<chrono::Utc>>::None),
+            )
+            .col_expr(
+                account_settings::Column::DeletedBy,
+                Expr::value(Option::<Uuid>::None),
+            )
+            .col_expr(account_settings::Column::UpdatedBy, Expr::value(updated_by))
+            .filter(account_settings::Column::AccountId.eq(account_id))
+            .filter(current)
+            .exec(self.db.conn())
+            .await?;
+        if updated.rows_affected == 1 || expected.is_some() {
+            return Ok(updated.rows_affected == 1);
+        }
+
+        let model = account_settings::ActiveModel {
+            account_id: Set(account_id),
+            avatar_url: Set(Some(avatar_url)),
+            created_by: Set(Some(updated_by)),
+            updated_by: Set(Some(updated_by)),
+            ..Default::default()
+        };
+        let inserted = account_settings::Entity::insert(model)
+            .on_conflict(
+                OnConflict::column(account_settings::Column::AccountId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(self.db.conn())
+            .await?;
+        Ok(inserted == 1)
+    }
+}
+
+/// Process-local stand-in for `SeaOrmAccountSettingsRepo` in tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryAccountSettingsRepo {
+    rows: std::sync::Mutex<Vec<account_settings::Model>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl AccountSettingsRepo for InMemoryAccountSettingsRepo {
+    async fn find(
+        &self,
+        account_id: i64,
+    ) -> Result<Option<account_settings::Model>, sea_orm::DbErr> {
+        let rows = self.rows.lock().unwrap();
+        Ok(rows
+            .iter()
+            .find(|row| row.account_id == account_id && row.deleted_at.is_none())
+            .cloned())
+    }
+
+    async fn upsert(
+        &self,
+        account_id: i64,
+        nickname: Option<String>,
+        avatar_url: Option<String>,
+        updated_by: Uuid,
+    ) -> Result<account_settings::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = chrono::Utc::now().into();
+        if let Some(row) = rows.iter_mut().find(|row| row.account_id == account_id) {
+            row.nickname = nickname;
+            row.avatar_url = avatar_url;
+            row.deleted_at = None;
+            row.deleted_by = None;
+            row.updated_by = Some(updated_by);
+            row.updated_at = now;
+            return Ok(row.clone());
+        }
+        let row = account_settings::Model {
+            account_id,
+            nickname,
+            avatar_url,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: Some(updated_by),
+            updated_by: Some(updated_by),
+            deleted_by: None,
+            purge_at: None,
+        };
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn replace_avatar_url(
+        &self,
+        account_id: i64,
+        expected: Option<&str>,
+        avatar_url: String,
+        updated_by: Uuid,
+    ) -> Result<bool, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = chrono::Utc::now().into();
+        let Some(row) = rows.iter_mut().find(|row| row.account_id == account_id) else {
+            if expected.is_some() {
+                return Ok(false);
+            }
+            rows.push(account_settings::Model {
+                account_id,
+                nickname: None,
+                avatar_url: Some(avatar_url),
+                created_at: now,
+                updated_at: now,
+                deleted_at: None,
+                created_by: Some(updated_by),
+                updated_by: Some(updated_by),
+                deleted_by: None,
+                purge_at: None,
+            });
+            return Ok(true);
+        };
+        let current = row
+            .deleted_at
+            .is_none()
+            .then_some(row.avatar_url.as_deref())
+            .flatten();
+        if current != expected {
+            return Ok(false);
+        }
+        row.avatar_url = Some(avatar_url);
+        row.deleted_at = None;
+        row.deleted_by = None;
+        row.updated_by = Some(updated_by);
+        row.updated_at = now;
+        Ok(true)
+    }
+}
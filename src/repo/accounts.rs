@@ -1,10 +1,44 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use sea_orm::prelude::Expr;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect,
+};
 use uuid::Uuid;
 
 use crate::{entities::accounts, state::DatabaseClient};
 
+/// Filters and keyset position for `AccountsRepo::list`.
+#[derive(Clone, Debug, Default)]
+pub struct AccountListQuery {
+    pub account_type: Option<String>,
+    /// Case-insensitive prefix of the email or the username.
+    pub search: Option<String>,
+    pub created_from: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub created_until: Option<DateTime<Utc>>,
+    pub include_deleted: bool,
+    /// Only accounts after this id in the chosen order.
+    pub after_id: Option<i64>,
+    /// Newest first.
+    pub descending: bool,
+    pub limit: u64,
+}
+
+/// `value` as a LIKE pattern matching itself, lowercased, followed by `%`.
+fn prefix_pattern(value: &str) -> String {
+    let mut pattern = String::with_capacity(value.len() + 1);
+    for c in value.trim().to_lowercase().chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
 #[async_trait]
 pub trait AccountsRepo: Send + Sync {
     async fn insert(&self, model: accounts::ActiveModel)
@@ -15,6 +49,7 @@ pub trait AccountsRepo: Send + Sync {
         model: accounts::ActiveModel,
     ) -> Result<accounts::Model, sea_orm::DbErr>;
     async fn find_by_uid(&self, uid: Uuid) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
+    async fn find_by_id(&self, id: i64) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
     async fn find_by_email(&self, email: &str) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
     async fn find_by_username(
         &self,
@@ -25,8 +60,20 @@ pub trait AccountsRepo: Send + Sync {
         txn: &DatabaseTransaction,
         id: i64,
     ) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
+    async fn find_by_email_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        email: &str,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
+    async fn find_by_username_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        username: &str,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
     async fn update(&self, model: accounts::ActiveModel)
         -> Result<accounts::Model, sea_orm::DbErr>;
+    /// At most `query.limit` accounts ordered by id.
+    async fn list(&self, query: &AccountListQuery) -> Result<Vec<accounts::Model>, sea_orm::DbErr>;
 }
 
 pub struct SeaOrmAccountsRepo {
@@ -64,6 +111,13 @@ impl AccountsRepo for SeaOrmAccountsRepo {
             .await
     }
 
+    async fn find_by_id(&self, id: i64) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        accounts::Entity::find_by_id(id)
+            .filter(accounts::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
     async fn find_by_email(&self, email: &str) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
         let normalized = email.trim().to_lowercase();
         accounts::Entity::find()
@@ -96,10 +150,266 @@ impl AccountsRepo for SeaOrmAccountsRepo {
             .await
     }
 
+    async fn find_by_email_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        email: &str,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        let normalized = email.trim().to_lowercase();
+        accounts::Entity::find()
+            .filter(accounts::Column::DeletedAt.is_null())
+            .filter(Expr::cust("lower(email)").eq(normalized))
+            .one(txn)
+            .await
+    }
+
+    async fn find_by_username_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        username: &str,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        let normalized = username.trim().to_lowercase();
+        accounts::Entity::find()
+            .filter(accounts::Column::DeletedAt.is_null())
+            .filter(Expr::cust("lower(username)").eq(normalized))
+            .one(txn)
+            .await
+    }
+
     async fn update(
         &self,
         model: accounts::ActiveModel,
     ) -> Result<accounts::Model, sea_orm::DbErr> {
         model.update(self.db.conn()).await
     }
+
+    async fn list(&self, query: &AccountListQuery) -> Result<Vec<accounts::Model>, sea_orm::DbErr> {
+        let mut select = accounts::Entity::find();
+        if !query.include_deleted {
+            select = select.filter(accounts::Column::DeletedAt.is_null());
+        }
+        if let Some(account_type) = &query.account_type {
+            select = select.filter(accounts::Column::AccountType.eq(account_type.as_str()));
+        }
+        if let Some(search) = &query.search {
+            let pattern = prefix_pattern(search);
+            select = select.filter(
+                Condition::any()
+                    .add(Expr::cust_with_values(
+                        "lower(email) LIKE $1",
+                        [pattern.clone()],
+                    ))
+                    .add(Expr::cust_with_values("lower(username) LIKE $1", [pattern])),
+            );
+        }
+        if let Some(from) = query.created_from {
+            select = select.filter(accounts::Column::CreatedAt.gte(from));
+        }
+        if let Some(until) = query.created_until {
+            select = select.filter(accounts::Column::CreatedAt.lt(until));
+        }
+        select = match (query.after_id, query.descending) {
+            (Some(id), true) => select.filter(accounts::Column::Id.lt(id)),
+            (Some(id), false) => select.filter(accounts::Column::Id.gt(id)),
+            (None, _) => select,
+        };
+        select = if query.descending {
+            select.order_by_desc(accounts::Column::Id)
+        } else {
+            select.order_by_asc(accounts::Column::Id)
+        };
+        select.limit(query.limit).all(self.db.conn()).await
+    }
+}
+
+/// Process-local stand-in for `SeaOrmAccountsRepo` in tests. Transactions are not modelled:
+/// the `_with_txn` variants write straight through and are not undone by a rollback.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryAccountsRepo {
+    rows: std::sync::Mutex<Vec<accounts::Model>>,
+}
+
+#[cfg(test)]
+impl InMemoryAccountsRepo {
+    fn find(&self, predicate: impl Fn(&accounts::Model) -> bool) -> Option<accounts::Model> {
+        let rows = self.rows.lock().unwrap();
+        rows.iter()
+            .filter(|row| row.deleted_at.is_none())
+            .find(|row| predicate(row))
+            .cloned()
+    }
+
+    /// Copies the set fields of `model` over `row`.
+    fn apply(mut model: accounts::ActiveModel, row: accounts::Model) -> accounts::Model {
+        accounts::Model {
+            id: model.id.take().unwrap_or(row.id),
+            uid: model.uid.take().unwrap_or(row.uid),
+            account_type: model.account_type.take().unwrap_or(row.account_type),
+            username: model.username.take().unwrap_or(row.username),
+            email: model.email.take().unwrap_or(row.email),
+            email_verified_at: model
+                .email_verified_at
+                .take()
+                .unwrap_or(row.email_verified_at),
+            phone: model.phone.take().unwrap_or(row.phone),
+            created_at: model.created_at.take().unwrap_or(row.created_at),
+            updated_at: model.updated_at.take().unwrap_or(row.updated_at),
+            deleted_at: model.deleted_at.take().unwrap_or(row.deleted_at),
+            created_by: model.created_by.take().unwrap_or(row.created_by),
+            updated_by: model.updated_by.take().unwrap_or(row.updated_by),
+            deleted_by: model.deleted_by.take().unwrap_or(row.deleted_by),
+            purge_at: model.purge_at.take().unwrap_or(row.purge_at),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl AccountsRepo for InMemoryAccountsRepo {
+    async fn insert(
+        &self,
+        model: accounts::ActiveModel,
+    ) -> Result<accounts::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = chrono::Utc::now().into();
+        let defaults = accounts::Model {
+            id: rows.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            uid: Uuid::new_v4(),
+            account_type: String::new(),
+            username: None,
+            email: None,
+            email_verified_at: None,
+            phone: None,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: None,
+            updated_by: None,
+            deleted_by: None,
+            purge_at: None,
+        };
+        let row = Self::apply(model, defaults);
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn insert_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        model: accounts::ActiveModel,
+    ) -> Result<accounts::Model, sea_orm::DbErr> {
+        self.insert(model).await
+    }
+
+    async fn find_by_uid(&self, uid: Uuid) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        Ok(self.find(|row| row.uid == uid))
+    }
+
+    async fn find_by_id(&self, id: i64) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        Ok(self.find(|row| row.id == id))
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        let normalized = email.trim().to_lowercase();
+        Ok(
+            self.find(|row| {
+                row.email.as_deref().map(str::to_lowercase) == Some(normalized.clone())
+            }),
+        )
+    }
+
+    async fn find_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        let normalized = username.trim().to_lowercase();
+        Ok(self
+            .find(|row| row.username.as_deref().map(str::to_lowercase) == Some(normalized.clone())))
+    }
+
+    async fn find_by_id_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        id: i64,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        self.find_by_id(id).await
+    }
+
+    async fn find_by_email_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        email: &str,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        self.find_by_email(email).await
+    }
+
+    async fn find_by_username_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        username: &str,
+    ) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
+        self.find_by_username(username).await
+    }
+
+    async fn update(
+        &self,
+        model: accounts::ActiveModel,
+    ) -> Result<accounts::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let id = model.id.clone().take();
+        let Some(row) = rows.iter_mut().find(|row| Some(row.id) == id) else {
+            return Err(sea_orm::DbErr::RecordNotUpdated);
+        };
+        *row = Self::apply(model, row.clone());
+        Ok(row.clone())
+    }
+
+    async fn list(&self, query: &AccountListQuery) -> Result<Vec<accounts::Model>, sea_orm::DbErr> {
+        let search = query
+            .search
+            .as_deref()
+            .map(|value| value.trim().to_lowercase());
+        let has_prefix = |value: &Option<String>, prefix: &str| {
+            value
+                .as_deref()
+                .is_some_and(|value| value.to_lowercase().starts_with(prefix))
+        };
+        let mut rows: Vec<_> = self
+            .rows
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|row| query.include_deleted || row.deleted_at.is_none())
+            .filter(|row| {
+                query
+                    .account_type
+                    .as_ref()
+                    .is_none_or(|account_type| &row.account_type == account_type)
+            })
+            .filter(|row| {
+                search.as_deref().is_none_or(|prefix| {
+                    has_prefix(&row.email, prefix) || has_prefix(&row.username, prefix)
+                })
+            })
+            .filter(|row| query.created_from.is_none_or(|from| row.created_at >= from))
+            .filter(|row| {
+                query
+                    .created_until
+                    .is_none_or(|until| row.created_at < until)
+            })
+            .filter(|row| match query.after_id {
+                Some(id) if query.descending => row.id < id,
+                Some(id) => row.id > id,
+                None => true,
+            })
+            .cloned()
+            .collect();
+        rows.sort_by_key(|row| row.id);
+        if query.descending {
+            rows.reverse();
+        }
+        rows.truncate(usize::try_from(query.limit).unwrap_or(usize::MAX));
+        Ok(rows)
+    }
 }
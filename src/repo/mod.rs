@@ -1,3 +1,9 @@
 pub mod account_authorizations;
 pub mod account_credentials;
+pub mod account_settings;
 pub mod accounts;
+pub mod oauth_clients;
+pub mod oauth_consents;
+pub mod roles;
+pub mod signing_keys;
+pub mod team_memberships;
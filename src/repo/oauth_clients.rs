@@ -0,0 +1,185 @@
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::{entities::oauth_clients, state::DatabaseClient};
+
+#[async_trait]
+pub trait OauthClientsRepo: Send + Sync {
+    async fn insert(
+        &self,
+        model: oauth_clients::ActiveModel,
+    ) -> Result<oauth_clients::Model, sea_orm::DbErr>;
+    async fn find_by_client_id(
+        &self,
+        client_id: &str,
+    ) -> Result<Option<oauth_clients::Model>, sea_orm::DbErr>;
+    async fn list_by_owner(
+        &self,
+        owner_account_id: i64,
+    ) -> Result<Vec<oauth_clients::Model>, sea_orm::DbErr>;
+    async fn update(
+        &self,
+        model: oauth_clients::ActiveModel,
+    ) -> Result<oauth_clients::Model, sea_orm::DbErr>;
+}
+
+pub struct SeaOrmOauthClientsRepo {
+    db: std::sync::Arc<dyn DatabaseClient>,
+}
+
+impl SeaOrmOauthClientsRepo {
+    pub fn new(db: std::sync::Arc<dyn DatabaseClient>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl OauthClientsRepo for SeaOrmOauthClientsRepo {
+    async fn insert(
+        &self,
+        model: oauth_clients::ActiveModel,
+    ) -> Result<oauth_clients::Model, sea_orm::DbErr> {
+        model.insert(self.db.conn()).await
+    }
+
+    async fn find_by_client_id(
+        &self,
+        client_id: &str,
+    ) -> Result<Option<oauth_clients::Model>, sea_orm::DbErr> {
+        oauth_clients::Entity::find()
+            .filter(oauth_clients::Column::ClientId.eq(client_id))
+            .filter(oauth_clients::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn list_by_owner(
+        &self,
+        owner_account_id: i64,
+    ) -> Result<Vec<oauth_clients::Model>, sea_orm::DbErr> {
+        oauth_clients::Entity::find()
+            .filter(oauth_clients::Column::OwnerAccountId.eq(owner_account_id))
+            .filter(oauth_clients::Column::DeletedAt.is_null())
+            .order_by_asc(oauth_clients::Column::Id)
+            .all(self.db.conn())
+            .await
+    }
+
+    async fn update(
+        &self,
+        model: oauth_clients::ActiveModel,
+    ) -> Result<oauth_clients::Model, sea_orm::DbErr> {
+        model.update(self.db.conn()).await
+    }
+}
+
+/// Process-local stand-in for `SeaOrmOauthClientsRepo` in tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryOauthClientsRepo {
+    rows: std::sync::Mutex<Vec<oauth_clients::Model>>,
+}
+
+#[cfg(test)]
+impl InMemoryOauthClientsRepo {
+    /// Copies the set fields of `model` over `row`.
+    fn apply(
+        mut model: oauth_clients::ActiveModel,
+        row: oauth_clients::Model,
+    ) -> oauth_clients::Model {
+        oauth_clients::Model {
+            id: model.id.take().unwrap_or(row.id),
+            client_id: model.client_id.take().unwrap_or(row.client_id),
+            client_secret_hash: model
+                .client_secret_hash
+                .take()
+                .unwrap_or(row.client_secret_hash),
+            name: model.name.take().unwrap_or(row.name),
+            redirect_uris: model.redirect_uris.take().unwrap_or(row.redirect_uris),
+            allowed_scopes: model.allowed_scopes.take().unwrap_or(row.allowed_scopes),
+            is_public: model.is_public.take().unwrap_or(row.is_public),
+            first_party: model.first_party.take().unwrap_or(row.first_party),
+            owner_account_id: model
+                .owner_account_id
+                .take()
+                .unwrap_or(row.owner_account_id),
+            created_at: model.created_at.take().unwrap_or(row.created_at),
+            updated_at: model.updated_at.take().unwrap_or(row.updated_at),
+            deleted_at: model.deleted_at.take().unwrap_or(row.deleted_at),
+            created_by: model.created_by.take().unwrap_or(row.created_by),
+            updated_by: model.updated_by.take().unwrap_or(row.updated_by),
+            deleted_by: model.deleted_by.take().unwrap_or(row.deleted_by),
+            purge_at: model.purge_at.take().unwrap_or(row.purge_at),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl OauthClientsRepo for InMemoryOauthClientsRepo {
+    async fn insert(
+        &self,
+        model: oauth_clients::ActiveModel,
+    ) -> Result<oauth_clients::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = chrono::Utc::now().into();
+        let defaults = oauth_clients::Model {
+            id: rows.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            client_id: String::new(),
+            client_secret_hash: None,
+            name: String::new(),
+            redirect_uris: serde_json::json!([]),
+            allowed_scopes: serde_json::json!([]),
+            is_public: false,
+            first_party: false,
+            owner_account_id: None,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: None,
+            updated_by: None,
+            deleted_by: None,
+            purge_at: None,
+        };
+        let row = Self::apply(model, defaults);
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn find_by_client_id(
+        &self,
+        client_id: &str,
+    ) -> Result<Option<oauth_clients::Model>, sea_orm::DbErr> {
+        let rows = self.rows.lock().unwrap();
+        Ok(rows
+            .iter()
+            .find(|row| row.client_id == client_id && row.deleted_at.is_none())
+            .cloned())
+    }
+
+    async fn list_by_owner(
+        &self,
+        owner_account_id: i64,
+    ) -> Result<Vec<oauth_clients::Model>, sea_orm::DbErr> {
+        let rows = self.rows.lock().unwrap();
+        Ok(rows
+            .iter()
+            .filter(|row| row.owner_account_id == Some(owner_account_id))
+            .filter(|row| row.deleted_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn update(
+        &self,
+        model: oauth_clients::ActiveModel,
+    ) -> Result<oauth_clients::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let id = model.id.clone().take();
+        let Some(row) = rows.iter_mut().find(|row| Some(row.id) == id) else {
+            return Err(sea_orm::DbErr::RecordNotUpdated);
+        };
+        *row = Self::apply(model, row.clone());
+        Ok(row.clone())
+    }
+}
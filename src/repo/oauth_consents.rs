@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter};
+
+use crate::{entities::oauth_consents, state::DatabaseClient};
+
+#[async_trait]
+pub trait OauthConsentsRepo: Send + Sync {
+    async fn find(
+        &self,
+        account_id: i64,
+        client_id: i64,
+    ) -> Result<Option<oauth_consents::Model>, sea_orm::DbErr>;
+    async fn insert(
+        &self,
+        model: oauth_consents::ActiveModel,
+    ) -> Result<oauth_consents::Model, sea_orm::DbErr>;
+    async fn update(
+        &self,
+        model: oauth_consents::ActiveModel,
+    ) -> Result<oauth_consents::Model, sea_orm::DbErr>;
+}
+
+pub struct SeaOrmOauthConsentsRepo {
+    db: std::sync::Arc<dyn DatabaseClient>,
+}
+
+impl SeaOrmOauthConsentsRepo {
+    pub fn new(db: std::sync::Arc<dyn DatabaseClient>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl OauthConsentsRepo for SeaOrmOauthConsentsRepo {
+    async fn find(
+        &self,
+        account_id: i64,
+        client_id: i64,
+    ) -> Result<Option<oauth_consents::Model>, sea_orm::DbErr> {
+        oauth_consents::Entity::find()
+            .filter(oauth_consents::Column::AccountId.eq(account_id))
+            .filter(oauth_consents::Column::ClientId.eq(client_id))
+            .filter(oauth_consents::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn insert(
+        &self,
+        model: oauth_consents::ActiveModel,
+    ) -> Result<oauth_consents::Model, sea_orm::DbErr> {
+        model.insert(self.db.conn()).await
+    }
+
+    async fn update(
+        &self,
+        model: oauth_consents::ActiveModel,
+    ) -> Result<oauth_consents::Model, sea_orm::DbErr> {
+        model.update(self.db.conn()).await
+    }
+}
+
+/// Process-local stand-in for `SeaOrmOauthConsentsRepo` in tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryOauthConsentsRepo {
+    rows: std::sync::Mutex<Vec<oauth_consents::Model>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl OauthConsentsRepo for InMemoryOauthConsentsRepo {
+    async fn find(
+        &self,
+        account_id: i64,
+        client_id: i64,
+    ) -> Result<Option<oauth_consents::Model>, sea_orm::DbErr> {
+        let rows = self.rows.lock().unwrap();
+        Ok(rows
+            .iter()
+            .find(|row| {
+                row.account_id == account_id
+                    && row.client_id == client_id
+                    && row.deleted_at.is_none()
+            })
+            .cloned())
+    }
+
+    async fn insert(
+        &self,
+        mut model: oauth_consents::ActiveModel,
+    ) -> Result<oauth_consents::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = chrono::Utc::now().into();
+        let row = oauth_consents::Model {
+            id: rows.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            account_id: model.account_id.take().unwrap_or_default(),
+            client_id: model.client_id.take().unwrap_or_default(),
+            scopes: model.scopes.take().unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: model.created_by.take().flatten(),
+            updated_by: model.updated_by.take().flatten(),
+            deleted_by: None,
+            purge_at: None,
+        };
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn update(
+        &self,
+        mut model: oauth_consents::ActiveModel,
+    ) -> Result<oauth_consents::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let id = model.id.clone().take();
+        let Some(row) = rows.iter_mut().find(|row| Some(row.id) == id) else {
+            return Err(sea_orm::DbErr::RecordNotUpdated);
+        };
+        if let Some(scopes) = model.scopes.take() {
+            row.scopes = scopes;
+        }
+        if let Some(updated_by) = model.updated_by.take() {
+            row.updated_by = updated_by;
+        }
+        row.updated_at = chrono::Utc::now().into();
+        Ok(row.clone())
+    }
+}
@@ -0,0 +1,323 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+use uuid::Uuid;
+
+use crate::{
+    entities::{account_roles, permissions, roles},
+    state::DatabaseClient,
+};
+
+/// Roles, the permissions they grant and the accounts holding them.
+#[async_trait]
+pub trait RolesRepo: Send + Sync {
+    async fn find_role_by_name(&self, name: &str) -> Result<Option<roles::Model>, sea_orm::DbErr>;
+    async fn list_roles(&self) -> Result<Vec<roles::Model>, sea_orm::DbErr>;
+    async fn insert_role(&self, model: roles::ActiveModel) -> Result<roles::Model, sea_orm::DbErr>;
+    async fn list_permissions(
+        &self,
+        role_ids: &[i64],
+    ) -> Result<Vec<permissions::Model>, sea_orm::DbErr>;
+    async fn insert_permission(
+        &self,
+        model: permissions::ActiveModel,
+    ) -> Result<permissions::Model, sea_orm::DbErr>;
+    async fn list_roles_for_account(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<roles::Model>, sea_orm::DbErr>;
+    async fn find_account_role(
+        &self,
+        account_id: i64,
+        role_id: i64,
+    ) -> Result<Option<account_roles::Model>, sea_orm::DbErr>;
+    async fn insert_account_role(
+        &self,
+        model: account_roles::ActiveModel,
+    ) -> Result<account_roles::Model, sea_orm::DbErr>;
+    /// Soft-deletes the grant.
+    async fn delete_account_role(
+        &self,
+        id: i64,
+        deleted_by: Option<Uuid>,
+    ) -> Result<(), sea_orm::DbErr>;
+}
+
+pub struct SeaOrmRolesRepo {
+    db: std::sync::Arc<dyn DatabaseClient>,
+}
+
+impl SeaOrmRolesRepo {
+    pub fn new(db: std::sync::Arc<dyn DatabaseClient>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RolesRepo for SeaOrmRolesRepo {
+    async fn find_role_by_name(&self, name: &str) -> Result<Option<roles::Model>, sea_orm::DbErr> {
+        roles::Entity::find()
+            .filter(roles::Column::Name.eq(name))
+            .filter(roles::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn list_roles(&self) -> Result<Vec<roles::Model>, sea_orm::DbErr> {
+        roles::Entity::find()
+            .filter(roles::Column::DeletedAt.is_null())
+            .order_by_asc(roles::Column::Name)
+            .all(self.db.conn())
+            .await
+    }
+
+    async fn insert_role(&self, model: roles::ActiveModel) -> Result<roles::Model, sea_orm::DbErr> {
+        model.insert(self.db.conn()).await
+    }
+
+    async fn list_permissions(
+        &self,
+        role_ids: &[i64],
+    ) -> Result<Vec<permissions::Model>, sea_orm::DbErr> {
+        if role_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        permissions::Entity::find()
+            .filter(permissions::Column::RoleId.is_in(role_ids.iter().copied()))
+            .filter(permissions::Column::DeletedAt.is_null())
+            .order_by_asc(permissions::Column::Name)
+            .all(self.db.conn())
+            .await
+    }
+
+    async fn insert_permission(
+        &self,
+        model: permissions::ActiveModel,
+    ) -> Result<permissions::Model, sea_orm::DbErr> {
+        model.insert(self.db.conn()).await
+    }
+
+    async fn list_roles_for_account(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<roles::Model>, sea_orm::DbErr> {
+        let role_ids: Vec<i64> = account_roles::Entity::find()
+            .filter(account_roles::Column::AccountId.eq(account_id))
+            .filter(account_roles::Column::DeletedAt.is_null())
+            .all(self.db.conn())
+            .await?
+            .into_iter()
+            .map(|grant| grant.role_id)
+            .collect();
+        if role_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        roles::Entity::find()
+            .filter(roles::Column::Id.is_in(role_ids))
+            .filter(roles::Column::DeletedAt.is_null())
+            .order_by_asc(roles::Column::Name)
+            .all(self.db.conn())
+            .await
+    }
+
+    async fn find_account_role(
+        &self,
+        account_id: i64,
+        role_id: i64,
+    ) -> Result<Option<account_roles::Model>, sea_orm::DbErr> {
+        account_roles::Entity::find()
+            .filter(account_roles::Column::AccountId.eq(account_id))
+            .filter(account_roles::Column::RoleId.eq(role_id))
+            .filter(account_roles::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn insert_account_role(
+        &self,
+        model: account_roles::ActiveModel,
+    ) -> Result<account_roles::Model, sea_orm::DbErr> {
+        model.insert(self.db.conn()).await
+    }
+
+    async fn delete_account_role(
+        &self,
+        id: i64,
+        deleted_by: Option<Uuid>,
+    ) -> Result<(), sea_orm::DbErr> {
+        // deleted_by must be set together with deleted_at (see the audit invariants).
+        let actor = deleted_by.unwrap_or_else(Uuid::nil);
+        let model = account_roles::ActiveModel {
+            id: Set(id),
+            deleted_at: Set(Some(Utc::now().into())),
+            deleted_by: Set(Some(actor)),
+            updated_by: Set(Some(actor)),
+            ..Default::default()
+        };
+        model.update(self.db.conn()).await?;
+        Ok(())
+    }
+}
+
+/// Process-local stand-in for `SeaOrmRolesRepo` in tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryRolesRepo {
+    roles: std::sync::Mutex<Vec<roles::Model>>,
+    permissions: std::sync::Mutex<Vec<permissions::Model>>,
+    account_roles: std::sync::Mutex<Vec<account_roles::Model>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl RolesRepo for InMemoryRolesRepo {
+    async fn find_role_by_name(&self, name: &str) -> Result<Option<roles::Model>, sea_orm::DbErr> {
+        let roles = self.roles.lock().unwrap();
+        Ok(roles
+            .iter()
+            .find(|role| role.name == name && role.deleted_at.is_none())
+            .cloned())
+    }
+
+    async fn list_roles(&self) -> Result<Vec<roles::Model>, sea_orm::DbErr> {
+        let mut roles: Vec<_> = self
+            .roles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|role| role.deleted_at.is_none())
+            .cloned()
+            .collect();
+        roles.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(roles)
+    }
+
+    async fn insert_role(
+        &self,
+        mut model: roles::ActiveModel,
+    ) -> Result<roles::Model, sea_orm::DbErr> {
+        let mut roles = self.roles.lock().unwrap();
+        let now = Utc::now().into();
+        let role = roles::Model {
+            id: roles.iter().map(|role| role.id).max().unwrap_or(0) + 1,
+            name: model.name.take().unwrap_or_default(),
+            description: model.description.take().flatten(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: model.created_by.take().flatten(),
+            updated_by: model.updated_by.take().flatten(),
+            deleted_by: None,
+            purge_at: None,
+        };
+        roles.push(role.clone());
+        Ok(role)
+    }
+
+    async fn list_permissions(
+        &self,
+        role_ids: &[i64],
+    ) -> Result<Vec<permissions::Model>, sea_orm::DbErr> {
+        let mut permissions: Vec<_> = self
+            .permissions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|permission| role_ids.contains(&permission.role_id))
+            .filter(|permission| permission.deleted_at.is_none())
+            .cloned()
+            .collect();
+        permissions.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(permissions)
+    }
+
+    async fn insert_permission(
+        &self,
+        mut model: permissions::ActiveModel,
+    ) -> Result<permissions::Model, sea_orm::DbErr> {
+        let mut permissions = self.permissions.lock().unwrap();
+        let now = Utc::now().into();
+        let permission = permissions::Model {
+            id: permissions.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            role_id: model.role_id.take().unwrap_or_default(),
+            name: model.name.take().unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: model.created_by.take().flatten(),
+            updated_by: model.updated_by.take().flatten(),
+            deleted_by: None,
+            purge_at: None,
+        };
+        permissions.push(permission.clone());
+        Ok(permission)
+    }
+
+    async fn list_roles_for_account(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<roles::Model>, sea_orm::DbErr> {
+        let role_ids: Vec<i64> = self
+            .account_roles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|grant| grant.account_id == account_id && grant.deleted_at.is_none())
+            .map(|grant| grant.role_id)
+            .collect();
+        let mut roles = self.list_roles().await?;
+        roles.retain(|role| role_ids.contains(&role.id));
+        Ok(roles)
+    }
+
+    async fn find_account_role(
+        &self,
+        account_id: i64,
+        role_id: i64,
+    ) -> Result<Option<account_roles::Model>, sea_orm::DbErr> {
+        let grants = self.account_roles.lock().unwrap();
+        Ok(grants
+            .iter()
+            .find(|grant| {
+                grant.account_id == account_id
+                    && grant.role_id == role_id
+                    && grant.deleted_at.is_none()
+            })
+            .cloned())
+    }
+
+    async fn insert_account_role(
+        &self,
+        mut model: account_roles::ActiveModel,
+    ) -> Result<account_roles::Model, sea_orm::DbErr> {
+        let mut grants = self.account_roles.lock().unwrap();
+        let now = Utc::now().into();
+        let grant = account_roles::Model {
+            id: grants.iter().map(|grant| grant.id).max().unwrap_or(0) + 1,
+            account_id: model.account_id.take().unwrap_or_default(),
+            role_id: model.role_id.take().unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: model.created_by.take().flatten(),
+            updated_by: model.updated_by.take().flatten(),
+            deleted_by: None,
+            purge_at: None,
+        };
+        grants.push(grant.clone());
+        Ok(grant)
+    }
+
+    async fn delete_account_role(
+        &self,
+        id: i64,
+        deleted_by: Option<Uuid>,
+    ) -> Result<(), sea_orm::DbErr> {
+        let mut grants = self.account_roles.lock().unwrap();
+        if let Some(grant) = grants.iter_mut().find(|grant| grant.id == id) {
+            grant.deleted_at = Some(Utc::now().into());
+            grant.deleted_by = Some(deleted_by.unwrap_or_else(Uuid::nil));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::{entities::signing_keys, state::DatabaseClient};
+
+#[async_trait]
+pub trait SigningKeysRepo: Send + Sync {
+    async fn insert(
+        &self,
+        model: signing_keys::ActiveModel,
+    ) -> Result<signing_keys::Model, sea_orm::DbErr>;
+    /// Keys that are still published, newest first.
+    async fn list_unexpired(&self) -> Result<Vec<signing_keys::Model>, sea_orm::DbErr>;
+}
+
+pub struct SeaOrmSigningKeysRepo {
+    db: std::sync::Arc<dyn DatabaseClient>,
+}
+
+impl SeaOrmSigningKeysRepo {
+    pub fn new(db: std::sync::Arc<dyn DatabaseClient>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SigningKeysRepo for SeaOrmSigningKeysRepo {
+    async fn insert(
+        &self,
+        model: signing_keys::ActiveModel,
+    ) -> Result<signing_keys::Model, sea_orm::DbErr> {
+        model.insert(self.db.conn()).await
+    }
+
+    async fn list_unexpired(&self) -> Result<Vec<signing_keys::Model>, sea_orm::DbErr> {
+        signing_keys::Entity::find()
+            .filter(signing_keys::Column::DeletedAt.is_null())
+            .filter(signing_keys::Column::ExpiresAt.gt(Utc::now()))
+            .order_by_desc(signing_keys::Column::NotAfter)
+            .all(self.db.conn())
+            .await
+    }
+}
+
+/// Process-local stand-in for `SeaOrmSigningKeysRepo` in tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemorySigningKeysRepo {
+    rows: std::sync::Mutex<Vec<signing_keys::Model>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl SigningKeysRepo for InMemorySigningKeysRepo {
+    async fn insert(
+        &self,
+        mut model: signing_keys::ActiveModel,
+    ) -> Result<signing_keys::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = Utc::now().into();
+        let row = signing_keys::Model {
+            id: rows.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            kid: model.kid.take().unwrap_or_default(),
+            algorithm: model.algorithm.take().unwrap_or_default(),
+            private_key_pem: model.private_key_pem.take().unwrap_or_default(),
+            public_jwk: model.public_jwk.take().unwrap_or_default(),
+            not_after: model.not_after.take().unwrap_or(now),
+            expires_at: model.expires_at.take().unwrap_or(now),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: model.created_by.take().flatten(),
+            updated_by: model.updated_by.take().flatten(),
+            deleted_by: None,
+            purge_at: None,
+        };
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn list_unexpired(&self) -> Result<Vec<signing_keys::Model>, sea_orm::DbErr> {
+        let now = Utc::now();
+        let mut keys: Vec<_> = self
+            .rows
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|row| row.deleted_at.is_none() && row.expires_at > now)
+            .cloned()
+            .collect();
+        keys.sort_by_key(|key| std::cmp::Reverse(key.not_after));
+        Ok(keys)
+    }
+}
@@ -0,0 +1,276 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use uuid::Uuid;
+
+use crate::{entities::team_memberships, state::DatabaseClient};
+
+#[async_trait]
+pub trait TeamMembershipsRepo: Send + Sync {
+    async fn insert_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        model: team_memberships::ActiveModel,
+    ) -> Result<team_memberships::Model, sea_orm::DbErr>;
+    async fn find(
+        &self,
+        team_account_id: i64,
+        member_account_id: i64,
+    ) -> Result<Option<team_memberships::Model>, sea_orm::DbErr>;
+    /// Members of the team, oldest first.
+    async fn list_for_team(
+        &self,
+        team_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr>;
+    /// Teams the account belongs to, oldest first.
+    async fn list_for_member(
+        &self,
+        member_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr>;
+    /// Like `list_for_team`, but locks the rows until `txn` ends, so that concurrent role
+    /// changes cannot each see another owner and leave the team with none.
+    async fn lock_team_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        team_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr>;
+    async fn update_role_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        id: i64,
+        role: &str,
+        updated_by: Uuid,
+    ) -> Result<team_memberships::Model, sea_orm::DbErr>;
+    /// Soft-deletes the membership.
+    async fn delete_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        id: i64,
+        deleted_by: Uuid,
+    ) -> Result<(), sea_orm::DbErr>;
+}
+
+pub struct SeaOrmTeamMembershipsRepo {
+    db: std::sync::Arc<dyn DatabaseClient>,
+}
+
+impl SeaOrmTeamMembershipsRepo {
+    pub fn new(db: std::sync::Arc<dyn DatabaseClient>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl TeamMembershipsRepo for SeaOrmTeamMembershipsRepo {
+    async fn insert_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        model: team_memberships::ActiveModel,
+    ) -> Result<team_memberships::Model, sea_orm::DbErr> {
+        model.insert(txn).await
+    }
+
+    async fn find(
+        &self,
+        team_account_id: i64,
+        member_account_id: i64,
+    ) -> Result<Option<team_memberships::Model>, sea_orm::DbErr> {
+        team_memberships::Entity::find()
+            .filter(team_memberships::Column::TeamAccountId.eq(team_account_id))
+            .filter(team_memberships::Column::MemberAccountId.eq(member_account_id))
+            .filter(team_memberships::Column::DeletedAt.is_null())
+            .one(self.db.conn())
+            .await
+    }
+
+    async fn list_for_team(
+        &self,
+        team_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr> {
+        team_memberships::Entity::find()
+            .filter(team_memberships::Column::TeamAccountId.eq(team_account_id))
+            .filter(team_memberships::Column::DeletedAt.is_null())
+            .order_by_asc(team_memberships::Column::Id)
+            .all(self.db.conn())
+            .await
+    }
+
+    async fn list_for_member(
+        &self,
+        member_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr> {
+        team_memberships::Entity::find()
+            .filter(team_memberships::Column::MemberAccountId.eq(member_account_id))
+            .filter(team_memberships::Column::DeletedAt.is_null())
+            .order_by_asc(team_memberships::Column::Id)
+            .all(self.db.conn())
+            .await
+    }
+
+    async fn lock_team_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        team_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr> {
+        team_memberships::Entity::find()
+            .filter(team_memberships::Column::TeamAccountId.eq(team_account_id))
+            .filter(team_memberships::Column::DeletedAt.is_null())
+            .order_by_asc(team_memberships::Column::Id)
+            .lock_exclusive()
+            .all(txn)
+            .await
+    }
+
+    async fn update_role_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        id: i64,
+        role: &str,
+        updated_by: Uuid,
+    ) -> Result<team_memberships::Model, sea_orm::DbErr> {
+        let model = team_memberships::ActiveModel {
+            id: Set(id),
+            role: Set(role.to_string()),
+            updated_by: Set(Some(updated_by)),
+            ..Default::default()
+        };
+        model.update(txn).await
+    }
+
+    async fn delete_with_txn(
+        &self,
+        txn: &DatabaseTransaction,
+        id: i64,
+        deleted_by: Uuid,
+    ) -> Result<(), sea_orm::DbErr> {
+        let model = team_memberships::ActiveModel {
+            id: Set(id),
+            deleted_at: Set(Some(Utc::now().into())),
+            deleted_by: Set(Some(deleted_by)),
+            updated_by: Set(Some(deleted_by)),
+            ..Default::default()
+        };
+        model.update(txn).await?;
+        Ok(())
+    }
+}
+
+/// Process-local stand-in for `SeaOrmTeamMembershipsRepo` in tests; `_with_txn` variants write
+/// straight through and nothing is locked.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryTeamMembershipsRepo {
+    rows: std::sync::Mutex<Vec<team_memberships::Model>>,
+}
+
+#[cfg(test)]
+impl InMemoryTeamMembershipsRepo {
+    fn filter(
+        &self,
+        predicate: impl Fn(&team_memberships::Model) -> bool,
+    ) -> Vec<team_memberships::Model> {
+        let rows = self.rows.lock().unwrap();
+        rows.iter()
+            .filter(|row| row.deleted_at.is_none())
+            .filter(|row| predicate(row))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl TeamMembershipsRepo for InMemoryTeamMembershipsRepo {
+    async fn insert_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        mut model: team_memberships::ActiveModel,
+    ) -> Result<team_memberships::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let now = Utc::now().into();
+        let row = team_memberships::Model {
+            id: rows.iter().map(|row| row.id).max().unwrap_or(0) + 1,
+            team_account_id: model.team_account_id.take().unwrap_or_default(),
+            member_account_id: model.member_account_id.take().unwrap_or_default(),
+            role: model.role.take().unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            created_by: model.created_by.take().flatten(),
+            updated_by: model.updated_by.take().flatten(),
+            deleted_by: None,
+            purge_at: None,
+        };
+        rows.push(row.clone());
+        Ok(row)
+    }
+
+    async fn find(
+        &self,
+        team_account_id: i64,
+        member_account_id: i64,
+    ) -> Result<Option<team_memberships::Model>, sea_orm::DbErr> {
+        Ok(self
+            .filter(|row| {
+                row.team_account_id == team_account_id && row.member_account_id == member_account_id
+            })
+            .into_iter()
+            .next())
+    }
+
+    async fn list_for_team(
+        &self,
+        team_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr> {
+        Ok(self.filter(|row| row.team_account_id == team_account_id))
+    }
+
+    async fn list_for_member(
+        &self,
+        member_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr> {
+        Ok(self.filter(|row| row.member_account_id == member_account_id))
+    }
+
+    async fn lock_team_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        team_account_id: i64,
+    ) -> Result<Vec<team_memberships::Model>, sea_orm::DbErr> {
+        self.list_for_team(team_account_id).await
+    }
+
+    async fn update_role_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        id: i64,
+        role: &str,
+        updated_by: Uuid,
+    ) -> Result<team_memberships::Model, sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        let Some(row) = rows.iter_mut().find(|row| row.id == id) else {
+            return Err(sea_orm::DbErr::RecordNotUpdated);
+        };
+        row.role = role.to_string();
+        row.updated_by = Some(updated_by);
+        row.updated_at = Utc::now().into();
+        Ok(row.clone())
+    }
+
+    async fn delete_with_txn(
+        &self,
+        _txn: &DatabaseTransaction,
+        id: i64,
+        deleted_by: Uuid,
+    ) -> Result<(), sea_orm::DbErr> {
+        let mut rows = self.rows.lock().unwrap();
+        if let Some(row) = rows.iter_mut().find(|row| row.id == id) {
+            row.deleted_at = Some(Utc::now().into());
+            row.deleted_by = Some(deleted_by);
+        }
+        Ok(())
+    }
+}
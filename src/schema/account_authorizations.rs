@@ -30,6 +30,12 @@ pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Re
                             .string()
                             .not_null(),
                     )
+                    .col(ColumnDef::new(AccountAuthorizations::FamilyId).uuid())
+                    .col(ColumnDef::new(AccountAuthorizations::Metadata).json_binary())
+                    .col(
+                        ColumnDef::new(AccountAuthorizations::LastUsedAt)
+                            .timestamp_with_time_zone(),
+                    )
                     .col(
                         ColumnDef::new(AccountAuthorizations::ExpiresAt).timestamp_with_time_zone(),
                     )
@@ -70,9 +76,37 @@ pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Re
 
     conn.execute(Statement::from_string(
         DbBackend::Postgres,
-        "CREATE UNIQUE INDEX IF NOT EXISTS account_authorizations_active_unique \
+        "ALTER TABLE account_authorizations \
+             ADD COLUMN IF NOT EXISTS family_id uuid, \
+             ADD COLUMN IF NOT EXISTS metadata jsonb, \
+             ADD COLUMN IF NOT EXISTS last_used_at timestamptz"
+            .to_string(),
+    ))
+    .await?;
+
+    // Only single-use verification tokens are limited to one active row per account; refresh
+    // tokens and similar grants are issued per client.
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "DROP INDEX IF EXISTS account_authorizations_active_unique".to_string(),
+    ))
+    .await?;
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS account_authorizations_single_active_unique \
              ON account_authorizations (account_id, token_type) \
-             WHERE revoked_at IS NULL AND deleted_at IS NULL"
+             WHERE revoked_at IS NULL AND deleted_at IS NULL \
+               AND token_type IN ('auth:verify_email', 'auth:reset_password', 'auth:mfa_pending')"
+            .to_string(),
+    ))
+    .await?;
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE INDEX IF NOT EXISTS account_authorizations_family_idx \
+             ON account_authorizations (family_id) \
+             WHERE family_id IS NOT NULL"
             .to_string(),
     ))
     .await?;
@@ -95,6 +129,9 @@ enum AccountAuthorizations {
     AccountId,
     TokenHash,
     TokenType,
+    FamilyId,
+    Metadata,
+    LastUsedAt,
     ExpiresAt,
     RevokedAt,
     CreatedAt,
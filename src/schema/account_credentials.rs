@@ -48,25 +48,41 @@ pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Re
                     .to_owned(),
             )
             .await?;
+    }
 
+    // Unlinked credentials are soft-deleted, so uniqueness only covers live rows; otherwise an
+    // unlinked identity could never be linked again.
+    for index in [
+        "account_credentials_unique_subject",
+        "account_credentials_unique_provider",
+        "account_credentials_single_provider_unique",
+    ] {
         conn.execute(Statement::from_string(
             DbBackend::Postgres,
-            "CREATE UNIQUE INDEX IF NOT EXISTS account_credentials_unique_provider \
-                 ON account_credentials (account_id, provider)"
-                .to_string(),
-        ))
-        .await?;
-
-        conn.execute(Statement::from_string(
-            DbBackend::Postgres,
-            "CREATE UNIQUE INDEX IF NOT EXISTS account_credentials_unique_subject \
-                 ON account_credentials (provider, provider_subject) \
-                 WHERE provider_subject IS NOT NULL"
-                .to_string(),
+            format!("DROP INDEX IF EXISTS {}", index),
         ))
         .await?;
     }
 
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS account_credentials_live_subject_unique \
+             ON account_credentials (provider, provider_subject) \
+             WHERE provider_subject IS NOT NULL AND deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    // WebAuthn allows several passkeys per account; every other provider stays one-per-account.
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS account_credentials_live_provider_unique \
+             ON account_credentials (account_id, provider) \
+             WHERE provider <> 'webauthn' AND deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
     Ok(())
 }
 
@@ -0,0 +1,82 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Result<(), DbErr> {
+    if !manager.has_table("account_roles").await? {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountRoles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountRoles::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountRoles::AccountId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountRoles::RoleId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountRoles::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(
+                        ColumnDef::new(AccountRoles::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(ColumnDef::new(AccountRoles::DeletedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(AccountRoles::CreatedBy).uuid())
+                    .col(ColumnDef::new(AccountRoles::UpdatedBy).uuid())
+                    .col(ColumnDef::new(AccountRoles::DeletedBy).uuid())
+                    .col(ColumnDef::new(AccountRoles::PurgeAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS account_roles_account_role_unique \
+             ON account_roles (account_id, role_id) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE INDEX IF NOT EXISTS account_roles_role_idx \
+             ON account_roles (role_id) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Iden)]
+enum AccountRoles {
+    Table,
+    Id,
+    AccountId,
+    RoleId,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    CreatedBy,
+    UpdatedBy,
+    DeletedBy,
+    PurgeAt,
+}
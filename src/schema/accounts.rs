@@ -24,6 +24,7 @@ pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Re
                     .col(ColumnDef::new(Accounts::AccountType).string().not_null())
                     .col(ColumnDef::new(Accounts::Username).string())
                     .col(ColumnDef::new(Accounts::Email).string())
+                    .col(ColumnDef::new(Accounts::EmailVerifiedAt).timestamp_with_time_zone())
                     .col(ColumnDef::new(Accounts::Phone).string())
                     .col(
                         ColumnDef::new(Accounts::CreatedAt)
@@ -71,6 +72,27 @@ pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Re
         .await?;
     }
 
+    if !manager.has_column("accounts", "email_verified_at").await? {
+        conn.execute(Statement::from_string(
+            DbBackend::Postgres,
+            "ALTER TABLE accounts ADD COLUMN IF NOT EXISTS email_verified_at timestamptz"
+                .to_string(),
+        ))
+        .await?;
+        // Logins used to be refused only while a verification token was pending, so every
+        // other account with an email counts as verified.
+        conn.execute(Statement::from_string(
+            DbBackend::Postgres,
+            "UPDATE accounts SET email_verified_at = now() \
+                 WHERE email IS NOT NULL AND NOT EXISTS ( \
+                     SELECT 1 FROM account_authorizations a \
+                     WHERE a.account_id = accounts.id AND a.token_type = 'auth:verify_email' \
+                       AND a.revoked_at IS NULL AND (a.expires_at IS NULL OR a.expires_at > now()))"
+                .to_string(),
+        ))
+        .await?;
+    }
+
     conn.execute(Statement::from_string(
         DbBackend::Postgres,
         "CREATE UNIQUE INDEX IF NOT EXISTS accounts_email_unique \
@@ -90,6 +112,7 @@ enum Accounts {
     AccountType,
     Username,
     Email,
+    EmailVerifiedAt,
     Phone,
     CreatedAt,
     UpdatedAt,
@@ -3,8 +3,15 @@ use sea_orm_migration::prelude::*;
 
 mod account_authorizations;
 mod account_credentials;
+mod account_roles;
 mod account_settings;
 mod accounts;
+mod oauth_clients;
+mod oauth_consents;
+mod permissions;
+mod roles;
+mod signing_keys;
+mod team_memberships;
 
 pub async fn apply(conn: &DatabaseConnection) -> Result<(), DbErr> {
     let manager = SchemaManager::new(conn);
@@ -19,6 +26,13 @@ pub async fn apply(conn: &DatabaseConnection) -> Result<(), DbErr> {
     account_settings::apply(&manager).await?;
     account_credentials::apply(&manager, conn).await?;
     account_authorizations::apply(&manager, conn).await?;
+    signing_keys::apply(&manager, conn).await?;
+    oauth_clients::apply(&manager, conn).await?;
+    oauth_consents::apply(&manager, conn).await?;
+    roles::apply(&manager, conn).await?;
+    permissions::apply(&manager, conn).await?;
+    account_roles::apply(&manager, conn).await?;
+    team_memberships::apply(&manager, conn).await?;
     apply_audit_invariants(conn).await?;
 
     Ok(())
@@ -45,6 +59,13 @@ $$ LANGUAGE plpgsql;
         "account_settings",
         "account_credentials",
         "account_authorizations",
+        "signing_keys",
+        "oauth_clients",
+        "oauth_consents",
+        "roles",
+        "permissions",
+        "account_roles",
+        "team_memberships",
     ] {
         let trigger_name = format!("trg_{}_set_updated_at", table);
         conn.execute(Statement::from_string(
@@ -0,0 +1,104 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Result<(), DbErr> {
+    if !manager.has_table("oauth_clients").await? {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthClients::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthClients::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(OauthClients::ClientId).string().not_null())
+                    .col(ColumnDef::new(OauthClients::ClientSecretHash).string())
+                    .col(ColumnDef::new(OauthClients::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(OauthClients::RedirectUris)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthClients::AllowedScopes)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthClients::IsPublic)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(OauthClients::FirstParty)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(ColumnDef::new(OauthClients::OwnerAccountId).big_integer())
+                    .col(
+                        ColumnDef::new(OauthClients::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(
+                        ColumnDef::new(OauthClients::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(ColumnDef::new(OauthClients::DeletedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(OauthClients::CreatedBy).uuid())
+                    .col(ColumnDef::new(OauthClients::UpdatedBy).uuid())
+                    .col(ColumnDef::new(OauthClients::DeletedBy).uuid())
+                    .col(ColumnDef::new(OauthClients::PurgeAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS oauth_clients_client_id_unique \
+             ON oauth_clients (client_id)"
+            .to_string(),
+    ))
+    .await?;
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE INDEX IF NOT EXISTS oauth_clients_owner_idx \
+             ON oauth_clients (owner_account_id) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Iden)]
+enum OauthClients {
+    Table,
+    Id,
+    ClientId,
+    ClientSecretHash,
+    Name,
+    RedirectUris,
+    AllowedScopes,
+    IsPublic,
+    FirstParty,
+    OwnerAccountId,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    CreatedBy,
+    UpdatedBy,
+    DeletedBy,
+    PurgeAt,
+}
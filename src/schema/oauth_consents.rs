@@ -0,0 +1,80 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Result<(), DbErr> {
+    if !manager.has_table("oauth_consents").await? {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthConsents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthConsents::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthConsents::AccountId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthConsents::ClientId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthConsents::Scopes)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthConsents::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(
+                        ColumnDef::new(OauthConsents::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(ColumnDef::new(OauthConsents::DeletedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(OauthConsents::CreatedBy).uuid())
+                    .col(ColumnDef::new(OauthConsents::UpdatedBy).uuid())
+                    .col(ColumnDef::new(OauthConsents::DeletedBy).uuid())
+                    .col(ColumnDef::new(OauthConsents::PurgeAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS oauth_consents_account_client_unique \
+             ON oauth_consents (account_id, client_id) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Iden)]
+enum OauthConsents {
+    Table,
+    Id,
+    AccountId,
+    ClientId,
+    Scopes,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    CreatedBy,
+    UpdatedBy,
+    DeletedBy,
+    PurgeAt,
+}
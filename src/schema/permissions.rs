@@ -0,0 +1,66 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Result<(), DbErr> {
+    if !manager.has_table("permissions").await? {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Permissions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Permissions::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Permissions::RoleId).big_integer().not_null())
+                    .col(ColumnDef::new(Permissions::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(Permissions::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(
+                        ColumnDef::new(Permissions::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(ColumnDef::new(Permissions::DeletedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(Permissions::CreatedBy).uuid())
+                    .col(ColumnDef::new(Permissions::UpdatedBy).uuid())
+                    .col(ColumnDef::new(Permissions::DeletedBy).uuid())
+                    .col(ColumnDef::new(Permissions::PurgeAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS permissions_role_name_unique \
+             ON permissions (role_id, name) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Iden)]
+enum Permissions {
+    Table,
+    Id,
+    RoleId,
+    Name,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    CreatedBy,
+    UpdatedBy,
+    DeletedBy,
+    PurgeAt,
+}
@@ -0,0 +1,66 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Result<(), DbErr> {
+    if !manager.has_table("roles").await? {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Roles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Roles::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Roles::Name).string().not_null())
+                    .col(ColumnDef::new(Roles::Description).string())
+                    .col(
+                        ColumnDef::new(Roles::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(
+                        ColumnDef::new(Roles::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(ColumnDef::new(Roles::DeletedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(Roles::CreatedBy).uuid())
+                    .col(ColumnDef::new(Roles::UpdatedBy).uuid())
+                    .col(ColumnDef::new(Roles::DeletedBy).uuid())
+                    .col(ColumnDef::new(Roles::PurgeAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS roles_name_unique \
+             ON roles (name) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Iden)]
+enum Roles {
+    Table,
+    Id,
+    Name,
+    Description,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    CreatedBy,
+    UpdatedBy,
+    DeletedBy,
+    PurgeAt,
+}
@@ -0,0 +1,93 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Result<(), DbErr> {
+    if !manager.has_table("signing_keys").await? {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SigningKeys::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SigningKeys::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(SigningKeys::Kid).string().not_null())
+                    .col(ColumnDef::new(SigningKeys::Algorithm).string().not_null())
+                    .col(ColumnDef::new(SigningKeys::PrivateKeyPem).text().not_null())
+                    .col(
+                        ColumnDef::new(SigningKeys::PublicJwk)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SigningKeys::NotAfter)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SigningKeys::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SigningKeys::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(
+                        ColumnDef::new(SigningKeys::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(ColumnDef::new(SigningKeys::DeletedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(SigningKeys::CreatedBy).uuid())
+                    .col(ColumnDef::new(SigningKeys::UpdatedBy).uuid())
+                    .col(ColumnDef::new(SigningKeys::DeletedBy).uuid())
+                    .col(ColumnDef::new(SigningKeys::PurgeAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS signing_keys_kid_unique ON signing_keys (kid)"
+            .to_string(),
+    ))
+    .await?;
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE INDEX IF NOT EXISTS signing_keys_expires_idx \
+             ON signing_keys (expires_at) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Iden)]
+enum SigningKeys {
+    Table,
+    Id,
+    Kid,
+    Algorithm,
+    PrivateKeyPem,
+    PublicJwk,
+    NotAfter,
+    ExpiresAt,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    CreatedBy,
+    UpdatedBy,
+    DeletedBy,
+    PurgeAt,
+}
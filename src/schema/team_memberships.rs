@@ -0,0 +1,92 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+pub async fn apply(manager: &SchemaManager<'_>, conn: &DatabaseConnection) -> Result<(), DbErr> {
+    if !manager.has_table("team_memberships").await? {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TeamMemberships::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TeamMemberships::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TeamMemberships::TeamAccountId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TeamMemberships::MemberAccountId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(TeamMemberships::Role).string().not_null())
+                    .col(
+                        ColumnDef::new(TeamMemberships::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(
+                        ColumnDef::new(TeamMemberships::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(SimpleExpr::Custom("now()".into())),
+                    )
+                    .col(ColumnDef::new(TeamMemberships::DeletedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(TeamMemberships::CreatedBy).uuid())
+                    .col(ColumnDef::new(TeamMemberships::UpdatedBy).uuid())
+                    .col(ColumnDef::new(TeamMemberships::DeletedBy).uuid())
+                    .col(ColumnDef::new(TeamMemberships::PurgeAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+
+        conn.execute(Statement::from_string(
+            DbBackend::Postgres,
+            "ALTER TABLE team_memberships ADD CONSTRAINT team_memberships_role_check \
+                 CHECK (role IN ('owner','admin','member'))"
+                .to_string(),
+        ))
+        .await?;
+    }
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE UNIQUE INDEX IF NOT EXISTS team_memberships_team_member_unique \
+             ON team_memberships (team_account_id, member_account_id) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    conn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "CREATE INDEX IF NOT EXISTS team_memberships_member_idx \
+             ON team_memberships (member_account_id) WHERE deleted_at IS NULL"
+            .to_string(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Iden)]
+enum TeamMemberships {
+    Table,
+    Id,
+    TeamAccountId,
+    MemberAccountId,
+    Role,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    CreatedBy,
+    UpdatedBy,
+    DeletedBy,
+    PurgeAt,
+}
@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{entities::accounts, repo::account_settings::AccountSettingsRepo};
+
+const MAX_NICKNAME_LEN: usize = 64;
+const MAX_AVATAR_URL_LEN: usize = 2048;
+
+/// Fields left `None` are kept; an empty string clears the field.
+pub struct UpdateAccountSettingsInput {
+    pub nickname: Option<String>,
+    pub avatar_url: Option<String>,
+    pub updated_by: Uuid,
+}
+
+#[derive(Debug, Default)]
+pub struct AccountSettings {
+    pub nickname: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct AccountSettingsError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl AccountSettingsError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("db_error", err.to_string())
+    }
+}
+
+/// Profile settings kept in `account_settings`. Accounts without a row read as empty settings;
+/// the row is created by the first update.
+#[async_trait]
+pub trait AccountSettingsService: Send + Sync {
+    async fn get(&self, account: &accounts::Model)
+        -> Result<AccountSettings, AccountSettingsError>;
+    async fn update(
+        &self,
+        account: &accounts::Model,
+        input: UpdateAccountSettingsInput,
+    ) -> Result<AccountSettings, AccountSettingsError>;
+}
+
+pub struct AccountSettingsServiceImpl {
+    settings_repo: Arc<dyn AccountSettingsRepo>,
+}
+
+impl AccountSettingsServiceImpl {
+    pub fn new(settings_repo: Arc<dyn AccountSettingsRepo>) -> Self {
+        Self { settings_repo }
+    }
+}
+
+fn validate_nickname(value: &str) -> Result<Option<String>, AccountSettingsError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if value.chars().count() > MAX_NICKNAME_LEN || value.chars().any(char::is_control) {
+        return Err(AccountSettingsError::new(
+            "invalid_nickname",
+            format!(
+                "nickname must be at most {} characters without control characters",
+                MAX_NICKNAME_LEN
+            ),
+        ));
+    }
+    Ok(Some(value.to_string()))
+}
+
+fn validate_avatar_url(value: &str) -> Result<Option<String>, AccountSettingsError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let valid = value.len() <= MAX_AVATAR_URL_LEN
+        && reqwest::Url::parse(value)
+            .is_ok_and(|url| matches!(url.scheme(), "https" | "http") && url.has_host());
+    if !valid {
+        return Err(AccountSettingsError::new(
+            "invalid_avatar_url",
+            "avatar_url must be an http or https URL",
+        ));
+    }
+    Ok(Some(value.to_string()))
+}
+
+#[async_trait]
+impl AccountSettingsService for AccountSettingsServiceImpl {
+    async fn get(
+        &self,
+        account: &accounts::Model,
+    ) -> Result<AccountSettings, AccountSettingsError> {
+        let settings = self
+            .settings_repo
+            .find(account.id)
+            .await
+            .map_err(AccountSettingsError::db)?;
+        Ok(settings
+            .map(|row| AccountSettings {
+                nickname: row.nickname,
+                avatar_url: row.avatar_url,
+            })
+            .unwrap_or_default())
+    }
+
+    async fn update(
+        &self,
+        account: &accounts::Model,
+        input: UpdateAccountSettingsInput,
+    ) -> Result<AccountSettings, AccountSettingsError> {
+        let current = self.get(account).await?;
+        let nickname = match input.nickname {
+            Some(value) => validate_nickname(&value)?,
+            None => current.nickname,
+        };
+        let avatar_url = match input.avatar_url {
+            Some(value) => validate_avatar_url(&value)?,
+            None => current.avatar_url,
+        };
+        let row = self
+            .settings_repo
+            .upsert(account.id, nickname, avatar_url, input.updated_by)
+            .await
+            .map_err(AccountSettingsError::db)?;
+        Ok(AccountSettings {
+            nickname: row.nickname,
+            avatar_url: row.avatar_url,
+        })
+    }
+}
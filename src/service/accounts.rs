@@ -4,7 +4,12 @@ use uuid::Uuid;
 
 use crate::{
     entities::{account_credentials, accounts},
-    repo::{account_credentials::AccountCredentialsRepo, accounts::AccountsRepo},
+    repo::{
+        account_authorizations::AccountAuthorizationsRepo,
+        account_credentials::AccountCredentialsRepo,
+        accounts::{AccountListQuery, AccountsRepo},
+    },
+    service::verification::{TOKEN_TYPE_RESET_PASSWORD, TOKEN_TYPE_VERIFY_EMAIL},
     state::DatabaseClient,
 };
 
@@ -13,16 +18,20 @@ pub struct CreateAccountInput {
     pub username: Option<String>,
     pub email: Option<String>,
     pub phone: Option<String>,
-    pub created_by: Option<Uuid>,
+    pub created_by: Uuid,
 }
 
 pub struct UpdateAccountInput {
     pub username: Option<String>,
     pub email: Option<String>,
     pub phone: Option<String>,
-    pub updated_by: Option<Uuid>,
+    pub updated_by: Uuid,
 }
 
+/// `username` and `email` only prefill a newly created account: a taken username gets a numeric
+/// suffix and a taken email is dropped rather than merged. `email` must be one the provider has
+/// verified, since the new account records it as verified. `metadata` is written to the
+/// credential on every call so provider profiles stay fresh.
 #[derive(Clone)]
 pub struct GetOrCreateByProviderSubjectInput {
     pub provider: String,
@@ -30,13 +39,25 @@ pub struct GetOrCreateByProviderSubjectInput {
     pub account_type: String,
     pub username: Option<String>,
     pub email: Option<String>,
+    pub metadata: Option<serde_json::Value>,
     pub created_by: Option<Uuid>,
 }
 
+pub struct AccountPage {
+    pub accounts: Vec<accounts::Model>,
+    /// Id of the last account, when more accounts match; pass it as `after_id` for the next
+    /// page.
+    pub next_cursor: Option<i64>,
+}
+
 #[async_trait]
 pub trait AccountsService: Send + Sync {
     async fn create(&self, input: CreateAccountInput) -> Result<accounts::Model, sea_orm::DbErr>;
     async fn get(&self, uid: Uuid) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
+    /// One page of at most `query.limit` accounts.
+    async fn list(&self, query: AccountListQuery) -> Result<AccountPage, sea_orm::DbErr>;
+    /// Changing the email clears its verification and revokes any verification or password
+    /// reset link still out for the previous address; the caller sends a new verification link.
     async fn update(
         &self,
         uid: Uuid,
@@ -45,20 +66,19 @@ pub trait AccountsService: Send + Sync {
     async fn delete(
         &self,
         uid: Uuid,
-        deleted_by: Option<Uuid>,
+        deleted_by: Uuid,
     ) -> Result<Option<accounts::Model>, sea_orm::DbErr>;
-    #[allow(dead_code)]
     async fn get_or_create_by_provider_subject(
         &self,
         input: GetOrCreateByProviderSubjectInput,
     ) -> Result<accounts::Model, sea_orm::DbErr>;
 }
 
-#[allow(dead_code)]
 pub struct AccountsServiceImpl {
     db: std::sync::Arc<dyn DatabaseClient>,
     accounts_repo: std::sync::Arc<dyn AccountsRepo>,
     credentials_repo: std::sync::Arc<dyn AccountCredentialsRepo>,
+    authorizations_repo: std::sync::Arc<dyn AccountAuthorizationsRepo>,
 }
 
 impl AccountsServiceImpl {
@@ -66,11 +86,13 @@ impl AccountsServiceImpl {
         db: std::sync::Arc<dyn DatabaseClient>,
         accounts_repo: std::sync::Arc<dyn AccountsRepo>,
         credentials_repo: std::sync::Arc<dyn AccountCredentialsRepo>,
+        authorizations_repo: std::sync::Arc<dyn AccountAuthorizationsRepo>,
     ) -> Self {
         Self {
             db,
             accounts_repo,
             credentials_repo,
+            authorizations_repo,
         }
     }
 }
@@ -84,8 +106,8 @@ impl AccountsService for AccountsServiceImpl {
             username: sea_orm::Set(input.username),
             email: sea_orm::Set(input.email),
             phone: sea_orm::Set(input.phone),
-            created_by: sea_orm::Set(input.created_by),
-            updated_by: sea_orm::Set(input.created_by),
+            created_by: sea_orm::Set(Some(input.created_by)),
+            updated_by: sea_orm::Set(Some(input.created_by)),
             ..Default::default()
         };
 
@@ -96,6 +118,27 @@ impl AccountsService for AccountsServiceImpl {
         self.accounts_repo.find_by_uid(uid).await
     }
 
+    async fn list(&self, query: AccountListQuery) -> Result<AccountPage, sea_orm::DbErr> {
+        // One extra row tells whether there is a next page.
+        let mut accounts = self
+            .accounts_repo
+            .list(&AccountListQuery {
+                limit: query.limit + 1,
+                ..query.clone()
+            })
+            .await?;
+        let next_cursor = if accounts.len() as u64 > query.limit {
+            accounts.truncate(query.limit as usize);
+            accounts.last().map(|account| account.id)
+        } else {
+            None
+        };
+        Ok(AccountPage {
+            accounts,
+            next_cursor,
+        })
+    }
+
     async fn update(
         &self,
         uid: Uuid,
@@ -105,6 +148,17 @@ impl AccountsService for AccountsServiceImpl {
             return Ok(None);
         };
 
+        let email_changed = input.email.as_deref().is_some_and(|email| {
+            model.email.as_deref().map(str::to_lowercase) != Some(email.to_lowercase())
+        });
+        if email_changed {
+            for token_type in [TOKEN_TYPE_VERIFY_EMAIL, TOKEN_TYPE_RESET_PASSWORD] {
+                self.authorizations_repo
+                    .revoke_all_for_account(model.id, token_type)
+                    .await?;
+            }
+        }
+
         let mut active: accounts::ActiveModel = model.into();
         if let Some(username) = input.username {
             active.username = sea_orm::Set(Some(username));
@@ -112,10 +166,13 @@ impl AccountsService for AccountsServiceImpl {
         if let Some(email) = input.email {
             active.email = sea_orm::Set(Some(email));
         }
+        if email_changed {
+            active.email_verified_at = sea_orm::Set(None);
+        }
         if let Some(phone) = input.phone {
             active.phone = sea_orm::Set(Some(phone));
         }
-        active.updated_by = sea_orm::Set(input.updated_by);
+        active.updated_by = sea_orm::Set(Some(input.updated_by));
 
         let updated = self.accounts_repo.update(active).await?;
         Ok(Some(updated))
@@ -124,20 +181,16 @@ impl AccountsService for AccountsServiceImpl {
     async fn delete(
         &self,
         uid: Uuid,
-        deleted_by: Option<Uuid>,
+        deleted_by: Uuid,
     ) -> Result<Option<accounts::Model>, sea_orm::DbErr> {
         let Some(model) = self.accounts_repo.find_by_uid(uid).await? else {
             return Ok(None);
         };
 
-        let actor = deleted_by
-            .or(model.updated_by)
-            .or(model.created_by)
-            .unwrap_or_else(Uuid::nil);
         let mut active: accounts::ActiveModel = model.into();
         active.deleted_at = sea_orm::Set(Some(chrono::Utc::now().into()));
-        active.deleted_by = sea_orm::Set(Some(actor));
-        active.updated_by = sea_orm::Set(Some(actor));
+        active.deleted_by = sea_orm::Set(Some(deleted_by));
+        active.updated_by = sea_orm::Set(Some(deleted_by));
 
         let updated = self.accounts_repo.update(active).await?;
         Ok(Some(updated))
@@ -187,10 +240,14 @@ async fn get_or_create_by_provider_subject_txn(
         .find_by_provider_subject_with_txn(txn, &input.provider, &input.provider_subject)
         .await?
     {
-        if let Some(account) = accounts_repo
-            .find_by_id_with_txn(txn, credential.account_id)
-            .await?
-        {
+        let account_id = credential.account_id;
+        if let Some(metadata) = &input.metadata {
+            let mut active: account_credentials::ActiveModel = credential.into();
+            active.metadata = sea_orm::Set(Some(metadata.clone()));
+            credentials_repo.update_with_txn(txn, active).await?;
+        }
+
+        if let Some(account) = accounts_repo.find_by_id_with_txn(txn, account_id).await? {
             return Ok(account);
         }
 
@@ -200,11 +257,29 @@ async fn get_or_create_by_provider_subject_txn(
         )));
     }
 
+    let username = match &input.username {
+        Some(value) => available_username(txn, accounts_repo, value).await?,
+        None => None,
+    };
+    let email = match input
+        .email
+        .as_deref()
+        .map(|value| value.trim().to_lowercase())
+    {
+        Some(value) if !value.is_empty() => accounts_repo
+            .find_by_email_with_txn(txn, &value)
+            .await?
+            .is_none()
+            .then_some(value),
+        _ => None,
+    };
+
     let account_model = accounts::ActiveModel {
         uid: sea_orm::Set(Uuid::new_v4()),
         account_type: sea_orm::Set(input.account_type.clone()),
-        username: sea_orm::Set(input.username.clone()),
-        email: sea_orm::Set(input.email.clone()),
+        username: sea_orm::Set(username),
+        email_verified_at: sea_orm::Set(email.as_ref().map(|_| chrono::Utc::now().into())),
+        email: sea_orm::Set(email),
         phone: sea_orm::Set(None),
         created_by: sea_orm::Set(input.created_by),
         updated_by: sea_orm::Set(input.created_by),
@@ -218,7 +293,7 @@ async fn get_or_create_by_provider_subject_txn(
         provider: sea_orm::Set(input.provider.clone()),
         provider_subject: sea_orm::Set(Some(input.provider_subject.clone())),
         password_hash: sea_orm::Set(None),
-        metadata: sea_orm::Set(None),
+        metadata: sea_orm::Set(input.metadata.clone()),
         created_by: sea_orm::Set(input.created_by),
         updated_by: sea_orm::Set(input.created_by),
         ..Default::default()
@@ -231,6 +306,36 @@ async fn get_or_create_by_provider_subject_txn(
     Ok(account)
 }
 
+/// Picks `base`, then `base-2` ... `base-9`, then a random suffix, skipping names already taken.
+async fn available_username(
+    txn: &DatabaseTransaction,
+    accounts_repo: &dyn AccountsRepo,
+    base: &str,
+) -> Result<Option<String>, sea_orm::DbErr> {
+    let base = base.trim().to_lowercase();
+    if base.is_empty() {
+        return Ok(None);
+    }
+
+    let candidates = std::iter::once(base.clone())
+        .chain((2..10).map(|n| format!("{}-{}", base, n)))
+        .chain(std::iter::once(format!(
+            "{}-{}",
+            base,
+            &Uuid::new_v4().simple().to_string()[..6]
+        )));
+    for candidate in candidates {
+        if accounts_repo
+            .find_by_username_with_txn(txn, &candidate)
+            .await?
+            .is_none()
+        {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -274,6 +379,7 @@ mod tests {
             account_type: "user".to_string(),
             username,
             email: None,
+            metadata: None,
             created_by: None,
         };
         let txn = db.conn().begin().await?;
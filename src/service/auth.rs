@@ -11,7 +11,16 @@ use crate::{
         account_authorizations::AccountAuthorizationsRepo,
         account_credentials::AccountCredentialsRepo, accounts::AccountsRepo,
     },
-    service::{session::SessionService, verification::VerificationService},
+    service::{
+        mfa::MfaService,
+        personal_access_tokens::TOKEN_TYPE_PERSONAL_ACCESS,
+        refresh_tokens::TOKEN_TYPE_REFRESH,
+        session::{SessionContext, SessionService},
+        verification::{
+            VerificationService, TOKEN_TYPE_MFA_PENDING, TOKEN_TYPE_RESET_PASSWORD,
+            TOKEN_TYPE_VERIFY_EMAIL,
+        },
+    },
     state::DatabaseClient,
 };
 
@@ -45,6 +54,41 @@ pub struct LoginOutput {
     pub session_id: String,
 }
 
+#[derive(Debug)]
+pub struct MfaChallenge {
+    pub mfa_token: String,
+    pub expires_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum LoginResult {
+    Authenticated(Box<LoginOutput>),
+    /// The password was correct but a second factor is required before a session is created.
+    MfaRequired(MfaChallenge),
+}
+
+pub struct ChangePasswordInput<'a> {
+    pub account_uid: uuid::Uuid,
+    pub current_session_id: &'a str,
+    pub current_password: Option<&'a str>,
+    pub new_password: &'a str,
+    pub sign_out_other_sessions: bool,
+}
+
+/// What `request_password_reset` sends to the address.
+#[derive(Debug)]
+pub enum PasswordResetOutput {
+    Reset {
+        email: String,
+        reset_token: String,
+    },
+    /// The address was never verified, so it gets a verification link instead of a reset link.
+    VerifyEmail {
+        email: String,
+        verify_token: String,
+    },
+}
+
 #[async_trait]
 pub trait AuthService: Send + Sync {
     async fn register(
@@ -53,7 +97,36 @@ pub trait AuthService: Send + Sync {
         username: Option<&str>,
         password: &str,
     ) -> Result<RegisterOutput, AuthError>;
-    async fn login(&self, identifier: &str, password: &str) -> Result<LoginOutput, AuthError>;
+    async fn login(
+        &self,
+        identifier: &str,
+        password: &str,
+        context: SessionContext,
+    ) -> Result<LoginResult, AuthError>;
+    /// Consumes a verify-email token and records the account email as verified.
+    async fn verify_email(&self, token: &str) -> Result<accounts::Model, AuthError>;
+    async fn complete_mfa_login(
+        &self,
+        mfa_token: &str,
+        code: &str,
+        context: SessionContext,
+    ) -> Result<LoginOutput, AuthError>;
+    /// Returns `None` when no account matches so callers can respond uniformly. Reset links only
+    /// go to verified addresses.
+    async fn request_password_reset(
+        &self,
+        email: &str,
+    ) -> Result<Option<PasswordResetOutput>, AuthError>;
+    /// Signs the account out everywhere: sessions, refresh tokens and personal access tokens.
+    async fn reset_password(
+        &self,
+        token: &str,
+        new_password: &str,
+    ) -> Result<accounts::Model, AuthError>;
+    /// Accounts without a password credential (e.g. GitHub-only) may set one without
+    /// `current_password`. Signing out other sessions also revokes every refresh token and
+    /// personal access token, since neither is tied to a session.
+    async fn change_password(&self, input: ChangePasswordInput<'_>) -> Result<(), AuthError>;
 }
 
 pub struct AuthServiceImpl {
@@ -63,6 +136,7 @@ pub struct AuthServiceImpl {
     authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
     sessions: Arc<dyn SessionService>,
     verification: Arc<dyn VerificationService>,
+    mfa: Arc<dyn MfaService>,
 }
 
 impl AuthServiceImpl {
@@ -73,6 +147,7 @@ impl AuthServiceImpl {
         authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
         sessions: Arc<dyn SessionService>,
         verification: Arc<dyn VerificationService>,
+        mfa: Arc<dyn MfaService>,
     ) -> Self {
         Self {
             db,
@@ -81,6 +156,7 @@ impl AuthServiceImpl {
             authorizations_repo,
             sessions,
             verification,
+            mfa,
         }
     }
 
@@ -177,6 +253,59 @@ impl AuthServiceImpl {
         }
         Ok(())
     }
+
+    async fn set_password_credential(
+        &self,
+        account: &accounts::Model,
+        password_hash: String,
+    ) -> Result<(), AuthError> {
+        let existing = self
+            .credentials_repo
+            .find_by_account_and_provider(account.id, PROVIDER_PASSWORD)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+
+        match existing {
+            Some(credential) => {
+                let mut active: account_credentials::ActiveModel = credential.into();
+                active.password_hash = sea_orm::Set(Some(password_hash));
+                active.updated_by = sea_orm::Set(Some(account.uid));
+                self.credentials_repo
+                    .update(active)
+                    .await
+                    .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+            }
+            None => {
+                let model = account_credentials::ActiveModel {
+                    account_id: sea_orm::Set(account.id),
+                    provider: sea_orm::Set(PROVIDER_PASSWORD.to_string()),
+                    provider_subject: sea_orm::Set(account.email.clone()),
+                    password_hash: sea_orm::Set(Some(password_hash)),
+                    metadata: sea_orm::Set(None),
+                    created_by: sea_orm::Set(Some(account.uid)),
+                    updated_by: sea_orm::Set(Some(account.uid)),
+                    ..Default::default()
+                };
+                self.credentials_repo
+                    .insert(model)
+                    .await
+                    .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn mark_email_verified(
+        &self,
+        account: accounts::Model,
+    ) -> Result<accounts::Model, AuthError> {
+        let mut active: accounts::ActiveModel = account.into();
+        active.email_verified_at = sea_orm::Set(Some(Utc::now().into()));
+        self.accounts_repo
+            .update(active)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))
+    }
 }
 
 #[async_trait]
@@ -246,7 +375,7 @@ impl AuthService for AuthServiceImpl {
 
         let verification = self
             .verification
-            .create_email_verification(account.id)
+            .create_token(account.id, TOKEN_TYPE_VERIFY_EMAIL)
             .await
             .map_err(|err| AuthError::new(err.code, err.message))?;
 
@@ -257,7 +386,12 @@ impl AuthService for AuthServiceImpl {
         })
     }
 
-    async fn login(&self, identifier: &str, password: &str) -> Result<LoginOutput, AuthError> {
+    async fn login(
+        &self,
+        identifier: &str,
+        password: &str,
+        context: SessionContext,
+    ) -> Result<LoginResult, AuthError> {
         let normalized = identifier.trim().to_lowercase();
         if normalized.is_empty() {
             return Err(AuthError::new("invalid_credentials", "invalid credentials"));
@@ -295,25 +429,92 @@ impl AuthService for AuthServiceImpl {
 
         Self::verify_password(&hash, password)?;
 
-        let pending_verification = self
-            .authorizations_repo
-            .find_active_by_account_and_type(
-                account.id,
-                self.verification.email_verification_type(),
-            )
-            .await
-            .map_err(|err| AuthError::new("db_error", err.to_string()))?;
-
-        if pending_verification.is_some() {
+        if account.email.is_some() && account.email_verified_at.is_none() {
             return Err(AuthError::new(
                 "email_not_verified",
                 "email verification required",
             ));
         }
 
+        let mfa_enabled = self
+            .mfa
+            .is_totp_enabled(account.id)
+            .await
+            .map_err(|err| AuthError::new(err.code, err.message))?;
+        if mfa_enabled {
+            let challenge = self
+                .verification
+                .create_token(account.id, TOKEN_TYPE_MFA_PENDING)
+                .await
+                .map_err(|err| AuthError::new(err.code, err.message))?;
+            return Ok(LoginResult::MfaRequired(MfaChallenge {
+                mfa_token: challenge.token,
+                expires_at: challenge.expires_at,
+            }));
+        }
+
+        let session_id = self
+            .sessions
+            .create(account.uid, context)
+            .await
+            .map_err(|err| AuthError::new("session_error", err.to_string()))?;
+
+        Ok(LoginResult::Authenticated(Box::new(LoginOutput {
+            account,
+            session_id,
+        })))
+    }
+
+    async fn verify_email(&self, token: &str) -> Result<accounts::Model, AuthError> {
+        let account_id = self
+            .verification
+            .consume_token(token, TOKEN_TYPE_VERIFY_EMAIL)
+            .await
+            .map_err(|err| AuthError::new(err.code, err.message))?;
+        let account = self
+            .accounts_repo
+            .find_by_id(account_id)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?
+            .ok_or_else(|| AuthError::new("invalid_token", "verification token is invalid"))?;
+        self.mark_email_verified(account).await
+    }
+
+    async fn complete_mfa_login(
+        &self,
+        mfa_token: &str,
+        code: &str,
+        context: SessionContext,
+    ) -> Result<LoginOutput, AuthError> {
+        // Challenges are single-use: a wrong code means starting over from the password step,
+        // which bounds guessing to one attempt per password verification.
+        let account_id = self
+            .verification
+            .consume_token(mfa_token, TOKEN_TYPE_MFA_PENDING)
+            .await
+            .map_err(|_| AuthError::new("invalid_mfa_token", "mfa challenge is invalid"))?;
+
+        let account = self
+            .accounts_repo
+            .find_by_id(account_id)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+
+        let Some(account) = account else {
+            return Err(AuthError::new(
+                "invalid_mfa_token",
+                "mfa challenge is invalid",
+            ));
+        };
+
+        self.mfa
+            .verify_code(account.id, code)
+            .await
+            .map_err(|err| AuthError::new(err.code, err.message))?;
+
         let session_id = self
             .sessions
-            .create(account.uid)
+            .create(account.uid, context)
             .await
             .map_err(|err| AuthError::new("session_error", err.to_string()))?;
 
@@ -322,4 +523,152 @@ impl AuthService for AuthServiceImpl {
             session_id,
         })
     }
+
+    async fn request_password_reset(
+        &self,
+        email: &str,
+    ) -> Result<Option<PasswordResetOutput>, AuthError> {
+        let email = Self::normalize_email(email)?;
+        let account = self
+            .accounts_repo
+            .find_by_email(&email)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+
+        let Some(account) = account else {
+            return Ok(None);
+        };
+        let email = account.email.unwrap_or(email);
+
+        if account.email_verified_at.is_none() {
+            let verification = self
+                .verification
+                .create_token(account.id, TOKEN_TYPE_VERIFY_EMAIL)
+                .await
+                .map_err(|err| AuthError::new(err.code, err.message))?;
+            return Ok(Some(PasswordResetOutput::VerifyEmail {
+                email,
+                verify_token: verification.token,
+            }));
+        }
+
+        let reset = self
+            .verification
+            .create_token(account.id, TOKEN_TYPE_RESET_PASSWORD)
+            .await
+            .map_err(|err| AuthError::new(err.code, err.message))?;
+
+        Ok(Some(PasswordResetOutput::Reset {
+            email,
+            reset_token: reset.token,
+        }))
+    }
+
+    async fn reset_password(
+        &self,
+        token: &str,
+        new_password: &str,
+    ) -> Result<accounts::Model, AuthError> {
+        Self::validate_password(new_password)?;
+
+        let account_id = self
+            .verification
+            .consume_token(token, TOKEN_TYPE_RESET_PASSWORD)
+            .await
+            .map_err(|err| AuthError::new(err.code, err.message))?;
+
+        let account = self
+            .accounts_repo
+            .find_by_id(account_id)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+
+        let Some(account) = account else {
+            return Err(AuthError::new(
+                "invalid_token",
+                "verification token is invalid",
+            ));
+        };
+
+        let password_hash = Self::hash_password(new_password)?;
+        self.set_password_credential(&account, password_hash)
+            .await?;
+
+        // Reset links only go to verified addresses, so a pending verification is moot.
+        if let Some(pending) = self
+            .authorizations_repo
+            .find_active_by_account_and_type(account.id, TOKEN_TYPE_VERIFY_EMAIL)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?
+        {
+            self.authorizations_repo
+                .revoke_by_id(pending.id)
+                .await
+                .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+        }
+        let account = self.mark_email_verified(account).await?;
+
+        self.sessions
+            .delete_all_for_account(account.uid, None)
+            .await
+            .map_err(|err| AuthError::new("session_error", err.to_string()))?;
+        for token_type in [TOKEN_TYPE_REFRESH, TOKEN_TYPE_PERSONAL_ACCESS] {
+            self.authorizations_repo
+                .revoke_all_for_account(account.id, token_type)
+                .await
+                .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+        }
+
+        Ok(account)
+    }
+
+    async fn change_password(&self, input: ChangePasswordInput<'_>) -> Result<(), AuthError> {
+        let account = self
+            .accounts_repo
+            .find_by_uid(input.account_uid)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+
+        let Some(account) = account else {
+            return Err(AuthError::new("account_not_found", "account not found"));
+        };
+
+        let credential = self
+            .credentials_repo
+            .find_by_account_and_provider(account.id, PROVIDER_PASSWORD)
+            .await
+            .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+
+        if let Some(hash) = credential.and_then(|credential| credential.password_hash) {
+            let Some(current_password) = input.current_password else {
+                return Err(AuthError::new(
+                    "current_password_required",
+                    "current password is required",
+                ));
+            };
+            Self::verify_password(&hash, current_password).map_err(|_| {
+                AuthError::new("invalid_current_password", "current password is incorrect")
+            })?;
+        }
+
+        Self::validate_password(input.new_password)?;
+        let password_hash = Self::hash_password(input.new_password)?;
+        self.set_password_credential(&account, password_hash)
+            .await?;
+
+        if input.sign_out_other_sessions {
+            self.sessions
+                .delete_all_for_account(account.uid, Some(input.current_session_id))
+                .await
+                .map_err(|err| AuthError::new("session_error", err.to_string()))?;
+            for token_type in [TOKEN_TYPE_REFRESH, TOKEN_TYPE_PERSONAL_ACCESS] {
+                self.authorizations_repo
+                    .revoke_all_for_account(account.id, token_type)
+                    .await
+                    .map_err(|err| AuthError::new("db_error", err.to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
 }
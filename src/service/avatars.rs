@@ -0,0 +1,273 @@
+use async_trait::async_trait;
+use image::{imageops::FilterType, ImageFormat, ImageReader, Limits};
+use std::{io::Cursor, sync::Arc};
+use uuid::Uuid;
+
+use crate::{
+    entities::accounts,
+    repo::account_settings::AccountSettingsRepo,
+    service::storage::{valid_key, ObjectStorage, StorageError},
+};
+
+/// Storage prefix of every avatar; `avatar_url_base` maps onto it.
+const KEY_PREFIX: &str = "avatars";
+const ACCEPTED_TYPES: &[(&str, ImageFormat)] = &[
+    ("image/png", ImageFormat::Png),
+    ("image/jpeg", ImageFormat::Jpeg),
+    ("image/gif", ImageFormat::Gif),
+    ("image/webp", ImageFormat::WebP),
+];
+/// Larger images are rejected before decoding, so small files cannot expand into huge bitmaps.
+const MAX_DIMENSION: u32 = 4096;
+/// Decoder allocation cap: an RGBA bitmap at `MAX_DIMENSION` plus as much again for the
+/// decoder's working buffers.
+const MAX_DECODE_ALLOC: u64 = MAX_DIMENSION as u64 * MAX_DIMENSION as u64 * 4 * 2;
+
+pub struct AvatarUpload {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Avatar {
+    /// The largest thumbnail, as written to `account_settings.avatar_url`.
+    pub avatar_url: String,
+    /// `(size, url)` for every thumbnail, smallest first.
+    pub thumbnails: Vec<(u32, String)>,
+}
+
+#[derive(Debug)]
+pub struct AvatarError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl AvatarError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("db_error", err.to_string())
+    }
+
+    fn storage(err: StorageError) -> Self {
+        Self::new("storage_error", err.message)
+    }
+}
+
+/// Avatars are decoded, cropped to a centred square and stored as PNG thumbnails of each
+/// configured size under `avatars/<account uid>/<version>/<size>.png`. Every upload gets a new
+/// version, so thumbnail URLs never change content and can be cached forever; the previous
+/// version is deleted once the new one is in place.
+#[async_trait]
+pub trait AvatarService: Send + Sync {
+    /// Creates the settings row if the account has none.
+    async fn upload(
+        &self,
+        account: &accounts::Model,
+        upload: AvatarUpload,
+        updated_by: Uuid,
+    ) -> Result<Avatar, AvatarError>;
+    /// The PNG at `path` below `avatar_url_base`, if any.
+    async fn thumbnail(&self, path: &str) -> Result<Option<Vec<u8>>, AvatarError>;
+    fn max_bytes(&self) -> usize;
+}
+
+pub struct AvatarServiceImpl {
+    storage: Arc<dyn ObjectStorage>,
+    settings_repo: Arc<dyn AccountSettingsRepo>,
+    max_bytes: usize,
+    sizes: Vec<u32>,
+    url_base: String,
+}
+
+impl AvatarServiceImpl {
+    pub fn new(
+        storage: Arc<dyn ObjectStorage>,
+        settings_repo: Arc<dyn AccountSettingsRepo>,
+        max_bytes: u64,
+        sizes: Vec<u32>,
+        url_base: String,
+    ) -> Self {
+        Self {
+            storage,
+            settings_repo,
+            max_bytes: usize::try_from(max_bytes).unwrap_or(usize::MAX),
+            sizes,
+            url_base,
+        }
+    }
+
+    fn key(account_uid: Uuid, version: &str, size: u32) -> String {
+        format!("{}/{}/{}/{}.png", KEY_PREFIX, account_uid, version, size)
+    }
+
+    fn url(&self, account_uid: Uuid, version: &str, size: u32) -> String {
+        format!("{}/{}/{}/{}.png", self.url_base, account_uid, version, size)
+    }
+
+    async fn delete_version(&self, account_uid: Uuid, version: &str) {
+        for &size in &self.sizes {
+            let key = Self::key(account_uid, version, size);
+            if let Err(err) = self.storage.delete(&key).await {
+                eprintln!("warning: failed to delete avatar {}: {}", key, err);
+            }
+        }
+    }
+
+    /// The version of a URL this service produced for the account; other URLs, such as one set
+    /// through the settings API, are left alone.
+    fn own_version<'a>(&self, account_uid: Uuid, url: &'a str) -> Option<&'a str> {
+        let rest = url.strip_prefix(&format!("{}/{}/", self.url_base, account_uid))?;
+        let (version, _) = rest.split_once('/')?;
+        valid_key(version).then_some(version)
+    }
+}
+
+/// Decodes the image and encodes a square PNG for each of `sizes`.
+fn thumbnails(
+    bytes: Vec<u8>,
+    format: ImageFormat,
+    sizes: &[u32],
+) -> Result<Vec<(u32, Vec<u8>)>, AvatarError> {
+    let invalid = |err: image::ImageError| AvatarError::new("invalid_image", err.to_string());
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(invalid)?;
+
+    let side = image.width().min(image.height());
+    if side == 0 {
+        return Err(AvatarError::new("invalid_image", "image is empty"));
+    }
+    let square = image.crop_imm(
+        (image.width() - side) / 2,
+        (image.height() - side) / 2,
+        side,
+        side,
+    );
+    sizes
+        .iter()
+        .map(|&size| {
+            let mut png = Cursor::new(Vec::new());
+            square
+                .resize_exact(size, size, FilterType::Lanczos3)
+                .write_to(&mut png, ImageFormat::Png)
+                .map_err(invalid)?;
+            Ok((size, png.into_inner()))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl AvatarService for AvatarServiceImpl {
+    async fn upload(
+        &self,
+        account: &accounts::Model,
+        upload: AvatarUpload,
+        updated_by: Uuid,
+    ) -> Result<Avatar, AvatarError> {
+        if upload.bytes.len() > self.max_bytes {
+            return Err(AvatarError::new(
+                "payload_too_large",
+                format!("avatars are limited to {} bytes", self.max_bytes),
+            ));
+        }
+        let Some(&(_, format)) = ACCEPTED_TYPES
+            .iter()
+            .find(|(content_type, _)| *content_type == upload.content_type)
+        else {
+            return Err(AvatarError::new(
+                "unsupported_media_type",
+                "avatars must be image/png, image/jpeg, image/gif or image/webp",
+            ));
+        };
+        if image::guess_format(&upload.bytes).ok() != Some(format) {
+            return Err(AvatarError::new(
+                "invalid_image",
+                format!("content is not {}", upload.content_type),
+            ));
+        }
+
+        let sizes = self.sizes.clone();
+        let thumbnails =
+            tokio::task::spawn_blocking(move || thumbnails(upload.bytes, format, &sizes))
+                .await
+                .map_err(|err| AvatarError::new("server_error", err.to_string()))??;
+
+        let version = Uuid::new_v4().simple().to_string();
+        for (size, png) in thumbnails {
+            let key = Self::key(account.uid, &version, size);
+            if let Err(err) = self.storage.put(&key, png, "image/png").await {
+                self.delete_version(account.uid, &version).await;
+                return Err(AvatarError::storage(err));
+            }
+        }
+
+        let largest = self.sizes.last().copied().unwrap_or_default();
+        let avatar_url = self.url(account.uid, &version, largest);
+        // Swap only the URL, retrying until no other write slips in between, so the nickname is
+        // never overwritten and the version removed below is exactly the one replaced.
+        let previous_url = loop {
+            let current = match self.settings_repo.find(account.id).await {
+                Ok(row) => row.and_then(|row| row.avatar_url),
+                Err(err) => {
+                    self.delete_version(account.uid, &version).await;
+                    return Err(AvatarError::db(err));
+                }
+            };
+            match self
+                .settings_repo
+                .replace_avatar_url(
+                    account.id,
+                    current.as_deref(),
+                    avatar_url.clone(),
+                    updated_by,
+                )
+                .await
+            {
+                Ok(true) => break current,
+                Ok(false) => continue,
+                Err(err) => {
+                    self.delete_version(account.uid, &version).await;
+                    return Err(AvatarError::db(err));
+                }
+            }
+        };
+
+        if let Some(previous) = previous_url
+            .as_deref()
+            .and_then(|url| self.own_version(account.uid, url))
+        {
+            self.delete_version(account.uid, previous).await;
+        }
+
+        Ok(Avatar {
+            avatar_url,
+            thumbnails: self
+                .sizes
+                .iter()
+                .map(|&size| (size, self.url(account.uid, &version, size)))
+                .collect(),
+        })
+    }
+
+    async fn thumbnail(&self, path: &str) -> Result<Option<Vec<u8>>, AvatarError> {
+        let key = format!("{}/{}", KEY_PREFIX, path);
+        if !valid_key(&key) || !key.ends_with(".png") {
+            return Ok(None);
+        }
+        self.storage.get(&key).await.map_err(AvatarError::storage)
+    }
+
+    fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+}
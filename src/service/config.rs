@@ -1,6 +1,6 @@
 use std::{env, sync::Arc};
 
-use crate::config::Config;
+use crate::config::{Config, OidcProviderConfig, S3StorageConfig};
 
 pub trait ConfigService: Send + Sync {
     fn port(&self) -> u16;
@@ -57,6 +57,72 @@ impl ConfigServiceImpl {
         Self::env_nonempty(key).map(|value| value.to_ascii_lowercase())
     }
 
+    /// Reads `OIDC_PROVIDERS=google,corp` and the matching `OIDC_GOOGLE_ISSUER`,
+    /// `OIDC_GOOGLE_CLIENT_ID`, ... variables. Incomplete providers are skipped with a warning.
+    fn oidc_providers() -> Vec<OidcProviderConfig> {
+        let Some(names) = Self::env_lower_nonempty("OIDC_PROVIDERS") else {
+            return Vec::new();
+        };
+
+        let mut providers = Vec::new();
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let prefix = format!("OIDC_{}", name.to_ascii_uppercase().replace('-', "_"));
+            let issuer = Self::env_nonempty(&format!("{}_ISSUER", prefix));
+            let client_id = Self::env_nonempty(&format!("{}_CLIENT_ID", prefix));
+            let redirect_url = Self::env_nonempty(&format!("{}_REDIRECT_URL", prefix));
+            let (Some(issuer), Some(client_id), Some(redirect_url)) =
+                (issuer, client_id, redirect_url)
+            else {
+                eprintln!(
+                    "oidc provider {} skipped: {}_ISSUER, {}_CLIENT_ID and {}_REDIRECT_URL are required",
+                    name, prefix, prefix, prefix
+                );
+                continue;
+            };
+            providers.push(OidcProviderConfig {
+                name: name.to_string(),
+                issuer: issuer.trim_end_matches('/').to_string(),
+                client_id,
+                client_secret: Self::env_nonempty(&format!("{}_CLIENT_SECRET", prefix)),
+                redirect_url,
+                scopes: Self::env_nonempty(&format!("{}_SCOPES", prefix))
+                    .unwrap_or_else(|| "openid email profile".to_string()),
+            });
+        }
+        providers
+    }
+
+    /// Reads `STORAGE_S3_ENDPOINT`, `STORAGE_S3_BUCKET`, `STORAGE_S3_ACCESS_KEY_ID` and
+    /// `STORAGE_S3_SECRET_ACCESS_KEY`, all required, and `STORAGE_S3_REGION`.
+    fn storage_s3() -> Option<S3StorageConfig> {
+        Some(S3StorageConfig {
+            endpoint: Self::env_nonempty("STORAGE_S3_ENDPOINT")?
+                .trim_end_matches('/')
+                .to_string(),
+            bucket: Self::env_nonempty("STORAGE_S3_BUCKET")?,
+            region: Self::env_nonempty("STORAGE_S3_REGION")
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            access_key_id: Self::env_nonempty("STORAGE_S3_ACCESS_KEY_ID")?,
+            secret_access_key: Self::env_nonempty("STORAGE_S3_SECRET_ACCESS_KEY")?,
+        })
+    }
+
+    fn env_list(key: &str) -> Vec<String> {
+        Self::env_nonempty(key)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|item| item.trim().to_string())
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     pub fn new() -> Self {
         let port = Self::env_u16("PORT").unwrap_or(3333);
         let github_client_id = Self::env_nonempty("AUTH_GITHUB_CLIENT_ID");
@@ -85,18 +151,95 @@ impl ConfigServiceImpl {
                 "https://api.github.com".to_string()
             }
         });
+        let github_state_ttl_seconds =
+            Self::env_u64("AUTH_GITHUB_STATE_TTL_SECONDS").unwrap_or(60 * 10);
+        let lowercase = |items: Vec<String>| -> Vec<String> {
+            items.iter().map(|item| item.to_ascii_lowercase()).collect()
+        };
+        let github_allowed_orgs = lowercase(Self::env_list("AUTH_GITHUB_ALLOWED_ORGS"));
+        let github_allowed_teams = lowercase(Self::env_list("AUTH_GITHUB_ALLOWED_TEAMS"));
+        let return_to_allowed_origins = lowercase(Self::env_list("AUTH_RETURN_TO_ALLOWED_ORIGINS"))
+            .into_iter()
+            .map(|origin| origin.trim_end_matches('/').to_string())
+            .collect();
         let redis_url = Self::env_nonempty("REDIS_URL");
         let session_ttl_seconds = Self::env_u64("SESSION_TTL_SECONDS").unwrap_or(60 * 60 * 24 * 7);
+        let session_idle_ttl_seconds =
+            Self::env_u64("SESSION_IDLE_TTL_SECONDS").unwrap_or(60 * 60 * 24);
+        let session_remember_me_ttl_seconds =
+            Self::env_u64("SESSION_REMEMBER_ME_TTL_SECONDS").unwrap_or(60 * 60 * 24 * 30);
+        let session_remember_me_idle_ttl_seconds =
+            Self::env_u64("SESSION_REMEMBER_ME_IDLE_TTL_SECONDS").unwrap_or(60 * 60 * 24 * 7);
         let verify_email_token_ttl_seconds =
             Self::env_u64("VERIFY_EMAIL_TOKEN_TTL_SECONDS").unwrap_or(60 * 60);
+        let reset_password_token_ttl_seconds =
+            Self::env_u64("RESET_PASSWORD_TOKEN_TTL_SECONDS").unwrap_or(60 * 30);
+        let team_invite_token_ttl_seconds =
+            Self::env_u64("TEAM_INVITE_TOKEN_TTL_SECONDS").unwrap_or(60 * 60 * 24 * 7);
         let cookie_secure = Self::env_bool("COOKIE_SECURE", false);
         let cookie_domain = Self::env_nonempty("COOKIE_DOMAIN");
         let session_key_prefix =
             Self::env_nonempty("SESSION_KEY_PREFIX").unwrap_or_else(|| "auth-api".to_string());
+        let mfa_encryption_key = Self::env_nonempty("MFA_ENCRYPTION_KEY");
+        let mfa_totp_issuer =
+            Self::env_nonempty("MFA_TOTP_ISSUER").unwrap_or_else(|| "auth-api".to_string());
+        let mfa_challenge_ttl_seconds =
+            Self::env_u64("MFA_CHALLENGE_TTL_SECONDS").unwrap_or(60 * 5);
+        let webauthn_rp_id = Self::env_lower_nonempty("WEBAUTHN_RP_ID");
+        let webauthn_rp_origin = Self::env_nonempty("WEBAUTHN_RP_ORIGIN");
+        let webauthn_rp_name =
+            Self::env_nonempty("WEBAUTHN_RP_NAME").unwrap_or_else(|| "auth-api".to_string());
+        let webauthn_challenge_ttl_seconds =
+            Self::env_u64("WEBAUTHN_CHALLENGE_TTL_SECONDS").unwrap_or(60 * 5);
+        let jwt_enabled = Self::env_bool("JWT_ENABLED", false);
+        let jwt_algorithm =
+            Self::env_nonempty("JWT_ALGORITHM").unwrap_or_else(|| "ES256".to_string());
+        let jwt_issuer = Self::env_nonempty("JWT_ISSUER").unwrap_or_else(|| "auth-api".to_string());
+        let jwt_audience = Self::env_nonempty("JWT_AUDIENCE");
+        let jwt_access_token_ttl_seconds =
+            Self::env_u64("JWT_ACCESS_TOKEN_TTL_SECONDS").unwrap_or(60 * 15);
+        let jwt_key_rotation_seconds =
+            Self::env_u64("JWT_KEY_ROTATION_SECONDS").unwrap_or(60 * 60 * 24 * 30);
+        let refresh_token_absolute_ttl_seconds =
+            Self::env_u64("REFRESH_TOKEN_ABSOLUTE_TTL_SECONDS").unwrap_or(60 * 60 * 24 * 30);
+        let refresh_token_idle_ttl_seconds =
+            Self::env_u64("REFRESH_TOKEN_IDLE_TTL_SECONDS").unwrap_or(60 * 60 * 24 * 7);
+        let oauth_code_ttl_seconds = Self::env_u64("OAUTH_CODE_TTL_SECONDS").unwrap_or(60);
+        let oauth_login_url = Self::env_nonempty("OAUTH_LOGIN_URL");
+        let oauth_consent_url = Self::env_nonempty("OAUTH_CONSENT_URL");
+        let jwt_private_key_path = Self::env_nonempty("JWT_PRIVATE_KEY_PATH");
+        let jwt_previous_key_paths = Self::env_list("JWT_PREVIOUS_KEY_PATHS");
+        let oidc_providers = Self::oidc_providers();
+        let oidc_state_ttl_seconds = Self::env_u64("OIDC_STATE_TTL_SECONDS").unwrap_or(60 * 10);
+        let forward_auth_login_url = Self::env_nonempty("FORWARD_AUTH_LOGIN_URL");
+        let forward_auth_cache_ttl_seconds =
+            Self::env_u64("FORWARD_AUTH_CACHE_TTL_SECONDS").unwrap_or(10);
+        let bootstrap_admins = Self::env_list("BOOTSTRAP_ADMINS");
+        let storage_backend =
+            Self::env_lower_nonempty("STORAGE_BACKEND").unwrap_or_else(|| "local".to_string());
+        let storage_local_dir =
+            Self::env_nonempty("STORAGE_LOCAL_DIR").unwrap_or_else(|| "data/storage".to_string());
+        let storage_s3 = Self::storage_s3();
+        let avatar_max_bytes = Self::env_u64("AVATAR_MAX_BYTES").unwrap_or(5 * 1024 * 1024);
+        let mut avatar_sizes: Vec<u32> = Self::env_list("AVATAR_SIZES")
+            .iter()
+            .filter_map(|size| size.parse().ok())
+            .filter(|size| (16..=1024).contains(size))
+            .collect();
+        if avatar_sizes.is_empty() {
+            avatar_sizes = vec![64, 128, 256];
+        }
+        avatar_sizes.sort_unstable();
+        avatar_sizes.dedup();
+        let avatar_url_base = Self::env_nonempty("AVATAR_URL_BASE")
+            .map(|value| value.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| "/api/v1/avatars".to_string());
 
         let resend_api_key = Self::env_nonempty("RESEND_API_KEY");
         let email_from = Self::env_nonempty("EMAIL_FROM");
         let verify_email_url_base = Self::env_nonempty("VERIFY_EMAIL_URL_BASE");
+        let reset_password_url_base = Self::env_nonempty("RESET_PASSWORD_URL_BASE");
+        let team_invite_url_base = Self::env_nonempty("TEAM_INVITE_URL_BASE");
         let email_provider = Self::env_lower_nonempty("EMAIL_PROVIDER");
         let smtp_host = Self::env_nonempty("SMTP_HOST");
         let smtp_port = Self::env_u16("SMTP_PORT");
@@ -113,15 +256,57 @@ impl ConfigServiceImpl {
                 github_authorize_url,
                 github_token_url,
                 github_api_base,
+                github_state_ttl_seconds,
+                github_allowed_orgs,
+                github_allowed_teams,
+                return_to_allowed_origins,
                 redis_url,
                 session_ttl_seconds,
+                session_idle_ttl_seconds,
+                session_remember_me_ttl_seconds,
+                session_remember_me_idle_ttl_seconds,
                 verify_email_token_ttl_seconds,
+                reset_password_token_ttl_seconds,
+                team_invite_token_ttl_seconds,
                 cookie_secure,
                 cookie_domain,
                 session_key_prefix,
+                mfa_encryption_key,
+                mfa_totp_issuer,
+                mfa_challenge_ttl_seconds,
+                webauthn_rp_id,
+                webauthn_rp_origin,
+                webauthn_rp_name,
+                webauthn_challenge_ttl_seconds,
+                jwt_enabled,
+                jwt_algorithm,
+                jwt_issuer,
+                jwt_audience,
+                jwt_access_token_ttl_seconds,
+                jwt_key_rotation_seconds,
+                jwt_private_key_path,
+                refresh_token_absolute_ttl_seconds,
+                oauth_code_ttl_seconds,
+                oauth_login_url,
+                oauth_consent_url,
+                refresh_token_idle_ttl_seconds,
+                jwt_previous_key_paths,
+                oidc_providers,
+                oidc_state_ttl_seconds,
+                forward_auth_login_url,
+                forward_auth_cache_ttl_seconds,
+                bootstrap_admins,
+                storage_backend,
+                storage_local_dir,
+                storage_s3,
+                avatar_max_bytes,
+                avatar_sizes,
+                avatar_url_base,
                 resend_api_key,
                 email_from,
                 verify_email_url_base,
+                reset_password_url_base,
+                team_invite_url_base,
                 email_provider,
                 smtp_host,
                 smtp_port,
@@ -133,6 +318,15 @@ impl ConfigServiceImpl {
     }
 }
 
+#[cfg(test)]
+impl ConfigServiceImpl {
+    pub fn from_config(config: Config) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+}
+
 impl ConfigService for ConfigServiceImpl {
     fn port(&self) -> u16 {
         self.config.port
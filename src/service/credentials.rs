@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+use sea_orm::SqlErr;
+use std::sync::Arc;
+
+use crate::{
+    entities::{account_credentials, accounts},
+    repo::account_credentials::AccountCredentialsRepo,
+    service::{mfa::PROVIDER_TOTP, webauthn::PROVIDER_WEBAUTHN},
+};
+
+#[derive(Debug)]
+pub struct CredentialError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl CredentialError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("db_error", err.to_string())
+    }
+}
+
+/// Second factors are attached to an account but cannot be used to sign in on their own.
+fn is_login_credential(credential: &account_credentials::Model) -> bool {
+    credential.provider != PROVIDER_TOTP
+}
+
+#[async_trait]
+pub trait CredentialsService: Send + Sync {
+    /// Login methods attached to the account (password, OAuth/OIDC providers, passkeys).
+    async fn list(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<account_credentials::Model>, CredentialError>;
+    /// Attaches an external identity to an existing account. Re-linking the same identity is a
+    /// no-op; an identity owned by another account is refused.
+    async fn link(
+        &self,
+        account: &accounts::Model,
+        provider: &str,
+        provider_subject: &str,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<account_credentials::Model, CredentialError>;
+    async fn unlink(
+        &self,
+        account: &accounts::Model,
+        credential_id: i64,
+    ) -> Result<(), CredentialError>;
+}
+
+pub struct CredentialsServiceImpl {
+    credentials_repo: Arc<dyn AccountCredentialsRepo>,
+}
+
+impl CredentialsServiceImpl {
+    pub fn new(credentials_repo: Arc<dyn AccountCredentialsRepo>) -> Self {
+        Self { credentials_repo }
+    }
+
+    fn already_linked(provider: &str) -> CredentialError {
+        CredentialError::new(
+            "provider_already_linked",
+            format!("this {} identity is linked to another account", provider),
+        )
+    }
+}
+
+#[async_trait]
+impl CredentialsService for CredentialsServiceImpl {
+    async fn list(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<account_credentials::Model>, CredentialError> {
+        let credentials = self
+            .credentials_repo
+            .list_by_account(account_id)
+            .await
+            .map_err(CredentialError::db)?;
+        Ok(credentials
+            .into_iter()
+            .filter(is_login_credential)
+            .collect())
+    }
+
+    async fn link(
+        &self,
+        account: &accounts::Model,
+        provider: &str,
+        provider_subject: &str,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<account_credentials::Model, CredentialError> {
+        if let Some(existing) = self
+            .credentials_repo
+            .find_by_provider_subject(provider, provider_subject)
+            .await
+            .map_err(CredentialError::db)?
+        {
+            if existing.account_id == account.id {
+                return Ok(existing);
+            }
+            return Err(Self::already_linked(provider));
+        }
+
+        // Every provider except passkeys is limited to one identity per account.
+        if provider != PROVIDER_WEBAUTHN
+            && self
+                .credentials_repo
+                .find_by_account_and_provider(account.id, provider)
+                .await
+                .map_err(CredentialError::db)?
+                .is_some()
+        {
+            return Err(CredentialError::new(
+                "provider_exists",
+                format!(
+                    "a different {} identity is already linked; unlink it first",
+                    provider
+                ),
+            ));
+        }
+
+        let model = account_credentials::ActiveModel {
+            account_id: sea_orm::Set(account.id),
+            provider: sea_orm::Set(provider.to_string()),
+            provider_subject: sea_orm::Set(Some(provider_subject.to_string())),
+            password_hash: sea_orm::Set(None),
+            metadata: sea_orm::Set(metadata),
+            created_by: sea_orm::Set(Some(account.uid)),
+            updated_by: sea_orm::Set(Some(account.uid)),
+            ..Default::default()
+        };
+        self.credentials_repo
+            .insert(model)
+            .await
+            .map_err(|err| match err.sql_err() {
+                Some(SqlErr::UniqueConstraintViolation(_)) => Self::already_linked(provider),
+                _ => CredentialError::db(err),
+            })
+    }
+
+    async fn unlink(
+        &self,
+        account: &accounts::Model,
+        credential_id: i64,
+    ) -> Result<(), CredentialError> {
+        let credentials = self
+            .credentials_repo
+            .list_by_account(account.id)
+            .await
+            .map_err(CredentialError::db)?;
+        let Some(credential) = credentials.iter().find(|c| c.id == credential_id) else {
+            return Err(CredentialError::new(
+                "credential_not_found",
+                "credential not found",
+            ));
+        };
+        if !is_login_credential(credential) {
+            return Err(CredentialError::new(
+                "credential_not_unlinkable",
+                format!(
+                    "{} is managed through its own endpoints",
+                    credential.provider
+                ),
+            ));
+        }
+        if credentials
+            .iter()
+            .filter(|c| is_login_credential(c))
+            .count()
+            <= 1
+        {
+            return Err(CredentialError::new(
+                "last_credential",
+                "cannot remove the last way to sign in to this account",
+            ));
+        }
+
+        self.credentials_repo
+            .soft_delete(credential_id, account.uid)
+            .await
+            .map_err(CredentialError::db)
+    }
+}
@@ -17,17 +17,40 @@ struct ResendEmailRequest<'a> {
     html: &'a str,
 }
 
-fn build_verification_email_html(verify_url: &str) -> String {
+pub struct EmailContent {
+    pub subject: &'static str,
+    pub html: String,
+}
+
+pub struct SmtpSettings<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub starttls: bool,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+}
+
+fn build_action_email_html(heading: &str, intro: &str, action_url: &str) -> String {
     format!(
         concat!(
             "<div style=\"font-family:ui-sans-serif,system-ui,-apple-system,Segoe UI,Roboto,Helvetica,Arial;line-height:1.5\">",
-            "<h2 style=\"margin:0 0 12px\">Verify your email</h2>",
-            "<p style=\"margin:0 0 12px\">Click this link to verify your email:</p>",
+            "<h2 style=\"margin:0 0 12px\">{heading}</h2>",
+            "<p style=\"margin:0 0 12px\">{intro}</p>",
             "<p style=\"margin:0 0 12px\"><a href=\"{url}\">{url}</a></p>",
             "<p style=\"margin:18px 0 0;color:#666;font-size:12px\">If you did not request this, you can ignore this email.</p>",
             "</div>"
         ),
-        url = verify_url
+        heading = heading,
+        intro = intro,
+        url = action_url
+    )
+}
+
+fn build_action_url(url_base: &str, token: &str) -> String {
+    format!(
+        "{}?token={}",
+        url_base.trim_end_matches('/'),
+        urlencoding::encode(token)
     )
 }
 
@@ -36,58 +59,109 @@ pub async fn try_send_verification_email(
     to: &str,
     verify_token: &str,
 ) -> Result<(), String> {
-    let (Some(from), Some(url_base)) = (
-        cfg.email_from.as_deref(),
-        cfg.verify_email_url_base.as_deref(),
-    ) else {
+    let Some(url_base) = cfg.verify_email_url_base.as_deref() else {
         return Ok(());
     };
-    let verify_url = format!(
-        "{}?token={}",
-        url_base.trim_end_matches('/'),
-        urlencoding::encode(verify_token)
-    );
+    let verify_url = build_action_url(url_base, verify_token);
+    let content = EmailContent {
+        subject: "Verify your email",
+        html: build_action_email_html(
+            "Verify your email",
+            "Click this link to verify your email:",
+            &verify_url,
+        ),
+    };
+    try_send_email(cfg, to, &content).await
+}
+
+pub async fn try_send_password_reset_email(
+    cfg: &Config,
+    to: &str,
+    reset_token: &str,
+) -> Result<(), String> {
+    let Some(url_base) = cfg.reset_password_url_base.as_deref() else {
+        return Ok(());
+    };
+    let reset_url = build_action_url(url_base, reset_token);
+    let content = EmailContent {
+        subject: "Reset your password",
+        html: build_action_email_html(
+            "Reset your password",
+            "Click this link to choose a new password:",
+            &reset_url,
+        ),
+    };
+    try_send_email(cfg, to, &content).await
+}
+
+pub async fn try_send_team_invite_email(
+    cfg: &Config,
+    to: &str,
+    team_name: &str,
+    invite_token: &str,
+) -> Result<(), String> {
+    let Some(url_base) = cfg.team_invite_url_base.as_deref() else {
+        return Ok(());
+    };
+    let invite_url = build_action_url(url_base, invite_token);
+    let content = EmailContent {
+        subject: "You have been invited to a team",
+        html: build_action_email_html(
+            "Join a team",
+            &format!(
+                "You have been invited to join {}. Click this link to accept or decline:",
+                html_escape(team_name)
+            ),
+            &invite_url,
+        ),
+    };
+    try_send_email(cfg, to, &content).await
+}
+
+/// Team names are chosen by users and end up in the email body.
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub async fn try_send_email(cfg: &Config, to: &str, content: &EmailContent) -> Result<(), String> {
+    let Some(from) = cfg.email_from.as_deref() else {
+        return Ok(());
+    };
+    let smtp = match (cfg.smtp_host.as_deref(), cfg.smtp_port) {
+        (Some(host), Some(port)) => Some(SmtpSettings {
+            host,
+            port,
+            starttls: cfg.smtp_starttls,
+            username: cfg.smtp_username.as_deref(),
+            password: cfg.smtp_password.as_deref(),
+        }),
+        _ => None,
+    };
 
     let provider = cfg.email_provider.as_deref().unwrap_or("auto");
     match provider {
         "smtp" => {
-            let (Some(host), Some(port)) = (cfg.smtp_host.as_deref(), cfg.smtp_port) else {
+            let Some(smtp) = smtp else {
                 return Err("EMAIL_PROVIDER=smtp but SMTP_HOST/SMTP_PORT are missing".to_string());
             };
-            send_verification_email_smtp(
-                host,
-                port,
-                cfg.smtp_starttls,
-                cfg.smtp_username.as_deref(),
-                cfg.smtp_password.as_deref(),
-                from,
-                to,
-                &verify_url,
-            )
-            .await
+            send_email_smtp(&smtp, from, to, content).await
         }
         "resend" => {
             let Some(api_key) = cfg.resend_api_key.as_deref() else {
                 return Err("EMAIL_PROVIDER=resend but RESEND_API_KEY is missing".to_string());
             };
-            send_verification_email_resend(api_key, from, to, &verify_url).await
+            send_email_resend(api_key, from, to, content).await
         }
         "auto" => {
-            if let (Some(host), Some(port)) = (cfg.smtp_host.as_deref(), cfg.smtp_port) {
-                return send_verification_email_smtp(
-                    host,
-                    port,
-                    cfg.smtp_starttls,
-                    cfg.smtp_username.as_deref(),
-                    cfg.smtp_password.as_deref(),
-                    from,
-                    to,
-                    &verify_url,
-                )
-                .await;
+            if let Some(smtp) = smtp {
+                return send_email_smtp(&smtp, from, to, content).await;
             }
             if let Some(api_key) = cfg.resend_api_key.as_deref() {
-                return send_verification_email_resend(api_key, from, to, &verify_url).await;
+                return send_email_resend(api_key, from, to, content).await;
             }
             Ok(())
         }
@@ -98,22 +172,19 @@ pub async fn try_send_verification_email(
     }
 }
 
-pub async fn send_verification_email_resend(
+pub async fn send_email_resend(
     api_key: &str,
     from: &str,
     to: &str,
-    verify_url: &str,
+    content: &EmailContent,
 ) -> Result<(), String> {
     let client = reqwest::Client::new();
 
-    let subject = "Verify your email";
-    let html = build_verification_email_html(verify_url);
-
     let payload = ResendEmailRequest {
         from,
         to: vec![to],
-        subject,
-        html: &html,
+        subject: content.subject,
+        html: &content.html,
     };
 
     let res = client
@@ -133,19 +204,12 @@ pub async fn send_verification_email_resend(
     Err(format!("resend returned {}: {}", status, body))
 }
 
-pub async fn send_verification_email_smtp(
-    host: &str,
-    port: u16,
-    starttls: bool,
-    username: Option<&str>,
-    password: Option<&str>,
+pub async fn send_email_smtp(
+    smtp: &SmtpSettings<'_>,
     from: &str,
     to: &str,
-    verify_url: &str,
+    content: &EmailContent,
 ) -> Result<(), String> {
-    let subject = "Verify your email";
-    let html = build_verification_email_html(verify_url);
-
     let from: Mailbox = from
         .parse()
         .map_err(|err| format!("invalid EMAIL_FROM: {}", err))?;
@@ -156,24 +220,24 @@ pub async fn send_verification_email_smtp(
     let msg = Message::builder()
         .from(from)
         .to(to)
-        .subject(subject)
+        .subject(content.subject)
         .header(header::ContentType::TEXT_HTML)
-        .body(html)
+        .body(content.html.clone())
         .map_err(|err| format!("build message failed: {}", err))?;
 
-    let mut builder = if starttls {
-        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+    let mut builder = if smtp.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp.host)
             .map_err(|err| format!("smtp transport init failed: {}", err))?
-            .port(port)
+            .port(smtp.port)
             .timeout(Some(Duration::from_secs(10)))
     } else {
         // Mailpit (local/CI) uses plain SMTP by default.
-        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
-            .port(port)
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(smtp.host)
+            .port(smtp.port)
             .timeout(Some(Duration::from_secs(10)))
     };
 
-    if let (Some(username), Some(password)) = (username, password) {
+    if let (Some(username), Some(password)) = (smtp.username, smtp.password) {
         builder = builder.credentials(lettre::transport::smtp::authentication::Credentials::new(
             username.to_string(),
             password.to_string(),
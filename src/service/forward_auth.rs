@@ -0,0 +1,86 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+use crate::service::verification::hash_token;
+
+/// Upper bound on cached credentials; the cache is emptied rather than grown past it.
+const MAX_ENTRIES: usize = 10_000;
+
+/// The identity forward-auth hands to the proxy as `X-Auth-*` headers.
+#[derive(Clone, Debug)]
+pub struct ForwardAuthIdentity {
+    pub account_uid: Uuid,
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Short-lived cache of resolved credentials, so that a proxy asking about every request
+/// does not cost a Redis and Postgres round trip each time. Entries can outlive a logout on
+/// another instance by up to the TTL.
+pub trait ForwardAuthCache: Send + Sync {
+    fn get(&self, credential: &str) -> Option<ForwardAuthIdentity>;
+    fn insert(&self, credential: &str, identity: ForwardAuthIdentity);
+    fn remove(&self, credential: &str);
+    /// Drops every cached credential of the account, for revocations that do not know the
+    /// credential in clear.
+    fn remove_account(&self, account_uid: Uuid);
+}
+
+pub struct InMemoryForwardAuthCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, ForwardAuthIdentity)>>,
+}
+
+impl InMemoryForwardAuthCache {
+    /// A zero TTL disables caching.
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            ttl: Duration::from_secs(ttl_seconds),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ForwardAuthCache for InMemoryForwardAuthCache {
+    fn get(&self, credential: &str) -> Option<ForwardAuthIdentity> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let entries = self.entries.lock().ok()?;
+        let (expires_at, identity) = entries.get(&hash_token(credential))?;
+        (*expires_at > Instant::now()).then(|| identity.clone())
+    }
+
+    fn insert(&self, credential: &str, identity: ForwardAuthIdentity) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let now = Instant::now();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, (expires_at, _)| *expires_at > now);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(hash_token(credential), (now + self.ttl, identity));
+    }
+
+    fn remove(&self, credential: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(&hash_token(credential));
+        }
+    }
+
+    fn remove_account(&self, account_uid: Uuid) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|_, (_, identity)| identity.account_uid != account_uid);
+        }
+    }
+}
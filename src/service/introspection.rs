@@ -0,0 +1,308 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::{
+    entities::{account_authorizations, oauth_clients},
+    repo::{account_authorizations::AccountAuthorizationsRepo, accounts::AccountsRepo},
+    service::{
+        jwt::JwtService,
+        personal_access_tokens::{
+            PersonalAccessTokenService, TOKEN_PREFIX, TOKEN_TYPE_PERSONAL_ACCESS,
+        },
+        refresh_tokens::TOKEN_TYPE_REFRESH,
+        session::{SessionLifetimes, SessionService},
+        verification::hash_token,
+    },
+};
+
+pub const TOKEN_KIND_ACCESS: &str = "access_token";
+pub const TOKEN_KIND_REFRESH: &str = "refresh_token";
+pub const TOKEN_KIND_SESSION: &str = "session";
+pub const TOKEN_KIND_PERSONAL_ACCESS: &str = "personal_access_token";
+
+/// What an active token stands for (RFC 7662 section 2.2). `client_id` is `None` for tokens from
+/// a first-party login.
+#[derive(Debug)]
+pub struct TokenInfo {
+    pub kind: &'static str,
+    pub sub: String,
+    pub iat: Option<i64>,
+    pub exp: Option<i64>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct IntrospectionError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl IntrospectionError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("server_error", err.to_string())
+    }
+
+    fn session(err: crate::service::session::SessionError) -> Self {
+        Self::new("server_error", err.to_string())
+    }
+}
+
+/// Looks up and revokes tokens on behalf of authenticated OAuth clients. Access tokens are JWTs
+/// and checked by signature; sessions live in Redis; refresh and personal access tokens in
+/// `account_authorizations`.
+///
+/// A client only learns about, or revokes, tokens it could have been handed legitimately: its own
+/// refresh tokens, any access token, session or personal access token a user might present to
+/// it, and for first-party clients, first-party refresh tokens too.
+#[async_trait]
+pub trait TokenIntrospectionService: Send + Sync {
+    /// Returns `None` for tokens that are unknown, expired, revoked or not visible to `client`.
+    async fn introspect(
+        &self,
+        client: &oauth_clients::Model,
+        token: &str,
+        token_type_hint: Option<&str>,
+    ) -> Result<Option<TokenInfo>, IntrospectionError>;
+    /// Revokes a refresh token (with its family) or personal access token, or ends a session. Unknown tokens are not an
+    /// error (RFC 7009 section 2.2); self-contained access tokens cannot be revoked.
+    async fn revoke(
+        &self,
+        client: &oauth_clients::Model,
+        token: &str,
+        token_type_hint: Option<&str>,
+    ) -> Result<(), IntrospectionError>;
+}
+
+pub struct TokenIntrospectionServiceImpl {
+    sessions: Arc<dyn SessionService>,
+    jwt: Arc<dyn JwtService>,
+    personal_access_tokens: Arc<dyn PersonalAccessTokenService>,
+    accounts_repo: Arc<dyn AccountsRepo>,
+    authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+    session_lifetimes: SessionLifetimes,
+}
+
+impl TokenIntrospectionServiceImpl {
+    pub fn new(
+        sessions: Arc<dyn SessionService>,
+        jwt: Arc<dyn JwtService>,
+        personal_access_tokens: Arc<dyn PersonalAccessTokenService>,
+        accounts_repo: Arc<dyn AccountsRepo>,
+        authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+        session_lifetimes: SessionLifetimes,
+    ) -> Self {
+        Self {
+            sessions,
+            jwt,
+            personal_access_tokens,
+            accounts_repo,
+            authorizations_repo,
+            session_lifetimes,
+        }
+    }
+
+    async fn access_token(&self, token: &str) -> Option<TokenInfo> {
+        let token = self.jwt.verify_access_token(token).await.ok()?;
+        Some(TokenInfo {
+            kind: TOKEN_KIND_ACCESS,
+            sub: token.sub,
+            iat: Some(token.iat),
+            exp: Some(token.exp),
+            scope: token.scope,
+            client_id: token.client_id,
+        })
+    }
+
+    async fn personal_access_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<TokenInfo>, IntrospectionError> {
+        let found = self
+            .personal_access_tokens
+            .authenticate(token)
+            .await
+            .map_err(|err| IntrospectionError::new("server_error", err.message))?;
+        Ok(found.map(|(account, token)| TokenInfo {
+            kind: TOKEN_KIND_PERSONAL_ACCESS,
+            sub: account.uid.to_string(),
+            iat: Some(token.created_at.timestamp()),
+            exp: token.expires_at.map(|expires_at| expires_at.timestamp()),
+            scope: token.scope(),
+            client_id: None,
+        }))
+    }
+
+    async fn session(&self, token: &str) -> Result<Option<TokenInfo>, IntrospectionError> {
+        let Some(session) = self
+            .sessions
+            .peek(token)
+            .await
+            .map_err(IntrospectionError::session)?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(TokenInfo {
+            kind: TOKEN_KIND_SESSION,
+            sub: session.account_uid.to_string(),
+            iat: Some(session.created_at.timestamp()),
+            exp: Some(self.session_lifetimes.expires_at(&session).timestamp()),
+            scope: None,
+            client_id: None,
+        }))
+    }
+
+    /// Active refresh token row, if `client` may see it.
+    async fn refresh_token(
+        &self,
+        client: &oauth_clients::Model,
+        token: &str,
+    ) -> Result<Option<account_authorizations::Model>, IntrospectionError> {
+        let record = self
+            .authorizations_repo
+            .find_by_token_hash(&hash_token(token))
+            .await
+            .map_err(IntrospectionError::db)?
+            .filter(|record| record.token_type == TOKEN_TYPE_REFRESH)
+            .filter(|record| record.revoked_at.is_none())
+            .filter(|record| {
+                record
+                    .expires_at
+                    .is_none_or(|expires_at| expires_at > Utc::now())
+            })
+            .filter(|record| match metadata_str(record, "client_id") {
+                Some(client_id) => client_id == client.client_id,
+                None => client.first_party,
+            });
+        Ok(record)
+    }
+
+    async fn refresh_token_info(
+        &self,
+        record: &account_authorizations::Model,
+    ) -> Result<Option<TokenInfo>, IntrospectionError> {
+        let Some(account) = self
+            .accounts_repo
+            .find_by_id(record.account_id)
+            .await
+            .map_err(IntrospectionError::db)?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(TokenInfo {
+            kind: TOKEN_KIND_REFRESH,
+            sub: account.uid.to_string(),
+            iat: Some(record.created_at.timestamp()),
+            exp: record.expires_at.map(|expires_at| expires_at.timestamp()),
+            scope: metadata_str(record, "scope").map(str::to_string),
+            client_id: metadata_str(record, "client_id").map(str::to_string),
+        }))
+    }
+}
+
+fn metadata_str<'a>(record: &'a account_authorizations::Model, key: &str) -> Option<&'a str> {
+    record.metadata.as_ref()?.get(key)?.as_str()
+}
+
+/// JWTs are the only tokens we hand out that contain dots, so there is no need to query
+/// Redis or the database for them (or to verify signatures on opaque tokens).
+fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+#[async_trait]
+impl TokenIntrospectionService for TokenIntrospectionServiceImpl {
+    async fn introspect(
+        &self,
+        client: &oauth_clients::Model,
+        token: &str,
+        token_type_hint: Option<&str>,
+    ) -> Result<Option<TokenInfo>, IntrospectionError> {
+        if looks_like_jwt(token) {
+            return Ok(self.access_token(token).await);
+        }
+        if token.starts_with(TOKEN_PREFIX) {
+            return self.personal_access_token(token).await;
+        }
+        // The hint only decides which store is asked first (RFC 7662 section 2.1).
+        if token_type_hint == Some(TOKEN_KIND_SESSION) {
+            if let Some(info) = self.session(token).await? {
+                return Ok(Some(info));
+            }
+        }
+        if let Some(record) = self.refresh_token(client, token).await? {
+            return self.refresh_token_info(&record).await;
+        }
+        if token_type_hint == Some(TOKEN_KIND_SESSION) {
+            return Ok(None);
+        }
+        self.session(token).await
+    }
+
+    async fn revoke(
+        &self,
+        client: &oauth_clients::Model,
+        token: &str,
+        _token_type_hint: Option<&str>,
+    ) -> Result<(), IntrospectionError> {
+        if looks_like_jwt(token) {
+            return Err(IntrospectionError::new(
+                "unsupported_token_type",
+                "access tokens expire on their own and cannot be revoked",
+            ));
+        }
+        if let Some(record) = self.refresh_token(client, token).await? {
+            match record.family_id {
+                Some(family_id) => self.authorizations_repo.revoke_family(family_id).await,
+                None => self
+                    .authorizations_repo
+                    .revoke_if_active(record.id)
+                    .await
+                    .map(u64::from),
+            }
+            .map_err(IntrospectionError::db)?;
+            return Ok(());
+        }
+        // Ending a browser session or a personal access token is reserved for first-party
+        // clients; for anyone else the token is treated like any other token they may not touch.
+        if !client.first_party {
+            return Ok(());
+        }
+        if token.starts_with(TOKEN_PREFIX) {
+            let record = self
+                .authorizations_repo
+                .find_active_by_token_hash(&hash_token(token))
+                .await
+                .map_err(IntrospectionError::db)?
+                .filter(|record| record.token_type == TOKEN_TYPE_PERSONAL_ACCESS);
+            if let Some(record) = record {
+                self.authorizations_repo
+                    .revoke_if_active(record.id)
+                    .await
+                    .map_err(IntrospectionError::db)?;
+            }
+            return Ok(());
+        }
+        if self
+            .sessions
+            .peek(token)
+            .await
+            .map_err(IntrospectionError::session)?
+            .is_some()
+        {
+            self.sessions
+                .delete(token)
+                .await
+                .map_err(IntrospectionError::session)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,591 @@
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{jwk::Jwk, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    entities::{accounts, signing_keys},
+    repo::signing_keys::SigningKeysRepo,
+};
+
+/// How long a database-backed key ring is trusted before re-reading `signing_keys`, so that
+/// keys rotated by another instance show up in our JWKS.
+const KEY_RING_REFRESH_SECONDS: i64 = 60;
+/// RFC 9068 `typ` for access tokens; ID tokens use plain `JWT`.
+const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+/// Extra time a retired key stays in the JWKS beyond the last token it could have signed.
+const KEY_PUBLISH_GRACE_SECONDS: i64 = 60 * 60;
+
+#[derive(Debug)]
+pub struct JwtError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl JwtError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn key(message: impl Into<String>) -> Self {
+        Self::new("jwt_key_error", message)
+    }
+}
+
+pub struct JwtSettings {
+    pub enabled: bool,
+    /// Algorithm for generated keys: `RS256`, `ES256` or `EdDSA`.
+    pub algorithm: String,
+    pub issuer: String,
+    pub audience: Option<String>,
+    pub access_token_ttl_seconds: u64,
+    pub key_rotation_seconds: u64,
+    /// When set, keys come from PEM files instead of `signing_keys`.
+    pub private_key_path: Option<String>,
+    /// Retired PEM keys that are still published so their tokens keep verifying.
+    pub previous_key_paths: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct AccessToken {
+    pub token: String,
+    pub expires_in: u64,
+}
+
+#[derive(Serialize)]
+struct AccessTokenClaims<'a> {
+    iss: &'a str,
+    sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aud: Option<&'a str>,
+    iat: i64,
+    exp: i64,
+    jti: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<&'a str>,
+}
+
+/// Claims of an access token issued by this service, after signature and expiry checks.
+#[derive(Debug, Deserialize)]
+pub struct VerifiedAccessToken {
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+impl VerifiedAccessToken {
+    /// First-party login tokens carry no `scope` and are not limited.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope
+            .as_deref()
+            .is_none_or(|scopes| scopes.split_whitespace().any(|value| value == scope))
+    }
+}
+
+#[async_trait]
+pub trait JwtService: Send + Sync {
+    /// Whether access tokens are issued at all (`JWT_ENABLED`).
+    fn enabled(&self) -> bool;
+    /// Returns `None` when JWT access tokens are disabled.
+    async fn issue_access_token(
+        &self,
+        account: &accounts::Model,
+    ) -> Result<Option<AccessToken>, JwtError>;
+    /// Token for an OAuth client, carrying `client_id` and `scope` claims (RFC 9068). `subject`
+    /// is the account uid, or the client id itself for `client_credentials`.
+    async fn issue_client_access_token(
+        &self,
+        subject: &str,
+        client_id: &str,
+        scope: &str,
+    ) -> Result<Option<AccessToken>, JwtError>;
+    /// OpenID Connect ID token for `client_id`; `claims` carries `sub` plus any profile claims.
+    async fn issue_id_token(
+        &self,
+        client_id: &str,
+        claims: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Option<String>, JwtError>;
+    /// Checks signature, issuer, audience, expiry and the `at+jwt` type, so ID tokens are not
+    /// accepted in place of access tokens.
+    async fn verify_access_token(&self, token: &str) -> Result<VerifiedAccessToken, JwtError>;
+    /// Public keys as a JWK Set (`{"keys": [...]}`), including keys retired from signing.
+    async fn jwks(&self) -> Result<serde_json::Value, JwtError>;
+}
+
+struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    jwk: serde_json::Value,
+}
+
+struct KeyRing {
+    signing: SigningKey,
+    published: Vec<serde_json::Value>,
+    refresh_after: Option<DateTime<Utc>>,
+}
+
+pub struct JwtServiceImpl {
+    settings: JwtSettings,
+    keys_repo: Arc<dyn SigningKeysRepo>,
+    ring: RwLock<Option<Arc<KeyRing>>>,
+}
+
+impl JwtServiceImpl {
+    /// PEM keys are loaded eagerly so a bad path fails at startup rather than on first login.
+    pub fn new(
+        settings: JwtSettings,
+        keys_repo: Arc<dyn SigningKeysRepo>,
+    ) -> Result<Self, JwtError> {
+        let ring = match (&settings.private_key_path, settings.enabled) {
+            (Some(path), true) => Some(Arc::new(Self::ring_from_files(
+                path,
+                &settings.previous_key_paths,
+            )?)),
+            _ => None,
+        };
+        Ok(Self {
+            settings,
+            keys_repo,
+            ring: RwLock::new(ring),
+        })
+    }
+
+    fn ring_from_files(path: &str, previous_paths: &[String]) -> Result<KeyRing, JwtError> {
+        let read = |path: &str| {
+            std::fs::read_to_string(path)
+                .map_err(|err| JwtError::key(format!("read {} failed: {}", path, err)))
+        };
+        let signing = signing_key_from_pem(&read(path)?)?;
+        let mut published = vec![signing.jwk.clone()];
+        for path in previous_paths {
+            published.push(signing_key_from_pem(&read(path)?)?.jwk);
+        }
+        Ok(KeyRing {
+            signing,
+            published,
+            refresh_after: None,
+        })
+    }
+
+    async fn ring_from_database(&self) -> Result<KeyRing, JwtError> {
+        let now = Utc::now();
+        let mut keys = self.list_keys().await?;
+        if !keys.iter().any(|key| key.not_after > now) {
+            self.insert_generated_key(now).await?;
+            keys = self.list_keys().await?;
+        }
+
+        // Newest first; more than one live key only happens when two instances rotate at once.
+        let Some(current) = keys.iter().find(|key| key.not_after > now) else {
+            return Err(JwtError::key("no usable signing key"));
+        };
+        let signing = signing_key_from_pem(&current.private_key_pem)?;
+        let refresh_after = std::cmp::min(
+            current.not_after.with_timezone(&Utc),
+            now + Duration::seconds(KEY_RING_REFRESH_SECONDS),
+        );
+        Ok(KeyRing {
+            signing,
+            published: keys.into_iter().map(|key| key.public_jwk).collect(),
+            refresh_after: Some(refresh_after),
+        })
+    }
+
+    async fn list_keys(&self) -> Result<Vec<signing_keys::Model>, JwtError> {
+        self.keys_repo
+            .list_unexpired()
+            .await
+            .map_err(|err| JwtError::new("db_error", err.to_string()))
+    }
+
+    async fn insert_generated_key(&self, now: DateTime<Utc>) -> Result<(), JwtError> {
+        let algorithm = parse_algorithm(&self.settings.algorithm)?;
+        let pem = generate_private_key_pem(algorithm)?;
+        let key = signing_key_from_pem(&pem)?;
+        let not_after = now + Duration::seconds(self.settings.key_rotation_seconds as i64);
+        let expires_at = not_after
+            + Duration::seconds(self.settings.access_token_ttl_seconds as i64)
+            + Duration::seconds(KEY_PUBLISH_GRACE_SECONDS);
+
+        let model = signing_keys::ActiveModel {
+            kid: sea_orm::Set(key.kid),
+            algorithm: sea_orm::Set(algorithm_name(key.algorithm).to_string()),
+            private_key_pem: sea_orm::Set(pem),
+            public_jwk: sea_orm::Set(key.jwk),
+            not_after: sea_orm::Set(not_after.into()),
+            expires_at: sea_orm::Set(expires_at.into()),
+            ..Default::default()
+        };
+        self.keys_repo
+            .insert(model)
+            .await
+            .map_err(|err| JwtError::new("db_error", err.to_string()))?;
+        Ok(())
+    }
+
+    async fn ring(&self) -> Result<Arc<KeyRing>, JwtError> {
+        let now = Utc::now();
+        let is_fresh = |ring: &KeyRing| ring.refresh_after.is_none_or(|after| after > now);
+        if let Some(ring) = self.ring.read().await.as_ref() {
+            if is_fresh(ring) {
+                return Ok(ring.clone());
+            }
+        }
+
+        let mut guard = self.ring.write().await;
+        if let Some(ring) = guard.as_ref() {
+            if is_fresh(ring) {
+                return Ok(ring.clone());
+            }
+        }
+        let ring = Arc::new(self.ring_from_database().await?);
+        *guard = Some(ring.clone());
+        Ok(ring)
+    }
+
+    fn encode<T: Serialize>(ring: &KeyRing, typ: &str, claims: &T) -> Result<String, JwtError> {
+        let mut header = Header::new(ring.signing.algorithm);
+        header.kid = Some(ring.signing.kid.clone());
+        header.typ = Some(typ.to_string());
+        jsonwebtoken::encode(&header, claims, &ring.signing.encoding)
+            .map_err(|err| JwtError::new("jwt_error", err.to_string()))
+    }
+
+    async fn sign(
+        &self,
+        subject: String,
+        client_id: Option<&str>,
+        scope: Option<&str>,
+    ) -> Result<Option<AccessToken>, JwtError> {
+        if !self.settings.enabled {
+            return Ok(None);
+        }
+
+        let ring = self.ring().await?;
+        let now = Utc::now().timestamp();
+        let ttl = self.settings.access_token_ttl_seconds;
+        let claims = AccessTokenClaims {
+            iss: &self.settings.issuer,
+            sub: subject,
+            aud: self.settings.audience.as_deref(),
+            iat: now,
+            exp: now + ttl as i64,
+            jti: Uuid::new_v4().to_string(),
+            client_id,
+            scope,
+        };
+        let token = Self::encode(&ring, ACCESS_TOKEN_TYPE, &claims)?;
+
+        Ok(Some(AccessToken {
+            token,
+            expires_in: ttl,
+        }))
+    }
+}
+
+#[async_trait]
+impl JwtService for JwtServiceImpl {
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    async fn issue_access_token(
+        &self,
+        account: &accounts::Model,
+    ) -> Result<Option<AccessToken>, JwtError> {
+        self.sign(account.uid.to_string(), None, None).await
+    }
+
+    async fn issue_client_access_token(
+        &self,
+        subject: &str,
+        client_id: &str,
+        scope: &str,
+    ) -> Result<Option<AccessToken>, JwtError> {
+        self.sign(subject.to_string(), Some(client_id), Some(scope))
+            .await
+    }
+
+    async fn issue_id_token(
+        &self,
+        client_id: &str,
+        mut claims: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Option<String>, JwtError> {
+        if !self.settings.enabled {
+            return Ok(None);
+        }
+
+        let ring = self.ring().await?;
+        let now = Utc::now().timestamp();
+        claims.insert("iss".to_string(), self.settings.issuer.clone().into());
+        claims.insert("aud".to_string(), client_id.into());
+        claims.insert("iat".to_string(), now.into());
+        claims.insert(
+            "exp".to_string(),
+            (now + self.settings.access_token_ttl_seconds as i64).into(),
+        );
+        Self::encode(&ring, "JWT", &claims).map(Some)
+    }
+
+    async fn verify_access_token(&self, token: &str) -> Result<VerifiedAccessToken, JwtError> {
+        let invalid = |message: &str| JwtError::new("invalid_token", message);
+        if !self.settings.enabled {
+            return Err(invalid("access tokens are not enabled"));
+        }
+
+        let header = jsonwebtoken::decode_header(token).map_err(|_| invalid("malformed token"))?;
+        if header.typ.as_deref() != Some(ACCESS_TOKEN_TYPE) {
+            return Err(invalid("not an access token"));
+        }
+        let ring = self.ring().await?;
+        let jwk = header
+            .kid
+            .as_deref()
+            .and_then(|kid| ring.published.iter().find(|jwk| jwk["kid"] == kid))
+            .ok_or_else(|| invalid("unknown signing key"))?;
+        // The key decides the algorithm; trusting `header.alg` invites algorithm confusion.
+        let algorithm = jwk["alg"]
+            .as_str()
+            .and_then(|alg| parse_algorithm(alg).ok())
+            .ok_or_else(|| invalid("bad key"))?;
+        if header.alg != algorithm {
+            return Err(invalid("token algorithm does not match its key"));
+        }
+        let jwk: Jwk = serde_json::from_value(jwk.clone()).map_err(|_| invalid("bad key"))?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| invalid("bad key"))?;
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[&self.settings.issuer]);
+        match &self.settings.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        jsonwebtoken::decode::<VerifiedAccessToken>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|err| invalid(&err.to_string()))
+    }
+
+    async fn jwks(&self) -> Result<serde_json::Value, JwtError> {
+        if !self.settings.enabled {
+            return Ok(serde_json::json!({ "keys": [] }));
+        }
+        let ring = self.ring().await?;
+        Ok(serde_json::json!({ "keys": ring.published }))
+    }
+}
+
+fn parse_algorithm(value: &str) -> Result<Algorithm, JwtError> {
+    match value.to_ascii_uppercase().as_str() {
+        "RS256" => Ok(Algorithm::RS256),
+        "ES256" => Ok(Algorithm::ES256),
+        "EDDSA" => Ok(Algorithm::EdDSA),
+        _ => Err(JwtError::key(format!(
+            "unsupported JWT_ALGORITHM {} (expected RS256, ES256 or EdDSA)",
+            value
+        ))),
+    }
+}
+
+fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::RS256 => "RS256",
+        Algorithm::ES256 => "ES256",
+        _ => "EdDSA",
+    }
+}
+
+fn b64(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// RFC 7638 thumbprint over the required members in lexicographic order.
+fn thumbprint(canonical: String) -> String {
+    b64(&Sha256::digest(canonical.as_bytes()))
+}
+
+fn generate_private_key_pem(algorithm: Algorithm) -> Result<String, JwtError> {
+    use p256::pkcs8::{EncodePrivateKey, LineEnding};
+
+    let mut rng = rand::thread_rng();
+    let pem = match algorithm {
+        Algorithm::RS256 => rsa::RsaPrivateKey::new(&mut rng, 2048)
+            .map_err(|err| JwtError::key(err.to_string()))?
+            .to_pkcs8_pem(LineEnding::LF),
+        Algorithm::ES256 => p256::SecretKey::random(&mut rng).to_pkcs8_pem(LineEnding::LF),
+        _ => ed25519_dalek::SigningKey::generate(&mut rng).to_pkcs8_pem(LineEnding::LF),
+    };
+    pem.map(|value| value.to_string())
+        .map_err(|err| JwtError::key(err.to_string()))
+}
+
+/// Accepts PKCS#8 keys of any supported type, plus PKCS#1 RSA and SEC1 EC keys.
+fn signing_key_from_pem(pem: &str) -> Result<SigningKey, JwtError> {
+    use p256::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+    use rsa::{pkcs1::DecodeRsaPrivateKey, pkcs1::EncodeRsaPrivateKey, traits::PublicKeyParts};
+
+    let rsa_key = rsa::RsaPrivateKey::from_pkcs8_pem(pem)
+        .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(pem));
+    if let Ok(key) = rsa_key {
+        let n = b64(&key.n().to_bytes_be());
+        let e = b64(&key.e().to_bytes_be());
+        let kid = thumbprint(format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n));
+        let der = key
+            .to_pkcs1_der()
+            .map_err(|err| JwtError::key(err.to_string()))?;
+        return Ok(SigningKey {
+            jwk: serde_json::json!({
+                "kty": "RSA", "use": "sig", "alg": "RS256", "kid": kid, "n": n, "e": e,
+            }),
+            kid,
+            algorithm: Algorithm::RS256,
+            encoding: EncodingKey::from_rsa_der(der.as_bytes()),
+        });
+    }
+
+    let ec_key =
+        p256::SecretKey::from_pkcs8_pem(pem).or_else(|_| p256::SecretKey::from_sec1_pem(pem));
+    if let Ok(key) = ec_key {
+        let point =
+            p256::elliptic_curve::sec1::ToEncodedPoint::to_encoded_point(&key.public_key(), false);
+        let (Some(x), Some(y)) = (point.x(), point.y()) else {
+            return Err(JwtError::key("invalid P-256 public key"));
+        };
+        let (x, y) = (b64(x), b64(y));
+        let kid = thumbprint(format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            x, y
+        ));
+        let der = key
+            .to_pkcs8_der()
+            .map_err(|err| JwtError::key(err.to_string()))?;
+        return Ok(SigningKey {
+            jwk: serde_json::json!({
+                "kty": "EC", "use": "sig", "alg": "ES256", "kid": kid, "crv": "P-256", "x": x, "y": y,
+            }),
+            kid,
+            algorithm: Algorithm::ES256,
+            encoding: EncodingKey::from_ec_der(der.as_bytes()),
+        });
+    }
+
+    if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+        let x = b64(key.verifying_key().as_bytes());
+        let kid = thumbprint(format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, x));
+        let der = key
+            .to_pkcs8_der()
+            .map_err(|err| JwtError::key(err.to_string()))?;
+        return Ok(SigningKey {
+            jwk: serde_json::json!({
+                "kty": "OKP", "use": "sig", "alg": "EdDSA", "kid": kid, "crv": "Ed25519", "x": x,
+            }),
+            kid,
+            algorithm: Algorithm::EdDSA,
+            encoding: EncodingKey::from_ed_der(der.as_bytes()),
+        });
+    }
+
+    Err(JwtError::key(
+        "unsupported private key (expected RSA, P-256 or Ed25519 PEM)",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_keys_sign_tokens_verifiable_with_their_jwk() {
+        for algorithm in [Algorithm::ES256, Algorithm::EdDSA] {
+            let pem = generate_private_key_pem(algorithm).unwrap();
+            let key = signing_key_from_pem(&pem).unwrap();
+            assert_eq!(key.algorithm, algorithm);
+            assert_eq!(key.jwk["kid"], key.kid.as_str());
+
+            let mut header = Header::new(algorithm);
+            header.kid = Some(key.kid.clone());
+            let claims = serde_json::json!({ "sub": "abc", "exp": Utc::now().timestamp() + 60 });
+            let token = jsonwebtoken::encode(&header, &claims, &key.encoding).unwrap();
+
+            let jwk: Jwk = serde_json::from_value(key.jwk.clone()).unwrap();
+            let decoded = jsonwebtoken::decode::<serde_json::Value>(
+                &token,
+                &DecodingKey::from_jwk(&jwk).unwrap(),
+                &Validation::new(algorithm),
+            )
+            .unwrap();
+            assert_eq!(decoded.claims["sub"], "abc");
+        }
+    }
+
+    #[test]
+    fn thumbprint_matches_rfc7638_example() {
+        let n = "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw";
+        let canonical = format!(r#"{{"e":"AQAB","kty":"RSA","n":"{}"}}"#, n);
+        assert_eq!(
+            thumbprint(canonical),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[tokio::test]
+    async fn access_tokens_must_use_their_keys_algorithm() {
+        use crate::repo::signing_keys::InMemorySigningKeysRepo;
+
+        let service = JwtServiceImpl::new(
+            JwtSettings {
+                enabled: true,
+                algorithm: "ES256".into(),
+                issuer: "https://auth.example".into(),
+                audience: None,
+                access_token_ttl_seconds: 60,
+                key_rotation_seconds: 3600,
+                private_key_path: None,
+                previous_key_paths: Vec::new(),
+            },
+            Arc::new(InMemorySigningKeysRepo::default()),
+        )
+        .unwrap();
+        let token = service
+            .sign("abc".into(), None, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            service.verify_access_token(&token.token).await.unwrap().sub,
+            "abc"
+        );
+
+        let ring = service.ring().await.unwrap();
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(ring.signing.kid.clone());
+        header.typ = Some(ACCESS_TOKEN_TYPE.to_string());
+        let claims = serde_json::json!({
+            "iss": "https://auth.example",
+            "sub": "mallory",
+            "exp": Utc::now().timestamp() + 60,
+        });
+        let forged = jsonwebtoken::encode(
+            &header,
+            &claims,
+            &EncodingKey::from_secret(ring.signing.jwk.to_string().as_bytes()),
+        )
+        .unwrap();
+        let err = service.verify_access_token(&forged).await.unwrap_err();
+        assert_eq!(err.message, "token algorithm does not match its key");
+    }
+}
@@ -0,0 +1,495 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::{
+    entities::{account_credentials, accounts},
+    repo::account_credentials::AccountCredentialsRepo,
+};
+
+pub const PROVIDER_TOTP: &str = "totp";
+
+const TOTP_PERIOD_SECONDS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+const TOTP_SKEW_STEPS: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(Debug)]
+pub struct MfaError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl MfaError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Layout of `account_credentials.metadata` for the `totp` provider.
+#[derive(Serialize, Deserialize)]
+struct TotpMetadata {
+    secret_ciphertext: String,
+    secret_nonce: String,
+    confirmed: bool,
+    last_used_step: Option<i64>,
+    #[serde(default)]
+    recovery_code_hashes: Vec<String>,
+}
+
+#[async_trait]
+pub trait MfaService: Send + Sync {
+    async fn begin_totp_enrollment(
+        &self,
+        account: &accounts::Model,
+    ) -> Result<TotpEnrollment, MfaError>;
+    /// Activates TOTP and returns the plaintext recovery codes (shown once).
+    async fn confirm_totp_enrollment(
+        &self,
+        account: &accounts::Model,
+        code: &str,
+    ) -> Result<Vec<String>, MfaError>;
+    async fn disable_totp(&self, account: &accounts::Model, code: &str) -> Result<(), MfaError>;
+    async fn is_totp_enabled(&self, account_id: i64) -> Result<bool, MfaError>;
+    /// Accepts a current TOTP code or an unused recovery code.
+    async fn verify_code(&self, account_id: i64, code: &str) -> Result<(), MfaError>;
+}
+
+pub struct MfaServiceImpl {
+    credentials_repo: Arc<dyn AccountCredentialsRepo>,
+    encryption_key: Option<[u8; 32]>,
+    issuer: String,
+}
+
+impl MfaServiceImpl {
+    pub fn new(
+        credentials_repo: Arc<dyn AccountCredentialsRepo>,
+        encryption_key: Option<&str>,
+        issuer: String,
+    ) -> Self {
+        let encryption_key = encryption_key.and_then(|value| {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(value)
+                .ok()?;
+            <[u8; 32]>::try_from(bytes.as_slice()).ok()
+        });
+        Self {
+            credentials_repo,
+            encryption_key,
+            issuer,
+        }
+    }
+
+    fn cipher(&self) -> Result<Aes256Gcm, MfaError> {
+        let Some(key) = &self.encryption_key else {
+            return Err(MfaError::new(
+                "mfa_not_configured",
+                "MFA_ENCRYPTION_KEY is not set or is not 32 base64-encoded bytes",
+            ));
+        };
+        Aes256Gcm::new_from_slice(key)
+            .map_err(|err| MfaError::new("mfa_not_configured", err.to_string()))
+    }
+
+    fn encrypt_secret(&self, secret: &[u8]) -> Result<(String, String), MfaError> {
+        let cipher = self.cipher()?;
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), secret)
+            .map_err(|err| MfaError::new("mfa_crypto_error", err.to_string()))?;
+        let engine = base64::engine::general_purpose::STANDARD;
+        Ok((engine.encode(ciphertext), engine.encode(nonce)))
+    }
+
+    fn decrypt_secret(&self, metadata: &TotpMetadata) -> Result<Vec<u8>, MfaError> {
+        let cipher = self.cipher()?;
+        let engine = base64::engine::general_purpose::STANDARD;
+        let ciphertext = engine
+            .decode(&metadata.secret_ciphertext)
+            .map_err(|err| MfaError::new("mfa_crypto_error", err.to_string()))?;
+        let nonce = engine
+            .decode(&metadata.secret_nonce)
+            .map_err(|err| MfaError::new("mfa_crypto_error", err.to_string()))?;
+        if nonce.len() != 12 {
+            return Err(MfaError::new("mfa_crypto_error", "invalid secret nonce"));
+        }
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|err| MfaError::new("mfa_crypto_error", err.to_string()))
+    }
+
+    fn hash_recovery_code(code: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(code.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn normalize_recovery_code(code: &str) -> String {
+        code.trim().to_ascii_lowercase().replace(' ', "")
+    }
+
+    fn generate_recovery_codes() -> Vec<String> {
+        (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let mut bytes = [0u8; 6];
+                rand::thread_rng().fill_bytes(&mut bytes);
+                let encoded = data_encoding::BASE32_NOPAD
+                    .encode(&bytes)
+                    .to_ascii_lowercase();
+                format!("{}-{}", &encoded[..5], &encoded[5..10])
+            })
+            .collect()
+    }
+
+    fn parse_metadata(credential: &account_credentials::Model) -> Result<TotpMetadata, MfaError> {
+        let Some(value) = credential.metadata.clone() else {
+            return Err(MfaError::new("mfa_not_enabled", "totp is not enrolled"));
+        };
+        serde_json::from_value(value)
+            .map_err(|err| MfaError::new("mfa_metadata_invalid", err.to_string()))
+    }
+
+    async fn find_totp_credential(
+        &self,
+        account_id: i64,
+    ) -> Result<Option<account_credentials::Model>, MfaError> {
+        self.credentials_repo
+            .find_by_account_and_provider(account_id, PROVIDER_TOTP)
+            .await
+            .map_err(|err| MfaError::new("db_error", err.to_string()))
+    }
+
+    /// Writes `metadata` only if the credential is unchanged since it was read, so two requests
+    /// cannot both spend the same TOTP step or recovery code. Returns whether the write won.
+    async fn save_metadata(
+        &self,
+        credential: account_credentials::Model,
+        metadata: &TotpMetadata,
+        actor: Option<uuid::Uuid>,
+    ) -> Result<bool, MfaError> {
+        let value = serde_json::to_value(metadata)
+            .map_err(|err| MfaError::new("mfa_metadata_invalid", err.to_string()))?;
+        let updated_at = credential.updated_at;
+        let active = account_credentials::ActiveModel {
+            id: sea_orm::Set(credential.id),
+            metadata: sea_orm::Set(Some(value)),
+            updated_by: sea_orm::Set(actor),
+            ..Default::default()
+        };
+        self.credentials_repo
+            .update_if_unchanged(active, updated_at)
+            .await
+            .map_err(|err| MfaError::new("db_error", err.to_string()))
+    }
+
+    /// Returns the matched time step so it can be recorded against replay.
+    fn match_totp(secret: &[u8], code: &str, last_used_step: Option<i64>) -> Option<i64> {
+        let code = code.trim();
+        if code.len() != TOTP_DIGITS as usize || !code.chars().all(|ch| ch.is_ascii_digit()) {
+            return None;
+        }
+        let current_step = Utc::now().timestamp() / TOTP_PERIOD_SECONDS;
+        (current_step - TOTP_SKEW_STEPS..=current_step + TOTP_SKEW_STEPS)
+            .filter(|step| last_used_step.is_none_or(|last| *step > last))
+            .find(|step| format_totp(totp_value(secret, *step as u64)) == code)
+    }
+}
+
+fn totp_value(secret: &[u8], step: u64) -> u32 {
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("hmac accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = (u32::from(digest[offset] & 0x7f) << 24)
+        | (u32::from(digest[offset + 1]) << 16)
+        | (u32::from(digest[offset + 2]) << 8)
+        | u32::from(digest[offset + 3]);
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+fn format_totp(value: u32) -> String {
+    format!("{:0width$}", value, width = TOTP_DIGITS as usize)
+}
+
+/// The code an authenticator app would show now for the base32 `secret` from enrollment.
+#[cfg(test)]
+pub(crate) fn current_totp(secret: &str) -> String {
+    let secret = data_encoding::BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .unwrap();
+    let step = Utc::now().timestamp() / TOTP_PERIOD_SECONDS;
+    format_totp(totp_value(&secret, step as u64))
+}
+
+#[async_trait]
+impl MfaService for MfaServiceImpl {
+    async fn begin_totp_enrollment(
+        &self,
+        account: &accounts::Model,
+    ) -> Result<TotpEnrollment, MfaError> {
+        let mut secret = [0u8; 20];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let (secret_ciphertext, secret_nonce) = self.encrypt_secret(&secret)?;
+        let metadata = TotpMetadata {
+            secret_ciphertext,
+            secret_nonce,
+            confirmed: false,
+            last_used_step: None,
+            recovery_code_hashes: Vec::new(),
+        };
+
+        match self.find_totp_credential(account.id).await? {
+            Some(existing) => {
+                if Self::parse_metadata(&existing).is_ok_and(|metadata| metadata.confirmed) {
+                    return Err(MfaError::new(
+                        "mfa_already_enabled",
+                        "totp is already enabled",
+                    ));
+                }
+                if !self
+                    .save_metadata(existing, &metadata, Some(account.uid))
+                    .await?
+                {
+                    return Err(MfaError::new(
+                        "mfa_enrollment_changed",
+                        "totp enrollment changed concurrently; try again",
+                    ));
+                }
+            }
+            None => {
+                let value = serde_json::to_value(&metadata)
+                    .map_err(|err| MfaError::new("mfa_metadata_invalid", err.to_string()))?;
+                let model = account_credentials::ActiveModel {
+                    account_id: sea_orm::Set(account.id),
+                    provider: sea_orm::Set(PROVIDER_TOTP.to_string()),
+                    provider_subject: sea_orm::Set(None),
+                    password_hash: sea_orm::Set(None),
+                    metadata: sea_orm::Set(Some(value)),
+                    created_by: sea_orm::Set(Some(account.uid)),
+                    updated_by: sea_orm::Set(Some(account.uid)),
+                    ..Default::default()
+                };
+                self.credentials_repo
+                    .insert(model)
+                    .await
+                    .map_err(|err| MfaError::new("db_error", err.to_string()))?;
+            }
+        }
+
+        let secret = data_encoding::BASE32_NOPAD.encode(&secret);
+        let label = account
+            .email
+            .clone()
+            .or_else(|| account.username.clone())
+            .unwrap_or_else(|| account.uid.to_string());
+        let otpauth_uri = format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            urlencoding::encode(&self.issuer),
+            urlencoding::encode(&label),
+            secret,
+            urlencoding::encode(&self.issuer),
+            TOTP_DIGITS,
+            TOTP_PERIOD_SECONDS
+        );
+
+        Ok(TotpEnrollment {
+            secret,
+            otpauth_uri,
+        })
+    }
+
+    async fn confirm_totp_enrollment(
+        &self,
+        account: &accounts::Model,
+        code: &str,
+    ) -> Result<Vec<String>, MfaError> {
+        let Some(credential) = self.find_totp_credential(account.id).await? else {
+            return Err(MfaError::new(
+                "mfa_not_enrolled",
+                "totp enrollment not started",
+            ));
+        };
+        let mut metadata = Self::parse_metadata(&credential)?;
+        if metadata.confirmed {
+            return Err(MfaError::new(
+                "mfa_already_enabled",
+                "totp is already enabled",
+            ));
+        }
+
+        let secret = self.decrypt_secret(&metadata)?;
+        let Some(step) = Self::match_totp(&secret, code, metadata.last_used_step) else {
+            return Err(MfaError::new("invalid_mfa_code", "invalid code"));
+        };
+
+        let recovery_codes = Self::generate_recovery_codes();
+        metadata.confirmed = true;
+        metadata.last_used_step = Some(step);
+        metadata.recovery_code_hashes = recovery_codes
+            .iter()
+            .map(|code| Self::hash_recovery_code(code))
+            .collect();
+        if !self
+            .save_metadata(credential, &metadata, Some(account.uid))
+            .await?
+        {
+            return Err(MfaError::new(
+                "mfa_enrollment_changed",
+                "totp enrollment changed concurrently; try again",
+            ));
+        }
+
+        Ok(recovery_codes)
+    }
+
+    async fn disable_totp(&self, account: &accounts::Model, code: &str) -> Result<(), MfaError> {
+        let Some(credential) = self.find_totp_credential(account.id).await? else {
+            return Err(MfaError::new("mfa_not_enabled", "totp is not enabled"));
+        };
+        if Self::parse_metadata(&credential).is_ok_and(|metadata| metadata.confirmed) {
+            self.verify_code(account.id, code).await?;
+        }
+
+        self.credentials_repo
+            .soft_delete(credential.id, account.uid)
+            .await
+            .map_err(|err| MfaError::new("db_error", err.to_string()))?;
+        Ok(())
+    }
+
+    async fn is_totp_enabled(&self, account_id: i64) -> Result<bool, MfaError> {
+        let Some(credential) = self.find_totp_credential(account_id).await? else {
+            return Ok(false);
+        };
+        Ok(Self::parse_metadata(&credential).is_ok_and(|metadata| metadata.confirmed))
+    }
+
+    async fn verify_code(&self, account_id: i64, code: &str) -> Result<(), MfaError> {
+        let Some(credential) = self.find_totp_credential(account_id).await? else {
+            return Err(MfaError::new("mfa_not_enabled", "totp is not enabled"));
+        };
+        let mut metadata = Self::parse_metadata(&credential)?;
+        if !metadata.confirmed {
+            return Err(MfaError::new("mfa_not_enabled", "totp is not enabled"));
+        }
+
+        let secret = self.decrypt_secret(&metadata)?;
+        if let Some(step) = Self::match_totp(&secret, code, metadata.last_used_step) {
+            metadata.last_used_step = Some(step);
+        } else {
+            let recovery_hash = Self::hash_recovery_code(&Self::normalize_recovery_code(code));
+            let before = metadata.recovery_code_hashes.len();
+            metadata
+                .recovery_code_hashes
+                .retain(|hash| *hash != recovery_hash);
+            if metadata.recovery_code_hashes.len() == before {
+                return Err(MfaError::new("invalid_mfa_code", "invalid code"));
+            }
+        }
+
+        // Losing the write means a concurrent request spent this code first.
+        if !self.save_metadata(credential, &metadata, None).await? {
+            return Err(MfaError::new("invalid_mfa_code", "invalid code"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totp_matches_rfc6238_sha1_vectors() {
+        let secret = b"12345678901234567890";
+        // RFC 6238 Appendix B, truncated to 6 digits.
+        for (time, expected) in [
+            (59u64, "287082"),
+            (1111111109, "081804"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            let step = time / TOTP_PERIOD_SECONDS as u64;
+            assert_eq!(format_totp(totp_value(secret, step)), expected);
+        }
+    }
+
+    #[test]
+    fn match_totp_rejects_replayed_step() {
+        let secret = b"12345678901234567890";
+        let step = Utc::now().timestamp() / TOTP_PERIOD_SECONDS;
+        let code = format_totp(totp_value(secret, step as u64));
+        assert_eq!(MfaServiceImpl::match_totp(secret, &code, None), Some(step));
+        assert_eq!(MfaServiceImpl::match_totp(secret, &code, Some(step)), None);
+    }
+
+    #[tokio::test]
+    async fn recovery_codes_are_spent_once_even_from_a_stale_read() {
+        use crate::repo::{
+            account_credentials::InMemoryAccountCredentialsRepo,
+            accounts::{AccountsRepo, InMemoryAccountsRepo},
+        };
+
+        let repo = Arc::new(InMemoryAccountCredentialsRepo::default());
+        let key = base64::engine::general_purpose::STANDARD.encode([7u8; 32]);
+        let service = MfaServiceImpl::new(repo.clone(), Some(&key), "test".into());
+        let account = InMemoryAccountsRepo::default()
+            .insert(accounts::ActiveModel {
+                account_type: sea_orm::Set("user".to_string()),
+                email: sea_orm::Set(Some("totp@example.com".to_string())),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let enrollment = service.begin_totp_enrollment(&account).await.unwrap();
+        let recovery_codes = service
+            .confirm_totp_enrollment(&account, &current_totp(&enrollment.secret))
+            .await
+            .unwrap();
+
+        // A second request that read the credential before the first one spent the code.
+        let stale = service
+            .find_totp_credential(account.id)
+            .await
+            .unwrap()
+            .unwrap();
+        service
+            .verify_code(account.id, &recovery_codes[0])
+            .await
+            .unwrap();
+        let mut metadata = MfaServiceImpl::parse_metadata(&stale).unwrap();
+        metadata.recovery_code_hashes.remove(0);
+        assert!(!service.save_metadata(stale, &metadata, None).await.unwrap());
+
+        let err = service
+            .verify_code(account.id, &recovery_codes[0])
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "invalid_mfa_code");
+        service
+            .verify_code(account.id, &recovery_codes[1])
+            .await
+            .unwrap();
+    }
+}
@@ -1,6 +1,22 @@
+pub mod account_settings;
 pub mod accounts;
 pub mod auth;
+pub mod avatars;
 pub mod config;
+pub mod credentials;
 pub mod email;
+pub mod forward_auth;
+pub mod introspection;
+pub mod jwt;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod personal_access_tokens;
+pub mod pkce;
+pub mod rbac;
+pub mod refresh_tokens;
 pub mod session;
+pub mod storage;
+pub mod teams;
 pub mod verification;
+pub mod webauthn;
@@ -0,0 +1,689 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    entities::{account_authorizations, accounts, oauth_clients, oauth_consents},
+    repo::{
+        account_authorizations::AccountAuthorizationsRepo, accounts::AccountsRepo,
+        oauth_clients::OauthClientsRepo, oauth_consents::OauthConsentsRepo,
+    },
+    service::{pkce, verification::hash_token},
+};
+
+pub const TOKEN_TYPE_OAUTH_CODE: &str = "oauth:code";
+pub const TOKEN_TYPE_OAUTH_CONSENT: &str = "oauth:consent";
+
+/// How long a user has to answer the consent screen.
+const CONSENT_TTL_SECONDS: i64 = 60 * 10;
+const MAX_REDIRECT_URIS: usize = 10;
+
+/// Codes are the RFC 6749 error names so handlers can pass them straight to clients.
+#[derive(Debug)]
+pub struct OauthError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl OauthError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("server_error", err.to_string())
+    }
+
+    fn invalid_grant(message: impl Into<String>) -> Self {
+        Self::new("invalid_grant", message)
+    }
+}
+
+pub struct RegisterClientInput {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub is_public: bool,
+}
+
+/// A validated `/oauth/authorize` request. Stored with pending consents and authorization codes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizeRequest {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub state: Option<String>,
+    pub code_challenge: String,
+    /// OpenID Connect `nonce`, echoed into the ID token.
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+impl AuthorizeRequest {
+    pub fn scope(&self) -> String {
+        self.scopes.join(" ")
+    }
+}
+
+/// The result of redeeming an authorization code.
+pub struct CodeGrant {
+    pub account: accounts::Model,
+    pub scope: String,
+    pub nonce: Option<String>,
+    /// Shared with refresh tokens issued for this code, so replaying the code revokes them.
+    pub family_id: Uuid,
+}
+
+pub fn client_scopes(client: &oauth_clients::Model) -> Vec<String> {
+    json_strings(&client.allowed_scopes)
+}
+
+pub fn client_redirect_uris(client: &oauth_clients::Model) -> Vec<String> {
+    json_strings(&client.redirect_uris)
+}
+
+/// Resolves requested scopes against what the client may ask for. Omitting `scope` means
+/// every allowed scope.
+pub fn resolve_scopes(
+    client: &oauth_clients::Model,
+    scope: Option<&str>,
+) -> Result<Vec<String>, OauthError> {
+    let allowed = client_scopes(client);
+    let requested: Vec<String> = match scope.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(scope) => scope.split_whitespace().map(str::to_string).collect(),
+        None => return Ok(allowed),
+    };
+    if let Some(scope) = requested.iter().find(|scope| !allowed.contains(scope)) {
+        return Err(OauthError::new(
+            "invalid_scope",
+            format!("scope {} is not allowed for this client", scope),
+        ));
+    }
+    let mut scopes = Vec::new();
+    for scope in requested {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Ok(scopes)
+}
+
+fn json_strings(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[async_trait]
+pub trait OauthService: Send + Sync {
+    /// Registers a client owned by `owner`. Returns the plaintext secret for confidential
+    /// clients; it is not retrievable afterwards.
+    async fn register_client(
+        &self,
+        owner: &accounts::Model,
+        input: RegisterClientInput,
+    ) -> Result<(oauth_clients::Model, Option<String>), OauthError>;
+    async fn list_clients(
+        &self,
+        owner: &accounts::Model,
+    ) -> Result<Vec<oauth_clients::Model>, OauthError>;
+    async fn delete_client(
+        &self,
+        owner: &accounts::Model,
+        client_id: &str,
+    ) -> Result<(), OauthError>;
+    /// Checks `client_id` and `redirect_uri`. Failures here must not redirect, since the
+    /// redirect target itself is untrusted.
+    async fn authorize_client(
+        &self,
+        client_id: &str,
+        redirect_uri: &str,
+    ) -> Result<oauth_clients::Model, OauthError>;
+    /// Authenticates a client at the token endpoint. Public clients present no secret.
+    async fn authenticate_client(
+        &self,
+        client_id: &str,
+        client_secret: Option<&str>,
+    ) -> Result<oauth_clients::Model, OauthError>;
+    /// Whether the account already approved these scopes for the client.
+    async fn has_consent(
+        &self,
+        account: &accounts::Model,
+        client: &oauth_clients::Model,
+        scopes: &[String],
+    ) -> Result<bool, OauthError>;
+    /// Parks the request until the user answers the consent screen; returns the consent id.
+    async fn begin_consent(
+        &self,
+        account: &accounts::Model,
+        request: &AuthorizeRequest,
+    ) -> Result<String, OauthError>;
+    async fn find_consent(
+        &self,
+        account: &accounts::Model,
+        consent_id: &str,
+    ) -> Result<(oauth_clients::Model, AuthorizeRequest), OauthError>;
+    /// Consumes a pending consent. Approval is remembered for later authorizations.
+    async fn finish_consent(
+        &self,
+        account: &accounts::Model,
+        consent_id: &str,
+        approve: bool,
+    ) -> Result<AuthorizeRequest, OauthError>;
+    async fn create_code(
+        &self,
+        account: &accounts::Model,
+        request: &AuthorizeRequest,
+    ) -> Result<String, OauthError>;
+    async fn exchange_code(
+        &self,
+        client: &oauth_clients::Model,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<CodeGrant, OauthError>;
+    /// OpenID Connect claims for the account, limited to what `scope` covers: `sub` always,
+    /// `preferred_username` with `profile`, `email`/`email_verified` with `email`.
+    async fn identity_claims(
+        &self,
+        account: &accounts::Model,
+        scope: &str,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, OauthError>;
+}
+
+pub struct OauthServiceImpl {
+    accounts_repo: Arc<dyn AccountsRepo>,
+    clients_repo: Arc<dyn OauthClientsRepo>,
+    consents_repo: Arc<dyn OauthConsentsRepo>,
+    authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+    code_ttl_seconds: u64,
+}
+
+impl OauthServiceImpl {
+    pub fn new(
+        accounts_repo: Arc<dyn AccountsRepo>,
+        clients_repo: Arc<dyn OauthClientsRepo>,
+        consents_repo: Arc<dyn OauthConsentsRepo>,
+        authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+        code_ttl_seconds: u64,
+    ) -> Self {
+        Self {
+            accounts_repo,
+            clients_repo,
+            consents_repo,
+            authorizations_repo,
+            code_ttl_seconds,
+        }
+    }
+
+    fn validate_redirect_uri(value: &str) -> Result<String, OauthError> {
+        let invalid = || {
+            OauthError::new(
+                "invalid_redirect_uri",
+                format!(
+                    "{} must be an https URL (http is allowed for localhost) without a fragment",
+                    value
+                ),
+            )
+        };
+        let value = value.trim();
+        let url = reqwest::Url::parse(value).map_err(|_| invalid())?;
+        if url.fragment().is_some() || !url.username().is_empty() {
+            return Err(invalid());
+        }
+        let loopback = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+        match url.scheme() {
+            "https" => Ok(value.to_string()),
+            "http" if loopback => Ok(value.to_string()),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn validate_scope(value: &str) -> Result<String, OauthError> {
+        let value = value.trim();
+        let valid = !value.is_empty()
+            && value.len() <= 64
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '_' | '.' | '-' | '/'));
+        if valid {
+            Ok(value.to_string())
+        } else {
+            Err(OauthError::new(
+                "invalid_scope",
+                format!("invalid scope {:?}", value),
+            ))
+        }
+    }
+
+    async fn client(&self, client_id: &str) -> Result<oauth_clients::Model, OauthError> {
+        self.clients_repo
+            .find_by_client_id(client_id)
+            .await
+            .map_err(OauthError::db)?
+            .ok_or_else(|| OauthError::new("invalid_client", "unknown client"))
+    }
+
+    async fn pending(
+        &self,
+        token: &str,
+        token_type: &str,
+    ) -> Result<Option<(account_authorizations::Model, AuthorizeRequest)>, OauthError> {
+        let record = self
+            .authorizations_repo
+            .find_by_token_hash(&hash_token(token))
+            .await
+            .map_err(OauthError::db)?
+            .filter(|record| record.token_type == token_type);
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let request = record
+            .metadata
+            .clone()
+            .and_then(|value| serde_json::from_value::<AuthorizeRequest>(value).ok());
+        Ok(request.map(|request| (record, request)))
+    }
+
+    async fn pending_consent(
+        &self,
+        account: &accounts::Model,
+        consent_id: &str,
+    ) -> Result<(account_authorizations::Model, AuthorizeRequest), OauthError> {
+        let not_found = || OauthError::new("consent_not_found", "consent request not found");
+        let Some((record, request)) = self.pending(consent_id, TOKEN_TYPE_OAUTH_CONSENT).await?
+        else {
+            return Err(not_found());
+        };
+        let active = record.revoked_at.is_none()
+            && record
+                .expires_at
+                .is_some_and(|expires_at| expires_at > Utc::now());
+        if !active || record.account_id != account.id {
+            return Err(not_found());
+        }
+        Ok((record, request))
+    }
+}
+
+#[async_trait]
+impl OauthService for OauthServiceImpl {
+    async fn register_client(
+        &self,
+        owner: &accounts::Model,
+        input: RegisterClientInput,
+    ) -> Result<(oauth_clients::Model, Option<String>), OauthError> {
+        let name = input.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > 100 {
+            return Err(OauthError::new(
+                "invalid_client_metadata",
+                "name must be 1-100 characters",
+            ));
+        }
+        if input.redirect_uris.is_empty() || input.redirect_uris.len() > MAX_REDIRECT_URIS {
+            return Err(OauthError::new(
+                "invalid_redirect_uri",
+                format!(
+                    "between 1 and {} redirect URIs are required",
+                    MAX_REDIRECT_URIS
+                ),
+            ));
+        }
+        let redirect_uris = input
+            .redirect_uris
+            .iter()
+            .map(|value| Self::validate_redirect_uri(value))
+            .collect::<Result<Vec<_>, _>>()?;
+        let scopes = input
+            .scopes
+            .iter()
+            .map(|value| Self::validate_scope(value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let client_id = Uuid::new_v4().simple().to_string();
+        let client_secret = (!input.is_public).then(pkce::random_token);
+        let model = oauth_clients::ActiveModel {
+            client_id: sea_orm::Set(client_id),
+            client_secret_hash: sea_orm::Set(client_secret.as_deref().map(hash_token)),
+            name: sea_orm::Set(name),
+            redirect_uris: sea_orm::Set(serde_json::json!(redirect_uris)),
+            allowed_scopes: sea_orm::Set(serde_json::json!(scopes)),
+            is_public: sea_orm::Set(input.is_public),
+            first_party: sea_orm::Set(false),
+            owner_account_id: sea_orm::Set(Some(owner.id)),
+            created_by: sea_orm::Set(Some(owner.uid)),
+            updated_by: sea_orm::Set(Some(owner.uid)),
+            ..Default::default()
+        };
+        let client = self
+            .clients_repo
+            .insert(model)
+            .await
+            .map_err(OauthError::db)?;
+        Ok((client, client_secret))
+    }
+
+    async fn list_clients(
+        &self,
+        owner: &accounts::Model,
+    ) -> Result<Vec<oauth_clients::Model>, OauthError> {
+        self.clients_repo
+            .list_by_owner(owner.id)
+            .await
+            .map_err(OauthError::db)
+    }
+
+    async fn delete_client(
+        &self,
+        owner: &accounts::Model,
+        client_id: &str,
+    ) -> Result<(), OauthError> {
+        let client = self
+            .clients_repo
+            .find_by_client_id(client_id)
+            .await
+            .map_err(OauthError::db)?
+            .filter(|client| client.owner_account_id == Some(owner.id))
+            .ok_or_else(|| OauthError::new("client_not_found", "client not found"))?;
+
+        let mut active: oauth_clients::ActiveModel = client.into();
+        active.deleted_at = sea_orm::Set(Some(Utc::now().into()));
+        active.deleted_by = sea_orm::Set(Some(owner.uid));
+        active.updated_by = sea_orm::Set(Some(owner.uid));
+        self.clients_repo
+            .update(active)
+            .await
+            .map_err(OauthError::db)?;
+        Ok(())
+    }
+
+    async fn authorize_client(
+        &self,
+        client_id: &str,
+        redirect_uri: &str,
+    ) -> Result<oauth_clients::Model, OauthError> {
+        let client = self.client(client_id).await?;
+        // Exact match only; prefix or pattern matching is how open redirects creep in.
+        if !client_redirect_uris(&client)
+            .iter()
+            .any(|uri| uri == redirect_uri)
+        {
+            return Err(OauthError::new(
+                "invalid_redirect_uri",
+                "redirect_uri is not registered for this client",
+            ));
+        }
+        Ok(client)
+    }
+
+    async fn authenticate_client(
+        &self,
+        client_id: &str,
+        client_secret: Option<&str>,
+    ) -> Result<oauth_clients::Model, OauthError> {
+        let client = self.client(client_id).await?;
+        let authenticated = match (&client.client_secret_hash, client_secret) {
+            (Some(expected), Some(secret)) => *expected == hash_token(secret),
+            (None, None) => client.is_public,
+            _ => false,
+        };
+        if !authenticated {
+            return Err(OauthError::new(
+                "invalid_client",
+                "client authentication failed",
+            ));
+        }
+        Ok(client)
+    }
+
+    async fn has_consent(
+        &self,
+        account: &accounts::Model,
+        client: &oauth_clients::Model,
+        scopes: &[String],
+    ) -> Result<bool, OauthError> {
+        if client.first_party {
+            return Ok(true);
+        }
+        let consent = self
+            .consents_repo
+            .find(account.id, client.id)
+            .await
+            .map_err(OauthError::db)?;
+        let Some(consent) = consent else {
+            return Ok(false);
+        };
+        let granted = json_strings(&consent.scopes);
+        Ok(scopes.iter().all(|scope| granted.contains(scope)))
+    }
+
+    async fn begin_consent(
+        &self,
+        account: &accounts::Model,
+        request: &AuthorizeRequest,
+    ) -> Result<String, OauthError> {
+        let consent_id = pkce::random_token();
+        let model = account_authorizations::ActiveModel {
+            account_id: sea_orm::Set(account.id),
+            token_hash: sea_orm::Set(hash_token(&consent_id)),
+            token_type: sea_orm::Set(TOKEN_TYPE_OAUTH_CONSENT.to_string()),
+            metadata: sea_orm::Set(Some(
+                serde_json::to_value(request)
+                    .map_err(|err| OauthError::new("server_error", err.to_string()))?,
+            )),
+            expires_at: sea_orm::Set(Some(
+                (Utc::now() + Duration::seconds(CONSENT_TTL_SECONDS)).into(),
+            )),
+            created_by: sea_orm::Set(Some(account.uid)),
+            updated_by: sea_orm::Set(Some(account.uid)),
+            ..Default::default()
+        };
+        self.authorizations_repo
+            .insert(model)
+            .await
+            .map_err(OauthError::db)?;
+        Ok(consent_id)
+    }
+
+    async fn find_consent(
+        &self,
+        account: &accounts::Model,
+        consent_id: &str,
+    ) -> Result<(oauth_clients::Model, AuthorizeRequest), OauthError> {
+        let (_, request) = self.pending_consent(account, consent_id).await?;
+        let client = self.client(&request.client_id).await?;
+        Ok((client, request))
+    }
+
+    async fn finish_consent(
+        &self,
+        account: &accounts::Model,
+        consent_id: &str,
+        approve: bool,
+    ) -> Result<AuthorizeRequest, OauthError> {
+        let (record, request) = self.pending_consent(account, consent_id).await?;
+        if !self
+            .authorizations_repo
+            .revoke_if_active(record.id)
+            .await
+            .map_err(OauthError::db)?
+        {
+            return Err(OauthError::new(
+                "consent_not_found",
+                "consent request not found",
+            ));
+        }
+        if !approve {
+            return Ok(request);
+        }
+
+        let client = self.client(&request.client_id).await?;
+        let existing = self
+            .consents_repo
+            .find(account.id, client.id)
+            .await
+            .map_err(OauthError::db)?;
+        match existing {
+            Some(existing) => {
+                let mut scopes = json_strings(&existing.scopes);
+                for scope in &request.scopes {
+                    if !scopes.contains(scope) {
+                        scopes.push(scope.clone());
+                    }
+                }
+                let mut active: oauth_consents::ActiveModel = existing.into();
+                active.scopes = sea_orm::Set(serde_json::json!(scopes));
+                active.updated_by = sea_orm::Set(Some(account.uid));
+                self.consents_repo
+                    .update(active)
+                    .await
+                    .map_err(OauthError::db)?;
+            }
+            None => {
+                let model = oauth_consents::ActiveModel {
+                    account_id: sea_orm::Set(account.id),
+                    client_id: sea_orm::Set(client.id),
+                    scopes: sea_orm::Set(serde_json::json!(request.scopes)),
+                    created_by: sea_orm::Set(Some(account.uid)),
+                    updated_by: sea_orm::Set(Some(account.uid)),
+                    ..Default::default()
+                };
+                self.consents_repo
+                    .insert(model)
+                    .await
+                    .map_err(OauthError::db)?;
+            }
+        }
+        Ok(request)
+    }
+
+    async fn create_code(
+        &self,
+        account: &accounts::Model,
+        request: &AuthorizeRequest,
+    ) -> Result<String, OauthError> {
+        let code = pkce::random_token();
+        let model = account_authorizations::ActiveModel {
+            account_id: sea_orm::Set(account.id),
+            token_hash: sea_orm::Set(hash_token(&code)),
+            token_type: sea_orm::Set(TOKEN_TYPE_OAUTH_CODE.to_string()),
+            family_id: sea_orm::Set(Some(Uuid::new_v4())),
+            metadata: sea_orm::Set(Some(
+                serde_json::to_value(request)
+                    .map_err(|err| OauthError::new("server_error", err.to_string()))?,
+            )),
+            expires_at: sea_orm::Set(Some(
+                (Utc::now() + Duration::seconds(self.code_ttl_seconds as i64)).into(),
+            )),
+            created_by: sea_orm::Set(Some(account.uid)),
+            updated_by: sea_orm::Set(Some(account.uid)),
+            ..Default::default()
+        };
+        self.authorizations_repo
+            .insert(model)
+            .await
+            .map_err(OauthError::db)?;
+        Ok(code)
+    }
+
+    async fn exchange_code(
+        &self,
+        client: &oauth_clients::Model,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<CodeGrant, OauthError> {
+        let Some((record, request)) = self.pending(code, TOKEN_TYPE_OAUTH_CODE).await? else {
+            return Err(OauthError::invalid_grant("authorization code is invalid"));
+        };
+        let Some(family_id) = record.family_id else {
+            return Err(OauthError::invalid_grant("authorization code is invalid"));
+        };
+        if request.client_id != client.client_id {
+            return Err(OauthError::invalid_grant(
+                "authorization code was issued to another client",
+            ));
+        }
+
+        // A replayed code means it leaked; revoke everything that was issued from it (RFC 6749
+        // section 4.1.2).
+        let replayed = record.revoked_at.is_some()
+            || !self
+                .authorizations_repo
+                .revoke_if_active(record.id)
+                .await
+                .map_err(OauthError::db)?;
+        if replayed {
+            self.authorizations_repo
+                .revoke_family(family_id)
+                .await
+                .map_err(OauthError::db)?;
+            return Err(OauthError::invalid_grant(
+                "authorization code was already used",
+            ));
+        }
+
+        if record
+            .expires_at
+            .is_none_or(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(OauthError::invalid_grant("authorization code has expired"));
+        }
+        if request.redirect_uri != redirect_uri {
+            return Err(OauthError::invalid_grant("redirect_uri does not match"));
+        }
+        if pkce::s256_challenge(code_verifier) != request.code_challenge {
+            return Err(OauthError::invalid_grant("code_verifier does not match"));
+        }
+
+        let account = self
+            .accounts_repo
+            .find_by_id(record.account_id)
+            .await
+            .map_err(OauthError::db)?
+            .ok_or_else(|| OauthError::invalid_grant("account no longer exists"))?;
+        Ok(CodeGrant {
+            account,
+            scope: request.scope(),
+            nonce: request.nonce,
+            family_id,
+        })
+    }
+
+    async fn identity_claims(
+        &self,
+        account: &accounts::Model,
+        scope: &str,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, OauthError> {
+        let scopes: Vec<&str> = scope.split_whitespace().collect();
+        let mut claims = serde_json::Map::new();
+        claims.insert("sub".to_string(), account.uid.to_string().into());
+        if scopes.contains(&"profile") {
+            claims.insert(
+                "preferred_username".to_string(),
+                account.username.clone().into(),
+            );
+        }
+        if scopes.contains(&"email") {
+            if let Some(email) = &account.email {
+                claims.insert("email".to_string(), email.clone().into());
+                claims.insert(
+                    "email_verified".to_string(),
+                    account.email_verified_at.is_some().into(),
+                );
+            }
+        }
+        Ok(claims)
+    }
+}
@@ -0,0 +1,602 @@
+use async_trait::async_trait;
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    config::OidcProviderConfig,
+    service::{pkce, session::ChallengeStore},
+};
+
+pub const PROVIDER_PREFIX: &str = "oidc:";
+
+const STATE_NAMESPACE: &str = "oidc";
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Debug)]
+pub struct OidcError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl OidcError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn provider(message: impl Into<String>) -> Self {
+        Self::new("oidc_provider_error", message)
+    }
+
+    fn invalid_id_token(message: impl Into<String>) -> Self {
+        Self::new("invalid_id_token", message)
+    }
+}
+
+/// The verified identity of a completed login.
+#[derive(Debug)]
+pub struct OidcIdentity {
+    /// `oidc:<name>`, used as `account_credentials.provider`.
+    pub provider: String,
+    pub subject: String,
+    /// Set when the flow was started to link this identity to an existing account.
+    pub link_account: Option<Uuid>,
+    /// Whether the login asked for the longer "remember me" session lifetime.
+    pub remember_me: bool,
+}
+
+#[async_trait]
+pub trait OidcService: Send + Sync {
+    /// Returns the provider's authorization URL with fresh `state`, `nonce` and PKCE challenge.
+    /// `link_account` and `remember_me` are carried through the state and handed back by
+    /// `complete`.
+    async fn authorization_url(
+        &self,
+        provider: &str,
+        link_account: Option<Uuid>,
+        remember_me: bool,
+    ) -> Result<String, OidcError>;
+    /// Exchanges the code and validates the ID token against the provider JWKS.
+    async fn complete(
+        &self,
+        provider: &str,
+        state: &str,
+        code: &str,
+    ) -> Result<OidcIdentity, OidcError>;
+    /// Drops the pending authorization for `state`, for callbacks that end without a code.
+    async fn discard(&self, state: &str) -> Result<(), OidcError>;
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingAuthorization {
+    provider: String,
+    nonce: String,
+    code_verifier: String,
+    #[serde(default)]
+    link_account: Option<Uuid>,
+    #[serde(default)]
+    remember_me: bool,
+}
+
+#[derive(Clone, Deserialize)]
+struct DiscoveryDocument {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default)]
+    token_endpoint_auth_methods_supported: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+}
+
+struct ProviderCache {
+    discovery: DiscoveryDocument,
+    jwks: JwkSet,
+}
+
+pub struct OidcServiceImpl {
+    providers: HashMap<String, OidcProviderConfig>,
+    challenges: Arc<dyn ChallengeStore>,
+    state_ttl_seconds: u64,
+    http: reqwest::Client,
+    cache: RwLock<HashMap<String, Arc<ProviderCache>>>,
+}
+
+impl OidcServiceImpl {
+    pub fn new(
+        providers: Vec<OidcProviderConfig>,
+        challenges: Arc<dyn ChallengeStore>,
+        state_ttl_seconds: u64,
+    ) -> Self {
+        Self {
+            providers: providers
+                .into_iter()
+                .map(|provider| (provider.name.clone(), provider))
+                .collect(),
+            challenges,
+            state_ttl_seconds,
+            http: reqwest::Client::new(),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn provider_config(&self, provider: &str) -> Result<&OidcProviderConfig, OidcError> {
+        self.providers.get(provider).ok_or_else(|| {
+            OidcError::new(
+                "unknown_provider",
+                format!("oidc provider {} is not configured", provider),
+            )
+        })
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, OidcError> {
+        let response = self
+            .http
+            .get(url)
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .map_err(|err| OidcError::provider(format!("request to {} failed: {}", url, err)))?;
+        if !response.status().is_success() {
+            return Err(OidcError::provider(format!(
+                "request to {} returned {}",
+                url,
+                response.status()
+            )));
+        }
+        response
+            .json::<T>()
+            .await
+            .map_err(|err| OidcError::provider(format!("invalid response from {}: {}", url, err)))
+    }
+
+    async fn fetch_provider(
+        &self,
+        config: &OidcProviderConfig,
+    ) -> Result<Arc<ProviderCache>, OidcError> {
+        let discovery: DiscoveryDocument = self
+            .get_json(&format!(
+                "{}/.well-known/openid-configuration",
+                config.issuer
+            ))
+            .await?;
+        if discovery.issuer.trim_end_matches('/') != config.issuer {
+            return Err(OidcError::provider(format!(
+                "discovery issuer {} does not match {}",
+                discovery.issuer, config.issuer
+            )));
+        }
+        let jwks: JwkSet = self.get_json(&discovery.jwks_uri).await?;
+        let cached = Arc::new(ProviderCache { discovery, jwks });
+        self.cache
+            .write()
+            .await
+            .insert(config.name.clone(), cached.clone());
+        Ok(cached)
+    }
+
+    /// Discovery and JWKS are cached per provider; `refresh` forces a refetch, e.g. after key
+    /// rotation left us without a matching `kid`.
+    async fn provider_metadata(
+        &self,
+        config: &OidcProviderConfig,
+        refresh: bool,
+    ) -> Result<Arc<ProviderCache>, OidcError> {
+        if !refresh {
+            if let Some(cached) = self.cache.read().await.get(&config.name) {
+                return Ok(cached.clone());
+            }
+        }
+        self.fetch_provider(config).await
+    }
+
+    fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+        match kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+    }
+
+    async fn exchange_code(
+        &self,
+        config: &OidcProviderConfig,
+        discovery: &DiscoveryDocument,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<String, OidcError> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", config.redirect_url.as_str()),
+            ("code_verifier", code_verifier),
+            ("client_id", config.client_id.as_str()),
+        ];
+        let mut request = self
+            .http
+            .post(&discovery.token_endpoint)
+            .header("Accept", "application/json");
+        if let Some(secret) = &config.client_secret {
+            // client_secret_basic is the spec default; only fall back to the form when the
+            // provider says it doesn't support it.
+            let basic_supported = discovery
+                .token_endpoint_auth_methods_supported
+                .as_ref()
+                .is_none_or(|methods| methods.iter().any(|m| m == "client_secret_basic"));
+            if basic_supported {
+                request = request.basic_auth(
+                    urlencoding::encode(&config.client_id),
+                    Some(urlencoding::encode(secret)),
+                );
+            } else {
+                form.push(("client_secret", secret.as_str()));
+            }
+        }
+
+        let response = request
+            .form(&form)
+            .send()
+            .await
+            .map_err(|err| OidcError::provider(format!("token request failed: {}", err)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(OidcError::provider(format!(
+                "token request returned {}: {}",
+                status, body
+            )));
+        }
+        let token: TokenResponse = response
+            .json()
+            .await
+            .map_err(|err| OidcError::provider(format!("token response parse failed: {}", err)))?;
+        token
+            .id_token
+            .ok_or_else(|| OidcError::provider("token response has no id_token"))
+    }
+
+    async fn verify_id_token(
+        &self,
+        config: &OidcProviderConfig,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let header = jsonwebtoken::decode_header(id_token)
+            .map_err(|err| OidcError::invalid_id_token(err.to_string()))?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(OidcError::invalid_id_token(format!(
+                "unsupported id token algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        let mut metadata = self.provider_metadata(config, false).await?;
+        if Self::find_key(&metadata.jwks, header.kid.as_deref()).is_none() {
+            metadata = self.provider_metadata(config, true).await?;
+        }
+        let jwk = Self::find_key(&metadata.jwks, header.kid.as_deref())
+            .ok_or_else(|| OidcError::invalid_id_token("no matching key in provider jwks"))?;
+        let key = DecodingKey::from_jwk(jwk)
+            .map_err(|err| OidcError::invalid_id_token(format!("unusable jwk: {}", err)))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = 60;
+        validation.set_issuer(&[metadata.discovery.issuer.as_str()]);
+        validation.set_audience(&[config.client_id.as_str()]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|err| OidcError::invalid_id_token(err.to_string()))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::invalid_id_token("nonce mismatch"));
+        }
+        Ok(claims)
+    }
+}
+
+#[async_trait]
+impl OidcService for OidcServiceImpl {
+    async fn authorization_url(
+        &self,
+        provider: &str,
+        link_account: Option<Uuid>,
+        remember_me: bool,
+    ) -> Result<String, OidcError> {
+        let config = self.provider_config(provider)?;
+        let metadata = self.provider_metadata(config, false).await?;
+
+        let state = pkce::random_token();
+        let pending = PendingAuthorization {
+            provider: config.name.clone(),
+            nonce: pkce::random_token(),
+            code_verifier: pkce::random_token(),
+            link_account,
+            remember_me,
+        };
+        let value = serde_json::to_string(&pending)
+            .map_err(|err| OidcError::new("oidc_error", err.to_string()))?;
+        self.challenges
+            .put_challenge(STATE_NAMESPACE, &state, &value, self.state_ttl_seconds)
+            .await
+            .map_err(|err| OidcError::new("session_error", err.to_string()))?;
+
+        let authorize_url = &metadata.discovery.authorization_endpoint;
+        let delimiter = if authorize_url.contains('?') {
+            "&"
+        } else {
+            "?"
+        };
+        Ok(format!(
+            "{}{}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&nonce={}&code_challenge={}&code_challenge_method=S256",
+            authorize_url,
+            delimiter,
+            urlencoding::encode(&config.client_id),
+            urlencoding::encode(&config.redirect_url),
+            urlencoding::encode(&config.scopes),
+            state,
+            pending.nonce,
+            pkce::s256_challenge(&pending.code_verifier),
+        ))
+    }
+
+    async fn complete(
+        &self,
+        provider: &str,
+        state: &str,
+        code: &str,
+    ) -> Result<OidcIdentity, OidcError> {
+        let config = self.provider_config(provider)?;
+        let pending = self
+            .challenges
+            .take_challenge(STATE_NAMESPACE, state)
+            .await
+            .map_err(|err| OidcError::new("session_error", err.to_string()))?
+            .and_then(|value| serde_json::from_str::<PendingAuthorization>(&value).ok())
+            .filter(|pending| pending.provider == config.name)
+            .ok_or_else(|| OidcError::new("invalid_state", "state is invalid or expired"))?;
+
+        let metadata = self.provider_metadata(config, false).await?;
+        let id_token = self
+            .exchange_code(config, &metadata.discovery, code, &pending.code_verifier)
+            .await?;
+        let claims = self
+            .verify_id_token(config, &id_token, &pending.nonce)
+            .await?;
+
+        Ok(OidcIdentity {
+            provider: format!("{}{}", PROVIDER_PREFIX, config.name),
+            subject: claims.sub,
+            link_account: pending.link_account,
+            remember_me: pending.remember_me,
+        })
+    }
+
+    async fn discard(&self, state: &str) -> Result<(), OidcError> {
+        self.challenges
+            .take_challenge(STATE_NAMESPACE, state)
+            .await
+            .map(|_| ())
+            .map_err(|err| OidcError::new("session_error", err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::session::{InMemorySessionService, SessionLifetimes};
+    use axum::{extract::State as AxumState, routing::get, routing::post, Form, Json, Router};
+    use base64::Engine;
+    use p256::pkcs8::EncodePrivateKey;
+    use tokio::sync::Mutex;
+
+    struct MockIdp {
+        issuer: String,
+        signing_key: jsonwebtoken::EncodingKey,
+        jwk: serde_json::Value,
+        /// `(nonce, code_challenge)` captured from the authorization URL.
+        expected: Mutex<Option<(String, String)>>,
+        nonce_override: Option<String>,
+    }
+
+    async fn discovery(AxumState(idp): AxumState<Arc<MockIdp>>) -> Json<serde_json::Value> {
+        Json(serde_json::json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn jwks(AxumState(idp): AxumState<Arc<MockIdp>>) -> Json<serde_json::Value> {
+        Json(serde_json::json!({ "keys": [idp.jwk] }))
+    }
+
+    async fn token(
+        AxumState(idp): AxumState<Arc<MockIdp>>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+        let (nonce, code_challenge) = idp.expected.lock().await.clone().unwrap_or_default();
+        let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+        if form.get("code").map(String::as_str) != Some("good-code")
+            || pkce::s256_challenge(&verifier) != code_challenge
+        {
+            return Err(axum::http::StatusCode::BAD_REQUEST);
+        }
+        let now = chrono::Utc::now().timestamp();
+        let claims = serde_json::json!({
+            "iss": idp.issuer,
+            "aud": "client-1",
+            "sub": "user-123",
+            "iat": now,
+            "exp": now + 300,
+            "nonce": idp.nonce_override.clone().unwrap_or(nonce),
+        });
+        let mut header = jsonwebtoken::Header::new(Algorithm::ES256);
+        header.kid = Some("test-key".to_string());
+        let id_token = jsonwebtoken::encode(&header, &claims, &idp.signing_key).unwrap();
+        Ok(Json(serde_json::json!({
+            "access_token": "at",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+    }
+
+    async fn start_mock_idp(nonce_override: Option<String>) -> Arc<MockIdp> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let secret = p256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let der = secret.to_pkcs8_der().unwrap();
+        let point = secret.verifying_key().to_encoded_point(false);
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        let idp = Arc::new(MockIdp {
+            issuer,
+            signing_key: jsonwebtoken::EncodingKey::from_ec_der(der.as_bytes()),
+            jwk: serde_json::json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "kid": "test-key",
+                "alg": "ES256",
+                "use": "sig",
+                "x": encode(point.x().unwrap()),
+                "y": encode(point.y().unwrap()),
+            }),
+            expected: Mutex::new(None),
+            nonce_override,
+        });
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(idp.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        idp
+    }
+
+    fn service(idp: &MockIdp) -> OidcServiceImpl {
+        OidcServiceImpl::new(
+            vec![OidcProviderConfig {
+                name: "mock".to_string(),
+                issuer: idp.issuer.clone(),
+                client_id: "client-1".to_string(),
+                client_secret: Some("secret-1".to_string()),
+                redirect_url: "http://localhost/callback".to_string(),
+                scopes: "openid email".to_string(),
+            }],
+            Arc::new(InMemorySessionService::new(SessionLifetimes {
+                idle_seconds: 600,
+                absolute_seconds: 3600,
+                remember_me_idle_seconds: 600,
+                remember_me_absolute_seconds: 3600,
+            })),
+            600,
+        )
+    }
+
+    fn query_param(url: &str, name: &str) -> String {
+        url.split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix(&format!("{}=", name)))
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn completes_login_against_mock_idp() {
+        let idp = start_mock_idp(None).await;
+        let service = service(&idp);
+
+        let url = service
+            .authorization_url("mock", None, false)
+            .await
+            .unwrap();
+        assert!(url.starts_with(&format!("{}/authorize?", idp.issuer)));
+        let state = query_param(&url, "state");
+        *idp.expected.lock().await = Some((
+            query_param(&url, "nonce"),
+            query_param(&url, "code_challenge"),
+        ));
+
+        let identity = service.complete("mock", &state, "good-code").await.unwrap();
+        assert_eq!(identity.provider, "oidc:mock");
+        assert_eq!(identity.subject, "user-123");
+
+        // state is single-use
+        let err = service
+            .complete("mock", &state, "good-code")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "invalid_state");
+    }
+
+    #[tokio::test]
+    async fn rejects_id_token_with_wrong_nonce() {
+        let idp = start_mock_idp(Some("other-nonce".to_string())).await;
+        let service = service(&idp);
+
+        let url = service
+            .authorization_url("mock", None, false)
+            .await
+            .unwrap();
+        *idp.expected.lock().await = Some((
+            query_param(&url, "nonce"),
+            query_param(&url, "code_challenge"),
+        ));
+
+        let err = service
+            .complete("mock", &query_param(&url, "state"), "good-code")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "invalid_id_token");
+    }
+
+    #[tokio::test]
+    async fn discarded_state_cannot_complete() {
+        let idp = start_mock_idp(None).await;
+        let service = service(&idp);
+
+        let url = service
+            .authorization_url("mock", None, false)
+            .await
+            .unwrap();
+        let state = query_param(&url, "state");
+        service.discard(&state).await.unwrap();
+
+        let err = service
+            .complete("mock", &state, "good-code")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "invalid_state");
+    }
+}
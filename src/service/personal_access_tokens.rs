@@ -0,0 +1,338 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    entities::{account_authorizations, accounts},
+    repo::{account_authorizations::AccountAuthorizationsRepo, accounts::AccountsRepo},
+    service::{pkce, verification::hash_token},
+};
+
+pub const TOKEN_TYPE_PERSONAL_ACCESS: &str = "auth:personal_access_token";
+/// Makes tokens recognisable to secret scanners and tells them apart from session ids and JWTs.
+pub const TOKEN_PREFIX: &str = "lat_";
+/// How much of the token is kept in clear so owners can tell their tokens apart.
+const DISPLAY_PREFIX_LEN: usize = 12;
+/// `last_used_at` is only rewritten when it is older than this, so busy scripts do not turn
+/// every request into a write.
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+const MAX_NAME_LEN: usize = 100;
+
+pub struct CreatePersonalAccessTokenInput {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<u32>,
+    /// A robot account created by the owner; defaults to the owner's own account.
+    pub account_uid: Option<Uuid>,
+}
+
+#[derive(Debug)]
+pub struct PersonalAccessTokenInfo {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub account_uid: Uuid,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PersonalAccessTokenInfo {
+    pub fn scope(&self) -> Option<String> {
+        (!self.scopes.is_empty()).then(|| self.scopes.join(" "))
+    }
+}
+
+#[derive(Debug)]
+pub struct PersonalAccessTokenError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl PersonalAccessTokenError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("db_error", err.to_string())
+    }
+}
+
+/// Long-lived bearer tokens for scripts and robot accounts, stored hashed in
+/// `account_authorizations`. A token is managed by whoever created it: the account itself, or
+/// the owner of the robot account it was issued for.
+#[async_trait]
+pub trait PersonalAccessTokenService: Send + Sync {
+    /// Returns the token in clear; it cannot be recovered afterwards.
+    async fn create(
+        &self,
+        owner: &accounts::Model,
+        input: CreatePersonalAccessTokenInput,
+    ) -> Result<(String, PersonalAccessTokenInfo), PersonalAccessTokenError>;
+    async fn list(
+        &self,
+        owner: &accounts::Model,
+    ) -> Result<Vec<PersonalAccessTokenInfo>, PersonalAccessTokenError>;
+    /// Returns the revoked token.
+    async fn revoke(
+        &self,
+        owner: &accounts::Model,
+        id: i64,
+    ) -> Result<PersonalAccessTokenInfo, PersonalAccessTokenError>;
+    /// Resolves a presented token to its account, or `None` if it is unknown, expired or revoked.
+    async fn authenticate(
+        &self,
+        token: &str,
+    ) -> Result<Option<(accounts::Model, PersonalAccessTokenInfo)>, PersonalAccessTokenError>;
+}
+
+pub struct PersonalAccessTokenServiceImpl {
+    accounts_repo: Arc<dyn AccountsRepo>,
+    authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+}
+
+impl PersonalAccessTokenServiceImpl {
+    pub fn new(
+        accounts_repo: Arc<dyn AccountsRepo>,
+        authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+    ) -> Self {
+        Self {
+            accounts_repo,
+            authorizations_repo,
+        }
+    }
+
+    /// The account a new token is for: the owner, or a robot account the owner created.
+    async fn target_account(
+        &self,
+        owner: &accounts::Model,
+        account_uid: Option<Uuid>,
+    ) -> Result<accounts::Model, PersonalAccessTokenError> {
+        let Some(account_uid) = account_uid.filter(|uid| *uid != owner.uid) else {
+            return Ok(owner.clone());
+        };
+        let account = self
+            .accounts_repo
+            .find_by_uid(account_uid)
+            .await
+            .map_err(PersonalAccessTokenError::db)?
+            .filter(|account| account.deleted_at.is_none())
+            .filter(|account| account.account_type == "robot")
+            .filter(|account| account.created_by == Some(owner.uid));
+        account.ok_or_else(|| {
+            PersonalAccessTokenError::new(
+                "forbidden",
+                "tokens can only be created for your own account or robot accounts you created",
+            )
+        })
+    }
+
+    async fn info(
+        &self,
+        record: &account_authorizations::Model,
+        owner: &accounts::Model,
+    ) -> Result<PersonalAccessTokenInfo, PersonalAccessTokenError> {
+        let account_uid = if record.account_id == owner.id {
+            owner.uid
+        } else {
+            self.accounts_repo
+                .find_by_id(record.account_id)
+                .await
+                .map_err(PersonalAccessTokenError::db)?
+                .map(|account| account.uid)
+                .unwrap_or_default()
+        };
+        Ok(token_info(record, account_uid))
+    }
+}
+
+fn token_info(
+    record: &account_authorizations::Model,
+    account_uid: Uuid,
+) -> PersonalAccessTokenInfo {
+    let metadata = record.metadata.as_ref();
+    let text = |key: &str| {
+        metadata
+            .and_then(|metadata| metadata.get(key))
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let scopes = metadata
+        .and_then(|metadata| metadata.get("scopes"))
+        .and_then(|value| value.as_array())
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    PersonalAccessTokenInfo {
+        id: record.id,
+        name: text("name"),
+        prefix: text("prefix"),
+        scopes,
+        account_uid,
+        expires_at: record.expires_at.map(|value| value.with_timezone(&Utc)),
+        last_used_at: record.last_used_at.map(|value| value.with_timezone(&Utc)),
+        created_at: record.created_at.with_timezone(&Utc),
+    }
+}
+
+fn validate_scopes(scopes: Vec<String>) -> Result<Vec<String>, PersonalAccessTokenError> {
+    let mut valid = Vec::new();
+    for scope in scopes {
+        let scope = scope.trim().to_string();
+        let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, ':' | '.' | '_' | '-');
+        if scope.is_empty() || !scope.chars().all(allowed) {
+            return Err(PersonalAccessTokenError::new(
+                "invalid_scope",
+                format!("invalid scope {:?}", scope),
+            ));
+        }
+        if !valid.contains(&scope) {
+            valid.push(scope);
+        }
+    }
+    Ok(valid)
+}
+
+#[async_trait]
+impl PersonalAccessTokenService for PersonalAccessTokenServiceImpl {
+    async fn create(
+        &self,
+        owner: &accounts::Model,
+        input: CreatePersonalAccessTokenInput,
+    ) -> Result<(String, PersonalAccessTokenInfo), PersonalAccessTokenError> {
+        let name = input.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            return Err(PersonalAccessTokenError::new(
+                "invalid_name",
+                format!("name must be 1 to {} characters", MAX_NAME_LEN),
+            ));
+        }
+        if input.expires_in_days == Some(0) {
+            return Err(PersonalAccessTokenError::new(
+                "invalid_expiry",
+                "expires_in_days must be at least 1",
+            ));
+        }
+        let scopes = validate_scopes(input.scopes)?;
+        let account = self.target_account(owner, input.account_uid).await?;
+
+        let token = format!("{}{}", TOKEN_PREFIX, pkce::random_token());
+        let expires_at = input
+            .expires_in_days
+            .map(|days| Utc::now() + Duration::days(days as i64));
+        let model = account_authorizations::ActiveModel {
+            account_id: sea_orm::Set(account.id),
+            token_hash: sea_orm::Set(hash_token(&token)),
+            token_type: sea_orm::Set(TOKEN_TYPE_PERSONAL_ACCESS.to_string()),
+            metadata: sea_orm::Set(Some(serde_json::json!({
+                "name": name,
+                "prefix": &token[..DISPLAY_PREFIX_LEN],
+                "scopes": scopes,
+            }))),
+            expires_at: sea_orm::Set(expires_at.map(Into::into)),
+            revoked_at: sea_orm::Set(None),
+            created_by: sea_orm::Set(Some(owner.uid)),
+            updated_by: sea_orm::Set(Some(owner.uid)),
+            ..Default::default()
+        };
+        let record = self
+            .authorizations_repo
+            .insert(model)
+            .await
+            .map_err(PersonalAccessTokenError::db)?;
+        Ok((token, token_info(&record, account.uid)))
+    }
+
+    async fn list(
+        &self,
+        owner: &accounts::Model,
+    ) -> Result<Vec<PersonalAccessTokenInfo>, PersonalAccessTokenError> {
+        let records = self
+            .authorizations_repo
+            .list_active_by_type_for_owner(owner.id, owner.uid, TOKEN_TYPE_PERSONAL_ACCESS)
+            .await
+            .map_err(PersonalAccessTokenError::db)?;
+        let mut tokens = Vec::with_capacity(records.len());
+        for record in &records {
+            tokens.push(self.info(record, owner).await?);
+        }
+        Ok(tokens)
+    }
+
+    async fn revoke(
+        &self,
+        owner: &accounts::Model,
+        id: i64,
+    ) -> Result<PersonalAccessTokenInfo, PersonalAccessTokenError> {
+        let record = self
+            .authorizations_repo
+            .find_by_id(id)
+            .await
+            .map_err(PersonalAccessTokenError::db)?
+            .filter(|record| record.token_type == TOKEN_TYPE_PERSONAL_ACCESS)
+            .filter(|record| record.revoked_at.is_none())
+            .filter(|record| record.account_id == owner.id || record.created_by == Some(owner.uid));
+        let Some(record) = record else {
+            return Err(PersonalAccessTokenError::new(
+                "not_found",
+                "token not found",
+            ));
+        };
+        self.authorizations_repo
+            .revoke_if_active(record.id)
+            .await
+            .map_err(PersonalAccessTokenError::db)?;
+        self.info(&record, owner).await
+    }
+
+    async fn authenticate(
+        &self,
+        token: &str,
+    ) -> Result<Option<(accounts::Model, PersonalAccessTokenInfo)>, PersonalAccessTokenError> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Ok(None);
+        }
+        let record = self
+            .authorizations_repo
+            .find_active_by_token_hash(&hash_token(token))
+            .await
+            .map_err(PersonalAccessTokenError::db)?
+            .filter(|record| record.token_type == TOKEN_TYPE_PERSONAL_ACCESS);
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let account = self
+            .accounts_repo
+            .find_by_id(record.account_id)
+            .await
+            .map_err(PersonalAccessTokenError::db)?
+            .filter(|account| account.deleted_at.is_none());
+        let Some(account) = account else {
+            return Ok(None);
+        };
+
+        let stale = record.last_used_at.is_none_or(|last_used_at| {
+            Utc::now() - last_used_at.with_timezone(&Utc)
+                > Duration::seconds(LAST_USED_RESOLUTION_SECONDS)
+        });
+        if stale {
+            self.authorizations_repo
+                .touch_last_used(record.id)
+                .await
+                .map_err(PersonalAccessTokenError::db)?;
+        }
+        let info = token_info(&record, account.uid);
+        Ok(Some((account, info)))
+    }
+}
@@ -0,0 +1,369 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    entities::{account_roles, accounts, permissions, roles},
+    repo::{accounts::AccountsRepo, roles::RolesRepo},
+    service::session::{SessionError, SessionService},
+};
+
+pub const PERMISSION_ACCOUNTS_READ: &str = "accounts:read";
+pub const PERMISSION_ACCOUNTS_WRITE: &str = "accounts:write";
+pub const PERMISSION_ROLES_WRITE: &str = "roles:write";
+
+pub const ROLE_ADMIN: &str = "admin";
+
+/// Roles every installation has, as `(name, description, permissions)`. `bootstrap` creates them
+/// and adds permissions missing from existing rows; it never removes any.
+const BUILTIN_ROLES: &[(&str, &str, &[&str])] = &[(
+    ROLE_ADMIN,
+    "Manages every account and role",
+    &[
+        PERMISSION_ACCOUNTS_READ,
+        PERMISSION_ACCOUNTS_WRITE,
+        PERMISSION_ROLES_WRITE,
+    ],
+)];
+
+#[derive(Debug)]
+pub struct RoleInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct RbacError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl RbacError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("db_error", err.to_string())
+    }
+
+    fn session(err: SessionError) -> Self {
+        Self::new("server_error", err.to_string())
+    }
+}
+
+/// Roles are named sets of permissions stored in `roles` and `permissions`; an account's
+/// effective permissions are the union over the roles granted to it in `account_roles`.
+///
+/// Sessions cache the effective permissions, so granting or revoking a role clears the cache on
+/// every session of the account.
+#[async_trait]
+pub trait RbacService: Send + Sync {
+    /// Sorted and without duplicates.
+    async fn permissions_for(&self, account: &accounts::Model) -> Result<Vec<String>, RbacError>;
+    async fn roles_for(&self, account: &accounts::Model) -> Result<Vec<RoleInfo>, RbacError>;
+    async fn list_roles(&self) -> Result<Vec<RoleInfo>, RbacError>;
+    /// Granting a role the account already holds is a no-op.
+    async fn grant_role(
+        &self,
+        account: &accounts::Model,
+        role: &str,
+        granted_by: Uuid,
+    ) -> Result<(), RbacError>;
+    /// Revoking a role the account does not hold is a no-op.
+    async fn revoke_role(
+        &self,
+        account: &accounts::Model,
+        role: &str,
+        revoked_by: Uuid,
+    ) -> Result<(), RbacError>;
+    /// Creates the built-in roles and grants `admin` to each of `admins`, given as account uids
+    /// or emails. Emails only match accounts that verified them, since anyone can register an
+    /// address; entries matching no account are logged and skipped.
+    async fn bootstrap(&self, admins: &[String]) -> Result<(), RbacError>;
+}
+
+pub struct RbacServiceImpl {
+    roles_repo: Arc<dyn RolesRepo>,
+    accounts_repo: Arc<dyn AccountsRepo>,
+    sessions: Arc<dyn SessionService>,
+}
+
+impl RbacServiceImpl {
+    pub fn new(
+        roles_repo: Arc<dyn RolesRepo>,
+        accounts_repo: Arc<dyn AccountsRepo>,
+        sessions: Arc<dyn SessionService>,
+    ) -> Self {
+        Self {
+            roles_repo,
+            accounts_repo,
+            sessions,
+        }
+    }
+
+    async fn role(&self, name: &str) -> Result<roles::Model, RbacError> {
+        self.roles_repo
+            .find_role_by_name(name)
+            .await
+            .map_err(RbacError::db)?
+            .ok_or_else(|| RbacError::new("not_found", format!("role {} not found", name)))
+    }
+
+    async fn role_infos(&self, roles: Vec<roles::Model>) -> Result<Vec<RoleInfo>, RbacError> {
+        let role_ids: Vec<i64> = roles.iter().map(|role| role.id).collect();
+        let permissions = self
+            .roles_repo
+            .list_permissions(&role_ids)
+            .await
+            .map_err(RbacError::db)?;
+        Ok(roles
+            .into_iter()
+            .map(|role| RoleInfo {
+                permissions: permissions
+                    .iter()
+                    .filter(|permission| permission.role_id == role.id)
+                    .map(|permission| permission.name.clone())
+                    .collect(),
+                name: role.name,
+                description: role.description,
+            })
+            .collect())
+    }
+
+    async fn grant(
+        &self,
+        account: &accounts::Model,
+        role: &str,
+        granted_by: Option<Uuid>,
+    ) -> Result<(), RbacError> {
+        let role = self.role(role).await?;
+        let existing = self
+            .roles_repo
+            .find_account_role(account.id, role.id)
+            .await
+            .map_err(RbacError::db)?;
+        if existing.is_some() {
+            return Ok(());
+        }
+        let model = account_roles::ActiveModel {
+            account_id: sea_orm::Set(account.id),
+            role_id: sea_orm::Set(role.id),
+            created_by: sea_orm::Set(granted_by),
+            updated_by: sea_orm::Set(granted_by),
+            ..Default::default()
+        };
+        self.roles_repo
+            .insert_account_role(model)
+            .await
+            .map_err(RbacError::db)?;
+        self.invalidate(account).await
+    }
+
+    /// Drops the cached permissions of every live session of the account.
+    async fn invalidate(&self, account: &accounts::Model) -> Result<(), RbacError> {
+        let sessions = self
+            .sessions
+            .list_for_account(account.uid)
+            .await
+            .map_err(RbacError::session)?;
+        for (session_id, _) in sessions {
+            self.sessions
+                .set_permissions(&session_id, None)
+                .await
+                .map_err(RbacError::session)?;
+        }
+        Ok(())
+    }
+
+    async fn ensure_builtin_roles(&self) -> Result<(), RbacError> {
+        for (name, description, wanted) in BUILTIN_ROLES {
+            let role = match self
+                .roles_repo
+                .find_role_by_name(name)
+                .await
+                .map_err(RbacError::db)?
+            {
+                Some(role) => role,
+                None => {
+                    let model = roles::ActiveModel {
+                        name: sea_orm::Set(name.to_string()),
+                        description: sea_orm::Set(Some(description.to_string())),
+                        ..Default::default()
+                    };
+                    self.roles_repo
+                        .insert_role(model)
+                        .await
+                        .map_err(RbacError::db)?
+                }
+            };
+            let existing = self
+                .roles_repo
+                .list_permissions(&[role.id])
+                .await
+                .map_err(RbacError::db)?;
+            for permission in wanted.iter() {
+                if existing.iter().any(|row| row.name == *permission) {
+                    continue;
+                }
+                let model = permissions::ActiveModel {
+                    role_id: sea_orm::Set(role.id),
+                    name: sea_orm::Set(permission.to_string()),
+                    ..Default::default()
+                };
+                self.roles_repo
+                    .insert_permission(model)
+                    .await
+                    .map_err(RbacError::db)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn find_account(&self, uid_or_email: &str) -> Result<Option<accounts::Model>, RbacError> {
+        let account = match uid_or_email.parse::<Uuid>() {
+            Ok(uid) => self.accounts_repo.find_by_uid(uid).await,
+            Err(_) => self
+                .accounts_repo
+                .find_by_email(uid_or_email)
+                .await
+                .map(|account| account.filter(|account| account.email_verified_at.is_some())),
+        }
+        .map_err(RbacError::db)?;
+        Ok(account.filter(|account| account.deleted_at.is_none()))
+    }
+}
+
+#[async_trait]
+impl RbacService for RbacServiceImpl {
+    async fn permissions_for(&self, account: &accounts::Model) -> Result<Vec<String>, RbacError> {
+        let roles = self
+            .roles_repo
+            .list_roles_for_account(account.id)
+            .await
+            .map_err(RbacError::db)?;
+        let role_ids: Vec<i64> = roles.iter().map(|role| role.id).collect();
+        let mut permissions: Vec<String> = self
+            .roles_repo
+            .list_permissions(&role_ids)
+            .await
+            .map_err(RbacError::db)?
+            .into_iter()
+            .map(|permission| permission.name)
+            .collect();
+        permissions.sort();
+        permissions.dedup();
+        Ok(permissions)
+    }
+
+    async fn roles_for(&self, account: &accounts::Model) -> Result<Vec<RoleInfo>, RbacError> {
+        let roles = self
+            .roles_repo
+            .list_roles_for_account(account.id)
+            .await
+            .map_err(RbacError::db)?;
+        self.role_infos(roles).await
+    }
+
+    async fn list_roles(&self) -> Result<Vec<RoleInfo>, RbacError> {
+        let roles = self.roles_repo.list_roles().await.map_err(RbacError::db)?;
+        self.role_infos(roles).await
+    }
+
+    async fn grant_role(
+        &self,
+        account: &accounts::Model,
+        role: &str,
+        granted_by: Uuid,
+    ) -> Result<(), RbacError> {
+        self.grant(account, role, Some(granted_by)).await
+    }
+
+    async fn revoke_role(
+        &self,
+        account: &accounts::Model,
+        role: &str,
+        revoked_by: Uuid,
+    ) -> Result<(), RbacError> {
+        let role = self.role(role).await?;
+        let Some(grant) = self
+            .roles_repo
+            .find_account_role(account.id, role.id)
+            .await
+            .map_err(RbacError::db)?
+        else {
+            return Ok(());
+        };
+        self.roles_repo
+            .delete_account_role(grant.id, Some(revoked_by))
+            .await
+            .map_err(RbacError::db)?;
+        self.invalidate(account).await
+    }
+
+    async fn bootstrap(&self, admins: &[String]) -> Result<(), RbacError> {
+        self.ensure_builtin_roles().await?;
+        for entry in admins {
+            match self.find_account(entry).await? {
+                Some(account) => self.grant(&account, ROLE_ADMIN, None).await?,
+                None => eprintln!(
+                    "BOOTSTRAP_ADMINS entry {} matches no account; skipped",
+                    entry
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{accounts::InMemoryAccountsRepo, roles::InMemoryRolesRepo};
+    use crate::service::session::{InMemorySessionService, SessionLifetimes};
+
+    #[tokio::test]
+    async fn bootstrap_only_matches_verified_emails() {
+        let accounts_repo = Arc::new(InMemoryAccountsRepo::default());
+        let service = RbacServiceImpl::new(
+            Arc::new(InMemoryRolesRepo::default()),
+            accounts_repo.clone(),
+            Arc::new(InMemorySessionService::new(SessionLifetimes {
+                idle_seconds: 600,
+                absolute_seconds: 3600,
+                remember_me_idle_seconds: 600,
+                remember_me_absolute_seconds: 3600,
+            })),
+        );
+        let insert = |email: &str, verified: bool| accounts::ActiveModel {
+            account_type: sea_orm::Set("user".to_string()),
+            email: sea_orm::Set(Some(email.to_string())),
+            email_verified_at: sea_orm::Set(verified.then(|| chrono::Utc::now().into())),
+            ..Default::default()
+        };
+        let squatter = accounts_repo
+            .insert(insert("root@example.com", false))
+            .await
+            .unwrap();
+        let owner = accounts_repo
+            .insert(insert("owner@example.com", true))
+            .await
+            .unwrap();
+
+        let admins = [
+            "root@example.com".to_string(),
+            "owner@example.com".to_string(),
+        ];
+        service.bootstrap(&admins).await.unwrap();
+        assert!(service.permissions_for(&squatter).await.unwrap().is_empty());
+        assert!(service
+            .permissions_for(&owner)
+            .await
+            .unwrap()
+            .contains(&PERMISSION_ROLES_WRITE.to_string()));
+    }
+}
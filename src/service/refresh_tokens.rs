@@ -0,0 +1,375 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::TransactionTrait;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    entities::{account_authorizations, accounts},
+    repo::{account_authorizations::AccountAuthorizationsRepo, accounts::AccountsRepo},
+    service::{pkce, verification::hash_token},
+    state::DatabaseClient,
+};
+
+pub const TOKEN_TYPE_REFRESH: &str = "auth:refresh";
+
+#[derive(Debug)]
+pub struct RefreshToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// What a refresh token was issued for: a first-party login (no client), or an OAuth client with
+/// the scope the account granted it.
+#[derive(Debug, Clone, Default)]
+pub struct RefreshGrant {
+    pub client_id: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RefreshTokenError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl RefreshTokenError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("db_error", err.to_string())
+    }
+
+    fn invalid() -> Self {
+        Self::new(
+            "invalid_refresh_token",
+            "refresh token is invalid or expired",
+        )
+    }
+
+    fn revoked() -> Self {
+        Self::new(
+            "refresh_token_revoked",
+            "refresh token was revoked; sign in again",
+        )
+    }
+
+    fn reused() -> Self {
+        Self::new(
+            "refresh_token_reused",
+            "refresh token was already used; all tokens from this login have been revoked",
+        )
+    }
+}
+
+/// Refresh tokens stored hashed in `account_authorizations`.
+///
+/// Every token issued from one login shares a `family_id`. Each use rotates the token, and
+/// presenting a token that was already rotated revokes the whole family, since either the
+/// legitimate client or an attacker is holding a stolen copy. Tokens revoked without being
+/// rotated (logout-all, password changes and resets) are simply refused.
+#[async_trait]
+pub trait RefreshTokenService: Send + Sync {
+    /// Starts a token family, or continues `family_id` when the family was opened by something
+    /// else that must be revoked together with it (an OAuth authorization code).
+    async fn issue(
+        &self,
+        account: &accounts::Model,
+        grant: RefreshGrant,
+        family_id: Option<Uuid>,
+    ) -> Result<RefreshToken, RefreshTokenError>;
+    /// Exchanges a refresh token for its successor. The token must have been issued to
+    /// `client_id` (`None` for first-party logins).
+    async fn rotate(
+        &self,
+        token: &str,
+        client_id: Option<&str>,
+    ) -> Result<(accounts::Model, RefreshToken, RefreshGrant), RefreshTokenError>;
+    /// Revokes every refresh token of the account, first-party and OAuth client alike.
+    async fn revoke_all(&self, account_id: i64) -> Result<u64, RefreshTokenError>;
+}
+
+pub struct RefreshTokenServiceImpl {
+    db: Arc<dyn DatabaseClient>,
+    accounts_repo: Arc<dyn AccountsRepo>,
+    authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+    absolute_ttl_seconds: u64,
+    idle_ttl_seconds: u64,
+}
+
+impl RefreshTokenServiceImpl {
+    pub fn new(
+        db: Arc<dyn DatabaseClient>,
+        accounts_repo: Arc<dyn AccountsRepo>,
+        authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+        absolute_ttl_seconds: u64,
+        idle_ttl_seconds: u64,
+    ) -> Self {
+        Self {
+            db,
+            accounts_repo,
+            authorizations_repo,
+            absolute_ttl_seconds,
+            idle_ttl_seconds,
+        }
+    }
+
+    /// Builds the next token in a family. Its idle expiry never extends past the family's
+    /// absolute expiry, which is fixed at login.
+    fn next_token(
+        &self,
+        account: &accounts::Model,
+        grant: &RefreshGrant,
+        family_id: Uuid,
+        family_expires_at: DateTime<Utc>,
+    ) -> (RefreshToken, account_authorizations::ActiveModel) {
+        let now = Utc::now();
+        let token = pkce::random_token();
+        let expires_at = std::cmp::min(
+            now + Duration::seconds(self.idle_ttl_seconds as i64),
+            family_expires_at,
+        );
+        let model = account_authorizations::ActiveModel {
+            account_id: sea_orm::Set(account.id),
+            token_hash: sea_orm::Set(hash_token(&token)),
+            token_type: sea_orm::Set(TOKEN_TYPE_REFRESH.to_string()),
+            family_id: sea_orm::Set(Some(family_id)),
+            metadata: sea_orm::Set(Some(serde_json::json!({
+                "family_expires_at": family_expires_at.to_rfc3339(),
+                "client_id": grant.client_id,
+                "scope": grant.scope,
+            }))),
+            expires_at: sea_orm::Set(Some(expires_at.into())),
+            revoked_at: sea_orm::Set(None),
+            created_by: sea_orm::Set(Some(account.uid)),
+            updated_by: sea_orm::Set(Some(account.uid)),
+            ..Default::default()
+        };
+        (RefreshToken { token, expires_at }, model)
+    }
+
+    /// Only a rotated token has a successor, so only its reappearance counts as reuse and takes
+    /// the family down; anything else was revoked where it stood.
+    async fn refuse_revoked(
+        &self,
+        record: &account_authorizations::Model,
+        family_id: Uuid,
+    ) -> RefreshTokenError {
+        let rotated = match self
+            .authorizations_repo
+            .has_successor(family_id, TOKEN_TYPE_REFRESH, record.id)
+            .await
+        {
+            Ok(rotated) => rotated,
+            Err(err) => return RefreshTokenError::db(err),
+        };
+        if !rotated {
+            return RefreshTokenError::revoked();
+        }
+        match self.revoke_family(family_id).await {
+            Ok(()) => RefreshTokenError::reused(),
+            Err(err) => err,
+        }
+    }
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<(), RefreshTokenError> {
+        self.authorizations_repo
+            .revoke_family(family_id)
+            .await
+            .map_err(RefreshTokenError::db)?;
+        Ok(())
+    }
+}
+
+fn metadata_str<'a>(record: &'a account_authorizations::Model, key: &str) -> Option<&'a str> {
+    record.metadata.as_ref()?.get(key)?.as_str()
+}
+
+fn family_expires_at(record: &account_authorizations::Model) -> Option<DateTime<Utc>> {
+    let value = metadata_str(record, "family_expires_at")?;
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|value| value.with_timezone(&Utc))
+}
+
+#[async_trait]
+impl RefreshTokenService for RefreshTokenServiceImpl {
+    async fn issue(
+        &self,
+        account: &accounts::Model,
+        grant: RefreshGrant,
+        family_id: Option<Uuid>,
+    ) -> Result<RefreshToken, RefreshTokenError> {
+        let family_expires_at = Utc::now() + Duration::seconds(self.absolute_ttl_seconds as i64);
+        let family_id = family_id.unwrap_or_else(Uuid::new_v4);
+        let (token, model) = self.next_token(account, &grant, family_id, family_expires_at);
+        self.authorizations_repo
+            .insert(model)
+            .await
+            .map_err(RefreshTokenError::db)?;
+        Ok(token)
+    }
+
+    async fn rotate(
+        &self,
+        token: &str,
+        client_id: Option<&str>,
+    ) -> Result<(accounts::Model, RefreshToken, RefreshGrant), RefreshTokenError> {
+        let record = self
+            .authorizations_repo
+            .find_by_token_hash(&hash_token(token))
+            .await
+            .map_err(RefreshTokenError::db)?
+            .filter(|record| record.token_type == TOKEN_TYPE_REFRESH);
+        let Some(record) = record else {
+            return Err(RefreshTokenError::invalid());
+        };
+        let (Some(family_id), Some(family_expires_at)) =
+            (record.family_id, family_expires_at(&record))
+        else {
+            return Err(RefreshTokenError::invalid());
+        };
+        // A token presented by the wrong client is rejected without touching its family, so a
+        // client cannot revoke another client's tokens by replaying them.
+        if metadata_str(&record, "client_id") != client_id {
+            return Err(RefreshTokenError::invalid());
+        }
+        let grant = RefreshGrant {
+            client_id: client_id.map(str::to_string),
+            scope: metadata_str(&record, "scope").map(str::to_string),
+        };
+
+        if record.revoked_at.is_some() {
+            return Err(self.refuse_revoked(&record, family_id).await);
+        }
+        if record
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(RefreshTokenError::invalid());
+        }
+
+        let Some(account) = self
+            .accounts_repo
+            .find_by_id(record.account_id)
+            .await
+            .map_err(RefreshTokenError::db)?
+        else {
+            self.revoke_family(family_id).await?;
+            return Err(RefreshTokenError::invalid());
+        };
+
+        let (next, model) = self.next_token(&account, &grant, family_id, family_expires_at);
+        let authorizations_repo = self.authorizations_repo.clone();
+        let record_id = record.id;
+        // The conditional revoke makes concurrent use of the same token count as reuse: only one
+        // request can retire it, and only that request gets a successor.
+        let rotated = self
+            .db
+            .conn()
+            .transaction(|txn| {
+                let authorizations_repo = authorizations_repo.clone();
+                let model = model.clone();
+                Box::pin(async move {
+                    if !authorizations_repo
+                        .revoke_if_active_with_txn(txn, record_id)
+                        .await?
+                    {
+                        return Ok::<_, sea_orm::DbErr>(false);
+                    }
+                    authorizations_repo.insert_with_txn(txn, model).await?;
+                    Ok(true)
+                })
+            })
+            .await
+            .map_err(|err| RefreshTokenError::new("db_error", err.to_string()))?;
+
+        if !rotated {
+            return Err(self.refuse_revoked(&record, family_id).await);
+        }
+        Ok((account, next, grant))
+    }
+
+    async fn revoke_all(&self, account_id: i64) -> Result<u64, RefreshTokenError> {
+        self.authorizations_repo
+            .revoke_all_for_account(account_id, TOKEN_TYPE_REFRESH)
+            .await
+            .map_err(RefreshTokenError::db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        repo::{
+            account_authorizations::InMemoryAccountAuthorizationsRepo,
+            accounts::InMemoryAccountsRepo,
+        },
+        state::InMemoryDatabaseClient,
+    };
+
+    async fn service() -> (RefreshTokenServiceImpl, accounts::Model) {
+        let accounts_repo = Arc::new(InMemoryAccountsRepo::default());
+        let account = accounts_repo
+            .insert(accounts::ActiveModel {
+                account_type: sea_orm::Set("user".to_string()),
+                email: sea_orm::Set(Some("ada@example.com".to_string())),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let service = RefreshTokenServiceImpl::new(
+            Arc::new(InMemoryDatabaseClient::new()),
+            accounts_repo,
+            Arc::new(InMemoryAccountAuthorizationsRepo::default()),
+            3600,
+            600,
+        );
+        (service, account)
+    }
+
+    #[tokio::test]
+    async fn replaying_a_rotated_token_revokes_its_family() {
+        let (service, account) = service().await;
+        let first = service
+            .issue(&account, RefreshGrant::default(), None)
+            .await
+            .unwrap();
+        let (_, second, _) = service.rotate(&first.token, None).await.unwrap();
+        let (_, third, _) = service.rotate(&second.token, None).await.unwrap();
+
+        let err = service.rotate(&first.token, None).await.unwrap_err();
+        assert_eq!(err.code, "refresh_token_reused");
+        // The newest token was never rotated, so it reads as revoked rather than reused.
+        let err = service.rotate(&third.token, None).await.unwrap_err();
+        assert_eq!(err.code, "refresh_token_revoked");
+    }
+
+    #[tokio::test]
+    async fn tokens_revoked_by_sign_out_are_not_reuse() {
+        let (service, account) = service().await;
+        let first = service
+            .issue(&account, RefreshGrant::default(), None)
+            .await
+            .unwrap();
+        let (_, second, _) = service.rotate(&first.token, None).await.unwrap();
+        let other = service
+            .issue(&account, RefreshGrant::default(), None)
+            .await
+            .unwrap();
+
+        assert_eq!(service.revoke_all(account.id).await.unwrap(), 2);
+        for token in [&second.token, &other.token] {
+            let err = service.rotate(token, None).await.unwrap_err();
+            assert_eq!(err.code, "refresh_token_revoked");
+        }
+        let err = service.rotate(&first.token, None).await.unwrap_err();
+        assert_eq!(err.code, "refresh_token_reused");
+    }
+}
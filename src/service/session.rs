@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use redis::aio::MultiplexedConnection;
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
@@ -7,10 +7,91 @@ use std::{fmt, sync::Arc};
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
+use crate::config::Config;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SessionData {
     pub account_uid: Uuid,
     pub created_at: DateTime<Utc>,
+    // Sessions created before these fields existed deserialize with them empty.
+    #[serde(default)]
+    pub last_seen_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub login_method: Option<String>,
+    #[serde(default)]
+    pub remember_me: bool,
+    /// Effective permissions, cached on first use and cleared when the account's roles change.
+    #[serde(default)]
+    pub permissions: Option<Vec<String>>,
+}
+
+/// Where and how a session was started, shown back to the user in their session list.
+#[derive(Clone, Debug, Default)]
+pub struct SessionContext {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// `password`, `password+totp`, `webauthn`, `github` or `oidc:<provider>`.
+    pub login_method: String,
+    /// Picks the long lifetimes over the short ones.
+    pub remember_me: bool,
+}
+
+/// A session ends after `idle` seconds without requests, and in any case `absolute` seconds
+/// after login. "Remember me" logins get their own, longer pair.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionLifetimes {
+    pub idle_seconds: u64,
+    pub absolute_seconds: u64,
+    pub remember_me_idle_seconds: u64,
+    pub remember_me_absolute_seconds: u64,
+}
+
+impl SessionLifetimes {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            idle_seconds: config.session_idle_ttl_seconds,
+            absolute_seconds: config.session_ttl_seconds,
+            remember_me_idle_seconds: config.session_remember_me_idle_ttl_seconds,
+            remember_me_absolute_seconds: config.session_remember_me_ttl_seconds,
+        }
+    }
+
+    /// `(idle, absolute)` in seconds.
+    pub fn for_remember_me(&self, remember_me: bool) -> (u64, u64) {
+        if remember_me {
+            (
+                self.remember_me_idle_seconds,
+                self.remember_me_absolute_seconds,
+            )
+        } else {
+            (self.idle_seconds, self.absolute_seconds)
+        }
+    }
+
+    pub fn absolute_expires_at(&self, session: &SessionData) -> DateTime<Utc> {
+        let (_, absolute) = self.for_remember_me(session.remember_me);
+        session.created_at + Duration::seconds(absolute as i64)
+    }
+
+    /// When the session ends if it sees no further activity.
+    pub fn expires_at(&self, session: &SessionData) -> DateTime<Utc> {
+        let (idle, _) = self.for_remember_me(session.remember_me);
+        let last_seen_at = session.last_seen_at.unwrap_or(session.created_at);
+        std::cmp::min(
+            last_seen_at + Duration::seconds(idle as i64),
+            self.absolute_expires_at(session),
+        )
+    }
+}
+
+/// Identifies a session in the session list without revealing the session id, which is a
+/// bearer credential.
+pub fn public_session_id(session_id: &str) -> String {
+    crate::service::verification::hash_token(session_id)[..16].to_string()
 }
 
 #[derive(Debug)]
@@ -44,70 +125,453 @@ impl From<serde_json::Error> for SessionError {
 
 #[async_trait]
 pub trait SessionService: Send + Sync {
-    async fn create(&self, account_uid: Uuid) -> Result<String, SessionError>;
+    async fn create(
+        &self,
+        account_uid: Uuid,
+        context: SessionContext,
+    ) -> Result<String, SessionError>;
+    /// Returns the live session and counts the call as activity, extending the idle timeout.
     async fn get(&self, session_id: &str) -> Result<Option<SessionData>, SessionError>;
-    #[allow(dead_code)]
+    /// Returns the live session without counting as activity, for callers that only inspect it.
+    async fn peek(&self, session_id: &str) -> Result<Option<SessionData>, SessionError>;
     async fn delete(&self, session_id: &str) -> Result<(), SessionError>;
+    /// Replaces the cached permissions without counting as activity. `None` clears the cache.
+    async fn set_permissions(
+        &self,
+        session_id: &str,
+        permissions: Option<Vec<String>>,
+    ) -> Result<(), SessionError>;
+    /// Live sessions of the account as `(session_id, data)`.
+    async fn list_for_account(
+        &self,
+        account_uid: Uuid,
+    ) -> Result<Vec<(String, SessionData)>, SessionError>;
+    /// Deletes every session of the account, optionally keeping `except_session_id` alive.
+    async fn delete_all_for_account(
+        &self,
+        account_uid: Uuid,
+        except_session_id: Option<&str>,
+    ) -> Result<(), SessionError>;
+}
+
+/// Short-lived, single-use values kept next to sessions (e.g. WebAuthn challenges).
+#[async_trait]
+pub trait ChallengeStore: Send + Sync {
+    async fn put_challenge(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), SessionError>;
+    /// Returns and removes the value so it can only be used once.
+    async fn take_challenge(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, SessionError>;
 }
 
+/// Activity only extends a session's TTL once per this many seconds, so that browsing does not
+/// turn every request into a Redis write. Idle timeouts are correspondingly approximate.
+const ACTIVITY_RESOLUTION_SECONDS: i64 = 60;
+
 pub struct RedisSessionService {
     conn: Arc<Mutex<MultiplexedConnection>>,
-    ttl_seconds: u64,
+    lifetimes: SessionLifetimes,
     key_prefix: String,
 }
 
 impl RedisSessionService {
     pub async fn new(
         redis_url: &str,
-        ttl_seconds: u64,
+        lifetimes: SessionLifetimes,
         key_prefix: String,
     ) -> Result<Self, SessionError> {
         let client = redis::Client::open(redis_url)?;
         let conn = client.get_multiplexed_async_connection().await?;
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
-            ttl_seconds,
+            lifetimes,
             key_prefix,
         })
     }
 
+    /// Seconds until the session expires, or `None` if it already has.
+    fn ttl_seconds(&self, session: &SessionData) -> Option<u64> {
+        let remaining = (self.lifetimes.expires_at(session) - Utc::now()).num_seconds();
+        (remaining > 0).then_some(remaining as u64)
+    }
+
     fn key(&self, session_id: &str) -> String {
         format!("{}:session:{}", self.key_prefix, session_id)
     }
+
+    fn challenge_key(&self, namespace: &str, key: &str) -> String {
+        format!("{}:challenge:{}:{}", self.key_prefix, namespace, key)
+    }
+
+    fn account_index_key(&self, account_uid: Uuid) -> String {
+        format!("{}:account_sessions:{}", self.key_prefix, account_uid)
+    }
 }
 
 #[async_trait]
 impl SessionService for RedisSessionService {
-    async fn create(&self, account_uid: Uuid) -> Result<String, SessionError> {
+    async fn create(
+        &self,
+        account_uid: Uuid,
+        context: SessionContext,
+    ) -> Result<String, SessionError> {
         let session_id = Uuid::new_v4().simple().to_string();
+        let now = Utc::now();
         let payload = SessionData {
             account_uid,
-            created_at: Utc::now(),
+            created_at: now,
+            last_seen_at: Some(now),
+            ip: context.ip,
+            user_agent: context.user_agent,
+            login_method: Some(context.login_method),
+            remember_me: context.remember_me,
+            permissions: None,
         };
         let value = serde_json::to_string(&payload)?;
+        let ttl_seconds = self.ttl_seconds(&payload).unwrap_or(1);
 
         let mut conn = self.conn.lock().await;
         let key = self.key(&session_id);
-        conn.set_ex::<_, _, ()>(key, value, self.ttl_seconds)
-            .await?;
+        conn.set_ex::<_, _, ()>(key, value, ttl_seconds).await?;
+
+        // The index only needs to outlive the sessions it references, and none can outlive the
+        // longest absolute lifetime.
+        let index_key = self.account_index_key(account_uid);
+        let index_ttl = std::cmp::max(
+            self.lifetimes.absolute_seconds,
+            self.lifetimes.remember_me_absolute_seconds,
+        );
+        conn.sadd::<_, _, ()>(&index_key, &session_id).await?;
+        conn.expire::<_, ()>(&index_key, index_ttl as i64).await?;
         Ok(session_id)
     }
 
     async fn get(&self, session_id: &str) -> Result<Option<SessionData>, SessionError> {
         let mut conn = self.conn.lock().await;
         let key = self.key(session_id);
-        let value: Option<String> = conn.get(key).await?;
+        let value: Option<String> = conn.get(&key).await?;
         let Some(value) = value else {
             return Ok(None);
         };
-        let session = serde_json::from_str(&value)?;
+        let mut session: SessionData = serde_json::from_str(&value)?;
+
+        let now = Utc::now();
+        let last_seen_at = session.last_seen_at.unwrap_or(session.created_at);
+        if (now - last_seen_at).num_seconds() < ACTIVITY_RESOLUTION_SECONDS {
+            return Ok(Some(session));
+        }
+        session.last_seen_at = Some(now);
+        let Some(ttl_seconds) = self.ttl_seconds(&session) else {
+            // Past its absolute lifetime; Redis would have dropped it shortly anyway.
+            let _: () = conn.del(&key).await?;
+            let index_key = self.account_index_key(session.account_uid);
+            let _: () = conn.srem(index_key, session_id).await?;
+            return Ok(None);
+        };
+        // XX so that a session deleted in the meantime is not resurrected.
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(serde_json::to_string(&session)?)
+            .arg("XX")
+            .arg("EX")
+            .arg(ttl_seconds)
+            .query_async::<()>(&mut *conn)
+            .await?;
         Ok(Some(session))
     }
 
+    async fn peek(&self, session_id: &str) -> Result<Option<SessionData>, SessionError> {
+        let mut conn = self.conn.lock().await;
+        let value: Option<String> = conn.get(self.key(session_id)).await?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let session: SessionData = serde_json::from_str(&value)?;
+        Ok(self.ttl_seconds(&session).map(|_| session))
+    }
+
     async fn delete(&self, session_id: &str) -> Result<(), SessionError> {
         let mut conn = self.conn.lock().await;
         let key = self.key(session_id);
+        let value: Option<String> = conn.get(&key).await?;
         let _: () = conn.del(key).await?;
+        if let Some(value) = value {
+            let session: SessionData = serde_json::from_str(&value)?;
+            let index_key = self.account_index_key(session.account_uid);
+            let _: () = conn.srem(index_key, session_id).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_permissions(
+        &self,
+        session_id: &str,
+        permissions: Option<Vec<String>>,
+    ) -> Result<(), SessionError> {
+        let mut conn = self.conn.lock().await;
+        let key = self.key(session_id);
+        let value: Option<String> = conn.get(&key).await?;
+        let Some(value) = value else {
+            return Ok(());
+        };
+        let mut session: SessionData = serde_json::from_str(&value)?;
+        session.permissions = permissions;
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(serde_json::to_string(&session)?)
+            .arg("XX")
+            .arg("KEEPTTL")
+            .query_async::<()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_for_account(
+        &self,
+        account_uid: Uuid,
+    ) -> Result<Vec<(String, SessionData)>, SessionError> {
+        let mut conn = self.conn.lock().await;
+        let index_key = self.account_index_key(account_uid);
+        let session_ids: Vec<String> = conn.smembers(&index_key).await?;
+        let mut sessions = Vec::with_capacity(session_ids.len());
+        for session_id in session_ids {
+            let value: Option<String> = conn.get(self.key(&session_id)).await?;
+            match value {
+                Some(value) => sessions.push((session_id, serde_json::from_str(&value)?)),
+                // Expired sessions leave their id behind in the index.
+                None => conn.srem::<_, _, ()>(&index_key, &session_id).await?,
+            }
+        }
+        Ok(sessions)
+    }
+
+    async fn delete_all_for_account(
+        &self,
+        account_uid: Uuid,
+        except_session_id: Option<&str>,
+    ) -> Result<(), SessionError> {
+        let mut conn = self.conn.lock().await;
+        let index_key = self.account_index_key(account_uid);
+        let session_ids: Vec<String> = conn.smembers(&index_key).await?;
+        for session_id in session_ids {
+            if Some(session_id.as_str()) == except_session_id {
+                continue;
+            }
+            let _: () = conn.del(self.key(&session_id)).await?;
+            let _: () = conn.srem(&index_key, &session_id).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChallengeStore for RedisSessionService {
+    async fn put_challenge(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), SessionError> {
+        let mut conn = self.conn.lock().await;
+        conn.set_ex::<_, _, ()>(self.challenge_key(namespace, key), value, ttl_seconds)
+            .await?;
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, SessionError> {
+        let mut conn = self.conn.lock().await;
+        let value: Option<String> = conn.get_del(self.challenge_key(namespace, key)).await?;
+        Ok(value)
+    }
+}
+
+/// Process-local stand-in for `RedisSessionService`, so handlers can be tested without Redis.
+#[cfg(test)]
+pub struct InMemorySessionService {
+    lifetimes: SessionLifetimes,
+    sessions: Mutex<std::collections::HashMap<String, SessionData>>,
+    challenges: Mutex<std::collections::HashMap<String, (DateTime<Utc>, String)>>,
+}
+
+#[cfg(test)]
+impl InMemorySessionService {
+    pub fn new(lifetimes: SessionLifetimes) -> Self {
+        Self {
+            lifetimes,
+            sessions: Mutex::new(Default::default()),
+            challenges: Mutex::new(Default::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl SessionService for InMemorySessionService {
+    async fn create(
+        &self,
+        account_uid: Uuid,
+        context: SessionContext,
+    ) -> Result<String, SessionError> {
+        let session_id = Uuid::new_v4().simple().to_string();
+        let now = Utc::now();
+        let session = SessionData {
+            account_uid,
+            created_at: now,
+            last_seen_at: Some(now),
+            ip: context.ip,
+            user_agent: context.user_agent,
+            login_method: Some(context.login_method),
+            remember_me: context.remember_me,
+            permissions: None,
+        };
+        self.sessions
+            .lock()
+            .await
+            .insert(session_id.clone(), session);
+        Ok(session_id)
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<SessionData>, SessionError> {
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(session_id) else {
+            return Ok(None);
+        };
+        let now = Utc::now();
+        if self.lifetimes.expires_at(session) <= now {
+            sessions.remove(session_id);
+            return Ok(None);
+        }
+        let last_seen_at = session.last_seen_at.unwrap_or(session.created_at);
+        if (now - last_seen_at).num_seconds() >= ACTIVITY_RESOLUTION_SECONDS {
+            session.last_seen_at = Some(now);
+        }
+        Ok(Some(session.clone()))
+    }
+
+    async fn peek(&self, session_id: &str) -> Result<Option<SessionData>, SessionError> {
+        let sessions = self.sessions.lock().await;
+        Ok(sessions
+            .get(session_id)
+            .filter(|session| self.lifetimes.expires_at(session) > Utc::now())
+            .cloned())
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), SessionError> {
+        self.sessions.lock().await.remove(session_id);
+        Ok(())
+    }
+
+    async fn set_permissions(
+        &self,
+        session_id: &str,
+        permissions: Option<Vec<String>>,
+    ) -> Result<(), SessionError> {
+        if let Some(session) = self.sessions.lock().await.get_mut(session_id) {
+            session.permissions = permissions;
+        }
+        Ok(())
+    }
+
+    async fn list_for_account(
+        &self,
+        account_uid: Uuid,
+    ) -> Result<Vec<(String, SessionData)>, SessionError> {
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock().await;
+        sessions.retain(|_, session| self.lifetimes.expires_at(session) > now);
+        Ok(sessions
+            .iter()
+            .filter(|(_, session)| session.account_uid == account_uid)
+            .map(|(session_id, session)| (session_id.clone(), session.clone()))
+            .collect())
+    }
+
+    async fn delete_all_for_account(
+        &self,
+        account_uid: Uuid,
+        except_session_id: Option<&str>,
+    ) -> Result<(), SessionError> {
+        self.sessions.lock().await.retain(|session_id, session| {
+            session.account_uid != account_uid || Some(session_id.as_str()) == except_session_id
+        });
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[async_trait]
+impl ChallengeStore for InMemorySessionService {
+    async fn put_challenge(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), SessionError> {
+        let expires_at = Utc::now() + Duration::seconds(ttl_seconds as i64);
+        self.challenges.lock().await.insert(
+            format!("{}:{}", namespace, key),
+            (expires_at, value.to_string()),
+        );
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, SessionError> {
+        let entry = self
+            .challenges
+            .lock()
+            .await
+            .remove(&format!("{}:{}", namespace, key));
+        Ok(entry
+            .filter(|(expires_at, _)| *expires_at > Utc::now())
+            .map(|(_, value)| value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn peeking_does_not_extend_the_idle_timeout() {
+        let service = InMemorySessionService::new(SessionLifetimes {
+            idle_seconds: 600,
+            absolute_seconds: 3600,
+            remember_me_idle_seconds: 600,
+            remember_me_absolute_seconds: 3600,
+        });
+        let context = SessionContext {
+            ip: None,
+            user_agent: None,
+            login_method: "password".to_string(),
+            remember_me: false,
+        };
+        let session_id = service.create(Uuid::new_v4(), context).await.unwrap();
+        let idle_since = Utc::now() - chrono::Duration::seconds(300);
+        if let Some(session) = service.sessions.lock().await.get_mut(&session_id) {
+            session.last_seen_at = Some(idle_since);
+        }
+
+        let peeked = service.peek(&session_id).await.unwrap().unwrap();
+        assert_eq!(peeked.last_seen_at, Some(idle_since));
+        let seen = service.get(&session_id).await.unwrap().unwrap();
+        assert!(seen.last_seen_at > Some(idle_since));
+    }
+}
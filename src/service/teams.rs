@@ -0,0 +1,678 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::TransactionTrait;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    entities::{account_authorizations, accounts, team_memberships},
+    repo::{
+        account_authorizations::AccountAuthorizationsRepo, accounts::AccountsRepo,
+        team_memberships::TeamMembershipsRepo,
+    },
+    service::{pkce, verification::hash_token},
+    state::DatabaseClient,
+};
+
+pub const TEAM_ROLE_OWNER: &str = "owner";
+pub const TEAM_ROLE_ADMIN: &str = "admin";
+pub const TEAM_ROLE_MEMBER: &str = "member";
+const TEAM_ROLES: [&str; 3] = [TEAM_ROLE_OWNER, TEAM_ROLE_ADMIN, TEAM_ROLE_MEMBER];
+
+/// Invitations are `account_authorizations` rows on the team account; the invited email and
+/// role live in `metadata`.
+pub const TOKEN_TYPE_TEAM_INVITE: &str = "team:invite";
+
+pub struct CreateTeamInput {
+    pub username: String,
+    pub email: Option<String>,
+}
+
+/// A team as seen by one of its members.
+#[derive(Debug)]
+pub struct TeamSummary {
+    pub uid: Uuid,
+    pub username: Option<String>,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct TeamMember {
+    pub account_uid: Uuid,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct TeamInvite {
+    pub id: i64,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub struct TeamsError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl TeamsError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn db(err: sea_orm::DbErr) -> Self {
+        Self::new("db_error", err.to_string())
+    }
+
+    fn not_found() -> Self {
+        Self::new("not_found", "team not found")
+    }
+
+    fn forbidden(message: &str) -> Self {
+        Self::new("forbidden", message)
+    }
+
+    fn invalid_token() -> Self {
+        Self::new("invalid_token", "invalid or expired invitation")
+    }
+
+    fn last_owner() -> Self {
+        Self::new("last_owner", "a team must keep at least one owner")
+    }
+}
+
+/// Team accounts and who belongs to them. Owners manage everything; admins manage members and
+/// admins but cannot touch owners; members can only leave. Every team keeps at least one owner.
+#[async_trait]
+pub trait TeamsService: Send + Sync {
+    /// Creates a team account with `owner` as its first owner.
+    async fn create_team(
+        &self,
+        owner: &accounts::Model,
+        input: CreateTeamInput,
+    ) -> Result<accounts::Model, TeamsError>;
+    async fn teams_for(&self, account: &accounts::Model) -> Result<Vec<TeamSummary>, TeamsError>;
+    /// Only members see the member list, unless `read_any` is set; anyone else gets
+    /// `not_found`.
+    async fn list_members(
+        &self,
+        team_uid: Uuid,
+        viewer: &accounts::Model,
+        read_any: bool,
+    ) -> Result<Vec<TeamMember>, TeamsError>;
+    async fn list_invites(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+    ) -> Result<Vec<TeamInvite>, TeamsError>;
+    /// Returns the invitation token in clear, for delivery by email. Inviting an address again
+    /// replaces its pending invitation.
+    async fn invite(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+        email: &str,
+        role: &str,
+    ) -> Result<(accounts::Model, String, TeamInvite), TeamsError>;
+    /// Joins the team the token invites to; the account's email must be the invited one, and
+    /// verified.
+    async fn accept_invite(
+        &self,
+        account: &accounts::Model,
+        token: &str,
+    ) -> Result<TeamSummary, TeamsError>;
+    /// Holding the token is enough to decline, so invitees need no account for it.
+    async fn decline_invite(&self, token: &str) -> Result<(), TeamsError>;
+    async fn change_role(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+        member_uid: Uuid,
+        role: &str,
+    ) -> Result<TeamMember, TeamsError>;
+    /// Members may always remove themselves.
+    async fn remove_member(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+        member_uid: Uuid,
+    ) -> Result<(), TeamsError>;
+}
+
+pub struct TeamsServiceImpl {
+    db: Arc<dyn DatabaseClient>,
+    accounts_repo: Arc<dyn AccountsRepo>,
+    memberships_repo: Arc<dyn TeamMembershipsRepo>,
+    authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+    invite_ttl_seconds: u64,
+}
+
+impl TeamsServiceImpl {
+    pub fn new(
+        db: Arc<dyn DatabaseClient>,
+        accounts_repo: Arc<dyn AccountsRepo>,
+        memberships_repo: Arc<dyn TeamMembershipsRepo>,
+        authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+        invite_ttl_seconds: u64,
+    ) -> Self {
+        Self {
+            db,
+            accounts_repo,
+            memberships_repo,
+            authorizations_repo,
+            invite_ttl_seconds,
+        }
+    }
+
+    async fn team(&self, uid: Uuid) -> Result<accounts::Model, TeamsError> {
+        self.accounts_repo
+            .find_by_uid(uid)
+            .await
+            .map_err(TeamsError::db)?
+            .filter(|account| account.account_type == "team" && account.deleted_at.is_none())
+            .ok_or_else(TeamsError::not_found)
+    }
+
+    async fn membership(
+        &self,
+        team: &accounts::Model,
+        account: &accounts::Model,
+    ) -> Result<Option<team_memberships::Model>, TeamsError> {
+        self.memberships_repo
+            .find(team.id, account.id)
+            .await
+            .map_err(TeamsError::db)
+    }
+
+    /// The actor's role, if it is allowed to manage the team at all.
+    async fn manager_role(
+        &self,
+        team: &accounts::Model,
+        actor: &accounts::Model,
+    ) -> Result<String, TeamsError> {
+        match self.membership(team, actor).await? {
+            Some(membership) if membership.role != TEAM_ROLE_MEMBER => Ok(membership.role),
+            Some(_) => Err(TeamsError::forbidden(
+                "only team owners and admins may manage the team",
+            )),
+            None => Err(TeamsError::not_found()),
+        }
+    }
+
+    async fn member(&self, membership: team_memberships::Model) -> Result<TeamMember, TeamsError> {
+        let account = self
+            .accounts_repo
+            .find_by_id(membership.member_account_id)
+            .await
+            .map_err(TeamsError::db)?;
+        Ok(TeamMember {
+            account_uid: account
+                .as_ref()
+                .map(|account| account.uid)
+                .unwrap_or_default(),
+            username: account
+                .as_ref()
+                .and_then(|account| account.username.clone()),
+            email: account.and_then(|account| account.email),
+            role: membership.role,
+            joined_at: membership.created_at.with_timezone(&Utc),
+        })
+    }
+
+    async fn member_account(&self, uid: Uuid) -> Result<accounts::Model, TeamsError> {
+        self.accounts_repo
+            .find_by_uid(uid)
+            .await
+            .map_err(TeamsError::db)?
+            .ok_or_else(|| TeamsError::new("not_found", "member not found"))
+    }
+
+    /// Gives `member` the role `new_role`, or removes them for `None`, with every membership of
+    /// the team locked; refuses changes that would leave the team without an owner.
+    async fn change_membership(
+        &self,
+        team: &accounts::Model,
+        member: &accounts::Model,
+        actor: &accounts::Model,
+        new_role: Option<&str>,
+    ) -> Result<Option<team_memberships::Model>, TeamsError> {
+        let memberships_repo = self.memberships_repo.clone();
+        let (team_id, member_id, actor_uid) = (team.id, member.id, actor.uid);
+        let new_role = new_role.map(str::to_string);
+        self.db
+            .conn()
+            .transaction(|txn| {
+                let memberships_repo = memberships_repo.clone();
+                let new_role = new_role.clone();
+                Box::pin(async move {
+                    let memberships = memberships_repo.lock_team_with_txn(txn, team_id).await?;
+                    let Some(target) = memberships
+                        .iter()
+                        .find(|membership| membership.member_account_id == member_id)
+                    else {
+                        return Ok::<_, sea_orm::DbErr>(Err(TeamsError::new(
+                            "not_found",
+                            "member not found",
+                        )));
+                    };
+                    let owners = memberships
+                        .iter()
+                        .filter(|membership| membership.role == TEAM_ROLE_OWNER)
+                        .count();
+                    let demotes_owner = target.role == TEAM_ROLE_OWNER
+                        && new_role.as_deref() != Some(TEAM_ROLE_OWNER);
+                    if demotes_owner && owners <= 1 {
+                        return Ok(Err(TeamsError::last_owner()));
+                    }
+                    match new_role {
+                        Some(role) => {
+                            let updated = memberships_repo
+                                .update_role_with_txn(txn, target.id, &role, actor_uid)
+                                .await?;
+                            Ok(Ok(Some(updated)))
+                        }
+                        None => {
+                            memberships_repo
+                                .delete_with_txn(txn, target.id, actor_uid)
+                                .await?;
+                            Ok(Ok(None))
+                        }
+                    }
+                })
+            })
+            .await
+            .map_err(|err| TeamsError::new("db_error", err.to_string()))?
+    }
+
+    /// Active invitation for `token`.
+    async fn invite_record(
+        &self,
+        token: &str,
+    ) -> Result<account_authorizations::Model, TeamsError> {
+        self.authorizations_repo
+            .find_active_by_token_hash(&hash_token(token))
+            .await
+            .map_err(TeamsError::db)?
+            .filter(|record| record.token_type == TOKEN_TYPE_TEAM_INVITE)
+            .ok_or_else(TeamsError::invalid_token)
+    }
+}
+
+fn validate_role(role: &str) -> Result<(), TeamsError> {
+    if TEAM_ROLES.contains(&role) {
+        Ok(())
+    } else {
+        Err(TeamsError::new(
+            "invalid_role",
+            "role must be owner, admin or member",
+        ))
+    }
+}
+
+/// Owners may do anything; admins may not touch owners or hand out the owner role.
+fn may_assign(actor_role: &str, current_role: &str, new_role: &str) -> bool {
+    actor_role == TEAM_ROLE_OWNER
+        || (current_role != TEAM_ROLE_OWNER && new_role != TEAM_ROLE_OWNER)
+}
+
+fn metadata_str<'a>(record: &'a account_authorizations::Model, key: &str) -> &'a str {
+    record
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get(key))
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+}
+
+fn invite_info(record: &account_authorizations::Model) -> TeamInvite {
+    TeamInvite {
+        id: record.id,
+        email: metadata_str(record, "email").to_string(),
+        role: metadata_str(record, "role").to_string(),
+        invited_by: record.created_by,
+        expires_at: record.expires_at.map(|value| value.with_timezone(&Utc)),
+    }
+}
+
+#[async_trait]
+impl TeamsService for TeamsServiceImpl {
+    async fn create_team(
+        &self,
+        owner: &accounts::Model,
+        input: CreateTeamInput,
+    ) -> Result<accounts::Model, TeamsError> {
+        let username = input.username.trim().to_string();
+        if username.is_empty() {
+            return Err(TeamsError::new("invalid_username", "username is required"));
+        }
+        if self
+            .accounts_repo
+            .find_by_username(&username)
+            .await
+            .map_err(TeamsError::db)?
+            .is_some()
+        {
+            return Err(TeamsError::new("username_taken", "username is taken"));
+        }
+
+        let accounts_repo = self.accounts_repo.clone();
+        let memberships_repo = self.memberships_repo.clone();
+        let (owner_id, owner_uid) = (owner.id, owner.uid);
+        let team = accounts::ActiveModel {
+            uid: sea_orm::Set(Uuid::new_v4()),
+            account_type: sea_orm::Set("team".to_string()),
+            username: sea_orm::Set(Some(username)),
+            email: sea_orm::Set(input.email),
+            created_by: sea_orm::Set(Some(owner_uid)),
+            updated_by: sea_orm::Set(Some(owner_uid)),
+            ..Default::default()
+        };
+        self.db
+            .conn()
+            .transaction(|txn| {
+                let accounts_repo = accounts_repo.clone();
+                let memberships_repo = memberships_repo.clone();
+                let team = team.clone();
+                Box::pin(async move {
+                    let team = accounts_repo.insert_with_txn(txn, team).await?;
+                    let membership = team_memberships::ActiveModel {
+                        team_account_id: sea_orm::Set(team.id),
+                        member_account_id: sea_orm::Set(owner_id),
+                        role: sea_orm::Set(TEAM_ROLE_OWNER.to_string()),
+                        created_by: sea_orm::Set(Some(owner_uid)),
+                        updated_by: sea_orm::Set(Some(owner_uid)),
+                        ..Default::default()
+                    };
+                    memberships_repo.insert_with_txn(txn, membership).await?;
+                    Ok::<_, sea_orm::DbErr>(team)
+                })
+            })
+            .await
+            .map_err(|err| TeamsError::new("db_error", err.to_string()))
+    }
+
+    async fn teams_for(&self, account: &accounts::Model) -> Result<Vec<TeamSummary>, TeamsError> {
+        let memberships = self
+            .memberships_repo
+            .list_for_member(account.id)
+            .await
+            .map_err(TeamsError::db)?;
+        let mut teams = Vec::with_capacity(memberships.len());
+        for membership in memberships {
+            let team = self
+                .accounts_repo
+                .find_by_id(membership.team_account_id)
+                .await
+                .map_err(TeamsError::db)?
+                .filter(|team| team.deleted_at.is_none());
+            if let Some(team) = team {
+                teams.push(TeamSummary {
+                    uid: team.uid,
+                    username: team.username,
+                    role: membership.role,
+                    joined_at: membership.created_at.with_timezone(&Utc),
+                });
+            }
+        }
+        Ok(teams)
+    }
+
+    async fn list_members(
+        &self,
+        team_uid: Uuid,
+        viewer: &accounts::Model,
+        read_any: bool,
+    ) -> Result<Vec<TeamMember>, TeamsError> {
+        let team = self.team(team_uid).await?;
+        if !read_any && self.membership(&team, viewer).await?.is_none() {
+            return Err(TeamsError::not_found());
+        }
+        let memberships = self
+            .memberships_repo
+            .list_for_team(team.id)
+            .await
+            .map_err(TeamsError::db)?;
+        let mut members = Vec::with_capacity(memberships.len());
+        for membership in memberships {
+            members.push(self.member(membership).await?);
+        }
+        Ok(members)
+    }
+
+    async fn list_invites(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+    ) -> Result<Vec<TeamInvite>, TeamsError> {
+        let team = self.team(team_uid).await?;
+        self.manager_role(&team, actor).await?;
+        let records = self
+            .authorizations_repo
+            .list_active_by_account_and_type(team.id, TOKEN_TYPE_TEAM_INVITE)
+            .await
+            .map_err(TeamsError::db)?;
+        Ok(records.iter().map(invite_info).collect())
+    }
+
+    async fn invite(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+        email: &str,
+        role: &str,
+    ) -> Result<(accounts::Model, String, TeamInvite), TeamsError> {
+        let email = email.trim().to_lowercase();
+        if !email.contains('@') {
+            return Err(TeamsError::new("invalid_email", "invalid email"));
+        }
+        validate_role(role)?;
+        let team = self.team(team_uid).await?;
+        let actor_role = self.manager_role(&team, actor).await?;
+        if !may_assign(&actor_role, TEAM_ROLE_MEMBER, role) {
+            return Err(TeamsError::forbidden("only owners may invite owners"));
+        }
+        let existing = self
+            .accounts_repo
+            .find_by_email(&email)
+            .await
+            .map_err(TeamsError::db)?;
+        if let Some(existing) = existing {
+            if self.membership(&team, &existing).await?.is_some() {
+                return Err(TeamsError::new(
+                    "already_member",
+                    "this email already belongs to a member",
+                ));
+            }
+        }
+
+        let pending = self
+            .authorizations_repo
+            .list_active_by_account_and_type(team.id, TOKEN_TYPE_TEAM_INVITE)
+            .await
+            .map_err(TeamsError::db)?;
+        for record in pending
+            .iter()
+            .filter(|record| metadata_str(record, "email") == email)
+        {
+            self.authorizations_repo
+                .revoke_if_active(record.id)
+                .await
+                .map_err(TeamsError::db)?;
+        }
+
+        let token = pkce::random_token();
+        let expires_at = Utc::now() + Duration::seconds(self.invite_ttl_seconds as i64);
+        let model = account_authorizations::ActiveModel {
+            account_id: sea_orm::Set(team.id),
+            token_hash: sea_orm::Set(hash_token(&token)),
+            token_type: sea_orm::Set(TOKEN_TYPE_TEAM_INVITE.to_string()),
+            metadata: sea_orm::Set(Some(serde_json::json!({
+                "email": email,
+                "role": role,
+            }))),
+            expires_at: sea_orm::Set(Some(expires_at.into())),
+            revoked_at: sea_orm::Set(None),
+            created_by: sea_orm::Set(Some(actor.uid)),
+            updated_by: sea_orm::Set(Some(actor.uid)),
+            ..Default::default()
+        };
+        let record = self
+            .authorizations_repo
+            .insert(model)
+            .await
+            .map_err(TeamsError::db)?;
+        Ok((team, token, invite_info(&record)))
+    }
+
+    async fn accept_invite(
+        &self,
+        account: &accounts::Model,
+        token: &str,
+    ) -> Result<TeamSummary, TeamsError> {
+        let record = self.invite_record(token).await?;
+        let invite = invite_info(&record);
+        let email_matches = account
+            .email
+            .as_deref()
+            .is_some_and(|email| email.trim().eq_ignore_ascii_case(&invite.email));
+        if !email_matches {
+            return Err(TeamsError::new(
+                "email_mismatch",
+                "this invitation was sent to a different email",
+            ));
+        }
+        if account.email_verified_at.is_none() {
+            return Err(TeamsError::new(
+                "email_unverified",
+                "verify your email before accepting invitations",
+            ));
+        }
+        let team = self
+            .accounts_repo
+            .find_by_id(record.account_id)
+            .await
+            .map_err(TeamsError::db)?
+            .filter(|team| team.deleted_at.is_none())
+            .ok_or_else(TeamsError::invalid_token)?;
+        if let Some(membership) = self.membership(&team, account).await? {
+            self.authorizations_repo
+                .revoke_if_active(record.id)
+                .await
+                .map_err(TeamsError::db)?;
+            return Ok(TeamSummary {
+                uid: team.uid,
+                username: team.username,
+                role: membership.role,
+                joined_at: membership.created_at.with_timezone(&Utc),
+            });
+        }
+
+        let authorizations_repo = self.authorizations_repo.clone();
+        let memberships_repo = self.memberships_repo.clone();
+        let membership = team_memberships::ActiveModel {
+            team_account_id: sea_orm::Set(team.id),
+            member_account_id: sea_orm::Set(account.id),
+            role: sea_orm::Set(invite.role.clone()),
+            created_by: sea_orm::Set(invite.invited_by),
+            updated_by: sea_orm::Set(Some(account.uid)),
+            ..Default::default()
+        };
+        let record_id = record.id;
+        // Revoking first makes the token single-use even when accepted twice concurrently.
+        let joined = self
+            .db
+            .conn()
+            .transaction(|txn| {
+                let authorizations_repo = authorizations_repo.clone();
+                let memberships_repo = memberships_repo.clone();
+                let membership = membership.clone();
+                Box::pin(async move {
+                    if !authorizations_repo
+                        .revoke_if_active_with_txn(txn, record_id)
+                        .await?
+                    {
+                        return Ok::<_, sea_orm::DbErr>(None);
+                    }
+                    Ok(Some(
+                        memberships_repo.insert_with_txn(txn, membership).await?,
+                    ))
+                })
+            })
+            .await
+            .map_err(|err| TeamsError::new("db_error", err.to_string()))?
+            .ok_or_else(TeamsError::invalid_token)?;
+        Ok(TeamSummary {
+            uid: team.uid,
+            username: team.username,
+            role: joined.role,
+            joined_at: joined.created_at.with_timezone(&Utc),
+        })
+    }
+
+    async fn decline_invite(&self, token: &str) -> Result<(), TeamsError> {
+        let record = self.invite_record(token).await?;
+        self.authorizations_repo
+            .revoke_if_active(record.id)
+            .await
+            .map_err(TeamsError::db)?;
+        Ok(())
+    }
+
+    async fn change_role(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+        member_uid: Uuid,
+        role: &str,
+    ) -> Result<TeamMember, TeamsError> {
+        validate_role(role)?;
+        let team = self.team(team_uid).await?;
+        let actor_role = self.manager_role(&team, actor).await?;
+        let member = self.member_account(member_uid).await?;
+        let current = self
+            .membership(&team, &member)
+            .await?
+            .ok_or_else(|| TeamsError::new("not_found", "member not found"))?;
+        if !may_assign(&actor_role, &current.role, role) {
+            return Err(TeamsError::forbidden(
+                "only owners may change the owner role",
+            ));
+        }
+        let updated = self
+            .change_membership(&team, &member, actor, Some(role))
+            .await?
+            .ok_or_else(|| TeamsError::new("not_found", "member not found"))?;
+        self.member(updated).await
+    }
+
+    async fn remove_member(
+        &self,
+        team_uid: Uuid,
+        actor: &accounts::Model,
+        member_uid: Uuid,
+    ) -> Result<(), TeamsError> {
+        let team = self.team(team_uid).await?;
+        let member = self.member_account(member_uid).await?;
+        if member.id != actor.id {
+            let actor_role = self.manager_role(&team, actor).await?;
+            let current = self
+                .membership(&team, &member)
+                .await?
+                .ok_or_else(|| TeamsError::new("not_found", "member not found"))?;
+            if !may_assign(&actor_role, &current.role, TEAM_ROLE_MEMBER) {
+                return Err(TeamsError::forbidden("only owners may remove owners"));
+            }
+        }
+        self.change_membership(&team, &member, actor, None).await?;
+        Ok(())
+    }
+}
@@ -9,7 +9,9 @@ use crate::{
     entities::account_authorizations, repo::account_authorizations::AccountAuthorizationsRepo,
 };
 
-const TOKEN_TYPE_VERIFY_EMAIL: &str = "auth:verify_email";
+pub const TOKEN_TYPE_VERIFY_EMAIL: &str = "auth:verify_email";
+pub const TOKEN_TYPE_RESET_PASSWORD: &str = "auth:reset_password";
+pub const TOKEN_TYPE_MFA_PENDING: &str = "auth:mfa_pending";
 
 #[derive(Debug)]
 pub struct VerificationToken {
@@ -32,26 +34,52 @@ impl VerificationError {
     }
 }
 
+/// Single-use tokens (email verification, password reset, MFA challenges) stored hashed in
+/// `account_authorizations`. Issuing a token revokes any active token of the same type, except
+/// MFA challenges: each sign-in gets its own, so a second device cannot void the first.
 #[async_trait]
 pub trait VerificationService: Send + Sync {
-    async fn create_email_verification(
+    async fn create_token(
         &self,
         account_id: i64,
+        token_type: &'static str,
     ) -> Result<VerificationToken, VerificationError>;
-    async fn verify_email_token(&self, token: &str) -> Result<i64, VerificationError>;
-    fn email_verification_type(&self) -> &'static str;
+    async fn consume_token(
+        &self,
+        token: &str,
+        token_type: &'static str,
+    ) -> Result<i64, VerificationError>;
 }
 
 pub struct VerificationServiceImpl {
     authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
-    ttl_seconds: u64,
+    verify_email_ttl_seconds: u64,
+    reset_password_ttl_seconds: u64,
+    mfa_pending_ttl_seconds: u64,
 }
 
 impl VerificationServiceImpl {
-    pub fn new(authorizations_repo: Arc<dyn AccountAuthorizationsRepo>, ttl_seconds: u64) -> Self {
+    pub fn new(
+        authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+        verify_email_ttl_seconds: u64,
+        reset_password_ttl_seconds: u64,
+        mfa_pending_ttl_seconds: u64,
+    ) -> Self {
         Self {
             authorizations_repo,
-            ttl_seconds,
+            verify_email_ttl_seconds,
+            reset_password_ttl_seconds,
+            mfa_pending_ttl_seconds,
+        }
+    }
+
+    /// `None` for token types this service does not issue.
+    fn ttl_seconds(&self, token_type: &str) -> Option<u64> {
+        match token_type {
+            TOKEN_TYPE_VERIFY_EMAIL => Some(self.verify_email_ttl_seconds),
+            TOKEN_TYPE_RESET_PASSWORD => Some(self.reset_password_ttl_seconds),
+            TOKEN_TYPE_MFA_PENDING => Some(self.mfa_pending_ttl_seconds),
+            _ => None,
         }
     }
 
@@ -60,29 +88,40 @@ impl VerificationServiceImpl {
         rand::thread_rng().fill_bytes(&mut bytes);
         base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
     }
+}
 
-    fn hash_token(token: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(token.as_bytes());
-        format!("{:x}", hasher.finalize())
-    }
+/// Tokens handed to clients are only ever stored as this digest.
+pub(crate) fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{:x}", hasher.finalize())
 }
 
 #[async_trait]
 impl VerificationService for VerificationServiceImpl {
-    async fn create_email_verification(
+    async fn create_token(
         &self,
         account_id: i64,
+        token_type: &'static str,
     ) -> Result<VerificationToken, VerificationError> {
+        let Some(ttl_seconds) = self.ttl_seconds(token_type) else {
+            return Err(VerificationError::new(
+                "invalid_token_type",
+                format!("{} tokens are not issued by this service", token_type),
+            ));
+        };
         let token = Self::generate_token();
-        let token_hash = Self::hash_token(&token);
-        let expires_at = Utc::now() + Duration::seconds(self.ttl_seconds as i64);
-
-        let active = self
-            .authorizations_repo
-            .find_active_by_account_and_type(account_id, TOKEN_TYPE_VERIFY_EMAIL)
-            .await
-            .map_err(|err| VerificationError::new("db_error", err.to_string()))?;
+        let token_hash = hash_token(&token);
+        let expires_at = Utc::now() + Duration::seconds(ttl_seconds as i64);
+
+        let active = if token_type == TOKEN_TYPE_MFA_PENDING {
+            None
+        } else {
+            self.authorizations_repo
+                .find_active_by_account_and_type(account_id, token_type)
+                .await
+                .map_err(|err| VerificationError::new("db_error", err.to_string()))?
+        };
 
         if let Some(existing) = active {
             let _ = self
@@ -95,7 +134,7 @@ impl VerificationService for VerificationServiceImpl {
         let model = account_authorizations::ActiveModel {
             account_id: sea_orm::Set(account_id),
             token_hash: sea_orm::Set(token_hash),
-            token_type: sea_orm::Set(TOKEN_TYPE_VERIFY_EMAIL.to_string()),
+            token_type: sea_orm::Set(token_type.to_string()),
             expires_at: sea_orm::Set(Some(expires_at.into())),
             revoked_at: sea_orm::Set(None),
             created_at: sea_orm::Set(Utc::now().into()),
@@ -111,8 +150,12 @@ impl VerificationService for VerificationServiceImpl {
         Ok(VerificationToken { token, expires_at })
     }
 
-    async fn verify_email_token(&self, token: &str) -> Result<i64, VerificationError> {
-        let token_hash = Self::hash_token(token);
+    async fn consume_token(
+        &self,
+        token: &str,
+        token_type: &'static str,
+    ) -> Result<i64, VerificationError> {
+        let token_hash = hash_token(token);
         let record = self
             .authorizations_repo
             .find_active_by_token_hash(&token_hash)
@@ -126,22 +169,75 @@ impl VerificationService for VerificationServiceImpl {
             ));
         };
 
-        if record.token_type != TOKEN_TYPE_VERIFY_EMAIL {
+        if record.token_type != token_type {
             return Err(VerificationError::new(
                 "invalid_token",
                 "verification token type mismatch",
             ));
         }
 
-        self.authorizations_repo
-            .revoke_by_id(record.id)
+        // Only the request that actually revokes the row may redeem it; a concurrent one that
+        // found the same active row loses here.
+        let revoked = self
+            .authorizations_repo
+            .revoke_if_active(record.id)
             .await
             .map_err(|err| VerificationError::new("db_error", err.to_string()))?;
+        if !revoked {
+            return Err(VerificationError::new(
+                "invalid_token",
+                "verification token is invalid",
+            ));
+        }
 
         Ok(record.account_id)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::account_authorizations::InMemoryAccountAuthorizationsRepo;
+
+    fn service() -> VerificationServiceImpl {
+        VerificationServiceImpl::new(
+            Arc::new(InMemoryAccountAuthorizationsRepo::default()),
+            3600,
+            3600,
+            300,
+        )
+    }
+
+    #[tokio::test]
+    async fn tokens_are_redeemed_once() {
+        let service = service();
+        let issued = service
+            .create_token(7, TOKEN_TYPE_RESET_PASSWORD)
+            .await
+            .unwrap();
+
+        let wrong_type = service
+            .consume_token(&issued.token, TOKEN_TYPE_VERIFY_EMAIL)
+            .await
+            .unwrap_err();
+        assert_eq!(wrong_type.code, "invalid_token");
+        assert_eq!(
+            service
+                .consume_token(&issued.token, TOKEN_TYPE_RESET_PASSWORD)
+                .await
+                .unwrap(),
+            7
+        );
+        let replayed = service
+            .consume_token(&issued.token, TOKEN_TYPE_RESET_PASSWORD)
+            .await
+            .unwrap_err();
+        assert_eq!(replayed.code, "invalid_token");
+    }
 
-    fn email_verification_type(&self) -> &'static str {
-        TOKEN_TYPE_VERIFY_EMAIL
+    #[tokio::test]
+    async fn unknown_token_types_are_not_issued() {
+        let err = service().create_token(7, "auth:unknown").await.unwrap_err();
+        assert_eq!(err.code, "invalid_token_type");
     }
 }
@@ -0,0 +1,682 @@
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::Utc;
+use ciborium::value::Value as CborValue;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    entities::{account_credentials, accounts},
+    repo::{account_credentials::AccountCredentialsRepo, accounts::AccountsRepo},
+    service::session::ChallengeStore,
+};
+
+pub const PROVIDER_WEBAUTHN: &str = "webauthn";
+
+const CHALLENGE_NAMESPACE: &str = "webauthn";
+const COSE_ALG_ES256: i64 = -7;
+const COSE_ALG_EDDSA: i64 = -8;
+const COSE_ALG_RS256: i64 = -257;
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Debug)]
+pub struct WebauthnError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl WebauthnError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new("invalid_credential", message)
+    }
+}
+
+/// Options handed to `navigator.credentials.create()/get()`, keyed by a challenge id that the
+/// client echoes back on `finish`.
+#[derive(Debug)]
+pub struct CeremonyStart {
+    pub challenge_id: String,
+    pub options: serde_json::Value,
+}
+
+pub struct RegistrationCredential {
+    pub id: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+    pub transports: Vec<String>,
+}
+
+pub struct AssertionCredential {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChallengeState {
+    ceremony: String,
+    challenge: String,
+    account_id: Option<i64>,
+}
+
+/// Layout of `account_credentials.metadata` for the `webauthn` provider.
+#[derive(Serialize, Deserialize)]
+struct WebauthnMetadata {
+    public_key: String,
+    alg: i64,
+    sign_count: u32,
+    #[serde(default)]
+    transports: Vec<String>,
+    name: Option<String>,
+    last_used_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    credential_id: Option<Vec<u8>>,
+    public_key: Option<Vec<u8>>,
+}
+
+#[async_trait]
+pub trait WebauthnService: Send + Sync {
+    async fn start_registration(
+        &self,
+        account: &accounts::Model,
+    ) -> Result<CeremonyStart, WebauthnError>;
+    async fn finish_registration(
+        &self,
+        account: &accounts::Model,
+        challenge_id: &str,
+        credential: RegistrationCredential,
+        name: Option<String>,
+    ) -> Result<account_credentials::Model, WebauthnError>;
+    /// Without an identifier the ceremony relies on discoverable credentials (passkeys).
+    async fn start_login(&self, identifier: Option<&str>) -> Result<CeremonyStart, WebauthnError>;
+    async fn finish_login(
+        &self,
+        challenge_id: &str,
+        credential: AssertionCredential,
+    ) -> Result<accounts::Model, WebauthnError>;
+}
+
+pub struct WebauthnServiceImpl {
+    accounts_repo: Arc<dyn AccountsRepo>,
+    credentials_repo: Arc<dyn AccountCredentialsRepo>,
+    challenges: Arc<dyn ChallengeStore>,
+    rp_id: Option<String>,
+    rp_origin: Option<String>,
+    rp_name: String,
+    challenge_ttl_seconds: u64,
+}
+
+impl WebauthnServiceImpl {
+    pub fn new(
+        accounts_repo: Arc<dyn AccountsRepo>,
+        credentials_repo: Arc<dyn AccountCredentialsRepo>,
+        challenges: Arc<dyn ChallengeStore>,
+        rp_id: Option<String>,
+        rp_origin: Option<String>,
+        rp_name: String,
+        challenge_ttl_seconds: u64,
+    ) -> Self {
+        Self {
+            accounts_repo,
+            credentials_repo,
+            challenges,
+            rp_id,
+            rp_origin,
+            rp_name,
+            challenge_ttl_seconds,
+        }
+    }
+
+    fn relying_party(&self) -> Result<(&str, &str), WebauthnError> {
+        match (self.rp_id.as_deref(), self.rp_origin.as_deref()) {
+            (Some(rp_id), Some(origin)) => Ok((rp_id, origin)),
+            _ => Err(WebauthnError::new(
+                "webauthn_not_configured",
+                "WEBAUTHN_RP_ID/WEBAUTHN_RP_ORIGIN are not set",
+            )),
+        }
+    }
+
+    fn encode(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    fn decode(value: &str) -> Result<Vec<u8>, WebauthnError> {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(value.trim().trim_end_matches('='))
+            .map_err(|err| WebauthnError::invalid(format!("invalid base64url: {}", err)))
+    }
+
+    async fn issue_challenge(
+        &self,
+        ceremony: &str,
+        account_id: Option<i64>,
+    ) -> Result<(String, String), WebauthnError> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let challenge = Self::encode(&bytes);
+        let challenge_id = Uuid::new_v4().simple().to_string();
+        let state = ChallengeState {
+            ceremony: ceremony.to_string(),
+            challenge: challenge.clone(),
+            account_id,
+        };
+        let value = serde_json::to_string(&state)
+            .map_err(|err| WebauthnError::new("webauthn_error", err.to_string()))?;
+        self.challenges
+            .put_challenge(
+                CHALLENGE_NAMESPACE,
+                &challenge_id,
+                &value,
+                self.challenge_ttl_seconds,
+            )
+            .await
+            .map_err(|err| WebauthnError::new("session_error", err.to_string()))?;
+        Ok((challenge_id, challenge))
+    }
+
+    async fn take_challenge(
+        &self,
+        challenge_id: &str,
+        ceremony: &str,
+    ) -> Result<ChallengeState, WebauthnError> {
+        let value = self
+            .challenges
+            .take_challenge(CHALLENGE_NAMESPACE, challenge_id)
+            .await
+            .map_err(|err| WebauthnError::new("session_error", err.to_string()))?;
+        let state = value
+            .and_then(|value| serde_json::from_str::<ChallengeState>(&value).ok())
+            .filter(|state| state.ceremony == ceremony);
+        state.ok_or_else(|| {
+            WebauthnError::new("invalid_challenge", "challenge is invalid or expired")
+        })
+    }
+
+    fn verify_client_data(
+        &self,
+        raw: &[u8],
+        expected_type: &str,
+        expected_challenge: &str,
+    ) -> Result<(), WebauthnError> {
+        let (_, origin) = self.relying_party()?;
+        let client_data: ClientData = serde_json::from_slice(raw)
+            .map_err(|err| WebauthnError::invalid(format!("invalid clientDataJSON: {}", err)))?;
+        if client_data.kind != expected_type {
+            return Err(WebauthnError::invalid("unexpected client data type"));
+        }
+        if Self::decode(&client_data.challenge)? != Self::decode(expected_challenge)? {
+            return Err(WebauthnError::invalid("challenge mismatch"));
+        }
+        if client_data.origin.trim_end_matches('/') != origin.trim_end_matches('/') {
+            return Err(WebauthnError::invalid("origin mismatch"));
+        }
+        Ok(())
+    }
+
+    fn verify_authenticator_data(&self, data: &AuthenticatorData) -> Result<(), WebauthnError> {
+        let (rp_id, _) = self.relying_party()?;
+        if data.rp_id_hash != Sha256::digest(rp_id.as_bytes()).as_slice() {
+            return Err(WebauthnError::invalid("rp id hash mismatch"));
+        }
+        if data.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebauthnError::invalid("user presence flag not set"));
+        }
+        // A passkey replaces the password outright, so possession alone is not enough.
+        if data.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(WebauthnError::invalid("user verification flag not set"));
+        }
+        Ok(())
+    }
+
+    fn parse_metadata(
+        credential: &account_credentials::Model,
+    ) -> Result<WebauthnMetadata, WebauthnError> {
+        let Some(value) = credential.metadata.clone() else {
+            return Err(WebauthnError::new(
+                "webauthn_metadata_invalid",
+                "credential metadata missing",
+            ));
+        };
+        serde_json::from_value(value)
+            .map_err(|err| WebauthnError::new("webauthn_metadata_invalid", err.to_string()))
+    }
+
+    async fn list_webauthn_credentials(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<account_credentials::Model>, WebauthnError> {
+        let credentials = self
+            .credentials_repo
+            .list_by_account(account_id)
+            .await
+            .map_err(|err| WebauthnError::new("db_error", err.to_string()))?;
+        Ok(credentials
+            .into_iter()
+            .filter(|credential| credential.provider == PROVIDER_WEBAUTHN)
+            .collect())
+    }
+
+    fn credential_descriptors(credentials: &[account_credentials::Model]) -> serde_json::Value {
+        let descriptors: Vec<serde_json::Value> = credentials
+            .iter()
+            .filter_map(|credential| {
+                let id = credential.provider_subject.clone()?;
+                let transports = Self::parse_metadata(credential)
+                    .map(|metadata| metadata.transports)
+                    .unwrap_or_default();
+                Some(json!({ "type": "public-key", "id": id, "transports": transports }))
+            })
+            .collect();
+        serde_json::Value::Array(descriptors)
+    }
+
+    async fn find_account_by_identifier(
+        &self,
+        identifier: &str,
+    ) -> Result<Option<accounts::Model>, WebauthnError> {
+        let normalized = identifier.trim().to_lowercase();
+        if normalized.is_empty() {
+            return Ok(None);
+        }
+        let result = if normalized.contains('@') {
+            self.accounts_repo.find_by_email(&normalized).await
+        } else {
+            self.accounts_repo.find_by_username(&normalized).await
+        };
+        result.map_err(|err| WebauthnError::new("db_error", err.to_string()))
+    }
+}
+
+fn parse_authenticator_data(bytes: &[u8]) -> Result<AuthenticatorData, WebauthnError> {
+    if bytes.len() < 37 {
+        return Err(WebauthnError::invalid("authenticator data too short"));
+    }
+    let rp_id_hash = bytes[..32].to_vec();
+    let flags = bytes[32];
+    let sign_count = u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]);
+
+    let mut credential_id = None;
+    let mut public_key = None;
+    if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        // aaguid (16) + credential id length (2)
+        let rest = &bytes[37..];
+        if rest.len() < 18 {
+            return Err(WebauthnError::invalid("attested credential data too short"));
+        }
+        let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+        let rest = &rest[18..];
+        if rest.len() < id_len {
+            return Err(WebauthnError::invalid("credential id truncated"));
+        }
+        credential_id = Some(rest[..id_len].to_vec());
+
+        // The COSE key is followed by optional extensions, so measure what the decoder consumed.
+        let key_bytes = &rest[id_len..];
+        let mut reader = key_bytes;
+        let _: CborValue = ciborium::de::from_reader(&mut reader)
+            .map_err(|err| WebauthnError::invalid(format!("invalid COSE key: {}", err)))?;
+        let consumed = key_bytes.len() - reader.len();
+        public_key = Some(key_bytes[..consumed].to_vec());
+    }
+
+    Ok(AuthenticatorData {
+        rp_id_hash,
+        flags,
+        sign_count,
+        credential_id,
+        public_key,
+    })
+}
+
+fn cbor_map_get(map: &[(CborValue, CborValue)], key: i64) -> Option<&CborValue> {
+    map.iter().find_map(|(k, v)| match k {
+        CborValue::Integer(value) if i128::from(*value) == i128::from(key) => Some(v),
+        _ => None,
+    })
+}
+
+fn cbor_bytes(map: &[(CborValue, CborValue)], key: i64) -> Result<&[u8], WebauthnError> {
+    match cbor_map_get(map, key) {
+        Some(CborValue::Bytes(bytes)) => Ok(bytes),
+        _ => Err(WebauthnError::invalid(format!("COSE key missing {}", key))),
+    }
+}
+
+fn cose_algorithm(cose_key: &[u8]) -> Result<i64, WebauthnError> {
+    let value: CborValue = ciborium::de::from_reader(cose_key)
+        .map_err(|err| WebauthnError::invalid(format!("invalid COSE key: {}", err)))?;
+    let CborValue::Map(map) = value else {
+        return Err(WebauthnError::invalid("COSE key is not a map"));
+    };
+    match cbor_map_get(&map, 3) {
+        Some(CborValue::Integer(alg)) => i64::try_from(i128::from(*alg))
+            .map_err(|_| WebauthnError::invalid("COSE algorithm out of range")),
+        _ => Err(WebauthnError::invalid("COSE key missing algorithm")),
+    }
+}
+
+fn verify_signature(
+    cose_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), WebauthnError> {
+    let value: CborValue = ciborium::de::from_reader(cose_key)
+        .map_err(|err| WebauthnError::invalid(format!("invalid COSE key: {}", err)))?;
+    let CborValue::Map(map) = value else {
+        return Err(WebauthnError::invalid("COSE key is not a map"));
+    };
+    let invalid_signature = |_| WebauthnError::invalid("signature verification failed");
+
+    match cose_algorithm(cose_key)? {
+        COSE_ALG_ES256 => {
+            use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+            let x = cbor_bytes(&map, -2)?;
+            let y = cbor_bytes(&map, -3)?;
+            if x.len() != 32 || y.len() != 32 {
+                return Err(WebauthnError::invalid("invalid P-256 coordinates"));
+            }
+            let point = p256::EncodedPoint::from_affine_coordinates(x.into(), y.into(), false);
+            let key = VerifyingKey::from_encoded_point(&point)
+                .map_err(|_| WebauthnError::invalid("invalid P-256 public key"))?;
+            let signature = Signature::from_der(signature).map_err(invalid_signature)?;
+            key.verify(message, &signature).map_err(invalid_signature)
+        }
+        COSE_ALG_EDDSA => {
+            use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+            let x: [u8; 32] = cbor_bytes(&map, -2)?
+                .try_into()
+                .map_err(|_| WebauthnError::invalid("invalid Ed25519 public key"))?;
+            let key = VerifyingKey::from_bytes(&x)
+                .map_err(|_| WebauthnError::invalid("invalid Ed25519 public key"))?;
+            let signature = Signature::from_slice(signature).map_err(invalid_signature)?;
+            key.verify(message, &signature).map_err(invalid_signature)
+        }
+        COSE_ALG_RS256 => {
+            use rsa::{
+                pkcs1v15::{Signature, VerifyingKey},
+                signature::Verifier,
+                BigUint, RsaPublicKey,
+            };
+            let n = BigUint::from_bytes_be(cbor_bytes(&map, -1)?);
+            let e = BigUint::from_bytes_be(cbor_bytes(&map, -2)?);
+            let key = RsaPublicKey::new(n, e)
+                .map_err(|_| WebauthnError::invalid("invalid RSA public key"))?;
+            let key = VerifyingKey::<Sha256>::new(key);
+            let signature = Signature::try_from(signature).map_err(invalid_signature)?;
+            key.verify(message, &signature).map_err(invalid_signature)
+        }
+        other => Err(WebauthnError::invalid(format!(
+            "unsupported COSE algorithm {}",
+            other
+        ))),
+    }
+}
+
+#[async_trait]
+impl WebauthnService for WebauthnServiceImpl {
+    async fn start_registration(
+        &self,
+        account: &accounts::Model,
+    ) -> Result<CeremonyStart, WebauthnError> {
+        let (rp_id, _) = self.relying_party()?;
+        let existing = self.list_webauthn_credentials(account.id).await?;
+        let (challenge_id, challenge) = self.issue_challenge("register", Some(account.id)).await?;
+
+        let name = account
+            .email
+            .clone()
+            .or_else(|| account.username.clone())
+            .unwrap_or_else(|| account.uid.to_string());
+        let options = json!({
+            "rp": { "id": rp_id, "name": self.rp_name },
+            "user": {
+                "id": Self::encode(account.uid.as_bytes()),
+                "name": name,
+                "displayName": account.username.clone().unwrap_or_else(|| name.clone()),
+            },
+            "challenge": challenge,
+            "pubKeyCredParams": [
+                { "type": "public-key", "alg": COSE_ALG_ES256 },
+                { "type": "public-key", "alg": COSE_ALG_EDDSA },
+                { "type": "public-key", "alg": COSE_ALG_RS256 },
+            ],
+            "timeout": self.challenge_ttl_seconds * 1000,
+            "attestation": "none",
+            "authenticatorSelection": {
+                "residentKey": "required",
+                "requireResidentKey": true,
+                "userVerification": "required",
+            },
+            "excludeCredentials": Self::credential_descriptors(&existing),
+        });
+
+        Ok(CeremonyStart {
+            challenge_id,
+            options,
+        })
+    }
+
+    async fn finish_registration(
+        &self,
+        account: &accounts::Model,
+        challenge_id: &str,
+        credential: RegistrationCredential,
+        name: Option<String>,
+    ) -> Result<account_credentials::Model, WebauthnError> {
+        let state = self.take_challenge(challenge_id, "register").await?;
+        if state.account_id != Some(account.id) {
+            return Err(WebauthnError::new(
+                "invalid_challenge",
+                "challenge is invalid or expired",
+            ));
+        }
+
+        let client_data = Self::decode(&credential.client_data_json)?;
+        self.verify_client_data(&client_data, "webauthn.create", &state.challenge)?;
+
+        // Only `attestation: "none"` is requested, so the attestation statement is not verified.
+        let attestation = Self::decode(&credential.attestation_object)?;
+        let attestation: CborValue = ciborium::de::from_reader(attestation.as_slice())
+            .map_err(|err| WebauthnError::invalid(format!("invalid attestationObject: {}", err)))?;
+        let CborValue::Map(attestation) = attestation else {
+            return Err(WebauthnError::invalid("attestationObject is not a map"));
+        };
+        let auth_data = attestation
+            .iter()
+            .find_map(|(k, v)| match (k, v) {
+                (CborValue::Text(key), CborValue::Bytes(bytes)) if key == "authData" => {
+                    Some(bytes.as_slice())
+                }
+                _ => None,
+            })
+            .ok_or_else(|| WebauthnError::invalid("attestationObject missing authData"))?;
+
+        let auth_data = parse_authenticator_data(auth_data)?;
+        self.verify_authenticator_data(&auth_data)?;
+        let (Some(credential_id), Some(public_key)) =
+            (auth_data.credential_id, auth_data.public_key)
+        else {
+            return Err(WebauthnError::invalid("attested credential data missing"));
+        };
+        if Self::decode(&credential.id)? != credential_id {
+            return Err(WebauthnError::invalid("credential id mismatch"));
+        }
+        let alg = cose_algorithm(&public_key)?;
+        if ![COSE_ALG_ES256, COSE_ALG_EDDSA, COSE_ALG_RS256].contains(&alg) {
+            return Err(WebauthnError::invalid(format!(
+                "unsupported COSE algorithm {}",
+                alg
+            )));
+        }
+
+        let credential_id = Self::encode(&credential_id);
+        let existing = self
+            .credentials_repo
+            .find_by_provider_subject(PROVIDER_WEBAUTHN, &credential_id)
+            .await
+            .map_err(|err| WebauthnError::new("db_error", err.to_string()))?;
+        if existing.is_some() {
+            return Err(WebauthnError::new(
+                "credential_exists",
+                "credential is already registered",
+            ));
+        }
+
+        let metadata = WebauthnMetadata {
+            public_key: Self::encode(&public_key),
+            alg,
+            sign_count: auth_data.sign_count,
+            transports: credential.transports,
+            name,
+            last_used_at: None,
+        };
+        let metadata = serde_json::to_value(&metadata)
+            .map_err(|err| WebauthnError::new("webauthn_error", err.to_string()))?;
+        let model = account_credentials::ActiveModel {
+            account_id: sea_orm::Set(account.id),
+            provider: sea_orm::Set(PROVIDER_WEBAUTHN.to_string()),
+            provider_subject: sea_orm::Set(Some(credential_id)),
+            password_hash: sea_orm::Set(None),
+            metadata: sea_orm::Set(Some(metadata)),
+            created_by: sea_orm::Set(Some(account.uid)),
+            updated_by: sea_orm::Set(Some(account.uid)),
+            ..Default::default()
+        };
+        self.credentials_repo
+            .insert(model)
+            .await
+            .map_err(|err| WebauthnError::new("db_error", err.to_string()))
+    }
+
+    async fn start_login(&self, identifier: Option<&str>) -> Result<CeremonyStart, WebauthnError> {
+        let (rp_id, _) = self.relying_party()?;
+        let account = match identifier {
+            Some(identifier) => self.find_account_by_identifier(identifier).await?,
+            None => None,
+        };
+        // The allow list stays empty for every identifier so the options reveal neither whether
+        // the account exists nor which passkeys it holds; login relies on discoverable
+        // credentials, and a known identifier only pins the challenge to that account.
+        let (challenge_id, challenge) = self
+            .issue_challenge("login", account.as_ref().map(|account| account.id))
+            .await?;
+
+        let options = json!({
+            "challenge": challenge,
+            "rpId": rp_id,
+            "timeout": self.challenge_ttl_seconds * 1000,
+            "userVerification": "required",
+            "allowCredentials": [],
+        });
+
+        Ok(CeremonyStart {
+            challenge_id,
+            options,
+        })
+    }
+
+    async fn finish_login(
+        &self,
+        challenge_id: &str,
+        credential: AssertionCredential,
+    ) -> Result<accounts::Model, WebauthnError> {
+        let state = self.take_challenge(challenge_id, "login").await?;
+
+        let credential_id = Self::encode(&Self::decode(&credential.id)?);
+        let stored = self
+            .credentials_repo
+            .find_by_provider_subject(PROVIDER_WEBAUTHN, &credential_id)
+            .await
+            .map_err(|err| WebauthnError::new("db_error", err.to_string()))?;
+        let Some(stored) = stored else {
+            return Err(WebauthnError::invalid("unknown credential"));
+        };
+        if state
+            .account_id
+            .is_some_and(|account_id| account_id != stored.account_id)
+        {
+            return Err(WebauthnError::invalid(
+                "credential does not belong to account",
+            ));
+        }
+
+        let account = self
+            .accounts_repo
+            .find_by_id(stored.account_id)
+            .await
+            .map_err(|err| WebauthnError::new("db_error", err.to_string()))?;
+        let Some(account) = account else {
+            return Err(WebauthnError::invalid("unknown credential"));
+        };
+        if let Some(user_handle) = credential.user_handle.as_deref() {
+            if Self::decode(user_handle)? != account.uid.as_bytes() {
+                return Err(WebauthnError::invalid("user handle mismatch"));
+            }
+        }
+
+        let client_data = Self::decode(&credential.client_data_json)?;
+        self.verify_client_data(&client_data, "webauthn.get", &state.challenge)?;
+
+        let raw_auth_data = Self::decode(&credential.authenticator_data)?;
+        let auth_data = parse_authenticator_data(&raw_auth_data)?;
+        self.verify_authenticator_data(&auth_data)?;
+
+        let mut metadata = Self::parse_metadata(&stored)?;
+        let public_key = Self::decode(&metadata.public_key)?;
+        let mut message = raw_auth_data.clone();
+        message.extend_from_slice(&Sha256::digest(&client_data));
+        verify_signature(&public_key, &message, &Self::decode(&credential.signature)?)?;
+
+        // Authenticators without counters always report 0; otherwise it must strictly increase.
+        if (auth_data.sign_count != 0 || metadata.sign_count != 0)
+            && auth_data.sign_count <= metadata.sign_count
+        {
+            return Err(WebauthnError::new(
+                "credential_cloned",
+                "signature counter did not increase",
+            ));
+        }
+
+        metadata.sign_count = auth_data.sign_count;
+        metadata.last_used_at = Some(Utc::now());
+        let metadata = serde_json::to_value(&metadata)
+            .map_err(|err| WebauthnError::new("webauthn_error", err.to_string()))?;
+        let mut active: account_credentials::ActiveModel = stored.into();
+        active.metadata = sea_orm::Set(Some(metadata));
+        self.credentials_repo
+            .update(active)
+            .await
+            .map_err(|err| WebauthnError::new("db_error", err.to_string()))?;
+
+        Ok(account)
+    }
+}
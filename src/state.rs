@@ -2,10 +2,33 @@ use sea_orm::DatabaseConnection;
 use std::sync::Arc;
 
 use crate::{
-    repo::{account_authorizations::AccountAuthorizationsRepo, accounts::AccountsRepo},
+    repo::{
+        account_authorizations::AccountAuthorizationsRepo,
+        account_credentials::AccountCredentialsRepo, account_settings::AccountSettingsRepo,
+        accounts::AccountsRepo, oauth_clients::OauthClientsRepo, oauth_consents::OauthConsentsRepo,
+        roles::RolesRepo, signing_keys::SigningKeysRepo, team_memberships::TeamMembershipsRepo,
+    },
     service::{
-        accounts::AccountsService, auth::AuthService, config::ConfigService,
-        session::SessionService, verification::VerificationService,
+        account_settings::AccountSettingsService,
+        accounts::AccountsService,
+        auth::AuthService,
+        avatars::AvatarService,
+        config::ConfigService,
+        credentials::CredentialsService,
+        forward_auth::ForwardAuthCache,
+        introspection::TokenIntrospectionService,
+        jwt::JwtService,
+        mfa::MfaService,
+        oauth::OauthService,
+        oidc::OidcService,
+        personal_access_tokens::PersonalAccessTokenService,
+        rbac::RbacService,
+        refresh_tokens::RefreshTokenService,
+        session::{ChallengeStore, SessionService},
+        storage::ObjectStorage,
+        teams::TeamsService,
+        verification::VerificationService,
+        webauthn::WebauthnService,
     },
 };
 
@@ -35,51 +58,347 @@ impl DatabaseClient for SeaOrmDatabaseClient {
     }
 }
 
+/// A connection that accepts transactions but no queries, for use with the in-memory repos:
+/// services can still open and commit transactions around repo calls.
+#[cfg(test)]
+pub struct InMemoryDatabaseClient {
+    conn: DatabaseConnection,
+}
+
+#[cfg(test)]
+impl InMemoryDatabaseClient {
+    pub fn new() -> Self {
+        Self {
+            conn: sea_orm::MockDatabase::new(sea_orm::DatabaseBackend::Postgres).into_connection(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl DatabaseClient for InMemoryDatabaseClient {
+    fn conn(&self) -> &DatabaseConnection {
+        &self.conn
+    }
+}
+
 pub struct AppState {
     db: Arc<dyn DatabaseClient>,
     accounts_repo: Arc<dyn AccountsRepo>,
     accounts: Arc<dyn AccountsService>,
+    account_settings: Arc<dyn AccountSettingsService>,
+    avatars: Arc<dyn AvatarService>,
     sessions: Arc<dyn SessionService>,
+    challenges: Arc<dyn ChallengeStore>,
     auth: Arc<dyn AuthService>,
     verification: Arc<dyn VerificationService>,
-    #[allow(dead_code)]
-    account_authorizations_repo: Arc<dyn AccountAuthorizationsRepo>,
+    mfa: Arc<dyn MfaService>,
+    credentials: Arc<dyn CredentialsService>,
+    jwt: Arc<dyn JwtService>,
+    refresh_tokens: Arc<dyn RefreshTokenService>,
+    personal_access_tokens: Arc<dyn PersonalAccessTokenService>,
+    oauth: Arc<dyn OauthService>,
+    introspection: Arc<dyn TokenIntrospectionService>,
+    webauthn: Arc<dyn WebauthnService>,
+    oidc: Arc<dyn OidcService>,
+    forward_auth_cache: Arc<dyn ForwardAuthCache>,
+    rbac: Arc<dyn RbacService>,
+    teams: Arc<dyn TeamsService>,
     config: Arc<dyn ConfigService>,
 }
 
-impl AppState {
-    pub async fn new() -> Arc<Self> {
-        let db = Arc::new(SeaOrmDatabaseClient::new().await);
-        let accounts_repo = Arc::new(crate::repo::accounts::SeaOrmAccountsRepo::new(db.clone()));
-        let account_credentials_repo = Arc::new(
-            crate::repo::account_credentials::SeaOrmAccountCredentialsRepo::new(db.clone()),
-        );
-        let account_authorizations_repo = Arc::new(
-            crate::repo::account_authorizations::SeaOrmAccountAuthorizationsRepo::new(db.clone()),
-        );
+/// Assembles an `AppState`. Anything not injected is built from the environment: configuration
+/// from `ConfigServiceImpl`, Postgres from `DATABASE_URL`, Redis from `REDIS_URL` and storage
+/// from `STORAGE_BACKEND`. Building also creates the built-in roles and grants admin to
+/// `BOOTSTRAP_ADMINS`.
+#[derive(Default)]
+pub struct AppStateBuilder {
+    config: Option<Arc<dyn ConfigService>>,
+    db: Option<Arc<dyn DatabaseClient>>,
+    accounts_repo: Option<Arc<dyn AccountsRepo>>,
+    account_credentials_repo: Option<Arc<dyn AccountCredentialsRepo>>,
+    account_authorizations_repo: Option<Arc<dyn AccountAuthorizationsRepo>>,
+    account_settings_repo: Option<Arc<dyn AccountSettingsRepo>>,
+    roles_repo: Option<Arc<dyn RolesRepo>>,
+    team_memberships_repo: Option<Arc<dyn TeamMembershipsRepo>>,
+    oauth_clients_repo: Option<Arc<dyn OauthClientsRepo>>,
+    oauth_consents_repo: Option<Arc<dyn OauthConsentsRepo>>,
+    signing_keys_repo: Option<Arc<dyn SigningKeysRepo>>,
+    sessions: Option<(Arc<dyn SessionService>, Arc<dyn ChallengeStore>)>,
+    storage: Option<Arc<dyn ObjectStorage>>,
+}
+
+/// Injection points for tests; production builds everything from the environment.
+#[cfg(test)]
+impl AppStateBuilder {
+    pub fn config(mut self, config: Arc<dyn ConfigService>) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn db(mut self, db: Arc<dyn DatabaseClient>) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    pub fn accounts_repo(mut self, repo: Arc<dyn AccountsRepo>) -> Self {
+        self.accounts_repo = Some(repo);
+        self
+    }
+
+    pub fn account_credentials_repo(mut self, repo: Arc<dyn AccountCredentialsRepo>) -> Self {
+        self.account_credentials_repo = Some(repo);
+        self
+    }
+
+    pub fn account_authorizations_repo(mut self, repo: Arc<dyn AccountAuthorizationsRepo>) -> Self {
+        self.account_authorizations_repo = Some(repo);
+        self
+    }
+
+    pub fn account_settings_repo(mut self, repo: Arc<dyn AccountSettingsRepo>) -> Self {
+        self.account_settings_repo = Some(repo);
+        self
+    }
+
+    pub fn roles_repo(mut self, repo: Arc<dyn RolesRepo>) -> Self {
+        self.roles_repo = Some(repo);
+        self
+    }
+
+    pub fn team_memberships_repo(mut self, repo: Arc<dyn TeamMembershipsRepo>) -> Self {
+        self.team_memberships_repo = Some(repo);
+        self
+    }
+
+    pub fn oauth_clients_repo(mut self, repo: Arc<dyn OauthClientsRepo>) -> Self {
+        self.oauth_clients_repo = Some(repo);
+        self
+    }
+
+    pub fn oauth_consents_repo(mut self, repo: Arc<dyn OauthConsentsRepo>) -> Self {
+        self.oauth_consents_repo = Some(repo);
+        self
+    }
+
+    pub fn signing_keys_repo(mut self, repo: Arc<dyn SigningKeysRepo>) -> Self {
+        self.signing_keys_repo = Some(repo);
+        self
+    }
+
+    pub fn storage(mut self, storage: Arc<dyn ObjectStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Sessions and challenges share a store.
+    pub fn sessions<S>(mut self, sessions: Arc<S>) -> Self
+    where
+        S: SessionService + ChallengeStore + 'static,
+    {
+        self.sessions = Some((sessions.clone(), sessions));
+        self
+    }
+}
+
+impl AppStateBuilder {
+    pub async fn build(self) -> Arc<AppState> {
+        let config = self
+            .config
+            .unwrap_or_else(|| Arc::new(crate::service::config::ConfigServiceImpl::new()));
+        let db = match self.db {
+            Some(db) => db,
+            None => Arc::new(SeaOrmDatabaseClient::new().await),
+        };
+        let accounts_repo = self.accounts_repo.unwrap_or_else(|| {
+            Arc::new(crate::repo::accounts::SeaOrmAccountsRepo::new(db.clone()))
+        });
+        let account_credentials_repo = self.account_credentials_repo.unwrap_or_else(|| {
+            Arc::new(
+                crate::repo::account_credentials::SeaOrmAccountCredentialsRepo::new(db.clone()),
+            )
+        });
+        let account_authorizations_repo = self.account_authorizations_repo.unwrap_or_else(|| {
+            Arc::new(
+                crate::repo::account_authorizations::SeaOrmAccountAuthorizationsRepo::new(
+                    db.clone(),
+                ),
+            )
+        });
+        let account_settings_repo = self.account_settings_repo.unwrap_or_else(|| {
+            Arc::new(crate::repo::account_settings::SeaOrmAccountSettingsRepo::new(db.clone()))
+        });
+        let roles_repo = self
+            .roles_repo
+            .unwrap_or_else(|| Arc::new(crate::repo::roles::SeaOrmRolesRepo::new(db.clone())));
+        let team_memberships_repo = self.team_memberships_repo.unwrap_or_else(|| {
+            Arc::new(crate::repo::team_memberships::SeaOrmTeamMembershipsRepo::new(db.clone()))
+        });
+        let session_lifetimes =
+            crate::service::session::SessionLifetimes::from_config(config.values());
+        let (sessions, challenges) = match self.sessions {
+            Some(sessions) => sessions,
+            None => {
+                let redis_url = config
+                    .values()
+                    .redis_url
+                    .clone()
+                    .expect("REDIS_URL is not set");
+                let sessions = Arc::new(
+                    crate::service::session::RedisSessionService::new(
+                        &redis_url,
+                        session_lifetimes,
+                        config.values().session_key_prefix.clone(),
+                    )
+                    .await
+                    .expect("redis connection failed"),
+                );
+                (sessions.clone() as Arc<dyn SessionService>, sessions as _)
+            }
+        };
+
         let accounts = Arc::new(crate::service::accounts::AccountsServiceImpl::new(
             db.clone(),
             accounts_repo.clone(),
             account_credentials_repo.clone(),
+            account_authorizations_repo.clone(),
         ));
-        let config = Arc::new(crate::service::config::ConfigServiceImpl::new());
-        let redis_url = config
-            .values()
-            .redis_url
-            .clone()
-            .expect("REDIS_URL is not set");
-        let sessions = Arc::new(
-            crate::service::session::RedisSessionService::new(
-                &redis_url,
-                config.values().session_ttl_seconds,
-                config.values().session_key_prefix.clone(),
+        let verification = Arc::new(crate::service::verification::VerificationServiceImpl::new(
+            account_authorizations_repo.clone(),
+            config.values().verify_email_token_ttl_seconds,
+            config.values().reset_password_token_ttl_seconds,
+            config.values().mfa_challenge_ttl_seconds,
+        ));
+        let mfa = Arc::new(crate::service::mfa::MfaServiceImpl::new(
+            account_credentials_repo.clone(),
+            config.values().mfa_encryption_key.as_deref(),
+            config.values().mfa_totp_issuer.clone(),
+        ));
+        let webauthn = Arc::new(crate::service::webauthn::WebauthnServiceImpl::new(
+            accounts_repo.clone(),
+            account_credentials_repo.clone(),
+            challenges.clone(),
+            config.values().webauthn_rp_id.clone(),
+            config.values().webauthn_rp_origin.clone(),
+            config.values().webauthn_rp_name.clone(),
+            config.values().webauthn_challenge_ttl_seconds,
+        ));
+        let oidc = Arc::new(crate::service::oidc::OidcServiceImpl::new(
+            config.values().oidc_providers.clone(),
+            challenges.clone(),
+            config.values().oidc_state_ttl_seconds,
+        ));
+        let credentials = Arc::new(crate::service::credentials::CredentialsServiceImpl::new(
+            account_credentials_repo.clone(),
+        ));
+        let signing_keys_repo = self.signing_keys_repo.unwrap_or_else(|| {
+            Arc::new(crate::repo::signing_keys::SeaOrmSigningKeysRepo::new(
+                db.clone(),
+            ))
+        });
+        let jwt = Arc::new(
+            crate::service::jwt::JwtServiceImpl::new(
+                crate::service::jwt::JwtSettings {
+                    enabled: config.values().jwt_enabled,
+                    algorithm: config.values().jwt_algorithm.clone(),
+                    issuer: config.values().jwt_issuer.clone(),
+                    audience: config.values().jwt_audience.clone(),
+                    access_token_ttl_seconds: config.values().jwt_access_token_ttl_seconds,
+                    key_rotation_seconds: config.values().jwt_key_rotation_seconds,
+                    private_key_path: config.values().jwt_private_key_path.clone(),
+                    previous_key_paths: config.values().jwt_previous_key_paths.clone(),
+                },
+                signing_keys_repo,
             )
+            .expect("jwt key load failed"),
+        );
+        let refresh_tokens = Arc::new(
+            crate::service::refresh_tokens::RefreshTokenServiceImpl::new(
+                db.clone(),
+                accounts_repo.clone(),
+                account_authorizations_repo.clone(),
+                config.values().refresh_token_absolute_ttl_seconds,
+                config.values().refresh_token_idle_ttl_seconds,
+            ),
+        );
+        let personal_access_tokens = Arc::new(
+            crate::service::personal_access_tokens::PersonalAccessTokenServiceImpl::new(
+                accounts_repo.clone(),
+                account_authorizations_repo.clone(),
+            ),
+        );
+        let oauth_clients_repo = self.oauth_clients_repo.unwrap_or_else(|| {
+            Arc::new(crate::repo::oauth_clients::SeaOrmOauthClientsRepo::new(
+                db.clone(),
+            ))
+        });
+        let oauth_consents_repo = self.oauth_consents_repo.unwrap_or_else(|| {
+            Arc::new(crate::repo::oauth_consents::SeaOrmOauthConsentsRepo::new(
+                db.clone(),
+            ))
+        });
+        let oauth = Arc::new(crate::service::oauth::OauthServiceImpl::new(
+            accounts_repo.clone(),
+            oauth_clients_repo,
+            oauth_consents_repo,
+            account_authorizations_repo.clone(),
+            config.values().oauth_code_ttl_seconds,
+        ));
+        let introspection = Arc::new(
+            crate::service::introspection::TokenIntrospectionServiceImpl::new(
+                sessions.clone(),
+                jwt.clone(),
+                personal_access_tokens.clone(),
+                accounts_repo.clone(),
+                account_authorizations_repo.clone(),
+                session_lifetimes,
+            ),
+        );
+        let forward_auth_cache =
+            Arc::new(crate::service::forward_auth::InMemoryForwardAuthCache::new(
+                config.values().forward_auth_cache_ttl_seconds,
+            ));
+        let rbac = Arc::new(crate::service::rbac::RbacServiceImpl::new(
+            roles_repo,
+            accounts_repo.clone(),
+            sessions.clone(),
+        ));
+        rbac.bootstrap(&config.values().bootstrap_admins)
             .await
-            .expect("redis connection failed"),
+            .map_err(|err| err.message)
+            .expect("role bootstrap failed");
+        let storage = self.storage.unwrap_or_else(|| {
+            let values = config.values();
+            match values.storage_backend.as_str() {
+                "local" => Arc::new(crate::service::storage::LocalStorage::new(
+                    &values.storage_local_dir,
+                )),
+                "s3" => Arc::new(crate::service::storage::S3Storage::new(
+                    values.storage_s3.clone().expect(
+                        "STORAGE_BACKEND=s3 needs STORAGE_S3_ENDPOINT, STORAGE_S3_BUCKET, \
+                         STORAGE_S3_ACCESS_KEY_ID and STORAGE_S3_SECRET_ACCESS_KEY",
+                    ),
+                )),
+                other => panic!("unknown STORAGE_BACKEND {}", other),
+            }
+        });
+        let account_settings = Arc::new(
+            crate::service::account_settings::AccountSettingsServiceImpl::new(
+                account_settings_repo.clone(),
+            ),
         );
-        let verification = Arc::new(crate::service::verification::VerificationServiceImpl::new(
+        let avatars = Arc::new(crate::service::avatars::AvatarServiceImpl::new(
+            storage,
+            account_settings_repo,
+            config.values().avatar_max_bytes,
+            config.values().avatar_sizes.clone(),
+            config.values().avatar_url_base.clone(),
+        ));
+        let teams = Arc::new(crate::service::teams::TeamsServiceImpl::new(
+            db.clone(),
+            accounts_repo.clone(),
+            team_memberships_repo,
             account_authorizations_repo.clone(),
-            config.values().verify_email_token_ttl_seconds,
+            config.values().team_invite_token_ttl_seconds,
         ));
         let auth = Arc::new(crate::service::auth::AuthServiceImpl::new(
             db.clone(),
@@ -88,19 +407,98 @@ impl AppState {
             account_authorizations_repo.clone(),
             sessions.clone(),
             verification.clone(),
+            mfa.clone(),
         ));
 
-        Arc::new(Self {
+        Arc::new(AppState {
             db,
             accounts_repo,
             accounts,
+            account_settings,
+            avatars,
+            challenges,
             sessions,
             auth,
             verification,
-            account_authorizations_repo,
+            mfa,
+            credentials,
+            jwt,
+            refresh_tokens,
+            personal_access_tokens,
+            oauth,
+            introspection,
+            webauthn,
+            oidc,
+            forward_auth_cache,
+            rbac,
+            teams,
             config,
         })
     }
+}
+
+impl AppState {
+    pub async fn new() -> Arc<Self> {
+        Self::builder().build().await
+    }
+
+    pub fn builder() -> AppStateBuilder {
+        AppStateBuilder::default()
+    }
+
+    /// State backed entirely by in-memory stores, for handler tests.
+    #[cfg(test)]
+    pub async fn in_memory() -> Arc<Self> {
+        Self::in_memory_with(|_| {}).await
+    }
+
+    /// Like `in_memory`, with `configure` applied to the configuration read from the
+    /// environment.
+    #[cfg(test)]
+    pub async fn in_memory_with(configure: impl FnOnce(&mut crate::config::Config)) -> Arc<Self> {
+        let mut values = crate::service::config::ConfigServiceImpl::new()
+            .values()
+            .clone();
+        configure(&mut values);
+        let config = Arc::new(crate::service::config::ConfigServiceImpl::from_config(
+            values,
+        ));
+        let lifetimes = crate::service::session::SessionLifetimes::from_config(config.values());
+        Self::builder()
+            .config(config)
+            .db(Arc::new(InMemoryDatabaseClient::new()))
+            .accounts_repo(Arc::new(
+                crate::repo::accounts::InMemoryAccountsRepo::default(),
+            ))
+            .account_credentials_repo(Arc::new(
+                crate::repo::account_credentials::InMemoryAccountCredentialsRepo::default(),
+            ))
+            .account_authorizations_repo(Arc::new(
+                crate::repo::account_authorizations::InMemoryAccountAuthorizationsRepo::default(),
+            ))
+            .account_settings_repo(Arc::new(
+                crate::repo::account_settings::InMemoryAccountSettingsRepo::default(),
+            ))
+            .roles_repo(Arc::new(crate::repo::roles::InMemoryRolesRepo::default()))
+            .team_memberships_repo(Arc::new(
+                crate::repo::team_memberships::InMemoryTeamMembershipsRepo::default(),
+            ))
+            .sessions(Arc::new(
+                crate::service::session::InMemorySessionService::new(lifetimes),
+            ))
+            .oauth_clients_repo(Arc::new(
+                crate::repo::oauth_clients::InMemoryOauthClientsRepo::default(),
+            ))
+            .oauth_consents_repo(Arc::new(
+                crate::repo::oauth_consents::InMemoryOauthConsentsRepo::default(),
+            ))
+            .signing_keys_repo(Arc::new(
+                crate::repo::signing_keys::InMemorySigningKeysRepo::default(),
+            ))
+            .storage(Arc::new(crate::service::storage::InMemoryStorage::default()))
+            .build()
+            .await
+    }
 
     pub fn db(&self) -> &dyn DatabaseClient {
         self.db.as_ref()
@@ -110,17 +508,24 @@ impl AppState {
         self.accounts.as_ref()
     }
 
+    pub fn account_settings(&self) -> &dyn AccountSettingsService {
+        self.account_settings.as_ref()
+    }
+
+    pub fn avatars(&self) -> &dyn AvatarService {
+        self.avatars.as_ref()
+    }
+
     pub fn sessions(&self) -> &dyn SessionService {
         self.sessions.as_ref()
     }
 
-    pub fn accounts_repo(&self) -> &dyn AccountsRepo {
-        self.accounts_repo.as_ref()
+    pub fn challenges(&self) -> &dyn ChallengeStore {
+        self.challenges.as_ref()
     }
 
-    #[allow(dead_code)]
-    pub fn account_authorizations_repo(&self) -> &dyn AccountAuthorizationsRepo {
-        self.account_authorizations_repo.as_ref()
+    pub fn accounts_repo(&self) -> &dyn AccountsRepo {
+        self.accounts_repo.as_ref()
     }
 
     pub fn auth(&self) -> &dyn AuthService {
@@ -131,6 +536,54 @@ impl AppState {
         self.verification.as_ref()
     }
 
+    pub fn mfa(&self) -> &dyn MfaService {
+        self.mfa.as_ref()
+    }
+
+    pub fn credentials(&self) -> &dyn CredentialsService {
+        self.credentials.as_ref()
+    }
+
+    pub fn jwt(&self) -> &dyn JwtService {
+        self.jwt.as_ref()
+    }
+
+    pub fn refresh_tokens(&self) -> &dyn RefreshTokenService {
+        self.refresh_tokens.as_ref()
+    }
+
+    pub fn personal_access_tokens(&self) -> &dyn PersonalAccessTokenService {
+        self.personal_access_tokens.as_ref()
+    }
+
+    pub fn oauth(&self) -> &dyn OauthService {
+        self.oauth.as_ref()
+    }
+
+    pub fn introspection(&self) -> &dyn TokenIntrospectionService {
+        self.introspection.as_ref()
+    }
+
+    pub fn webauthn(&self) -> &dyn WebauthnService {
+        self.webauthn.as_ref()
+    }
+
+    pub fn oidc(&self) -> &dyn OidcService {
+        self.oidc.as_ref()
+    }
+
+    pub fn forward_auth_cache(&self) -> &dyn ForwardAuthCache {
+        self.forward_auth_cache.as_ref()
+    }
+
+    pub fn rbac(&self) -> &dyn RbacService {
+        self.rbac.as_ref()
+    }
+
+    pub fn teams(&self) -> &dyn TeamsService {
+        self.teams.as_ref()
+    }
+
     pub fn config(&self) -> &dyn ConfigService {
         self.config.as_ref()
     }